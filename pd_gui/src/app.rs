@@ -1,41 +1,173 @@
 mod artist;
-pub mod audio;
 mod composition;
-mod synth;
+mod engine;
+mod history;
+mod keymap;
+mod midi;
+mod musical_typing;
+mod paths;
+mod project;
+mod recovery;
+pub mod settings;
+mod tabs;
 mod ui;
 // static ICON: &[u8] = include_bytes!("../resources/sqr.png");
 // const ICON_HEIGHT: u32 = 250;
 // const ICON_WIDTH: u32 = 250;
 
+use fundsp::hacker::Net64;
 use iced::{
-    button, keyboard, pane_grid, Button, Column, Command, Container, Element,
-    Length, PaneGrid, Row, Subscription, Text,
+    button, keyboard, pane_grid, pick_list, Button, Color, Column, Command,
+    Container, Element, Length, PaneGrid, PickList, Row, Subscription, Text,
 };
 use iced_audio::Normal;
 use iced_aw::graphics::icons::icon_to_char;
+use iced_aw::{modal, Modal};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 // use iced_aw::{graphics::icons::icon_to_char, Icon, ICON_FONT};
-use iced_native::{event, subscription, Event};
+use iced_native::{event, subscription, window, Event};
 
-use ui::colors::{PANE_ID_COLOR_FOCUSED, PANE_ID_COLOR_UNFOCUSED};
-use ui::components::panes::{style, Pane};
+use composition::{Composition, Track, TrackInstrument};
+use engine::automation::{
+    Breakpoint, Lane, LanePlayer, Mode, Recording, Target,
+};
+use engine::effects::convolution::{ConvolutionReverb, Kernel, KernelSettings};
+use engine::effects::{create_params, Effect, EffectKind, EffectSlot, Param};
+use engine::instrument::{Adsr, Instrument};
+use engine::midi_effects::{MidiChain, MidiEffect};
+use engine::modulation::{ModulationPlayer, Source};
+use engine::patch::graph::{Graph, GraphError};
+use engine::patch::{self, PatchFile, PatchSynth};
+use engine::sample::{SampleBuffer, SampleLibrary};
+use engine::sampler::{SamplerPatch, Zone};
+use engine::sequencer::{self, Pattern, MAX_PADS};
+use engine::transcription::Transcription;
+use engine::transport::ClipPlayer;
+use engine::tuning::{self, KeyboardMap, Scale, Tuning};
+use engine::wavetable::Slicing;
+use engine::{Engine, EngineCommand, VOLUME};
+use history::{History, Step};
+use keymap::{Action, Binding, Key, Keymap, CHORD_LENGTH};
+use midi::MidiClip;
+use musical_typing::MusicalTyping;
+use project::{EditorSettings, EngineSettings, MixerSettings, Project};
+use recovery::Work;
+use settings::{Flags, Settings};
+use tabs::Tab;
+use ui::components::automation::AutomationEdit;
+use ui::components::command_palette::{self, CommandPalette, Entry};
+use ui::components::graph_editor::GraphEdit;
+use ui::components::panes::content::{Content, Context};
+use ui::components::panes::{style, Pane, PaneKind};
+use ui::components::step_sequencer::StepField;
+use ui::components::tabs::{TabBar, TabLabel};
+use ui::components::tuning::ROOT_FREQUENCY;
+use ui::components::workspaces::WorkspaceBar;
+use ui::layout::Layout;
+use ui::theme::{self, palette, ThemeType};
+use ui::workspaces::Workspaces;
 
 pub struct PsycheDaily {
-    // theme: Theme,
+    settings: Settings,
+    // The settings file, which leaves out overrides from the command line
+    saved_settings: Settings,
+    // Settings being dragged or typed, applied on release or submit
+    settings_draft: Option<Settings>,
+    theme_picker: pick_list::State<ThemeType>,
+    // Built-in and user themes, rescanned by the stash tick
+    themes: Vec<ThemeType>,
     panes: pane_grid::State<Pane>,
-    panes_created: usize,
     focus: Option<pane_grid::Pane>,
     // Open a audio I/O stream for a default channel
     start_new_composition: button::State,
 
-    has_sample_creator_open: bool,
     is_composition_mode: bool,
     toggle_sidepanel: button::State,
-    pane_names: HashMap<String, pane_grid::Pane>,
     switch_on: bool,
+
+    engine: Engine,
+    musical_typing: MusicalTyping,
+    toggle_musical_typing: button::State,
+    // Mixer channel whose instrument receives notes from musical typing
+    focused_instrument: usize,
+
+    composition: Composition,
+    sample_library: SampleLibrary,
+    is_playing: bool,
+    toggle_playback: button::State,
+    // Position in the drum track's pattern chain shown in the step editor
+    selected_pattern: usize,
+    // Shared parameter handles of every insert, by slot id
+    effect_params: HashMap<u64, Vec<Param>>,
+    // The insert parameter held with the mouse, and whether the insert waits
+    // for its release to be rebuilt
+    grabbed_effect_param: Option<(u64, usize)>,
+    is_rebuild_waiting: bool,
+    // Convolution kernels by insert id, with what they were prepared from
+    kernels: HashMap<u64, (KernelSettings, Arc<Kernel>)>,
+    // Kernels being prepared, only the latest one asked for is used
+    kernel_requests: HashMap<u64, KernelSettings>,
+    // Kernels to start preparing once the current message is handled
+    kernel_jobs: Vec<(u64, KernelSettings, Arc<SampleBuffer>)>,
+    // Shared parameter handles of every instrument, by track
+    instrument_params: HashMap<usize, Vec<Param>>,
+    // Shared fader handles, by track
+    volume_params: HashMap<usize, Param>,
+    // Patch files played by tracks, by track
+    patches: HashMap<usize, PatchFile>,
+    // Why the graph of a track doesn't compile, by track
+    graph_errors: HashMap<usize, GraphError>,
+    // The graph each track plays, so edits that keep the sound skip a rebuild
+    playing_graphs: HashMap<usize, Graph>,
+    // Graph argument being typed: node, argument and text
+    graph_draft: Option<(u64, usize, String)>,
+    // Automation passes in progress
+    recordings: Vec<Recording>,
+    // How samples are turned into clips
+    transcription: Transcription,
+    tempo: f64,
+    // The bundle the project was last saved to or opened from
+    project_path: Option<PathBuf>,
+    // The project as last saved, in JSON, to tell whether there is unsaved
+    // work
+    saved: Option<String>,
+    // Whether `saved` differed from the project when it was last checked
+    is_unsaved: bool,
+    // The unsaved tabs as last written for recovery, in JSON
+    autosaved: Option<Vec<String>>,
+    // Work the last session left unsaved, until it's restored or discarded
+    recovered: Vec<Work>,
+    restore_recovered: button::State,
+    discard_recovered: button::State,
+    should_exit: bool,
+    history: History,
+    workspaces: Workspaces,
+    // The workspace last switched to or saved
+    workspace: Option<usize>,
+    // Name the panes are saved as a workspace under
+    workspace_name: String,
+    workspace_bar: WorkspaceBar,
+    // Open compositions, the active one is `None` as it lives in the app
+    tabs: Vec<Option<Tab>>,
+    active_tab: usize,
+    tab_bar: TabBar,
+    // A tab with unsaved work waiting for its close to be confirmed
+    closing_tab: Option<usize>,
+    confirm_close: button::State,
+    cancel_close: button::State,
+    keymap: Keymap,
+    // The action being rebound and the keys pressed for it so far
+    rebinding: Option<(Action, Vec<Key>)>,
+    command_palette: modal::State<CommandPalette>,
+    palette_query: String,
+    // Index of the highlighted match
+    palette_selected: usize,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum Message {
     // Pane Grid related
     Split(pane_grid::Axis, pane_grid::Pane),
@@ -47,6 +179,10 @@ pub enum Message {
     TogglePin(pane_grid::Pane),
     Close(pane_grid::Pane),
     CloseFocused,
+    // Opens a kind of pane in a pane that was just split off
+    SetPaneKind(pane_grid::Pane, PaneKind),
+    // Splits the focused pane to open a kind of pane next to it
+    OpenPane(PaneKind),
 
     ////
     CreateCompositionPressed,
@@ -56,74 +192,356 @@ pub enum Message {
 
     // ------
     TestToggle,
-    DB(Normal),
     ////
-}
 
-pub enum ThemeType {
-    Light,
-    Dark,
+    // MIXER
+    // Makes a track the one played, edited and shown in the mixer
+    FocusTrack(usize),
+    // The focused track's fader, in dB
+    SetVolume(f64),
+    GrabVolume,
+    ReleaseVolume,
+
+    // MUSICAL TYPING
+    ToggleMusicalTyping,
+    MusicalKeyPressed(keyboard::KeyCode),
+    MusicalKeyReleased(keyboard::KeyCode),
+
+    // COMPOSITION
+    FileDropped(PathBuf),
+    UseSampleAsInstrument(i64),
+    UseSampleAsWavetable(i64),
+    SetWavetableSlicing(Slicing),
+    UseSampleAsGranular(i64),
+    ToggleGranularDrone,
+    // Recompiles the patch files that changed
+    ReloadPatches,
+    TogglePlayback,
+    SetInstrumentParam(usize, Normal),
+    GrabInstrumentParam(usize),
+    ReleaseInstrumentParam(usize),
+
+    // PATCH GRAPH
+    UseGraphInstrument,
+    EditGraph(GraphEdit),
+
+    // SAMPLER
+    // Edit the zones and envelope of the focused track's sampler
+    AddZone,
+    RemoveZone(usize),
+    SetZone(usize, Zone),
+    SetSamplerEnvelope(Adsr),
+
+    // STEP SEQUENCER
+    AddDrumLane(i64),
+    AddPattern,
+    SelectPattern(usize),
+    SetPatternLength {
+        pattern: usize,
+        length: usize,
+    },
+    SetSwing(usize, Normal),
+    ToggleStep {
+        pattern: usize,
+        lane: usize,
+        step: usize,
+    },
+    AdjustStep {
+        pattern: usize,
+        lane: usize,
+        step: usize,
+        field: StepField,
+        amount: f32,
+    },
+
+    // PROJECT
+    SaveProject,
+    SaveProjectAs,
+    OpenProject,
+    // A bundle was picked in a dialog, `None` when it was cancelled
+    SaveProjectTo(Option<PathBuf>),
+    OpenProjectFrom(Option<PathBuf>),
+
+    // TABS
+    NewTab,
+    SwitchTab(usize),
+    // Switches to the tab this many places away, wrapping around
+    CycleTab(isize),
+    // Moves the active tab this many places along the bar
+    MoveTab(isize),
+    CloseTab(usize),
+    CloseActiveTab,
+    ConfirmCloseTab,
+    CancelCloseTab,
+
+    // WORKSPACES
+    SwitchWorkspace(usize),
+    SetWorkspaceName(String),
+    SaveWorkspace,
+    DeleteWorkspace,
+
+    // HISTORY
+    Undo,
+    Redo,
+
+    // APPEARANCE
+    SetTheme(ThemeType),
+
+    // SETTINGS
+    SetSettings(Settings),
+    // Shows a setting being dragged or typed without applying it
+    EditSettings(Settings),
+    ApplySettings,
+    PickProjectsDir,
+    ProjectsDirPicked(Option<PathBuf>),
+
+    // KEYMAP
+    // A key was pressed outside of text inputs
+    KeyPressed(keyboard::KeyCode, keyboard::Modifiers),
+    // Records the keys pressed next as the binding of an action
+    RecordBinding(Action),
+    FinishBinding,
+    CancelBinding,
+    ResetBinding(Action),
+
+    // COMMAND PALETTE
+    ToggleCommandPalette,
+    CloseCommandPalette,
+    SetPaletteQuery(String),
+    // Runs a match of the query, by its place in the list
+    RunCommand(usize),
+
+    // RECOVERY
+    // Keeps the latest state where the panic hook finds it
+    Stash,
+    Autosave,
+    // An autosave finished, `false` when it failed
+    Autosaved(bool),
+    RestoreRecovered,
+    DiscardRecovered,
+    CloseRequested,
+
+    // AUDIO TO MIDI
+    // Replaces the focused track's clip with the notes of a sample
+    SampleToClip(i64),
+    SetTranscription(Transcription),
+
+    // TUNING
+    SetRootFrequency(Normal),
+    ResetTuning,
+
+    // MIDI EFFECTS
+    AddMidiEffect(MidiEffect),
+    RemoveMidiEffect(usize),
+    SetMidiEffect(usize, MidiEffect),
+
+    // INSERT EFFECTS
+    AddEffect(EffectKind),
+    RemoveEffect(u64),
+    MoveEffect(u64, isize),
+    ToggleBypass(u64),
+    SetEffectParam(u64, usize, Normal),
+    SetImpulseResponse(u64, i64),
+    // A control of an insert parameter was grabbed or let go
+    GrabEffectParam(u64, usize),
+    ReleaseEffectParam(u64, usize),
+    // A convolution kernel was prepared in the background
+    KernelPrepared(u64, KernelSettings, Arc<Kernel>),
+
+    // AUTOMATION
+    SetAutomationMode(Mode),
+    AddAutomationLane(Target),
+    RemoveAutomationLane(usize),
+    EditAutomation(usize, AutomationEdit),
+
+    // MODULATION
+    AddModulationSource(Source),
+    RemoveModulationSource(usize),
+    SetModulationSource(usize, Source),
+    SetModulationDepth {
+        source: usize,
+        target: Target,
+        depth: f64,
+    },
+
+    // Redraws what the audio thread moves: meters, playheads and automated
+    // controls
+    Tick,
 }
 
 impl iced::Application for PsycheDaily {
     type Message = Message;
     type Executor = iced::executor::Default;
-    type Flags = ();
-
-    fn new(_flags: ()) -> (Self, Command<Message>) {
-        let (panes, _) = pane_grid::State::new(Pane::new(0));
-        (
-            Self {
-                // theme: ThemeType::Dark,
-                is_composition_mode: false,
-                start_new_composition: button::State::new(),
-                panes,
-                panes_created: 1, // FIXME should be 0 on init
-                focus: None,
-                has_sample_creator_open: false,
-                toggle_sidepanel: button::State::new(),
-                pane_names: HashMap::new(),
-                switch_on: false,
-            },
-            Command::none(),
-        )
+    type Flags = Flags;
+
+    fn new(flags: Flags) -> (Self, Command<Message>) {
+        let Flags { saved, settings } = flags;
+        match theme::load(&settings.appearance.theme) {
+            Ok(palette) => theme::set_palette(palette),
+            Err(err) => eprintln!("{:#}", err),
+        }
+
+        let (panes, _) =
+            pane_grid::State::new(Pane::new(Some(PaneKind::Composition)));
+        let engine = Engine::start(settings.audio.latency);
+        let mut app = Self {
+            tempo: settings.defaults.tempo,
+            settings,
+            saved_settings: saved,
+            settings_draft: None,
+            theme_picker: pick_list::State::default(),
+            themes: theme::themes(),
+            is_composition_mode: false,
+            start_new_composition: button::State::new(),
+            panes,
+            focus: None,
+            toggle_sidepanel: button::State::new(),
+            switch_on: false,
+            engine,
+            musical_typing: MusicalTyping::default(),
+            toggle_musical_typing: button::State::new(),
+            focused_instrument: 0,
+            composition: Composition::default(),
+            sample_library: SampleLibrary::default(),
+            is_playing: false,
+            toggle_playback: button::State::new(),
+            selected_pattern: 0,
+            effect_params: HashMap::new(),
+            grabbed_effect_param: None,
+            is_rebuild_waiting: false,
+            kernels: HashMap::new(),
+            kernel_requests: HashMap::new(),
+            kernel_jobs: vec![],
+            instrument_params: HashMap::new(),
+            volume_params: HashMap::new(),
+            patches: HashMap::new(),
+            graph_errors: HashMap::new(),
+            playing_graphs: HashMap::new(),
+            graph_draft: None,
+            recordings: vec![],
+            transcription: Transcription::default(),
+            project_path: None,
+            saved: None,
+            is_unsaved: false,
+            autosaved: None,
+            recovered: recovery::pending(),
+            restore_recovered: button::State::new(),
+            discard_recovered: button::State::new(),
+            should_exit: false,
+            history: History::default(),
+            workspaces: Workspaces::load().unwrap_or_else(|err| {
+                eprintln!("{:#}", err);
+                Workspaces::default()
+            }),
+            workspace: None,
+            workspace_name: String::new(),
+            workspace_bar: WorkspaceBar::new(),
+            tabs: vec![None],
+            active_tab: 0,
+            tab_bar: TabBar::default(),
+            closing_tab: None,
+            confirm_close: button::State::new(),
+            cancel_close: button::State::new(),
+            keymap: Keymap::load().unwrap_or_else(|err| {
+                eprintln!("{:#}", err);
+                Keymap::default()
+            }),
+            rebinding: None,
+            command_palette: modal::State::new(CommandPalette::default()),
+            palette_query: String::new(),
+            palette_selected: 0,
+        };
+        for keymap::Conflict(action, other) in app.keymap.conflicts() {
+            eprintln!(
+                "the keys of {:?} and {:?} clash, one of them won't work",
+                action.name(),
+                other.name()
+            );
+        }
+
+        // The panes as they were when the app was last closed
+        let layout = app.workspaces.last.clone();
+        app.set_layout(&layout);
+
+        app.musical_typing.is_enabled = app.settings.defaults.musical_typing;
+        app.engine.send(EngineCommand::SetTempo(app.tempo));
+        app.sync_tuning();
+        for track in 0..app.composition.tracks.len() {
+            app.sync_track(track);
+        }
+        app.saved = serde_json::to_string(&app.project()).ok();
+        recovery::install_panic_hook();
+
+        (app, Command::none())
     }
 
     fn title(&self) -> String {
-        String::from("Psyche Daily")
+        match &self.project_path {
+            Some(path) => format!("Psyche Daily - {}", project::name(path)),
+            None => String::from("Psyche Daily"),
+        }
+    }
+
+    fn should_exit(&self) -> bool {
+        self.should_exit
+    }
+
+    fn background_color(&self) -> Color {
+        palette().background
     }
 
     fn update(&mut self, message: Message) -> Command<Message> {
+        if !self.history.is_settled() {
+            let state = self.state();
+            self.history.settle(&state);
+        }
+        if let Some(step) = history_step(&message) {
+            if self.history.edit(step) {
+                let state = self.state();
+                self.history.start(state);
+            }
+        }
+
         match message {
             Message::CreateCompositionPressed => {
                 self.is_composition_mode = true
             }
             Message::Split(axis, pane) => {
-                let result = self.panes.split(
-                    axis,
-                    &pane,
-                    Pane::new(self.panes_created),
-                );
+                // The new pane offers the kinds to open in it
+                let result = self.panes.split(axis, &pane, Pane::new(None));
 
                 if let Some((pane, _)) = result {
                     self.focus = Some(pane);
                 }
-
-                self.panes_created += 1;
             }
             Message::SplitFocused(axis) => {
                 if let Some(pane) = self.focus {
-                    let result = self.panes.split(
-                        axis,
-                        &pane,
-                        Pane::new(self.panes_created),
-                    );
+                    let result = self.panes.split(axis, &pane, Pane::new(None));
 
                     if let Some((pane, _)) = result {
                         self.focus = Some(pane);
                     }
+                }
+            }
+            Message::SetPaneKind(pane, kind) => {
+                if let Some(pane) = self.panes.get_mut(&pane) {
+                    pane.content = Content::new(Some(kind));
+                }
+            }
+            Message::OpenPane(kind) => {
+                let pane = self.focus.or_else(|| {
+                    self.panes.iter().next().map(|(pane, _)| *pane)
+                });
+                let result = pane.and_then(|pane| {
+                    self.panes.split(
+                        pane_grid::Axis::Vertical,
+                        &pane,
+                        Pane::new(Some(kind)),
+                    )
+                });
 
-                    self.panes_created += 1;
+                if let Some((pane, _)) = result {
+                    self.focus = Some(pane);
                 }
             }
             Message::FocusAdjacent(direction) => {
@@ -179,15 +597,12 @@ impl iced::Application for PsycheDaily {
                 let result = self.panes.split(
                     axis,
                     &pane,
-                    Pane::new(self.panes_created),
+                    Pane::new(Some(PaneKind::SampleCreator)),
                 );
 
                 if let Some((pane, _)) = result {
                     self.focus = Some(pane);
                 }
-
-                self.panes_created += 1; // TODO: keep track of named panes in a vector/hashmap
-                self.has_sample_creator_open = !self.has_sample_creator_open;
             }
             Message::OpenAudioDefaultChannel => {
                 self.switch_on = !self.switch_on;
@@ -195,167 +610,2625 @@ impl iced::Application for PsycheDaily {
             Message::TestToggle => {
                 println!("Toggle");
             }
-            Message::DB(normal) => {
-                //  self.output_text =
-                // info_text_db("VSliderDB", self.db_range.unmap_to_value(normal));
-
-                // TODO: Update output text in sample_creator pane
+            Message::SetVolume(db) => self.set_volume(db),
+            Message::GrabVolume => {
+                self.grab_param(self.focused_instrument, Target::Volume);
             }
-        }
-
-        Command::none()
-    }
+            Message::ReleaseVolume => {
+                self.release_param(self.focused_instrument, Target::Volume);
+            }
+            Message::FocusTrack(track) => {
+                if track < self.composition.tracks.len()
+                    && track != self.focused_instrument
+                {
+                    // Held keys would never see their release
+                    for event in self.musical_typing.release_all() {
+                        self.engine.note(self.focused_instrument, event);
+                    }
+                    self.graph_draft = None;
+                    self.focused_instrument = track;
+                }
+            }
+            Message::ToggleMusicalTyping => {
+                for event in self.musical_typing.toggle() {
+                    self.engine.note(self.focused_instrument, event);
+                }
+            }
+            Message::MusicalKeyPressed(key_code) => {
+                if let Some(event) = self.musical_typing.key_pressed(key_code) {
+                    self.engine.note(self.focused_instrument, event);
+                }
+            }
+            Message::MusicalKeyReleased(key_code) => {
+                if let Some(event) = self.musical_typing.key_released(key_code)
+                {
+                    self.engine.note(self.focused_instrument, event);
+                }
+            }
+            // Patches and MIDI files are played by the focused track, Scala
+            // files tune the composition and anything else is taken as a
+            // sample
+            Message::FileDropped(path) => {
+                let extension = path.extension().and_then(|ext| ext.to_str());
+                if extension == Some(patch::EXTENSION) {
+                    let track = self.focused_instrument;
+                    if let Some(track) = self.composition.tracks.get_mut(track)
+                    {
+                        track.set_instrument(TrackInstrument::Patch { path });
+                    }
+                    self.sync_track(track);
+                } else if extension == Some(midi::FILE_EXTENSION) {
+                    match MidiClip::load(&path) {
+                        Ok(clip) => self.set_clip(clip),
+                        Err(err) => eprintln!(
+                            "failed to load MIDI file {:?}: {:#}",
+                            path, err
+                        ),
+                    }
+                } else if extension == Some(tuning::SCALE_EXTENSION) {
+                    match Scale::load(&path) {
+                        Ok(scale) => {
+                            self.composition.tuning.scale = Some(scale);
+                            self.sync_tuning();
+                        }
+                        Err(err) => {
+                            eprintln!(
+                                "failed to load scale {:?}: {}",
+                                path, err
+                            )
+                        }
+                    }
+                } else if extension == Some(tuning::KEYBOARD_EXTENSION) {
+                    match KeyboardMap::load(&path) {
+                        Ok(keyboard) => {
+                            let tuning = &mut self.composition.tuning;
+                            tuning.keyboard = Some(keyboard);
+                            // The mapping brings its own reference
+                            tuning.root_frequency = None;
+                            self.sync_tuning();
+                        }
+                        Err(err) => eprintln!(
+                            "failed to load keyboard mapping {:?}: {}",
+                            path, err
+                        ),
+                    }
+                } else {
+                    self.composition.add_sample(&path);
+                }
+            }
+            Message::UseSampleAsInstrument(sample_id) => {
+                let track = self.focused_instrument;
+                if let Some(track) = self.composition.tracks.get_mut(track) {
+                    track.set_instrument(TrackInstrument::Sampler(
+                        SamplerPatch::single(sample_id),
+                    ));
+                }
+                self.sync_track(track);
+            }
+            // Frames are cut the way the sample's length suggests, which the
+            // track can change afterwards
+            Message::UseSampleAsWavetable(sample_id) => {
+                let slicing = self
+                    .composition
+                    .sample(sample_id)
+                    .and_then(|sample| {
+                        self.sample_library.get_or_load(sample).ok()
+                    })
+                    .map_or(Slicing::SingleCycle, |buffer| {
+                        Slicing::guess(buffer.frames())
+                    });
 
-    fn view(&mut self) -> Element<Message> {
-        //         column![
-        //     text("Bezier tool example").width(Length::Shrink).size(50),
-        //     self.bezier.view(&self.curves).map(Message::AddCurve),
-        //     button("Clear").padding(8).on_press(Message::Clear),
-        // ]
+                let track = self.focused_instrument;
+                if let Some(track) = self.composition.tracks.get_mut(track) {
+                    track.set_instrument(TrackInstrument::Wavetable {
+                        sample_id,
+                        slicing,
+                    });
+                }
+                self.sync_track(track);
+            }
+            Message::SetWavetableSlicing(slicing) => {
+                let track = self.focused_instrument;
+                if let Some(TrackInstrument::Wavetable {
+                    slicing: current,
+                    ..
+                }) = self
+                    .composition
+                    .tracks
+                    .get_mut(track)
+                    .map(|track| &mut track.instrument)
+                {
+                    *current = slicing;
+                }
+                self.sync_track(track);
+            }
+            Message::UseSampleAsGranular(sample_id) => {
+                let track = self.focused_instrument;
+                if let Some(track) = self.composition.tracks.get_mut(track) {
+                    track.set_instrument(TrackInstrument::Granular {
+                        sample_id,
+                        drone: false,
+                    });
+                }
+                self.sync_track(track);
+            }
+            Message::ToggleGranularDrone => {
+                let track = self.focused_instrument;
+                if let Some(TrackInstrument::Granular { drone, .. }) = self
+                    .composition
+                    .tracks
+                    .get_mut(track)
+                    .map(|track| &mut track.instrument)
+                {
+                    *drone = !*drone;
+                }
+                self.sync_track(track);
+            }
+            Message::ReloadPatches => {
+                for (channel, file) in &mut self.patches {
+                    let params = match self.instrument_params.get(channel) {
+                        Some(params) => params.clone(),
+                        None => continue,
+                    };
 
-        // TODO: On press -> should open a new panel (for now -> might become a modal)
-        //     .push(Text::new("Show composition swim lanes"))
-        let focus = self.focus;
-        let total_panes = self.panes.len();
+                    if let Some(net) = file.reload() {
+                        swap_patch(&self.engine, *channel, params, net);
+                    }
+                }
+            }
+            Message::UseGraphInstrument => {
+                let track = self.focused_instrument;
+                if let Some(track) = self.composition.tracks.get_mut(track) {
+                    track.set_instrument(TrackInstrument::Graph(Graph::new()));
+                }
+                self.sync_track(track);
+            }
+            Message::EditGraph(edit) => {
+                let channel = self.focused_instrument;
+                let graph = match self
+                    .composition
+                    .tracks
+                    .get_mut(channel)
+                    .map(|track| &mut track.instrument)
+                {
+                    Some(TrackInstrument::Graph(graph)) => graph,
+                    _ => return Command::none(),
+                };
 
-        let mut pane_grid = PaneGrid::new(&mut self.panes, |id, pane| {
-            let is_focused = focus == Some(id);
-            let has_sample_creator_open = self.has_sample_creator_open;
+                match edit {
+                    // New units cascade down from the top left
+                    GraphEdit::AddNode(unit) => {
+                        let offset = (graph.nodes.len() % 8) as f32 * 24.0;
+                        graph.add_node(unit, (160.0 + offset, 20.0 + offset));
+                    }
+                    // Moving doesn't change the sound
+                    GraphEdit::MoveNode { id, x, y } => {
+                        graph.move_node(id, (x, y));
+                        return Command::none();
+                    }
+                    GraphEdit::RemoveNode(id) => graph.remove_node(id),
+                    GraphEdit::Connect { from, to } => graph.connect(from, to),
+                    GraphEdit::Disconnect(to) => {
+                        graph.disconnect(to);
+                    }
+                    GraphEdit::SetArg { id, index, text } => {
+                        let value = text.trim().parse::<f64>();
+                        self.graph_draft = Some((id, index, text));
+                        match value {
+                            Ok(value) => {
+                                if let Err(err) =
+                                    graph.set_arg(id, index, value)
+                                {
+                                    self.graph_errors.insert(channel, err);
+                                    return Command::none();
+                                }
+                            }
+                            Err(_) => return Command::none(),
+                        }
+                    }
+                }
 
-            let mut pane_name = format!("{}", pane.content.pane_name);
+                // Like an argument typed again or a wire redrawn
+                if self
+                    .playing_graphs
+                    .get(&channel)
+                    .map_or(false, |playing| playing.is_same_net(graph))
+                {
+                    self.graph_errors.remove(&channel);
+                    return Command::none();
+                }
 
-            if has_sample_creator_open == true && pane.content.id == 1 {
-                pane_name = "Sample creator".to_string();
+                // The last good version keeps playing while there are errors
+                match graph.compile() {
+                    Ok(net) => {
+                        self.graph_errors.remove(&channel);
+                        if let Some(params) =
+                            self.instrument_params.get(&channel)
+                        {
+                            let params = params.clone();
+                            swap_patch(&self.engine, channel, params, net);
+                            self.playing_graphs.insert(channel, graph.clone());
+                        }
+                    }
+                    Err(err) => {
+                        self.graph_errors.insert(channel, err);
+                    }
+                }
             }
+            Message::TogglePlayback => {
+                if self.is_playing {
+                    // Passes end where the transport stopped, read it before
+                    // the engine rewinds
+                    while !self.recordings.is_empty() {
+                        self.finish_recording(0);
+                    }
+                    self.engine.send(EngineCommand::Stop);
+                } else {
+                    self.engine.send(EngineCommand::Play);
+                }
+                self.is_playing = !self.is_playing;
 
-            // let x = pane_grid::Node::splits(pane_grid).map(|split| split);
-
-            let title = Row::with_children(vec![
-                // Text::new("Pane").into(), <<-- should probably showcontent title [e.g composition-name]
-                Text::new(&pane_name)
-                    .color(if is_focused {
-                        PANE_ID_COLOR_FOCUSED
-                    } else {
-                        PANE_ID_COLOR_UNFOCUSED
-                    })
-                    .into(),
-            ])
-            .spacing(5);
+                if self.is_playing {
+                    self.start_write_passes();
+                }
+            }
+            Message::SetInstrumentParam(index, normal) => {
+                self.set_instrument_param(index, normal.as_f32());
+            }
+            Message::GrabInstrumentParam(index) => {
+                let target = Target::Instrument { param: index };
+                self.grab_param(self.focused_instrument, target);
+            }
+            Message::ReleaseInstrumentParam(index) => {
+                let target = Target::Instrument { param: index };
+                self.release_param(self.focused_instrument, target);
+            }
+            Message::AddDrumLane(sample_id) => {
+                let track = self.drum_track();
+                let pattern = self.selected_pattern_index(track);
 
-            let title_bar = pane_grid::TitleBar::new(title)
-                .controls(pane.controls.view(id, total_panes, pane.is_pinned))
-                .padding(1)
-                .style(style::TitleBar { is_focused });
+                // Every new sample takes a pad, and pads run out at the top
+                // of the keyboard
+                let kit = match &self.composition.tracks[track].instrument {
+                    TrackInstrument::StepSequencer { chain } => {
+                        sequencer::kit(self.composition.chain(chain))
+                    }
+                    _ => vec![],
+                };
+                let lanes = self.composition.patterns[pattern].lanes.len();
+                if lanes >= MAX_PADS
+                    || (kit.len() >= MAX_PADS && !kit.contains(&sample_id))
+                {
+                    return Command::none();
+                }
 
-            pane_grid::Content::new(pane.content.view(
-                id,
-                total_panes,
-                pane.is_pinned,
-                has_sample_creator_open,
-                pane_name,
-            ))
-            .title_bar(title_bar) // <<-- // TODO: Title bar should probably be something like tabs with project-name
-            .style(style::Pane { is_focused })
-        })
-        .width(Length::Fill)
-        .height(Length::Fill)
-        .spacing(10)
-        // .on_click(Message::Clicked)
-        .on_drag(Message::Dragged)
-        .on_resize(10, Message::Resized);
+                self.composition.patterns[pattern].add_lane(sample_id);
+                // A new lane can bring a new sample into the kit
+                self.sync_track(track);
+            }
+            Message::AddPattern => {
+                let track = self.drum_track();
+                let previous = self.selected_pattern_index(track);
 
-        pane_grid = pane_grid.on_click(Message::Clicked);
+                // Start from the same lanes with all steps cleared
+                let mut pattern = Pattern::new(&format!(
+                    "Pattern {}",
+                    self.composition.patterns.len() + 1
+                ));
+                for lane in &self.composition.patterns[previous].lanes {
+                    pattern.add_lane(lane.sample_id);
+                }
+                self.composition.patterns.push(pattern);
 
-        let mut wrapper = Row::new().height(Length::Fill).width(Length::Fill);
+                let index = self.composition.patterns.len() - 1;
+                if let TrackInstrument::StepSequencer { chain } =
+                    &mut self.composition.tracks[track].instrument
+                {
+                    chain.push(index);
+                    self.selected_pattern = chain.len() - 1;
+                }
+                self.sync_track(track);
+            }
+            Message::SelectPattern(position) => {
+                self.selected_pattern = position;
+            }
+            Message::SetPatternLength { pattern, length } => {
+                if let Some(pattern) =
+                    self.composition.patterns.get_mut(pattern)
+                {
+                    pattern.set_length(length);
+                }
+                self.sync_sequencers();
+            }
+            Message::SetSwing(pattern, normal) => {
+                if let Some(pattern) =
+                    self.composition.patterns.get_mut(pattern)
+                {
+                    pattern.swing = normal.as_f32();
+                }
+                self.sync_sequencers();
+            }
+            Message::ToggleStep {
+                pattern,
+                lane,
+                step,
+            } => {
+                if let Some(step) = self.step_mut(pattern, lane, step) {
+                    step.is_active = !step.is_active;
+                }
+                self.sync_sequencers();
+            }
+            Message::AdjustStep {
+                pattern,
+                lane,
+                step,
+                field,
+                amount,
+            } => {
+                if let Some(step) = self.step_mut(pattern, lane, step) {
+                    match field {
+                        StepField::Velocity => {
+                            step.velocity = (step.velocity as f32
+                                + amount * 8.0)
+                                .clamp(1.0, 127.0)
+                                as u8;
+                        }
+                        StepField::Probability => {
+                            step.probability = (step.probability
+                                + amount * 0.05)
+                                .clamp(0.0, 1.0);
+                        }
+                        StepField::Nudge => {
+                            step.nudge =
+                                (step.nudge + amount * 0.05).clamp(-0.5, 0.5);
+                        }
+                    }
+                }
+                self.sync_sequencers();
+            }
+            Message::AddEffect(kind) => {
+                let channel = self.focused_instrument;
+                let id = self.composition.next_effect_id();
+                let slot = EffectSlot::new(id, kind);
+                let params = kind.create_params(&slot.values);
+                let effect = self.build_effect(&slot, &params);
 
-        let mut column_1: Column<Message> = Column::new().height(Length::Fill);
-        // .push(Space::new(Length::Units(50), Length::Units(0)));
+                if let Some(track) = self.composition.tracks.get_mut(channel) {
+                    self.engine.send(EngineCommand::InsertEffect {
+                        channel,
+                        index: track.inserts.len(),
+                        id,
+                        effect,
+                        is_bypassed: false,
+                    });
+                    track.inserts.push(slot);
+                    self.effect_params.insert(id, params);
+                }
+            }
+            Message::RemoveEffect(id) => {
+                if let Some((channel, position)) =
+                    self.composition.find_insert(id)
+                {
+                    self.composition.tracks[channel].inserts.remove(position);
+                    self.effect_params.remove(&id);
+                    self.kernels.remove(&id);
+                    self.kernel_requests.remove(&id);
+                    self.engine
+                        .send(EngineCommand::RemoveEffect { channel, id });
 
-        // Show option to creation a new composition when not already in composition mode
-        if !self.is_composition_mode {
-            column_1 = column_1.push(
-                Button::new(
-                    &mut self.start_new_composition,
-                    Text::new("Composition +"),
-                )
-                .on_press(Message::CreateCompositionPressed)
-                .style(style::Button::Primary),
-            )
-        }
+                    let targets_effect = |target: &Target| matches!(target, Target::Effect { id: effect, .. } if *effect == id);
+                    self.composition.tracks[channel]
+                        .automation
+                        .retain(|lane| !targets_effect(&lane.target));
+                    self.recordings
+                        .retain(|recording| !targets_effect(&recording.target));
+                    self.composition.tracks[channel]
+                        .modulation
+                        .retain_targets(|target| !targets_effect(&target));
+                    self.sync_automation(channel);
+                    self.sync_modulation(channel);
+                }
+            }
+            Message::MoveEffect(id, offset) => {
+                if let Some((channel, position)) =
+                    self.composition.find_insert(id)
+                {
+                    let inserts = &mut self.composition.tracks[channel].inserts;
+                    let index = (position as isize + offset)
+                        .clamp(0, inserts.len() as isize - 1)
+                        as usize;
 
-        // Show sidebar with composition relatd options when in composition mode
-        if self.is_composition_mode {
-            column_1 = column_1.push(
-                Button::new(
-                    &mut self.toggle_sidepanel,
-                    Text::new(icon_to_char(iced_aw::Icon::ArrowBarLeft))
-                        .font(iced_aw::ICON_FONT), // TODO Add sample browser
-                )
-                .on_press(Message::TestToggle)
-                .style(style::Button::Control),
-            )
-            // .push(Text::new(&self.output_text))
-        }
+                    let slot = inserts.remove(position);
+                    inserts.insert(index, slot);
+                    self.engine.send(EngineCommand::MoveEffect {
+                        channel,
+                        id,
+                        index,
+                    });
+                }
+            }
+            Message::ToggleBypass(id) => {
+                if let Some((channel, position)) =
+                    self.composition.find_insert(id)
+                {
+                    let slot =
+                        &mut self.composition.tracks[channel].inserts[position];
+                    slot.is_bypassed = !slot.is_bypassed;
 
-        let mut column_2: Column<Message> = Column::new().height(Length::Fill);
+                    self.engine.send(EngineCommand::BypassEffect {
+                        channel,
+                        id,
+                        is_bypassed: slot.is_bypassed,
+                    });
+                }
+            }
+            Message::SetEffectParam(id, index, normal) => {
+                self.set_effect_param(id, index, normal.as_f32());
+            }
+            Message::SetImpulseResponse(id, sample_id) => {
+                if let Some((channel, position)) =
+                    self.composition.find_insert(id)
+                {
+                    self.composition.tracks[channel].inserts[position]
+                        .impulse_response = Some(sample_id);
+                    self.rebuild_effect(id);
+                }
+            }
+            Message::GrabEffectParam(id, index) => {
+                self.grabbed_effect_param = Some((id, index));
+                if let Some((channel, _)) = self.composition.find_insert(id) {
+                    let target = Target::Effect { id, param: index };
+                    self.grab_param(channel, target);
+                }
+            }
+            Message::ReleaseEffectParam(id, index) => {
+                self.grabbed_effect_param = None;
+                if std::mem::take(&mut self.is_rebuild_waiting) {
+                    self.rebuild_effect(id);
+                }
+                if let Some((channel, _)) = self.composition.find_insert(id) {
+                    let target = Target::Effect { id, param: index };
+                    self.release_param(channel, target);
+                }
+            }
+            Message::KernelPrepared(id, settings, kernel) => {
+                // A newer kernel was asked for meanwhile
+                if self.kernel_requests.get(&id) != Some(&settings) {
+                    return Command::none();
+                }
+                self.kernel_requests.remove(&id);
+                self.kernels.insert(id, (settings, Arc::clone(&kernel)));
 
-        // Show composition panes
-        if self.is_composition_mode == true {
-            column_2 = column_2.push(pane_grid);
-        }
+                if let (Some((channel, _)), Some(params)) = (
+                    self.composition.find_insert(id),
+                    self.effect_params.get(&id),
+                ) {
+                    self.engine.send(EngineCommand::ReplaceEffect {
+                        channel,
+                        id,
+                        effect: Box::new(ConvolutionReverb::new(
+                            params.clone(),
+                            Some(kernel),
+                        )),
+                    });
+                }
+            }
+            Message::SetAutomationMode(mode) => {
+                let channel = self.focused_instrument;
 
-        wrapper = wrapper.push(column_1).push(column_2);
+                // Passes recorded under the old mode are kept
+                while let Some(index) = self
+                    .recordings
+                    .iter()
+                    .position(|recording| recording.channel == channel)
+                {
+                    self.finish_recording(index);
+                }
 
-        Container::new(wrapper)
-            .width(Length::Fill)
-            .height(Length::Fill)
-            .padding(0)
-            .into()
-    }
+                if let Some(track) = self.composition.tracks.get_mut(channel) {
+                    track.automation_mode = mode;
+                }
+                self.sync_automation(channel);
+            }
+            Message::AddAutomationLane(target) => {
+                let channel = self.focused_instrument;
+                let value =
+                    self.target_param(channel, target).map(Param::normal);
 
-    fn subscription(&self) -> Subscription<Message> {
-        subscription::events_with(|event, status| {
-            if let event::Status::Captured = status {
-                return None;
+                if let Some(track) = self.composition.tracks.get_mut(channel) {
+                    // Start from the current value, so adding a lane
+                    // changes nothing
+                    let mut lane = Lane::new(target);
+                    if let Some(value) = value {
+                        lane.insert(Breakpoint::new(0.0, value as f64));
+                    }
+                    track.automation.push(lane);
+                }
+                self.sync_automation(channel);
             }
+            Message::RemoveAutomationLane(index) => {
+                let channel = self.focused_instrument;
 
-            match event {
-                Event::Keyboard(keyboard::Event::KeyPressed {
-                    modifiers,
-                    key_code,
-                }) if modifiers.command() => handle_hotkey(key_code),
-                _ => None,
+                if let Some(track) = self.composition.tracks.get_mut(channel) {
+                    if index < track.automation.len() {
+                        let lane = track.automation.remove(index);
+                        self.recordings.retain(|recording| {
+                            recording.channel != channel
+                                || recording.target != lane.target
+                        });
+                    }
+                }
+                self.sync_automation(channel);
             }
-        })
-    }
-}
+            Message::EditAutomation(index, edit) => {
+                let channel = self.focused_instrument;
+                let lane = match self
+                    .composition
+                    .tracks
+                    .get_mut(channel)
+                    .and_then(|track| track.automation.get_mut(index))
+                {
+                    Some(lane) => lane,
+                    None => return Command::none(),
+                };
 
-// -------------------
-// -------------------
-// -------------------
-// -------------------
-// -------------------
-// -------------------
-// -------------------
-// TODO Figure out where handle_hotkey belongs
+                match edit {
+                    AutomationEdit::Add { beat, value } => {
+                        lane.insert(Breakpoint::new(beat, value));
+                    }
+                    // Breakpoints can't be dragged past their neighbours,
+                    // so indices stay put during a drag
+                    AutomationEdit::Move { point, beat, value } => {
+                        let previous = point
+                            .checked_sub(1)
+                            .and_then(|previous| lane.breakpoints.get(previous))
+                            .map_or(0.0, |previous| previous.beat);
+                        let next = lane
+                            .breakpoints
+                            .get(point + 1)
+                            .map_or(f64::MAX, |next| next.beat);
 
-fn handle_hotkey(key_code: keyboard::KeyCode) -> Option<Message> {
-    use keyboard::KeyCode;
-    use pane_grid::{Axis, Direction};
+                        if let Some(breakpoint) =
+                            lane.breakpoints.get_mut(point)
+                        {
+                            breakpoint.beat = beat.clamp(previous, next);
+                            breakpoint.value = value.clamp(0.0, 1.0);
+                        }
+                    }
+                    AutomationEdit::Remove(point) => {
+                        if point < lane.breakpoints.len() {
+                            lane.breakpoints.remove(point);
+                        }
+                    }
+                    AutomationEdit::SetCurve { point, curve } => {
+                        if let Some(breakpoint) =
+                            lane.breakpoints.get_mut(point)
+                        {
+                            breakpoint.curve = curve;
+                        }
+                    }
+                }
+                self.sync_automation(channel);
+            }
+            Message::SaveProject => match self.project_path.clone() {
+                Some(path) => self.save_project(path),
+                None => return self.update(Message::SaveProjectAs),
+            },
+            Message::SaveProjectAs => {
+                let name = format!(
+                    "{}.{}",
+                    self.composition.title,
+                    project::EXTENSION
+                );
+                return Command::perform(
+                    self.project_dialog().set_file_name(&name).save_file(),
+                    |file| {
+                        Message::SaveProjectTo(file.map(|file| {
+                            project::bundle_path(file.path().to_path_buf())
+                        }))
+                    },
+                );
+            }
+            Message::OpenProject => {
+                return Command::perform(
+                    self.project_dialog().pick_folder(),
+                    |folder| {
+                        Message::OpenProjectFrom(
+                            folder.map(|folder| folder.path().to_path_buf()),
+                        )
+                    },
+                );
+            }
+            Message::SaveProjectTo(path) => {
+                if let Some(path) = path {
+                    self.save_project(path);
+                }
+            }
+            Message::OpenProjectFrom(path) => {
+                if let Some(path) = path {
+                    self.open_project(path);
+                }
+            }
+            Message::SampleToClip(sample_id) => {
+                let buffer = match self.composition.sample(sample_id) {
+                    Some(sample) => {
+                        match self.sample_library.get_or_load(sample) {
+                            Ok(buffer) => buffer,
+                            Err(err) => {
+                                eprintln!(
+                                    "failed to load sample {:?}: {}",
+                                    sample.path, err
+                                );
+                                return Command::none();
+                            }
+                        }
+                    }
+                    None => return Command::none(),
+                };
+
+                let clip = self.transcription.transcribe(&buffer, self.tempo);
+                self.set_clip(clip);
+            }
+            Message::SetTranscription(transcription) => {
+                self.transcription = transcription;
+            }
+            Message::SetRootFrequency(normal) => {
+                self.composition.tuning.root_frequency =
+                    Some(ROOT_FREQUENCY.from_normal(normal.as_f32()));
+                self.sync_tuning();
+            }
+            Message::ResetTuning => {
+                self.composition.tuning = Tuning::default();
+                self.sync_tuning();
+            }
+            // A new zone starts as a copy of the last one
+            Message::AddZone => {
+                let sample = self.composition.samples.first().map(|s| s.id);
+                self.edit_sampler(|patch| {
+                    let zone = patch
+                        .zones
+                        .last()
+                        .cloned()
+                        .or_else(|| sample.map(Zone::full_range));
+                    patch.zones.extend(zone);
+                });
+            }
+            Message::RemoveZone(index) => self.edit_sampler(|patch| {
+                if index < patch.zones.len() {
+                    patch.zones.remove(index);
+                }
+            }),
+            Message::SetZone(index, zone) => self.edit_sampler(|patch| {
+                if let Some(current) = patch.zones.get_mut(index) {
+                    *current = zone;
+                }
+            }),
+            Message::SetSamplerEnvelope(adsr) => {
+                self.edit_sampler(|patch| patch.adsr = adsr)
+            }
+            Message::AddMidiEffect(effect) => {
+                let channel = self.focused_instrument;
+                if let Some(track) = self.composition.tracks.get_mut(channel) {
+                    track.midi_effects.push(effect);
+                }
+                self.sync_midi_effects(channel);
+            }
+            Message::RemoveMidiEffect(index) => {
+                let channel = self.focused_instrument;
+                if let Some(track) = self.composition.tracks.get_mut(channel) {
+                    if index < track.midi_effects.len() {
+                        track.midi_effects.remove(index);
+                    }
+                }
+                self.sync_midi_effects(channel);
+            }
+            Message::SetMidiEffect(index, effect) => {
+                let channel = self.focused_instrument;
+                if let Some(slot) = self
+                    .composition
+                    .tracks
+                    .get_mut(channel)
+                    .and_then(|track| track.midi_effects.get_mut(index))
+                {
+                    *slot = effect;
+                }
+                self.sync_midi_effects(channel);
+            }
+            Message::AddModulationSource(source) => {
+                let channel = self.focused_instrument;
+                if let Some(track) = self.composition.tracks.get_mut(channel) {
+                    track.modulation.sources.push(source);
+                }
+                self.sync_modulation(channel);
+            }
+            Message::RemoveModulationSource(index) => {
+                let channel = self.focused_instrument;
+                if let Some(track) = self.composition.tracks.get_mut(channel) {
+                    track.modulation.remove_source(index);
+                }
+                self.sync_modulation(channel);
+            }
+            Message::SetModulationSource(index, source) => {
+                let channel = self.focused_instrument;
+                if let Some(slot) =
+                    self.composition.tracks.get_mut(channel).and_then(|track| {
+                        track.modulation.sources.get_mut(index)
+                    })
+                {
+                    *slot = source;
+                }
+                self.sync_modulation(channel);
+            }
+            Message::SetModulationDepth {
+                source,
+                target,
+                depth,
+            } => {
+                let channel = self.focused_instrument;
+                // Only parameters read while playing can be destinations
+                if self.target_param(channel, target).is_none() {
+                    return Command::none();
+                }
+                if let Some(track) = self.composition.tracks.get_mut(channel) {
+                    track.modulation.set_depth(source, target, depth);
+                }
+                self.sync_modulation(channel);
+            }
+            Message::NewTab => {
+                self.closing_tab = None;
+                let tab = self.empty_tab();
+                self.open_tab(tab);
+            }
+            Message::SwitchTab(index) => self.switch_tab(index),
+            Message::CycleTab(offset) => {
+                let count = self.tabs.len() as isize;
+                let index =
+                    (self.active_tab as isize + offset).rem_euclid(count);
+                self.switch_tab(index as usize);
+            }
+            Message::MoveTab(offset) => {
+                let index = self.active_tab as isize + offset;
+                if (0..self.tabs.len() as isize).contains(&index) {
+                    self.closing_tab = None;
+                    self.tabs.swap(self.active_tab, index as usize);
+                    self.active_tab = index as usize;
+                }
+            }
+            Message::CloseTab(index) => {
+                let is_unsaved = match self.tabs.get(index) {
+                    Some(Some(tab)) => tab.is_unsaved,
+                    Some(None) => self.unsaved().is_some(),
+                    None => return Command::none(),
+                };
+                if is_unsaved {
+                    self.closing_tab = Some(index);
+                } else {
+                    self.close_tab(index);
+                }
+            }
+            Message::CloseActiveTab => {
+                return self.update(Message::CloseTab(self.active_tab));
+            }
+            Message::ConfirmCloseTab => {
+                if let Some(index) = self.closing_tab.take() {
+                    self.close_tab(index);
+                }
+            }
+            Message::CancelCloseTab => self.closing_tab = None,
+            Message::SwitchWorkspace(index) => {
+                if let Some(workspace) = self.workspaces.saved.get(index) {
+                    let layout = workspace.layout.clone();
+                    self.workspace_name = workspace.name.clone();
+                    self.workspace = Some(index);
+                    self.set_layout(&layout);
+                }
+            }
+            Message::SetWorkspaceName(name) => self.workspace_name = name,
+            Message::SaveWorkspace => {
+                let name = self.workspace_name.trim().to_string();
+                if !name.is_empty() {
+                    let layout = Layout::capture(&self.panes);
+                    self.workspace = Some(self.workspaces.insert(name, layout));
+                    self.save_workspaces();
+                }
+            }
+            Message::DeleteWorkspace => {
+                if let Some(index) = self.workspace.take() {
+                    if index < self.workspaces.saved.len() {
+                        self.workspaces.saved.remove(index);
+                        self.save_workspaces();
+                    }
+                }
+            }
+            Message::Undo => {
+                let state = self.state();
+                if let Some(state) = self.history.undo(state) {
+                    self.restore_state(&state);
+                }
+            }
+            Message::Redo => {
+                let state = self.state();
+                if let Some(state) = self.history.redo(state) {
+                    self.restore_state(&state);
+                }
+            }
+            Message::SetTheme(theme) => {
+                let mut settings = self.settings.clone();
+                settings.appearance.theme = theme;
+                self.set_settings(settings);
+            }
+            Message::SetSettings(settings) => self.set_settings(settings),
+            Message::EditSettings(settings) => {
+                self.settings_draft = Some(settings);
+            }
+            Message::ApplySettings => {
+                if let Some(settings) = self.settings_draft.take() {
+                    self.set_settings(settings);
+                }
+            }
+            Message::PickProjectsDir => {
+                return Command::perform(
+                    self.project_dialog().pick_folder(),
+                    |folder| {
+                        Message::ProjectsDirPicked(
+                            folder.map(|folder| folder.path().to_path_buf()),
+                        )
+                    },
+                );
+            }
+            Message::ProjectsDirPicked(dir) => {
+                if let Some(dir) = dir {
+                    let mut settings = self.settings.clone();
+                    settings.paths.projects = Some(dir);
+                    self.set_settings(settings);
+                }
+            }
+            Message::KeyPressed(key_code, modifiers) => {
+                let key = Key {
+                    key_code,
+                    modifiers,
+                };
+
+                if let Some((_, keys)) = &mut self.rebinding {
+                    if key_code == keyboard::KeyCode::Escape && keys.is_empty()
+                    {
+                        self.rebinding = None;
+                    } else if key.is_bindable() {
+                        keys.push(key);
+                        if keys.len() == CHORD_LENGTH {
+                            self.finish_binding();
+                        }
+                    }
+                    return Command::none();
+                }
+
+                // The search field lets the arrows through to move between
+                // the matches
+                if self.command_palette.is_shown() {
+                    match key_code {
+                        keyboard::KeyCode::Up => {
+                            self.palette_selected =
+                                self.palette_selected.saturating_sub(1);
+                            return Command::none();
+                        }
+                        keyboard::KeyCode::Down => {
+                            let count = self.palette_entries().len();
+                            if self.palette_selected + 1 < count {
+                                self.palette_selected += 1;
+                            }
+                            return Command::none();
+                        }
+                        _ => {}
+                    }
+                }
+
+                // Unmodified keys play notes while musical typing is on,
+                // unless they finish a chord
+                if self.musical_typing.is_enabled
+                    && modifiers.is_empty()
+                    && !self.keymap.is_pending()
+                {
+                    return Command::none();
+                }
+
+                if let Some(action) = self.keymap.press(key) {
+                    return self.update(action.message());
+                }
+            }
+            Message::RecordBinding(action) => {
+                self.rebinding = Some((action, vec![]));
+            }
+            Message::FinishBinding => self.finish_binding(),
+            Message::CancelBinding => self.rebinding = None,
+            Message::ResetBinding(action) => {
+                self.keymap.reset(action);
+                self.save_keymap();
+            }
+            Message::ToggleCommandPalette => {
+                if self.command_palette.is_shown() {
+                    self.close_command_palette();
+                } else {
+                    self.command_palette.show(true);
+                    self.command_palette.inner_mut().focus();
+                }
+            }
+            Message::CloseCommandPalette => self.close_command_palette(),
+            Message::SetPaletteQuery(query) => {
+                self.palette_query = query;
+                self.palette_selected = 0;
+            }
+            Message::RunCommand(index) => {
+                let entry = self.palette_entries().into_iter().nth(index);
+                self.close_command_palette();
+                if let Some(entry) = entry {
+                    return self.update(entry.message);
+                }
+            }
+            // Nothing is stashed or autosaved over the recovered work before
+            // the user decides on it
+            Message::Stash => {
+                self.is_unsaved = self.unsaved().is_some();
+                // Picks up palette files added since the app started
+                self.themes = theme::themes();
+                if self.recovered.is_empty() {
+                    recovery::stash(
+                        self.unsaved_work()
+                            .into_iter()
+                            .map(|(work, _)| work)
+                            .collect(),
+                    );
+                }
+            }
+            Message::Autosave if self.recovered.is_empty() => {
+                let (work, states): (Vec<Work>, Vec<String>) =
+                    self.unsaved_work().into_iter().unzip();
+                if self.autosaved.as_ref() != Some(&states) {
+                    self.autosaved = Some(states);
+                    return Command::perform(
+                        async move {
+                            recovery::save(&work).map_err(|err| {
+                                eprintln!("failed to autosave: {:#}", err)
+                            })
+                        },
+                        |result| Message::Autosaved(result.is_ok()),
+                    );
+                }
+            }
+            Message::Autosave => {}
+            Message::Autosaved(is_saved) => {
+                // Tries again on the next round
+                if !is_saved {
+                    self.autosaved = None;
+                }
+            }
+            Message::RestoreRecovered => {
+                // Each in a tab of its own. Still unsaved, they stay in the
+                // recovery bundles until they are saved
+                for work in std::mem::take(&mut self.recovered) {
+                    let tab = Tab {
+                        project: work.project,
+                        path: work.origin,
+                        saved: None,
+                        is_unsaved: true,
+                        history: History::default(),
+                    };
+                    if self.project_path.is_none() && self.unsaved().is_none() {
+                        self.unpark(tab);
+                    } else {
+                        self.open_tab(tab);
+                    }
+                }
+                self.autosaved = None;
+            }
+            Message::DiscardRecovered => {
+                self.recovered.clear();
+                recovery::discard();
+            }
+            Message::CloseRequested => {
+                self.write_recovery();
+                self.workspaces.last = Layout::capture(&self.panes);
+                self.save_workspaces();
+                self.should_exit = true;
+            }
+            Message::Tick => {}
+        }
+
+        self.prepare_kernels()
+    }
+
+    fn view(&mut self) -> Element<Message> {
+        // TODO: On press -> should open a new panel (for now -> might become a modal)
+        //     .push(Text::new("Show composition swim lanes"))
+        let tab_labels: Vec<TabLabel> = (0..self.tabs.len())
+            .map(|index| self.tab_label(index))
+            .collect();
+        let palette_entries = if self.command_palette.is_shown() {
+            self.palette_entries()
+        } else {
+            vec![]
+        };
+        let focus = self.focus;
+        let total_panes = self.panes.len();
+        let focused_track = self.focused_instrument;
+        let context = Context {
+            composition: &self.composition,
+            selected_pattern: self.selected_pattern,
+            focused_track,
+            effect_params: &self.effect_params,
+            instrument_params: self.instrument_params.get(&focused_track),
+            patch: self.patches.get(&focused_track),
+            graph_draft: self.graph_draft.as_ref(),
+            graph_error: self.graph_errors.get(&focused_track),
+            transcription: &self.transcription,
+            playhead: if self.is_playing {
+                Some(self.engine.beat())
+            } else {
+                None
+            },
+            has_sample_creator_open: self.panes.iter().any(|(_, pane)| {
+                pane.content.kind() == Some(PaneKind::SampleCreator)
+            }),
+            keymap: &self.keymap,
+            rebinding: self.rebinding.as_ref(),
+            settings: &self.settings,
+            settings_draft: self.settings_draft.as_ref(),
+            themes: &self.themes,
+            sample_library: &self.sample_library,
+        };
+
+        let mut pane_grid = PaneGrid::new(&mut self.panes, |id, pane| {
+            let is_focused = focus == Some(id);
+
+            // let x = pane_grid::Node::splits(pane_grid).map(|split| split);
+
+            let title = Row::with_children(vec![
+                // Text::new("Pane").into(), <<-- should probably showcontent title [e.g composition-name]
+                Text::new(pane.content.title())
+                    .color(if is_focused {
+                        palette().accent
+                    } else {
+                        palette().accent_muted
+                    })
+                    .into(),
+            ])
+            .spacing(5);
+
+            let title_bar = pane_grid::TitleBar::new(title)
+                .controls(pane.controls.view(id, total_panes, pane.is_pinned))
+                .padding(1)
+                .style(style::TitleBar { is_focused });
+
+            pane_grid::Content::new(pane.content.view(id, &context))
+                .title_bar(title_bar)
+                .style(style::Pane { is_focused })
+        })
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .spacing(10)
+        // .on_click(Message::Clicked)
+        .on_drag(Message::Dragged)
+        .on_resize(10, Message::Resized);
+
+        pane_grid = pane_grid.on_click(Message::Clicked);
+
+        let mut wrapper = Row::new().height(Length::Fill).width(Length::Fill);
+
+        let mut column_1: Column<Message> = Column::new().height(Length::Fill);
+        // .push(Space::new(Length::Units(50), Length::Units(0)));
+
+        // Show option to creation a new composition when not already in composition mode
+        if !self.is_composition_mode {
+            column_1 = column_1.push(
+                Button::new(
+                    &mut self.start_new_composition,
+                    Text::new("Composition +"),
+                )
+                .on_press(Message::CreateCompositionPressed)
+                .style(style::Button::Primary),
+            )
+        }
+
+        // Show sidebar with composition relatd options when in composition mode
+        if self.is_composition_mode {
+            column_1 = column_1
+                .push(
+                    Button::new(
+                        &mut self.toggle_sidepanel,
+                        Text::new(icon_to_char(iced_aw::Icon::ArrowBarLeft))
+                            .font(iced_aw::ICON_FONT), // TODO Add sample browser
+                    )
+                    .on_press(Message::TestToggle)
+                    .style(style::Button::Control),
+                )
+                // .push(Text::new(&self.output_text))
+                .push(
+                    Button::new(
+                        &mut self.toggle_musical_typing,
+                        Text::new(icon_to_char(
+                            if self.musical_typing.is_enabled {
+                                iced_aw::Icon::KeyboardFill
+                            } else {
+                                iced_aw::Icon::Keyboard
+                            },
+                        ))
+                        .font(iced_aw::ICON_FONT),
+                    )
+                    .on_press(Message::ToggleMusicalTyping)
+                    .style(
+                        if self.musical_typing.is_enabled {
+                            style::Button::Primary
+                        } else {
+                            style::Button::Control
+                        },
+                    ),
+                )
+                .push(
+                    Button::new(
+                        &mut self.toggle_playback,
+                        Text::new(icon_to_char(if self.is_playing {
+                            iced_aw::Icon::StopFill
+                        } else {
+                            iced_aw::Icon::PlayFill
+                        }))
+                        .font(iced_aw::ICON_FONT),
+                    )
+                    .on_press(Message::TogglePlayback)
+                    .style(if self.is_playing {
+                        style::Button::Primary
+                    } else {
+                        style::Button::Control
+                    }),
+                )
+        }
+
+        column_1 = column_1.push(
+            PickList::new(
+                &mut self.theme_picker,
+                self.themes.clone(),
+                Some(self.settings.appearance.theme.clone()),
+                Message::SetTheme,
+            )
+            .text_size(14),
+        );
+
+        let mut column_2: Column<Message> = Column::new().height(Length::Fill);
+
+        // Show composition panes
+        if self.is_composition_mode == true {
+            column_2 = column_2
+                .push(self.tab_bar.view(tab_labels, self.active_tab))
+                .push(self.workspace_bar.view(
+                    &self.workspaces,
+                    self.workspace,
+                    &self.workspace_name,
+                ))
+                .push(pane_grid);
+        }
+
+        wrapper = wrapper.push(column_1).push(column_2);
+
+        let mut content = Column::new().height(Length::Fill);
+        if !self.recovered.is_empty() {
+            content = content.push(
+                Row::new()
+                    .padding(5)
+                    .spacing(10)
+                    .push(
+                        Text::new(
+                            "Unsaved work from the last session was kept",
+                        )
+                        .width(Length::Fill),
+                    )
+                    .push(
+                        Button::new(
+                            &mut self.restore_recovered,
+                            Text::new("Restore"),
+                        )
+                        .on_press(Message::RestoreRecovered)
+                        .style(style::Button::Primary),
+                    )
+                    .push(
+                        Button::new(
+                            &mut self.discard_recovered,
+                            Text::new("Discard"),
+                        )
+                        .on_press(Message::DiscardRecovered)
+                        .style(style::Button::Control),
+                    ),
+            );
+        }
+        if let Some(index) = self.closing_tab {
+            let name = self.tab_label(index).name;
+            content = content.push(
+                Row::new()
+                    .padding(5)
+                    .spacing(10)
+                    .push(
+                        Text::new(format!(
+                            "{} has unsaved changes, close it anyway?",
+                            name
+                        ))
+                        .width(Length::Fill),
+                    )
+                    .push(
+                        Button::new(
+                            &mut self.confirm_close,
+                            Text::new("Close without saving"),
+                        )
+                        .on_press(Message::ConfirmCloseTab)
+                        .style(style::Button::Destructive),
+                    )
+                    .push(
+                        Button::new(
+                            &mut self.cancel_close,
+                            Text::new("Cancel"),
+                        )
+                        .on_press(Message::CancelCloseTab)
+                        .style(style::Button::Control),
+                    ),
+            );
+        }
+        content = content.push(wrapper);
+
+        let content = Container::new(content)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .padding(0)
+            .style(style::PsycheDaily);
+
+        let query = self.palette_query.clone();
+        let selected = self.palette_selected;
+        Modal::new(&mut self.command_palette, content, move |command_palette| {
+            command_palette.view(&query, &palette_entries, selected)
+        })
+        .backdrop(Message::CloseCommandPalette)
+        .on_esc(Message::CloseCommandPalette)
+        .into()
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        let hotkeys = subscription::events_with(|event, status| {
+            if let event::Status::Captured = status {
+                return None;
+            }
+
+            match event {
+                Event::Keyboard(keyboard::Event::KeyPressed {
+                    modifiers,
+                    key_code,
+                }) => Some(Message::KeyPressed(key_code, modifiers)),
+                Event::Window(window::Event::FileDropped(path)) => {
+                    Some(Message::FileDropped(path))
+                }
+                Event::Window(window::Event::CloseRequested) => {
+                    Some(Message::CloseRequested)
+                }
+                _ => None,
+            }
+        });
+
+        let mut subscriptions = vec![
+            hotkeys,
+            iced::time::every(Duration::from_secs(1)).map(|_| Message::Stash),
+            iced::time::every(Duration::from_secs(30))
+                .map(|_| Message::Autosave),
+        ];
+
+        if self.musical_typing.is_enabled {
+            subscriptions
+                .push(subscription::events_with(handle_musical_typing));
+        }
+
+        let is_moving = self
+            .composition
+            .tracks
+            .get(self.focused_instrument)
+            .map_or(false, |track| {
+                let has_meters = track.inserts.iter().any(|slot| {
+                    slot.kind.params().iter().any(|spec| spec.is_meter)
+                });
+                let has_automation =
+                    self.is_playing && !track.automation.is_empty();
+                let has_modulation = !track.modulation.routes.is_empty();
+
+                has_meters || has_automation || has_modulation
+            });
+        if !self.patches.is_empty() {
+            subscriptions.push(
+                iced::time::every(Duration::from_millis(500))
+                    .map(|_| Message::ReloadPatches),
+            );
+        }
+
+        if is_moving {
+            subscriptions.push(
+                iced::time::every(Duration::from_millis(33))
+                    .map(|_| Message::Tick),
+            );
+        }
+
+        Subscription::batch(subscriptions)
+    }
+}
+
+impl PsycheDaily {
+    // Pushes a track's instrument and clip to its mixer channel
+    fn sync_track(&mut self, index: usize) {
+        self.sync_instrument(index);
+
+        let track = match self.composition.tracks.get(index) {
+            Some(track) => track,
+            None => return,
+        };
+
+        self.engine.send(EngineCommand::SetMidiEffects {
+            channel: index,
+            effects: MidiChain::new(&track.midi_effects),
+        });
+        self.engine.send(EngineCommand::SetClip {
+            channel: index,
+            clip: track.clip.as_ref().map(ClipPlayer::from_clip),
+        });
+        self.engine.send(EngineCommand::SetSequencer {
+            channel: index,
+            sequencer: self.composition.build_sequencer(track),
+        });
+
+        self.sync_inserts(index);
+        self.sync_volume(index);
+        self.sync_automation(index);
+        self.sync_modulation(index);
+    }
+
+    fn sync_volume(&mut self, index: usize) {
+        let track = match self.composition.tracks.get(index) {
+            Some(track) => track,
+            None => return,
+        };
+
+        let volume = Param::new(&VOLUME, track.volume);
+        self.volume_params.insert(index, volume.clone());
+        self.engine.send(EngineCommand::SetVolume {
+            channel: index,
+            volume,
+        });
+    }
+
+    // Builds a track's instrument with fresh parameters. Automation and
+    // modulation hold the old parameters until they are synced too
+    fn sync_instrument(&mut self, index: usize) {
+        let track = match self.composition.tracks.get(index) {
+            Some(track) => track,
+            None => return,
+        };
+
+        let params =
+            create_params(track.instrument.params(), &track.instrument_values);
+        self.graph_errors.remove(&index);
+        let instrument: Box<dyn Instrument> = match &track.instrument {
+            // The watcher compiles patches, so it knows about errors
+            TrackInstrument::Patch { path } => {
+                self.playing_graphs.remove(&index);
+                let mut file = PatchFile::new(path);
+                let net = file.reload();
+                self.patches.insert(index, file);
+                Box::new(PatchSynth::new(params.clone(), net))
+            }
+            TrackInstrument::Graph(graph) => {
+                self.patches.remove(&index);
+                let net = match graph.compile() {
+                    Ok(net) => {
+                        self.playing_graphs.insert(index, graph.clone());
+                        Some(net)
+                    }
+                    Err(err) => {
+                        self.playing_graphs.remove(&index);
+                        self.graph_errors.insert(index, err);
+                        None
+                    }
+                };
+                Box::new(PatchSynth::new(params.clone(), net))
+            }
+            _ => {
+                self.patches.remove(&index);
+                self.playing_graphs.remove(&index);
+                self.composition.build_instrument(
+                    track,
+                    &params,
+                    &mut self.sample_library,
+                )
+            }
+        };
+        self.instrument_params.insert(index, params);
+
+        self.engine.send(EngineCommand::SetInstrument {
+            channel: index,
+            instrument,
+        });
+    }
+
+    // Rebuilds a track's insert chain with fresh parameters
+    fn sync_inserts(&mut self, index: usize) {
+        let inserts = match self.composition.tracks.get(index) {
+            Some(track) => track.inserts.clone(),
+            None => return,
+        };
+
+        self.engine
+            .send(EngineCommand::ClearEffects { channel: index });
+        for (position, slot) in inserts.iter().enumerate() {
+            let params = slot.kind.create_params(&slot.values);
+            let effect = self.build_effect(slot, &params);
+
+            self.engine.send(EngineCommand::InsertEffect {
+                channel: index,
+                index: position,
+                id: slot.id,
+                effect,
+                is_bypassed: slot.is_bypassed,
+            });
+            self.effect_params.insert(slot.id, params);
+        }
+    }
+
+    // Live parameters are picked up by the running effect; the others need a
+    // new instance, which the engine crossfades in. A dragged control only
+    // rebuilds once it's let go
+    fn set_effect_param(&mut self, id: u64, index: usize, normal: f32) {
+        let (channel, position) = match self.composition.find_insert(id) {
+            Some(found) => found,
+            None => return,
+        };
+        let params = match self.effect_params.get(&id) {
+            Some(params) => params,
+            None => return,
+        };
+        // Meters are only written by their effect, and never saved
+        let param = match params.get(index) {
+            Some(param) if !param.spec.is_meter => param,
+            _ => return,
+        };
+
+        param.set_normal(normal);
+
+        let slot = &mut self.composition.tracks[channel].inserts[position];
+        if let Some(value) = slot.values.get_mut(index) {
+            *value = param.base();
+        }
+
+        if param.spec.is_live {
+            let (beat, value) = (self.engine.beat(), param.normal() as f64);
+            let target = Target::Effect { id, param: index };
+            if let Some(recording) = self.start_recording(channel, target) {
+                recording.record(beat, value);
+            }
+        } else if self.grabbed_effect_param == Some((id, index)) {
+            self.is_rebuild_waiting = true;
+        } else {
+            self.rebuild_effect(id);
+        }
+    }
+
+    fn set_instrument_param(&mut self, index: usize, normal: f32) {
+        let channel = self.focused_instrument;
+        let param = match self
+            .instrument_params
+            .get(&channel)
+            .and_then(|params| params.get(index))
+        {
+            Some(param) => param,
+            None => return,
+        };
+
+        param.set_normal(normal);
+        let (base, value) = (param.base(), param.normal() as f64);
+
+        let track = &mut self.composition.tracks[channel];
+        let specs = track.instrument.params();
+        if track.instrument_values.len() < specs.len() {
+            let start = track.instrument_values.len();
+            track
+                .instrument_values
+                .extend(specs[start..].iter().map(|spec| spec.default));
+        }
+        track.instrument_values[index] = base;
+
+        let beat = self.engine.beat();
+        let target = Target::Instrument { param: index };
+        if let Some(recording) = self.start_recording(channel, target) {
+            recording.record(beat, value);
+        }
+    }
+
+    fn set_volume(&mut self, db: f64) {
+        let channel = self.focused_instrument;
+        let param = match self.volume_params.get(&channel) {
+            Some(param) => param,
+            None => return,
+        };
+
+        param.set(db);
+        let (base, value) = (param.base(), param.normal() as f64);
+        if let Some(track) = self.composition.tracks.get_mut(channel) {
+            track.volume = base;
+        }
+
+        let beat = self.engine.beat();
+        if let Some(recording) = self.start_recording(channel, Target::Volume) {
+            recording.record(beat, value);
+        }
+    }
+
+    // Lanes and routes to parameters that can't move while playing, like
+    // old ones from before a parameter needed a rebuild, are left out
+    fn target_param(&self, channel: usize, target: Target) -> Option<&Param> {
+        let param = match target {
+            Target::Effect { id, param } => {
+                self.effect_params.get(&id)?.get(param)
+            }
+            Target::Instrument { param } => {
+                self.instrument_params.get(&channel)?.get(param)
+            }
+            Target::Volume => self.volume_params.get(&channel),
+        }?;
+
+        Some(param).filter(|param| param.spec.is_automatable())
+    }
+
+    // A grabbed control records from the moment it's touched
+    fn grab_param(&mut self, channel: usize, target: Target) {
+        if let Some(recording) = self.start_recording(channel, target) {
+            recording.is_touching = true;
+        }
+    }
+
+    fn release_param(&mut self, channel: usize, target: Target) {
+        let touched = self.recordings.iter().position(|recording| {
+            recording.channel == channel
+                && recording.target == target
+                && recording.is_touching
+        });
+
+        if let Some(index) = touched {
+            if self.composition.tracks[channel].automation_mode == Mode::Touch {
+                self.finish_recording(index);
+            } else {
+                self.recordings[index].is_touching = false;
+            }
+        }
+    }
+
+    // Sends a track's lanes to its channel, leaving out the ones being
+    // recorded so they don't fight the controls
+    fn sync_automation(&mut self, channel: usize) {
+        let track = match self.composition.tracks.get(channel) {
+            Some(track) => track,
+            None => return,
+        };
+
+        let lanes = if track.automation_mode == Mode::Off {
+            vec![]
+        } else {
+            track
+                .automation
+                .iter()
+                .filter(|lane| {
+                    !self.recordings.iter().any(|recording| {
+                        recording.channel == channel
+                            && recording.target == lane.target
+                    })
+                })
+                .filter_map(|lane| {
+                    let param =
+                        self.target_param(channel, lane.target)?.clone();
+                    Some(LanePlayer::new(param, lane.clone()))
+                })
+                .collect()
+        };
+
+        self.engine
+            .send(EngineCommand::SetAutomation { channel, lanes });
+    }
 
-    let direction = match key_code {
-        KeyCode::Up => Some(Direction::Up),
-        KeyCode::Down => Some(Direction::Down),
-        KeyCode::Left => Some(Direction::Left),
-        KeyCode::Right => Some(Direction::Right),
-        _ => None,
+    /// Everything a project file holds, as it is now
+    fn project(&self) -> Project {
+        Project {
+            version: project::VERSION,
+            composition: self.composition.clone(),
+            engine: EngineSettings { tempo: self.tempo },
+            mixer: MixerSettings {
+                focused_track: self.focused_instrument,
+            },
+            editor: EditorSettings {
+                selected_pattern: self.selected_pattern,
+                transcription: self.transcription,
+            },
+            layout: Layout::capture(&self.panes),
+        }
+    }
+
+    fn save_project(&mut self, path: PathBuf) {
+        let project = self.project();
+        match project.save(&path) {
+            Ok(()) => {
+                self.project_path = Some(path);
+                self.saved = serde_json::to_string(&project).ok();
+                self.is_unsaved = false;
+                self.write_recovery();
+            }
+            Err(err) => eprintln!("failed to save project: {:#}", err),
+        }
+    }
+
+    fn tab_label(&self, index: usize) -> TabLabel {
+        match &self.tabs[index] {
+            Some(tab) => TabLabel {
+                name: tabs::name(
+                    tab.path.as_ref(),
+                    &tab.project.composition.title,
+                ),
+                is_unsaved: tab.is_unsaved,
+            },
+            None => TabLabel {
+                name: tabs::name(
+                    self.project_path.as_ref(),
+                    &self.composition.title,
+                ),
+                is_unsaved: self.is_unsaved,
+            },
+        }
+    }
+
+    // Takes the active composition out of the app, so another one can take
+    // its place
+    fn park(&mut self) -> Tab {
+        Tab {
+            is_unsaved: self.unsaved().is_some(),
+            project: self.project(),
+            path: self.project_path.take(),
+            saved: self.saved.take(),
+            history: std::mem::take(&mut self.history),
+        }
+    }
+
+    fn unpark(&mut self, tab: Tab) {
+        self.load_project(tab.project);
+        self.project_path = tab.path;
+        self.saved = tab.saved;
+        self.is_unsaved = tab.is_unsaved;
+        self.history = tab.history;
+    }
+
+    fn switch_tab(&mut self, index: usize) {
+        if index == self.active_tab {
+            return;
+        }
+        let tab = match self.tabs.get_mut(index).and_then(Option::take) {
+            Some(tab) => tab,
+            None => return,
+        };
+
+        self.closing_tab = None;
+        self.tabs[self.active_tab] = Some(self.park());
+        self.active_tab = index;
+        self.unpark(tab);
+    }
+
+    // Opens a composition in a new tab after the active one
+    fn open_tab(&mut self, tab: Tab) {
+        self.tabs[self.active_tab] = Some(self.park());
+        self.active_tab += 1;
+        self.tabs.insert(self.active_tab, None);
+        self.unpark(tab);
+    }
+
+    // A new composition, in the panes of the active one
+    fn empty_tab(&self) -> Tab {
+        let project = Project {
+            version: project::VERSION,
+            composition: Composition::default(),
+            engine: EngineSettings {
+                tempo: self.settings.defaults.tempo,
+            },
+            mixer: MixerSettings::default(),
+            editor: EditorSettings::default(),
+            layout: Layout::capture(&self.panes),
+        };
+
+        Tab {
+            saved: serde_json::to_string(&project).ok(),
+            is_unsaved: false,
+            project,
+            path: None,
+            history: History::default(),
+        }
+    }
+
+    fn close_tab(&mut self, index: usize) {
+        if index >= self.tabs.len() {
+            return;
+        }
+        // There is always a tab open
+        if self.tabs.len() == 1 {
+            let tab = self.empty_tab();
+            self.unpark(tab);
+        } else {
+            if index == self.active_tab {
+                let neighbour = if index + 1 < self.tabs.len() {
+                    index + 1
+                } else {
+                    index - 1
+                };
+                self.switch_tab(neighbour);
+            }
+            self.tabs.remove(index);
+            if self.active_tab > index {
+                self.active_tab -= 1;
+            }
+        }
+
+        // Its work is dropped along with it, the other tabs' is kept
+        self.write_recovery();
+    }
+
+    // The unsaved work of every tab, with the state it was taken from
+    fn unsaved_work(&self) -> Vec<(Work, String)> {
+        self.tabs
+            .iter()
+            .filter_map(|tab| match tab {
+                Some(tab) if tab.is_unsaved => {
+                    let state = serde_json::to_string(&tab.project).ok()?;
+                    let work = Work {
+                        project: tab.project.clone(),
+                        origin: tab.path.clone(),
+                    };
+                    Some((work, state))
+                }
+                Some(_) => None,
+                None => self.unsaved().map(|(project, state)| {
+                    let work = Work {
+                        project,
+                        origin: self.project_path.clone(),
+                    };
+                    (work, state)
+                }),
+            })
+            .collect()
+    }
+
+    // Brings the recovery bundles up to date right away, after a tab was
+    // saved or closed or the app is quitting
+    fn write_recovery(&mut self) {
+        if !self.recovered.is_empty() {
+            return;
+        }
+
+        let (work, states): (Vec<Work>, Vec<String>) =
+            self.unsaved_work().into_iter().unzip();
+        match recovery::save(&work) {
+            Ok(()) => self.autosaved = Some(states),
+            Err(err) => {
+                eprintln!("failed to autosave: {:#}", err);
+                self.autosaved = None;
+            }
+        }
+        recovery::stash(work);
+    }
+
+    // Opens a bundle in a tab of its own, unless the active one is still
+    // untouched
+    fn open_project(&mut self, path: PathBuf) {
+        let open = self.tabs.iter().position(|tab| match tab {
+            Some(tab) => tab.path.as_ref() == Some(&path),
+            None => self.project_path.as_ref() == Some(&path),
+        });
+        if let Some(index) = open {
+            self.switch_tab(index);
+            return;
+        }
+
+        let project = match Project::load(&path) {
+            Ok(project) => project,
+            Err(err) => {
+                eprintln!("failed to open project: {:#}", err);
+                return;
+            }
+        };
+        let tab = Tab {
+            project,
+            path: Some(path),
+            saved: None,
+            is_unsaved: false,
+            history: History::default(),
+        };
+        if self.project_path.is_none() && self.unsaved().is_none() {
+            self.unpark(tab);
+        } else {
+            self.closing_tab = None;
+            self.open_tab(tab);
+        }
+        self.saved = serde_json::to_string(&self.project()).ok();
+    }
+
+    // Rebuilds the panes when they differ from `layout`
+    fn set_layout(&mut self, layout: &Layout) {
+        if Layout::capture(&self.panes) == *layout {
+            return;
+        }
+
+        self.panes =
+            pane_grid::State::with_configuration(layout.configuration());
+        self.focus = None;
+    }
+
+    fn save_workspaces(&self) {
+        if let Err(err) = self.workspaces.save() {
+            eprintln!("failed to save workspaces: {:#}", err);
+        }
+    }
+
+    // A theme that fails to load leaves the current one in place
+    fn set_settings(&mut self, mut settings: Settings) {
+        self.settings_draft = None;
+        if settings.appearance.theme != self.settings.appearance.theme {
+            match theme::load(&settings.appearance.theme) {
+                Ok(palette) => theme::set_palette(palette),
+                Err(err) => {
+                    eprintln!("{:#}", err);
+                    settings.appearance.theme =
+                        self.settings.appearance.theme.clone();
+                }
+            }
+        }
+
+        if settings.audio.latency != self.settings.audio.latency {
+            self.engine.set_latency(settings.audio.latency);
+        }
+
+        self.saved_settings.take_changes(&self.settings, &settings);
+        self.settings = settings;
+        if let Err(err) = self.saved_settings.save() {
+            eprintln!("failed to save settings: {:#}", err);
+        }
+    }
+
+    // Open and save dialogs start in the projects directory, when it's set
+    fn project_dialog(&self) -> rfd::AsyncFileDialog {
+        let dialog = rfd::AsyncFileDialog::new();
+        match &self.settings.paths.projects {
+            Some(dir) => dialog.set_directory(dir),
+            None => dialog,
+        }
+    }
+
+    fn save_keymap(&self) {
+        if let Err(err) = self.keymap.save() {
+            eprintln!("failed to save key bindings: {:#}", err);
+        }
+    }
+
+    fn close_command_palette(&mut self) {
+        self.command_palette.show(false);
+        self.palette_query.clear();
+        self.palette_selected = 0;
+    }
+
+    // Everything the command palette offers: the actions of the keymap,
+    // opening panes, inserting effects and turning samples into instruments
+    fn commands(&self) -> Vec<Entry> {
+        let entry = |name: String, message| Entry {
+            name,
+            binding: None,
+            message,
+        };
+
+        let mut commands: Vec<Entry> = Action::ALL
+            .into_iter()
+            .filter(|action| *action != Action::CommandPalette)
+            .map(|action| Entry {
+                name: action.name().to_string(),
+                binding: self.keymap.binding(action).map(Binding::to_string),
+                message: action.message(),
+            })
+            .collect();
+
+        for (index, track) in self.composition.tracks.iter().enumerate() {
+            commands.push(entry(
+                format!("Focus {}", track.name),
+                Message::FocusTrack(index),
+            ));
+        }
+        for kind in PaneKind::ALL {
+            commands.push(entry(
+                format!("Open {} pane", kind.title().to_lowercase()),
+                Message::OpenPane(kind),
+            ));
+        }
+        for kind in EffectKind::ALL {
+            commands.push(entry(
+                format!("Insert {}", kind.name()),
+                Message::AddEffect(kind),
+            ));
+        }
+        for effect in [MidiEffect::ARPEGGIATOR, MidiEffect::CHORD] {
+            commands.push(entry(
+                format!("Insert {}", effect.name()),
+                Message::AddMidiEffect(effect),
+            ));
+        }
+        commands.push(entry(
+            String::from("Use a patch graph as the instrument"),
+            Message::UseGraphInstrument,
+        ));
+        for sample in &self.composition.samples {
+            commands.push(entry(
+                format!("Play {} as the instrument", sample.name),
+                Message::UseSampleAsInstrument(sample.id),
+            ));
+            commands.push(entry(
+                format!("Play {} as a wavetable", sample.name),
+                Message::UseSampleAsWavetable(sample.id),
+            ));
+            commands.push(entry(
+                format!("Play {} as grains", sample.name),
+                Message::UseSampleAsGranular(sample.id),
+            ));
+        }
+
+        commands
+    }
+
+    // The commands matching the palette's query, best first
+    fn palette_entries(&self) -> Vec<Entry> {
+        command_palette::search(self.commands(), &self.palette_query)
+    }
+
+    // Binds the action being rebound to the keys pressed for it
+    fn finish_binding(&mut self) {
+        if let Some((action, keys)) = self.rebinding.take() {
+            if !keys.is_empty() {
+                self.keymap.bind(action, Some(Binding::new(keys)));
+                self.save_keymap();
+            }
+        }
+    }
+
+    // The project as JSON, what undo steps and saves are compared by
+    fn state(&self) -> String {
+        serde_json::to_string(&self.project()).unwrap_or_default()
+    }
+
+    fn restore_state(&mut self, state: &str) {
+        match serde_json::from_str(state) {
+            Ok(project) => self.restore(project),
+            Err(err) => eprintln!("failed to restore an undo step: {}", err),
+        }
+    }
+
+    // The project and its JSON, when it changed since it was last saved
+    fn unsaved(&self) -> Option<(Project, String)> {
+        let project = self.project();
+        let state = serde_json::to_string(&project).ok()?;
+        (self.saved.as_ref() != Some(&state)).then(|| (project, state))
+    }
+
+    // Replaces the session with a project, from the engine to the panes
+    fn load_project(&mut self, project: Project) {
+        self.is_composition_mode = true;
+        if self.is_playing {
+            self.update(Message::TogglePlayback);
+        }
+
+        // Every channel is rebuilt
+        self.engine.send(EngineCommand::TruncateChannels(0));
+        self.composition = Composition::default();
+        self.sample_library = SampleLibrary::default();
+        self.effect_params.clear();
+        self.kernels.clear();
+        self.kernel_requests.clear();
+        self.instrument_params.clear();
+        self.volume_params.clear();
+        self.patches.clear();
+        self.graph_errors.clear();
+        self.playing_graphs.clear();
+        self.graph_draft = None;
+        self.recordings.clear();
+        self.history = History::default();
+
+        self.restore(project);
+    }
+
+    // Brings the session to a project's state, only rebuilding the tracks
+    // and panes that differ from it
+    fn restore(&mut self, project: Project) {
+        let previous =
+            std::mem::replace(&mut self.composition, project.composition);
+
+        self.tempo = project.engine.tempo;
+        self.focused_instrument = project.mixer.focused_track;
+        self.selected_pattern = project.editor.selected_pattern;
+        self.transcription = project.editor.transcription;
+
+        self.set_layout(&project.layout);
+
+        let tracks = self.composition.tracks.len();
+        if tracks < previous.tracks.len() {
+            self.engine.send(EngineCommand::TruncateChannels(tracks));
+            self.patches.retain(|track, _| *track < tracks);
+            self.instrument_params.retain(|track, _| *track < tracks);
+            self.volume_params.retain(|track, _| *track < tracks);
+            self.graph_errors.retain(|track, _| *track < tracks);
+            self.playing_graphs.retain(|track, _| *track < tracks);
+        }
+        self.engine.send(EngineCommand::SetTempo(self.tempo));
+        self.sync_tuning();
+
+        // Drum kits and step sequencers are built from the patterns
+        let is_patterns_changed =
+            previous.patterns != self.composition.patterns;
+        for track in 0..tracks {
+            let old = previous.tracks.get(track);
+            let is_kit_changed = is_patterns_changed
+                && match (
+                    old.map(|old| &old.instrument),
+                    &self.composition.tracks[track].instrument,
+                ) {
+                    (
+                        Some(TrackInstrument::StepSequencer { chain: before }),
+                        TrackInstrument::StepSequencer { chain },
+                    ) => {
+                        sequencer::kit(previous.chain(before))
+                            != sequencer::kit(self.composition.chain(chain))
+                    }
+                    _ => false,
+                };
+
+            match old {
+                Some(old) => self.restore_track(track, old, is_kit_changed),
+                None => self.sync_track(track),
+            }
+        }
+        if is_patterns_changed {
+            self.sync_sequencers();
+        }
+    }
+
+    // Brings a channel from `old` to the track's state. Moved controls are
+    // set on the running parameters, only what changed shape is rebuilt
+    fn restore_track(
+        &mut self,
+        index: usize,
+        old: &Track,
+        is_kit_changed: bool,
+    ) {
+        let track = self.composition.tracks[index].clone();
+        if *old == track && !is_kit_changed {
+            return;
+        }
+
+        // New parameter handles need new lanes and routes
+        let mut is_rebuilt = false;
+        if old.instrument != track.instrument || is_kit_changed {
+            self.sync_instrument(index);
+            self.engine.send(EngineCommand::SetSequencer {
+                channel: index,
+                sequencer: self.composition.build_sequencer(&track),
+            });
+            is_rebuilt = true;
+        } else if let Some(params) = self.instrument_params.get(&index) {
+            set_values(params, &track.instrument_values);
+        }
+
+        if old.midi_effects != track.midi_effects {
+            self.sync_midi_effects(index);
+        }
+        if old.clip != track.clip {
+            self.engine.send(EngineCommand::SetClip {
+                channel: index,
+                clip: track.clip.as_ref().map(ClipPlayer::from_clip),
+            });
+        }
+
+        let is_same_chain =
+            old.inserts.len() == track.inserts.len()
+                && old.inserts.iter().zip(&track.inserts).all(|(old, slot)| {
+                    old.id == slot.id && old.kind == slot.kind
+                });
+        if is_same_chain {
+            for (old, slot) in old.inserts.iter().zip(&track.inserts) {
+                if let Some(params) = self.effect_params.get(&slot.id) {
+                    set_values(params, &slot.values);
+                }
+                if old.is_bypassed != slot.is_bypassed {
+                    self.engine.send(EngineCommand::BypassEffect {
+                        channel: index,
+                        id: slot.id,
+                        is_bypassed: slot.is_bypassed,
+                    });
+                }
+
+                let specs = slot.kind.params();
+                let is_rebuild_changed = old.impulse_response
+                    != slot.impulse_response
+                    || specs.iter().enumerate().any(|(index, spec)| {
+                        !spec.is_live
+                            && old.values.get(index) != slot.values.get(index)
+                    });
+                if is_rebuild_changed {
+                    self.rebuild_effect(slot.id);
+                }
+            }
+        } else {
+            self.sync_inserts(index);
+            is_rebuilt = true;
+        }
+
+        if let Some(volume) = self.volume_params.get(&index) {
+            volume.set(track.volume);
+        }
+
+        if is_rebuilt
+            || old.automation != track.automation
+            || old.automation_mode != track.automation_mode
+        {
+            self.sync_automation(index);
+        }
+        if is_rebuilt || old.modulation != track.modulation {
+            self.sync_modulation(index);
+        }
+    }
+
+    // Every instrument plays the composition's tuning
+    fn sync_tuning(&mut self) {
+        let table = self.composition.tuning.table();
+        self.engine.send(EngineCommand::SetTuning(Box::new(table)));
+    }
+
+    // Changes the focused track's sampler, if it plays one
+    fn edit_sampler(&mut self, edit: impl FnOnce(&mut SamplerPatch)) {
+        let channel = self.focused_instrument;
+        match self
+            .composition
+            .tracks
+            .get_mut(channel)
+            .map(|track| &mut track.instrument)
+        {
+            Some(TrackInstrument::Sampler(patch)) => edit(patch),
+            _ => return,
+        }
+
+        self.sync_instrument(channel);
+        self.sync_automation(channel);
+        self.sync_modulation(channel);
+    }
+
+    // Replaces the focused track's clip
+    fn set_clip(&mut self, clip: MidiClip) {
+        let channel = self.focused_instrument;
+        if let Some(track) = self.composition.tracks.get_mut(channel) {
+            self.engine.send(EngineCommand::SetClip {
+                channel,
+                clip: Some(ClipPlayer::from_clip(&clip)),
+            });
+            track.clip = Some(clip);
+        }
+    }
+
+    // Sends a track's MIDI effects to its channel
+    fn sync_midi_effects(&mut self, channel: usize) {
+        if let Some(track) = self.composition.tracks.get(channel) {
+            self.engine.send(EngineCommand::SetMidiEffects {
+                channel,
+                effects: MidiChain::new(&track.midi_effects),
+            });
+        }
+    }
+
+    // Sends a track's modulation matrix to its channel
+    fn sync_modulation(&mut self, channel: usize) {
+        let track = match self.composition.tracks.get(channel) {
+            Some(track) => track,
+            None => return,
+        };
+
+        let modulation = ModulationPlayer::new(&track.modulation, |target| {
+            self.target_param(channel, target).cloned()
+        });
+
+        self.engine.send(EngineCommand::SetModulation {
+            channel,
+            modulation,
+        });
+    }
+
+    /// The pass recording a target, started at the current position when
+    /// the track records and the transport runs
+    fn start_recording(
+        &mut self,
+        channel: usize,
+        target: Target,
+    ) -> Option<&mut Recording> {
+        let existing = self.recordings.iter().position(|recording| {
+            recording.channel == channel && recording.target == target
+        });
+        if let Some(index) = existing {
+            return self.recordings.get_mut(index);
+        }
+
+        let value = self.target_param(channel, target)?.normal() as f64;
+        let track = self.composition.tracks.get_mut(channel)?;
+        if !self.is_playing || !track.automation_mode.is_recording() {
+            return None;
+        }
+        if !track.automation.iter().any(|lane| lane.target == target) {
+            track.automation.push(Lane::new(target));
+        }
+
+        self.recordings.push(Recording::new(
+            channel,
+            target,
+            self.engine.beat(),
+            value,
+        ));
+        self.sync_automation(channel);
+
+        self.recordings.last_mut()
+    }
+
+    // Write mode records every lane of a track from the start of playback
+    fn start_write_passes(&mut self) {
+        for channel in 0..self.composition.tracks.len() {
+            let track = &self.composition.tracks[channel];
+            if track.automation_mode != Mode::Write {
+                continue;
+            }
+
+            let targets: Vec<Target> =
+                track.automation.iter().map(|lane| lane.target).collect();
+            for target in targets {
+                self.start_recording(channel, target);
+            }
+        }
+    }
+
+    fn finish_recording(&mut self, index: usize) {
+        let recording = self.recordings.remove(index);
+        let (channel, target) = (recording.channel, recording.target);
+
+        if let Some(lane) =
+            self.composition.tracks.get_mut(channel).and_then(|track| {
+                track
+                    .automation
+                    .iter_mut()
+                    .find(|lane| lane.target == target)
+            })
+        {
+            recording.finish(lane, self.engine.beat());
+        }
+
+        self.sync_automation(channel);
+    }
+
+    // Swaps in a new instance of an insert, built from its current state.
+    // Convolution reverbs are swapped once their new kernel is ready
+    fn rebuild_effect(&mut self, id: u64) {
+        let (channel, position) = match self.composition.find_insert(id) {
+            Some(found) => found,
+            None => return,
+        };
+        let params = match self.effect_params.get(&id) {
+            Some(params) => params.clone(),
+            None => return,
+        };
+
+        let slot = self.composition.tracks[channel].inserts[position].clone();
+        if slot.kind == EffectKind::Convolution {
+            let settings =
+                KernelSettings::new(&slot, &params, self.engine.sample_rate());
+            let is_current = matches!(
+                (settings, self.kernels.get(&id)),
+                (Some(settings), Some((prepared, _))) if settings == *prepared
+            );
+            if let (Some(settings), false) = (settings, is_current) {
+                self.request_kernel(id, settings);
+                return;
+            }
+        }
+
+        let effect = self.build_effect(&slot, &params);
+        self.engine.send(EngineCommand::ReplaceEffect {
+            channel,
+            id,
+            effect,
+        });
+    }
+
+    // Builds the engine effect of an insert. A convolution reverb gets the
+    // kernel last prepared for it, and a new one is asked for when its
+    // settings changed
+    fn build_effect(
+        &mut self,
+        slot: &EffectSlot,
+        params: &[Param],
+    ) -> Box<dyn Effect> {
+        if slot.kind != EffectKind::Convolution {
+            return slot.kind.build(params);
+        }
+
+        let id = slot.id;
+        let settings =
+            KernelSettings::new(slot, params, self.engine.sample_rate());
+        let prepared = self.kernels.get(&id).cloned();
+        let kernel = match (settings, prepared) {
+            (Some(settings), Some((prepared, kernel))) => {
+                if settings != prepared {
+                    self.request_kernel(id, settings);
+                }
+                Some(kernel)
+            }
+            (Some(settings), None) => {
+                self.request_kernel(id, settings);
+                None
+            }
+            (None, _) => None,
+        };
+
+        Box::new(ConvolutionReverb::new(params.to_vec(), kernel))
+    }
+
+    // Queues a kernel to be prepared in the background, unless the same one
+    // already is
+    fn request_kernel(&mut self, id: u64, settings: KernelSettings) {
+        if self.kernel_requests.get(&id) == Some(&settings) {
+            return;
+        }
+
+        let buffer = match self.composition.sample(settings.sample_id) {
+            Some(sample) => match self.sample_library.get_or_load(sample) {
+                Ok(buffer) => buffer,
+                Err(err) => {
+                    eprintln!(
+                        "failed to load impulse response {:?}: {}",
+                        sample.path, err
+                    );
+                    return;
+                }
+            },
+            None => return,
+        };
+
+        self.kernel_requests.insert(id, settings);
+        self.kernel_jobs.push((id, settings, buffer));
+    }
+
+    // Starts preparing the kernels asked for, off the UI thread like
+    // autosaves
+    fn prepare_kernels(&mut self) -> Command<Message> {
+        Command::batch(self.kernel_jobs.drain(..).map(
+            |(id, settings, buffer)| {
+                Command::perform(
+                    async move { Arc::new(Kernel::prepare(&buffer, settings)) },
+                    move |kernel| Message::KernelPrepared(id, settings, kernel),
+                )
+            },
+        ))
+    }
+
+    // Step edits keep the kit as is, so only the sequencers are rebuilt
+    fn sync_sequencers(&mut self) {
+        for (index, track) in self.composition.tracks.iter().enumerate() {
+            if let Some(sequencer) = self.composition.build_sequencer(track) {
+                self.engine.send(EngineCommand::SetSequencer {
+                    channel: index,
+                    sequencer: Some(sequencer),
+                });
+            }
+        }
+    }
+
+    /// Index of the first drum track, created along with an empty pattern
+    /// when the composition has none yet
+    fn drum_track(&mut self) -> usize {
+        let existing = self.composition.tracks.iter().position(|track| {
+            matches!(track.instrument, TrackInstrument::StepSequencer { .. })
+        });
+
+        existing.unwrap_or_else(|| {
+            self.composition.patterns.push(Pattern::new("Pattern 1"));
+            let chain = vec![self.composition.patterns.len() - 1];
+
+            self.composition.tracks.push(Track::new(
+                "Drums",
+                TrackInstrument::StepSequencer { chain },
+            ));
+            self.selected_pattern = 0;
+
+            self.composition.tracks.len() - 1
+        })
+    }
+
+    // The pattern behind the selected chain position, repairing the chain
+    // if it points nowhere
+    fn selected_pattern_index(&mut self, track: usize) -> usize {
+        let patterns = &mut self.composition.patterns;
+
+        if let TrackInstrument::StepSequencer { chain } =
+            &mut self.composition.tracks[track].instrument
+        {
+            chain.retain(|index| *index < patterns.len());
+            if chain.is_empty() {
+                patterns.push(Pattern::new("Pattern 1"));
+                chain.push(patterns.len() - 1);
+            }
+
+            self.selected_pattern = self.selected_pattern.min(chain.len() - 1);
+            return chain[self.selected_pattern];
+        }
+
+        0
+    }
+
+    fn step_mut(
+        &mut self,
+        pattern: usize,
+        lane: usize,
+        step: usize,
+    ) -> Option<&mut sequencer::Step> {
+        self.composition
+            .patterns
+            .get_mut(pattern)?
+            .lanes
+            .get_mut(lane)?
+            .steps
+            .get_mut(step)
+    }
+}
+
+// -------------------
+// -------------------
+// -------------------
+// -------------------
+// -------------------
+// -------------------
+// -------------------
+// Moves running parameters to saved values, or their defaults where none
+// were saved. Meters are left to their effect
+fn set_values(params: &[Param], values: &[f64]) {
+    for (index, param) in params.iter().enumerate() {
+        if !param.spec.is_meter {
+            param.set(values.get(index).copied().unwrap_or(param.spec.default));
+        }
+    }
+}
+
+// A recompiled patch replaces the playing one, which the engine crossfades
+// into it with the held notes carried over
+fn swap_patch(engine: &Engine, channel: usize, params: Vec<Param>, net: Net64) {
+    engine.send(EngineCommand::SetInstrument {
+        channel,
+        instrument: Box::new(PatchSynth::new(params, Some(net))),
+    });
+}
+
+// How a message joins the undo history, `None` when it edits nothing that's
+// saved
+fn history_step(message: &Message) -> Option<Step> {
+    let step = match message {
+        Message::Split(..)
+        | Message::SplitFocused(_)
+        | Message::TogglePin(_)
+        | Message::Close(_)
+        | Message::CloseFocused
+        | Message::SetPaneKind(..)
+        | Message::OpenCreateNewSamplePane(..)
+        | Message::Dragged(pane_grid::DragEvent::Dropped { .. })
+        | Message::SwitchWorkspace(_) => Step::Edit,
+        Message::Resized(event) => Step::merge(("resize", event.split)),
+
+        Message::FileDropped(_)
+        | Message::UseSampleAsInstrument(_)
+        | Message::UseSampleAsWavetable(_)
+        | Message::UseSampleAsGranular(_)
+        | Message::ToggleGranularDrone
+        | Message::UseGraphInstrument
+        | Message::SampleToClip(_)
+        | Message::ResetTuning => Step::Edit,
+        Message::SetWavetableSlicing(_) => Step::merge("slicing"),
+        Message::SetTranscription(_) => Step::merge("transcription"),
+        Message::SetRootFrequency(_) => Step::merge("root"),
+
+        Message::GrabInstrumentParam(_)
+        | Message::GrabEffectParam(..)
+        | Message::GrabVolume => Step::Begin,
+        Message::ReleaseInstrumentParam(_)
+        | Message::ReleaseEffectParam(..)
+        | Message::ReleaseVolume => Step::End,
+        Message::SetVolume(_) => Step::merge("volume"),
+        Message::SetInstrumentParam(param, _) => {
+            Step::merge(("instrument", param))
+        }
+        Message::SetEffectParam(slot, param, _) => {
+            Step::merge(("effect", slot, param))
+        }
+
+        Message::EditGraph(GraphEdit::MoveNode { id, .. }) => {
+            Step::merge(("node", id))
+        }
+        Message::EditGraph(GraphEdit::SetArg { id, index, .. }) => {
+            Step::merge(("argument", id, index))
+        }
+        Message::EditGraph(_) => Step::Edit,
+
+        Message::AddZone | Message::RemoveZone(_) => Step::Edit,
+        Message::SetZone(index, _) => Step::merge(("zone", index)),
+        Message::SetSamplerEnvelope(_) => Step::merge("envelope"),
+
+        Message::AddDrumLane(_)
+        | Message::AddPattern
+        | Message::SetPatternLength { .. }
+        | Message::ToggleStep { .. } => Step::Edit,
+        Message::SetSwing(pattern, _) => Step::merge(("swing", pattern)),
+        Message::AdjustStep {
+            pattern,
+            lane,
+            step,
+            field,
+            ..
+        } => Step::merge(("step", pattern, lane, step, field)),
+
+        Message::AddMidiEffect(_) | Message::RemoveMidiEffect(_) => Step::Edit,
+        Message::SetMidiEffect(index, _) => Step::merge(("midi effect", index)),
+
+        Message::AddEffect(_)
+        | Message::RemoveEffect(_)
+        | Message::MoveEffect(..)
+        | Message::ToggleBypass(_)
+        | Message::SetImpulseResponse(..) => Step::Edit,
+
+        Message::SetAutomationMode(_)
+        | Message::AddAutomationLane(_)
+        | Message::RemoveAutomationLane(_) => Step::Edit,
+        Message::EditAutomation(lane, AutomationEdit::Move { point, .. }) => {
+            Step::merge(("point", lane, point))
+        }
+        Message::EditAutomation(..) => Step::Edit,
+
+        Message::AddModulationSource(_)
+        | Message::RemoveModulationSource(_) => Step::Edit,
+        Message::SetModulationSource(index, _) => {
+            Step::merge(("source", index))
+        }
+        Message::SetModulationDepth { source, target, .. } => {
+            Step::merge(("depth", source, target))
+        }
+
+        _ => return None,
     };
 
-    match key_code {
-        KeyCode::V => Some(Message::SplitFocused(Axis::Vertical)),
-        KeyCode::H => Some(Message::SplitFocused(Axis::Horizontal)),
-        KeyCode::W => Some(Message::CloseFocused),
-        _ => direction.map(Message::FocusAdjacent),
+    Some(step)
+}
+
+// Unmodified key presses play notes, releases are always forwarded so a note
+// can't get stuck when a modifier goes down while the key is held
+fn handle_musical_typing(
+    event: Event,
+    status: event::Status,
+) -> Option<Message> {
+    if let event::Status::Captured = status {
+        return None;
+    }
+
+    match event {
+        Event::Keyboard(keyboard::Event::KeyPressed {
+            modifiers,
+            key_code,
+        }) if modifiers.is_empty() => {
+            Some(Message::MusicalKeyPressed(key_code))
+        }
+        Event::Keyboard(keyboard::Event::KeyReleased { key_code, .. }) => {
+            Some(Message::MusicalKeyReleased(key_code))
+        }
+        _ => None,
     }
 }
 
@@ -1,8 +1,9 @@
 use serde::{Deserialize, Serialize};
 
 use super::composition::Composition;
+use super::settings::BackendSettings;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Artist {
     pub id: i64,
     pub name: String,
@@ -10,8 +11,10 @@ pub struct Artist {
 }
 
 #[tokio::main]
-pub async fn get_artists() -> Result<Vec<Artist>, Box<dyn std::error::Error>> {
-    let artists: Vec<Artist> = reqwest::get("http://127.0.0.1:8000/artists")
+pub async fn get_artists(
+    backend: &BackendSettings,
+) -> Result<Vec<Artist>, Box<dyn std::error::Error>> {
+    let artists: Vec<Artist> = reqwest::get(backend.endpoint("artists"))
         .await?
         .json()
         .await?;
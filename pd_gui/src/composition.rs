@@ -1,18 +1,146 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
 use iced::Column;
 use serde::{Deserialize, Serialize};
 
 use super::artist::Artist;
+use super::engine::automation::{Lane, Mode, Target};
+use super::engine::effects::{EffectSlot, Param, ParamSpec};
+use super::engine::granular::{self, GranularSynth};
+use super::engine::instrument::{Instrument, PulseSynth, PULSE_SYNTH_PARAMS};
+use super::engine::midi_effects::MidiEffect;
+use super::engine::modulation::Matrix;
+use super::engine::patch::{self, graph::Graph, PatchSynth};
+use super::engine::sample::SampleLibrary;
+use super::engine::sampler::{self, Sampler, SamplerPatch};
+use super::engine::sequencer::{self, Pattern, StepSequencer};
+use super::engine::tuning::Tuning;
+use super::engine::wavetable::{self, Slicing, Wavetable, WavetableSynth};
+use super::engine::VOLUME;
+use super::midi::MidiClip;
+use super::settings::BackendSettings;
+
+// A sample is audio from the local-fs or ipfs (the latter with a potential pointer to a blockchain node) // TODO: Support ipfs sources
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub id: i64,
+    pub name: String,
+    pub path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum TrackInstrument {
+    PulseSynth,
+    Sampler(SamplerPatch),
+    // Plays the composition patterns at these indices one after another
+    StepSequencer { chain: Vec<usize> },
+    // A patch file, recompiled whenever it changes
+    Patch { path: PathBuf },
+    // A patch drawn in the graph editor, saved with the composition
+    Graph(Graph),
+    // A composition sample cut into wavetable frames
+    Wavetable { sample_id: i64, slicing: Slicing },
+    // Grain clouds out of a composition sample, following notes or, as a
+    // drone, sounding on their own
+    Granular { sample_id: i64, drone: bool },
+}
+
+impl TrackInstrument {
+    /// Parameters the instrument exposes to automation and modulation
+    pub fn params(&self) -> &'static [ParamSpec] {
+        match self {
+            TrackInstrument::PulseSynth => &PULSE_SYNTH_PARAMS,
+            TrackInstrument::Sampler(_)
+            | TrackInstrument::StepSequencer { .. } => &sampler::PARAMS,
+            TrackInstrument::Patch { .. } | TrackInstrument::Graph(_) => {
+                &patch::PARAMS
+            }
+            TrackInstrument::Wavetable { .. } => &wavetable::PARAMS,
+            TrackInstrument::Granular { .. } => &granular::PARAMS,
+        }
+    }
+}
+
+/// A track plays one instrument on the mixer channel with the same index
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Track {
+    pub name: String,
+    pub instrument: TrackInstrument,
+    // MIDI effects in processing order, in front of the instrument
+    #[serde(default)]
+    pub midi_effects: Vec<MidiEffect>,
+    // Values of the instrument's params, by index
+    #[serde(default)]
+    pub instrument_values: Vec<f64>,
+    pub clip: Option<MidiClip>,
+    // Insert effects in processing order
+    #[serde(default)]
+    pub inserts: Vec<EffectSlot>,
+    #[serde(default)]
+    pub automation: Vec<Lane>,
+    #[serde(default)]
+    pub automation_mode: Mode,
+    #[serde(default)]
+    pub modulation: Matrix,
+    // Fader position in dB
+    #[serde(default)]
+    pub volume: f64,
+}
+
+impl Track {
+    pub fn new(name: &str, instrument: TrackInstrument) -> Self {
+        Self {
+            name: name.to_string(),
+            instrument,
+            midi_effects: vec![],
+            instrument_values: vec![],
+            clip: None,
+            inserts: vec![],
+            automation: vec![],
+            automation_mode: Mode::default(),
+            modulation: Matrix::default(),
+            volume: VOLUME.default,
+        }
+    }
+
+    /// Swaps the instrument. Values, lanes and routes of the old
+    /// instrument's parameters are dropped unless the new one has the same,
+    /// since they would land on the wrong ones
+    pub fn set_instrument(&mut self, instrument: TrackInstrument) {
+        let names = |instrument: &TrackInstrument| {
+            instrument.params().iter().map(|spec| spec.name)
+        };
 
-type Sample = i32; // A sample is audio from the local-fs or ipfs (the latter with a potential pointer to a blockchain node) // TODO: Create the correct type
+        if !names(&self.instrument).eq(names(&instrument)) {
+            let is_instrument =
+                |target: Target| matches!(target, Target::Instrument { .. });
+
+            self.instrument_values.clear();
+            self.automation.retain(|lane| !is_instrument(lane.target));
+            self.modulation
+                .retain_targets(|target| !is_instrument(target));
+        }
+
+        self.instrument = instrument;
+    }
+}
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Composition {
     pub id: i64,
     pub artist: String,
     pub title: String,
     pub desc: String,
+    #[serde(default)]
     pub samples: Vec<Sample>,
     pub collaborators: Vec<Artist>,
+    #[serde(default)]
+    pub tracks: Vec<Track>,
+    #[serde(default)]
+    pub patterns: Vec<Pattern>,
+    #[serde(default)]
+    pub tuning: Tuning,
 }
 
 impl Default for Composition {
@@ -24,6 +152,9 @@ impl Default for Composition {
             desc: "Default values to test with".to_string(),
             samples: vec![],
             collaborators: vec![],
+            tracks: vec![Track::new("Synth", TrackInstrument::PulseSynth)],
+            patterns: vec![],
+            tuning: Tuning::default(),
         }
     }
 }
@@ -31,9 +162,10 @@ impl Default for Composition {
 impl Composition {
     #[tokio::main]
     pub async fn get_compositions(
+        backend: &BackendSettings,
     ) -> Result<Vec<Composition>, Box<dyn std::error::Error>> {
         let compositions: Vec<Composition> =
-            reqwest::get("http://127.0.0.1:8000/compositions")
+            reqwest::get(backend.endpoint("compositions"))
                 .await?
                 .json()
                 .await?;
@@ -44,4 +176,184 @@ impl Composition {
     pub fn container<Msg>() -> Column<'static, Msg> {
         Column::new()
     }
+
+    /// Adds a sample from the local-fs and returns its id
+    pub fn add_sample(&mut self, path: &Path) -> i64 {
+        let id = self
+            .samples
+            .iter()
+            .map(|sample| sample.id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| format!("Sample {}", id));
+
+        self.samples.push(Sample {
+            id,
+            name,
+            path: path.to_path_buf(),
+        });
+
+        id
+    }
+
+    /// An id no insert on any track uses yet
+    pub fn next_effect_id(&self) -> u64 {
+        self.tracks
+            .iter()
+            .flat_map(|track| &track.inserts)
+            .map(|slot| slot.id + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Track index and chain position of an insert
+    pub fn find_insert(&self, id: u64) -> Option<(usize, usize)> {
+        self.tracks.iter().enumerate().find_map(|(track, t)| {
+            t.inserts
+                .iter()
+                .position(|slot| slot.id == id)
+                .map(|position| (track, position))
+        })
+    }
+
+    pub fn sample(&self, id: i64) -> Option<&Sample> {
+        self.samples.iter().find(|sample| sample.id == id)
+    }
+
+    /// Builds the engine instrument for a track, loading the samples it uses
+    pub fn build_instrument(
+        &self,
+        track: &Track,
+        params: &[Param],
+        library: &mut SampleLibrary,
+    ) -> Box<dyn Instrument> {
+        let params = params.to_vec();
+        match &track.instrument {
+            TrackInstrument::PulseSynth => Box::new(PulseSynth::new(params)),
+            TrackInstrument::Sampler(patch) => {
+                Box::new(self.build_sampler(patch, params, library))
+            }
+            TrackInstrument::StepSequencer { chain } => {
+                let kit = sequencer::kit(self.chain(chain));
+                Box::new(self.build_sampler(
+                    &sequencer::kit_patch(&kit),
+                    params,
+                    library,
+                ))
+            }
+            TrackInstrument::Patch { path } => {
+                let net = match patch::load(path) {
+                    Ok(net) => Some(net),
+                    Err(err) => {
+                        eprintln!("failed to load patch {:?}: {}", path, err);
+                        None
+                    }
+                };
+                Box::new(PatchSynth::new(params, net))
+            }
+            TrackInstrument::Graph(graph) => {
+                let net = match graph.compile() {
+                    Ok(net) => Some(net),
+                    Err(err) => {
+                        eprintln!("failed to compile graph: {}", err);
+                        None
+                    }
+                };
+                Box::new(PatchSynth::new(params, net))
+            }
+            TrackInstrument::Wavetable { sample_id, slicing } => {
+                let table = self
+                    .sample(*sample_id)
+                    .map(|sample| match library.get_or_load(sample) {
+                        Ok(buffer) => Wavetable::from_buffer(&buffer, *slicing),
+                        Err(err) => {
+                            eprintln!(
+                                "failed to load sample {:?}: {}",
+                                sample.path, err
+                            );
+                            Wavetable::new(&[])
+                        }
+                    })
+                    .unwrap_or_else(|| Wavetable::new(&[]));
+                if table.frame_count() == 0 {
+                    eprintln!(
+                        "wavetable of sample {} has no cycles",
+                        sample_id
+                    );
+                }
+                Box::new(WavetableSynth::new(params, Arc::new(table)))
+            }
+            TrackInstrument::Granular { sample_id, drone } => {
+                let buffer = self.sample(*sample_id).and_then(|sample| {
+                    match library.get_or_load(sample) {
+                        Ok(buffer) => Some(buffer),
+                        Err(err) => {
+                            eprintln!(
+                                "failed to load sample {:?}: {}",
+                                sample.path, err
+                            );
+                            None
+                        }
+                    }
+                });
+                Box::new(GranularSynth::new(params, buffer, *drone))
+            }
+        }
+    }
+
+    /// The step sequencer that drives a drum track, if the track is one
+    pub fn build_sequencer(&self, track: &Track) -> Option<StepSequencer> {
+        match &track.instrument {
+            TrackInstrument::StepSequencer { chain } => {
+                let patterns: Vec<Pattern> =
+                    self.chain(chain).cloned().collect();
+                Some(StepSequencer::new(&patterns))
+            }
+            _ => None,
+        }
+    }
+
+    /// Patterns of a chain in playing order, skipping deleted ones
+    pub fn chain<'a>(
+        &'a self,
+        chain: &'a [usize],
+    ) -> impl Iterator<Item = &'a Pattern> + 'a {
+        chain.iter().filter_map(|index| self.patterns.get(*index))
+    }
+
+    fn build_sampler(
+        &self,
+        patch: &SamplerPatch,
+        params: Vec<Param>,
+        library: &mut SampleLibrary,
+    ) -> Sampler {
+        let buffers: Vec<_> = patch
+            .zones
+            .iter()
+            .filter_map(|zone| {
+                let sample = self.sample(zone.sample_id)?;
+                match library.get_or_load(sample) {
+                    Ok(buffer) => Some((zone.sample_id, buffer)),
+                    Err(err) => {
+                        eprintln!(
+                            "failed to load sample {:?}: {}",
+                            sample.path, err
+                        );
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        Sampler::new(patch, params, |id| {
+            buffers
+                .iter()
+                .find(|(sample_id, _)| *sample_id == id)
+                .map(|(_, buffer)| buffer.clone())
+        })
+    }
 }
@@ -0,0 +1,380 @@
+//! Parameter automation.
+//!
+//! Lanes hold breakpoints positioned in beats. Values are normalized, so a
+//! curve follows the parameter's own scale (a straight line on a frequency
+//! is a sweep that sounds even).
+
+use serde::{Deserialize, Serialize};
+
+use super::effects::Param;
+
+// Recorded moves closer together than this are merged
+const RECORD_RESOLUTION: f64 = 1.0 / 64.0;
+
+/// Shape of the segment between a breakpoint and the next one
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Curve {
+    Linear,
+    // Positive curvature starts slow and ends fast, negative the opposite
+    Exponential { curvature: f64 },
+    // Control points of a cubic bezier in the unit square the segment spans
+    Bezier { x1: f64, y1: f64, x2: f64, y2: f64 },
+}
+
+impl Default for Curve {
+    fn default() -> Self {
+        Curve::Linear
+    }
+}
+
+impl Curve {
+    pub const EXPONENTIAL: Curve = Curve::Exponential { curvature: 4.0 };
+    pub const BEZIER: Curve = Curve::Bezier {
+        x1: 0.4,
+        y1: 0.0,
+        x2: 0.6,
+        y2: 1.0,
+    };
+
+    /// The next curve type, for editors that cycle through them
+    pub fn next(&self) -> Curve {
+        match self {
+            Curve::Linear => Curve::EXPONENTIAL,
+            Curve::Exponential { .. } => Curve::BEZIER,
+            Curve::Bezier { .. } => Curve::Linear,
+        }
+    }
+
+    /// Progress through a segment at `x`, both between 0 and 1
+    pub fn shape(&self, x: f64) -> f64 {
+        match *self {
+            Curve::Linear => x,
+            Curve::Exponential { curvature } => {
+                if curvature.abs() < 1e-3 {
+                    x
+                } else {
+                    ((curvature * x).exp() - 1.0) / (curvature.exp() - 1.0)
+                }
+            }
+            Curve::Bezier { x1, y1, x2, y2 } => bezier(x, x1, y1, x2, y2),
+        }
+    }
+}
+
+// The y of a cubic bezier from (0, 0) to (1, 1) at a given x. With both
+// control points' x inside the square, x only ever grows along the curve,
+// so bisection finds it
+fn bezier(x: f64, x1: f64, y1: f64, x2: f64, y2: f64) -> f64 {
+    let cubic = |t: f64, a: f64, b: f64| {
+        let u = 1.0 - t;
+        3.0 * u * u * t * a + 3.0 * u * t * t * b + t * t * t
+    };
+    let (x1, x2) = (x1.clamp(0.0, 1.0), x2.clamp(0.0, 1.0));
+
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..24 {
+        let middle = (low + high) / 2.0;
+        if cubic(middle, x1, x2) < x {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    cubic((low + high) / 2.0, y1, y2)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Breakpoint {
+    pub beat: f64,
+    pub value: f64,
+    // Shape of the segment that starts here
+    pub curve: Curve,
+}
+
+impl Breakpoint {
+    pub fn new(beat: f64, value: f64) -> Self {
+        Self {
+            beat: beat.max(0.0),
+            value: value.clamp(0.0, 1.0),
+            curve: Curve::Linear,
+        }
+    }
+}
+
+/// What a lane moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Target {
+    // A parameter of an insert, by slot id and parameter index
+    Effect { id: u64, param: usize },
+    // A parameter of the track's instrument, by index
+    Instrument { param: usize },
+    // The track's fader
+    Volume,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lane {
+    pub target: Target,
+    // Sorted by beat
+    pub breakpoints: Vec<Breakpoint>,
+}
+
+impl Lane {
+    pub fn new(target: Target) -> Self {
+        Self {
+            target,
+            breakpoints: vec![],
+        }
+    }
+
+    /// The normalized value at a beat. Before the first and after the last
+    /// breakpoint the lane holds their value
+    pub fn value_at(&self, beat: f64) -> Option<f64> {
+        let next = self.breakpoints.partition_point(|point| point.beat <= beat);
+
+        let value = match (
+            next.checked_sub(1).map(|index| &self.breakpoints[index]),
+            self.breakpoints.get(next),
+        ) {
+            (None, None) => return None,
+            (Some(point), None) | (None, Some(point)) => point.value,
+            (Some(from), Some(to)) => {
+                let span = to.beat - from.beat;
+                let x = if span > 0.0 {
+                    (beat - from.beat) / span
+                } else {
+                    1.0
+                };
+
+                from.value + (to.value - from.value) * from.curve.shape(x)
+            }
+        };
+
+        Some(value.clamp(0.0, 1.0))
+    }
+
+    /// Adds a breakpoint in beat order, returning its index
+    pub fn insert(&mut self, point: Breakpoint) -> usize {
+        let index = self
+            .breakpoints
+            .partition_point(|other| other.beat <= point.beat);
+        self.breakpoints.insert(index, point);
+
+        index
+    }
+
+    /// Replaces everything between `from` and `to` with `points`
+    pub fn overwrite(&mut self, from: f64, to: f64, points: Vec<Breakpoint>) {
+        self.breakpoints
+            .retain(|point| point.beat < from || point.beat > to);
+        self.breakpoints.extend(points);
+        self.breakpoints.sort_by(|a, b| a.beat.total_cmp(&b.beat));
+    }
+}
+
+/// How a track's lanes react to its controls while the transport runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mode {
+    // Lanes are ignored
+    Off,
+    // Lanes play back
+    Read,
+    // A control records while it's held, then goes back to reading
+    Touch,
+    // A control records from the first touch until the transport stops
+    Latch,
+    // Every lane records from the start until the transport stops
+    Write,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::Read
+    }
+}
+
+impl Mode {
+    pub const ALL: [Mode; 5] =
+        [Mode::Off, Mode::Read, Mode::Touch, Mode::Latch, Mode::Write];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Off => "Off",
+            Mode::Read => "Read",
+            Mode::Touch => "Touch",
+            Mode::Latch => "Latch",
+            Mode::Write => "Write",
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        matches!(self, Mode::Touch | Mode::Latch | Mode::Write)
+    }
+}
+
+/// Control moves captured during one pass, written into a lane when it ends
+#[derive(Debug, Clone)]
+pub struct Recording {
+    pub channel: usize,
+    pub target: Target,
+    // Touch recordings end when the control is let go
+    pub is_touching: bool,
+    start: f64,
+    points: Vec<Breakpoint>,
+}
+
+impl Recording {
+    pub fn new(channel: usize, target: Target, beat: f64, value: f64) -> Self {
+        Self {
+            channel,
+            target,
+            is_touching: false,
+            start: beat,
+            points: vec![Breakpoint::new(beat, value)],
+        }
+    }
+
+    pub fn record(&mut self, beat: f64, value: f64) {
+        match self.points.last_mut() {
+            Some(last) if beat - last.beat < RECORD_RESOLUTION => {
+                last.value = value.clamp(0.0, 1.0);
+            }
+            // Hold the previous value until just before the move, instead of
+            // ramping all the way from the last one
+            Some(last) => {
+                let held = last.value;
+                self.points
+                    .push(Breakpoint::new(beat - RECORD_RESOLUTION, held));
+                self.points.push(Breakpoint::new(beat, value));
+            }
+            None => self.points.push(Breakpoint::new(beat, value)),
+        }
+    }
+
+    /// Writes the pass into `lane`, holding the last value up to `beat`
+    pub fn finish(mut self, lane: &mut Lane, beat: f64) {
+        if let Some(last) = self.points.last().copied() {
+            self.record(beat.max(last.beat), last.value);
+        }
+
+        let end = self.points.last().map_or(self.start, |point| point.beat);
+        lane.overwrite(self.start, end, self.points);
+    }
+}
+
+/// A lane as the engine plays it, moving the parameter every frame
+pub struct LanePlayer {
+    param: Param,
+    lane: Lane,
+}
+
+impl LanePlayer {
+    pub fn new(param: Param, lane: Lane) -> Self {
+        Self { param, lane }
+    }
+
+    pub fn apply(&self, beat: f64) {
+        if let Some(value) = self.lane.value_at(beat) {
+            self.param.set_normal(value as f32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    const CURVES: [Curve; 5] = [
+        Curve::Linear,
+        Curve::EXPONENTIAL,
+        Curve::Exponential { curvature: -4.0 },
+        Curve::Exponential { curvature: 0.0 },
+        Curve::BEZIER,
+    ];
+
+    #[test]
+    fn curves_run_from_corner_to_corner() {
+        for curve in CURVES {
+            assert_close(curve.shape(0.0), 0.0);
+            assert_close(curve.shape(1.0), 1.0);
+
+            // And only ever rise in between
+            let mut previous = 0.0;
+            for step in 1..=100 {
+                let value = curve.shape(step as f64 / 100.0);
+                assert!(value >= previous - 1e-9, "{:?} falls", curve);
+                previous = value;
+            }
+        }
+    }
+
+    #[test]
+    fn curvature_bends_the_segment() {
+        assert!(Curve::EXPONENTIAL.shape(0.5) < 0.5);
+        assert!(Curve::Exponential { curvature: -4.0 }.shape(0.5) > 0.5);
+        assert_close(Curve::BEZIER.shape(0.5), 0.5);
+    }
+
+    #[test]
+    fn lanes_hit_their_breakpoints() {
+        let mut lane = Lane::new(Target::Volume);
+        assert_eq!(lane.value_at(0.0), None);
+
+        for curve in CURVES {
+            lane.breakpoints = vec![
+                Breakpoint {
+                    curve,
+                    ..Breakpoint::new(1.0, 0.2)
+                },
+                Breakpoint::new(3.0, 0.8),
+            ];
+
+            assert_close(lane.value_at(1.0).unwrap(), 0.2);
+            assert_close(lane.value_at(3.0).unwrap(), 0.8);
+            // Held before the first and after the last
+            assert_close(lane.value_at(0.0).unwrap(), 0.2);
+            assert_close(lane.value_at(8.0).unwrap(), 0.8);
+        }
+
+        lane.breakpoints[0].curve = Curve::Linear;
+        assert_close(lane.value_at(2.0).unwrap(), 0.5);
+    }
+
+    #[test]
+    fn recording_holds_until_the_next_move() {
+        let mut lane = Lane::new(Target::Volume);
+        lane.insert(Breakpoint::new(0.0, 0.0));
+        lane.insert(Breakpoint::new(8.0, 1.0));
+
+        let mut recording = Recording::new(0, Target::Volume, 2.0, 0.4);
+        recording.record(4.0, 0.6);
+        recording.finish(&mut lane, 5.0);
+
+        let beats: Vec<f64> =
+            lane.breakpoints.iter().map(|point| point.beat).collect();
+        assert_eq!(
+            beats,
+            vec![
+                0.0,
+                2.0,
+                4.0 - RECORD_RESOLUTION,
+                4.0,
+                5.0 - RECORD_RESOLUTION,
+                5.0,
+                8.0
+            ]
+        );
+        assert_close(lane.value_at(3.0).unwrap(), 0.4);
+        assert_close(lane.value_at(4.5).unwrap(), 0.6);
+    }
+}
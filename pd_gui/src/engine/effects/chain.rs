@@ -0,0 +1,254 @@
+use std::sync::mpsc::Sender;
+
+use super::{Effect, Param};
+use crate::app::engine::Garbage;
+
+// Long enough to avoid clicks, short enough to feel immediate
+const FADE_SECONDS: f64 = 0.01;
+// Inserts a chain is sized for. More still work, but make it reallocate
+const RESERVED_INSERTS: usize = 16;
+// Doubled so a whole chain can fade out while its replacement fades in
+const RESERVED_SLOTS: usize = 2 * RESERVED_INSERTS;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pending {
+    Nothing,
+    Remove,
+    Move(usize),
+}
+
+struct Slot {
+    id: u64,
+    effect: Box<dyn Effect>,
+    is_bypassed: bool,
+    // Crossfade between the dry and processed signal
+    gain: f64,
+    target: f64,
+    pending: Pending,
+    // A newer instance waiting for the previous replacement to finish
+    replacement: Option<Box<dyn Effect>>,
+}
+
+/// Engine-side insert chain of a channel strip. Every change fades the
+/// affected effect out or in, so edits never click while audio is running
+pub struct EffectChain {
+    slots: Vec<Slot>,
+    step: f64,
+    // Removed effects are dropped on another thread, away from the callback
+    garbage: Sender<Garbage>,
+}
+
+impl EffectChain {
+    pub fn new(sample_rate: f64, garbage: Sender<Garbage>) -> Self {
+        Self {
+            slots: Vec::with_capacity(RESERVED_SLOTS),
+            step: 1.0 / (FADE_SECONDS * sample_rate),
+            garbage,
+        }
+    }
+
+    /// Inserts at `index` among the effects that aren't fading out
+    pub fn insert(
+        &mut self,
+        index: usize,
+        id: u64,
+        effect: Box<dyn Effect>,
+        is_bypassed: bool,
+    ) {
+        let index = self.raw_index(index);
+        self.insert_raw(index, id, effect, is_bypassed);
+    }
+
+    fn insert_raw(
+        &mut self,
+        index: usize,
+        id: u64,
+        effect: Box<dyn Effect>,
+        is_bypassed: bool,
+    ) {
+        let slot = Slot {
+            id,
+            effect,
+            is_bypassed,
+            gain: 0.0,
+            target: if is_bypassed { 0.0 } else { 1.0 },
+            pending: Pending::Nothing,
+            replacement: None,
+        };
+
+        self.slots.insert(index.min(self.slots.len()), slot);
+    }
+
+    /// Swaps an effect for a new instance, e.g. after a parameter that needs
+    /// a rebuild changed. Both are crossfaded in place. While a replacement
+    /// is still fading, only the newest instance waits for it
+    pub fn replace(&mut self, id: u64, effect: Box<dyn Effect>) {
+        let index = match self.position(id) {
+            Some(index) => index,
+            None => {
+                let _ = self.garbage.send(Garbage::Effect(effect));
+                return;
+            }
+        };
+
+        let is_replacing = self.slots.iter().any(|slot| {
+            slot.id == id && slot.pending == Pending::Remove && slot.gain > 0.0
+        });
+        if is_replacing {
+            let previous = self.slots[index].replacement.replace(effect);
+            if let Some(previous) = previous {
+                let _ = self.garbage.send(Garbage::Effect(previous));
+            }
+            return;
+        }
+
+        let is_bypassed = self.slots[index].is_bypassed;
+        self.remove(id);
+        self.insert_raw(index + 1, id, effect, is_bypassed);
+    }
+
+    pub fn remove(&mut self, id: u64) {
+        if let Some(slot) = self.slot_mut(id) {
+            slot.target = 0.0;
+            slot.pending = Pending::Remove;
+        }
+    }
+
+    pub fn set_bypass(&mut self, id: u64, is_bypassed: bool) {
+        if let Some(slot) = self.slot_mut(id) {
+            slot.is_bypassed = is_bypassed;
+            if slot.pending == Pending::Nothing {
+                slot.target = if is_bypassed { 0.0 } else { 1.0 };
+            }
+        }
+    }
+
+    /// Fades the effect out, moves it while silent and fades it back in
+    pub fn move_to(&mut self, id: u64, index: usize) {
+        if let Some(slot) = self.slot_mut(id) {
+            if slot.pending != Pending::Remove {
+                slot.target = 0.0;
+                slot.pending = Pending::Move(index);
+            }
+        }
+    }
+
+    /// Fades every effect out, used when a whole track is reloaded. The
+    /// effects inserted next fade in meanwhile
+    pub fn clear(&mut self) {
+        for slot in &mut self.slots {
+            slot.target = 0.0;
+            slot.pending = Pending::Remove;
+        }
+    }
+
+    /// Drops every effect at once, for channels that went silent
+    pub fn drain(&mut self) {
+        for slot in self.slots.drain(..) {
+            let _ = self.garbage.send(Garbage::Effect(slot.effect));
+            if let Some(replacement) = slot.replacement {
+                let _ = self.garbage.send(Garbage::Effect(replacement));
+            }
+        }
+    }
+
+    pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let mut signal = input;
+        let mut has_settled = false;
+
+        for slot in &mut self.slots {
+            if slot.gain < slot.target {
+                slot.gain = (slot.gain + self.step).min(slot.target);
+            } else if slot.gain > slot.target {
+                slot.gain = (slot.gain - self.step).max(slot.target);
+            }
+            has_settled |= slot.gain == 0.0 && slot.pending != Pending::Nothing;
+
+            if slot.gain > 0.0 {
+                let wet = slot.effect.tick(signal);
+                signal = (
+                    signal.0 + (wet.0 - signal.0) * slot.gain,
+                    signal.1 + (wet.1 - signal.1) * slot.gain,
+                );
+            }
+        }
+
+        if has_settled {
+            self.apply_pending();
+        }
+
+        signal
+    }
+
+    fn apply_pending(&mut self) {
+        let mut index = 0;
+
+        while index < self.slots.len() {
+            let slot = &self.slots[index];
+            if slot.gain > 0.0 || slot.pending == Pending::Nothing {
+                index += 1;
+                continue;
+            }
+
+            let mut slot = self.slots.remove(index);
+            match slot.pending {
+                Pending::Remove => {
+                    let id = slot.id;
+                    let _ = self.garbage.send(Garbage::Effect(slot.effect));
+                    if let Some(replacement) = slot.replacement {
+                        let _ = self.garbage.send(Garbage::Effect(replacement));
+                    }
+
+                    // The replacement that waited for this one can start
+                    let waiting = self
+                        .slot_mut(id)
+                        .and_then(|slot| slot.replacement.take());
+                    if let Some(effect) = waiting {
+                        self.replace(id, effect);
+                    }
+                }
+                Pending::Move(target) => {
+                    slot.pending = Pending::Nothing;
+                    slot.target = if slot.is_bypassed { 0.0 } else { 1.0 };
+                    let target = self.raw_index(target);
+                    self.slots.insert(target, slot);
+                    // Don't revisit the slot if it moved further down
+                    if target >= index {
+                        continue;
+                    }
+                    index += 1;
+                }
+                Pending::Nothing => {}
+            }
+        }
+    }
+
+    pub fn params(&self, id: u64) -> Option<&[Param]> {
+        let index = self.position(id)?;
+        Some(self.slots[index].effect.params())
+    }
+
+    // Where the live effect at `index` sits among all slots, the end if there
+    // are fewer
+    fn raw_index(&self, index: usize) -> usize {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.pending != Pending::Remove)
+            .nth(index)
+            .map_or(self.slots.len(), |(raw, _)| raw)
+    }
+
+    // A replaced effect shares its id with the one fading out, only the live
+    // one is addressed
+    fn position(&self, id: u64) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|slot| slot.id == id && slot.pending != Pending::Remove)
+    }
+
+    fn slot_mut(&mut self, id: u64) -> Option<&mut Slot> {
+        let index = self.position(id)?;
+        Some(&mut self.slots[index])
+    }
+}
@@ -0,0 +1,88 @@
+use super::{Effect, Param, ParamSpec};
+
+pub const THRESHOLD: usize = 0;
+pub const RATIO: usize = 1;
+pub const ATTACK: usize = 2;
+pub const RELEASE: usize = 3;
+pub const KNEE: usize = 4;
+pub const MAKEUP: usize = 5;
+pub const GAIN_REDUCTION: usize = 6;
+
+/// Levels are in dB, times in milliseconds
+pub const PARAMS: [ParamSpec; 7] = [
+    ParamSpec::live("Threshold", -60.0, 0.0, -18.0),
+    ParamSpec::live("Ratio", 1.0, 20.0, 4.0).logarithmic(),
+    ParamSpec::live("Attack", 0.1, 100.0, 10.0).logarithmic(),
+    ParamSpec::live("Release", 10.0, 1_000.0, 100.0).logarithmic(),
+    ParamSpec::live("Knee", 0.0, 24.0, 6.0),
+    ParamSpec::live("Makeup", 0.0, 24.0, 0.0),
+    ParamSpec::meter("Gain reduction", 0.0, 24.0),
+];
+
+/// Output level for an input level with a soft knee, both in dB
+pub fn transfer(input: f64, threshold: f64, ratio: f64, knee: f64) -> f64 {
+    let over = input - threshold;
+
+    if 2.0 * over < -knee {
+        input
+    } else if 2.0 * over.abs() <= knee && knee > 0.0 {
+        input + (1.0 / ratio - 1.0) * (over + knee / 2.0).powi(2) / (2.0 * knee)
+    } else {
+        threshold + over / ratio
+    }
+}
+
+/// Feed-forward, stereo linked compressor
+pub struct Compressor {
+    params: Vec<Param>,
+    // Smoothed gain reduction in dB
+    envelope: f64,
+    sample_rate: f64,
+}
+
+impl Compressor {
+    pub fn new(params: Vec<Param>) -> Self {
+        Self {
+            params,
+            envelope: 0.0,
+            sample_rate: 44_100.0,
+        }
+    }
+
+    fn coefficient(&self, milliseconds: f64) -> f64 {
+        (-1.0 / (milliseconds / 1_000.0 * self.sample_rate)).exp()
+    }
+}
+
+impl Effect for Compressor {
+    fn params(&self) -> &[Param] {
+        &self.params
+    }
+
+    fn reset(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+        self.envelope = 0.0;
+    }
+
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let value = |index: usize| self.params[index].get();
+
+        let peak = input.0.abs().max(input.1.abs());
+        let level = 20.0 * peak.max(1e-9).log10();
+        let reduction = level
+            - transfer(level, value(THRESHOLD), value(RATIO), value(KNEE));
+
+        let coefficient = if reduction > self.envelope {
+            self.coefficient(value(ATTACK))
+        } else {
+            self.coefficient(value(RELEASE))
+        };
+        self.envelope =
+            coefficient * self.envelope + (1.0 - coefficient) * reduction;
+
+        self.params[GAIN_REDUCTION].set(self.envelope);
+
+        let gain = 10f64.powf((value(MAKEUP) - self.envelope) / 20.0);
+        (input.0 * gain, input.1 * gain)
+    }
+}
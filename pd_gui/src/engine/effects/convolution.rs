@@ -0,0 +1,421 @@
+//! Convolution reverb with uniformly partitioned FFT convolution.
+//!
+//! The impulse response is split into blocks of `PARTITION` samples and each
+//! block is transformed once, into a `Kernel`. That takes a while for long
+//! impulse responses, so kernels are prepared in the background and shared
+//! between the instances of a reverb. The audio thread then only transforms
+//! its own input, one block at a time, and sums the products with every
+//! partition.
+
+use std::fmt;
+use std::sync::Arc;
+
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+
+use super::{Effect, EffectSlot, Param, ParamSpec};
+use crate::app::engine::sample::SampleBuffer;
+
+pub const PRE_DELAY: usize = 0;
+pub const IR_START: usize = 1;
+pub const IR_LENGTH: usize = 2;
+pub const MIX: usize = 3;
+
+/// Pre-delay and IR start are in milliseconds, IR length in seconds
+pub const PARAMS: [ParamSpec; 4] = [
+    ParamSpec::live("Pre-delay", 0.0, 500.0, 0.0),
+    ParamSpec::rebuild("IR start", 0.0, 1_000.0, 0.0),
+    ParamSpec::rebuild("IR length", 0.1, 10.0, 10.0).logarithmic(),
+    ParamSpec::live("Mix", 0.0, 1.0, 0.3),
+];
+
+// Block size of the convolution. The wet signal is one block late, which the
+// pre-delay makes up for where it can
+const PARTITION: usize = 512;
+const FFT_SIZE: usize = 2 * PARTITION;
+const BINS: usize = FFT_SIZE / 2 + 1;
+// Trimmed impulse responses fade out instead of stopping dead
+const FADE_OUT: f64 = 0.01;
+
+/// What a kernel is prepared from, to tell whether it's still current
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KernelSettings {
+    pub sample_id: i64,
+    // In seconds
+    pub start: f64,
+    pub length: f64,
+    pub sample_rate: f64,
+}
+
+impl KernelSettings {
+    /// `None` while the reverb has no impulse response
+    pub fn new(
+        slot: &EffectSlot,
+        params: &[Param],
+        sample_rate: f64,
+    ) -> Option<Self> {
+        Some(Self {
+            sample_id: slot.impulse_response?,
+            start: params[IR_START].get() / 1_000.0,
+            length: params[IR_LENGTH].get(),
+            sample_rate,
+        })
+    }
+}
+
+/// The transformed partitions of an impulse response, one set per side
+pub struct Kernel {
+    sample_rate: f64,
+    sides: [Vec<Vec<Complex<f64>>>; 2],
+}
+
+impl Kernel {
+    /// Cuts `length` seconds out of `buffer` from `start` seconds, resampled
+    /// to `sample_rate`. Mono impulse responses are used for both sides
+    pub fn new(
+        buffer: &SampleBuffer,
+        start: f64,
+        length: f64,
+        sample_rate: f64,
+    ) -> Self {
+        let step = buffer.sample_rate / sample_rate;
+        let first = start * sample_rate;
+        let available = (buffer.frames() as f64 / step - first).max(0.0);
+        let frames = (length * sample_rate).min(available) as usize;
+        let fade = (FADE_OUT * sample_rate).max(1.0);
+
+        let mut left = Vec::with_capacity(frames);
+        let mut right = Vec::with_capacity(frames);
+        for frame in 0..frames {
+            let (l, r) = buffer.frame_at((first + frame as f64) * step);
+            let gain = ((frames - frame) as f64 / fade).min(1.0);
+            left.push(l * gain);
+            right.push(r * gain);
+        }
+
+        // Unit energy on the louder side keeps the wet level close to the
+        // dry one, whatever the capture level. The inverse transform is not
+        // normalized either, so that is folded in here too
+        let energy = |side: &[f64]| side.iter().map(|s| s * s).sum::<f64>();
+        let energy = energy(&left).max(energy(&right));
+        let gain = if energy > 0.0 {
+            1.0 / (energy.sqrt() * FFT_SIZE as f64)
+        } else {
+            0.0
+        };
+
+        let forward = RealFftPlanner::<f64>::new().plan_fft_forward(FFT_SIZE);
+        let transform = |side: &[f64]| {
+            side.chunks(PARTITION)
+                .map(|chunk| {
+                    let mut input = vec![0.0; FFT_SIZE];
+                    for (input, sample) in input.iter_mut().zip(chunk) {
+                        *input = sample * gain;
+                    }
+
+                    let mut spectrum = forward.make_output_vec();
+                    // The buffers are sized by the plan itself
+                    let _ = forward.process(&mut input, &mut spectrum);
+                    spectrum
+                })
+                .collect()
+        };
+
+        Self {
+            sample_rate,
+            sides: [transform(&left), transform(&right)],
+        }
+    }
+
+    pub fn prepare(buffer: &SampleBuffer, settings: KernelSettings) -> Self {
+        Self::new(
+            buffer,
+            settings.start,
+            settings.length,
+            settings.sample_rate,
+        )
+    }
+
+    fn partitions(&self) -> usize {
+        self.sides[0].len()
+    }
+}
+
+impl fmt::Debug for Kernel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Kernel")
+            .field("sample_rate", &self.sample_rate)
+            .field("partitions", &self.partitions())
+            .finish()
+    }
+}
+
+struct Convolver {
+    // Last two input blocks, the newest in the second half
+    input: Vec<f64>,
+    // Spectra of past input blocks, a ring with `head` as the newest
+    history: Vec<Vec<Complex<f64>>>,
+    head: usize,
+    sum: Vec<Complex<f64>>,
+    output: Vec<f64>,
+    result: Vec<f64>,
+}
+
+impl Convolver {
+    fn new(partitions: usize) -> Self {
+        Self {
+            input: vec![0.0; FFT_SIZE],
+            history: vec![vec![Complex::default(); BINS]; partitions],
+            head: 0,
+            sum: vec![Complex::default(); BINS],
+            output: vec![0.0; PARTITION],
+            result: vec![0.0; FFT_SIZE],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.input.fill(0.0);
+        self.output.fill(0.0);
+        for spectrum in &mut self.history {
+            spectrum.fill(Complex::default());
+        }
+    }
+
+    // Overlap-save: of the circular convolution of two blocks, only the
+    // second half is free of wrapped around samples
+    fn process(
+        &mut self,
+        kernel: &[Vec<Complex<f64>>],
+        forward: &dyn RealToComplex<f64>,
+        inverse: &dyn ComplexToReal<f64>,
+        scratch: &mut [Complex<f64>],
+    ) {
+        let count = self.history.len();
+        self.head = (self.head + 1) % count;
+
+        // The transform uses its input as scratch space
+        self.result.copy_from_slice(&self.input);
+        let _ = forward.process_with_scratch(
+            &mut self.result,
+            &mut self.history[self.head],
+            scratch,
+        );
+
+        self.sum.fill(Complex::default());
+        for (age, partition) in kernel.iter().enumerate() {
+            let spectrum = &self.history[(self.head + count - age) % count];
+            for ((sum, x), h) in
+                self.sum.iter_mut().zip(spectrum).zip(partition)
+            {
+                *sum += x * h;
+            }
+        }
+
+        // Products of real spectra keep the imaginary DC and Nyquist bins at
+        // zero, so the inverse transform can't complain
+        let _ = inverse.process_with_scratch(
+            &mut self.sum,
+            &mut self.result,
+            scratch,
+        );
+        self.output.copy_from_slice(&self.result[PARTITION..]);
+
+        self.input.copy_within(PARTITION.., 0);
+    }
+}
+
+pub struct ConvolutionReverb {
+    params: Vec<Param>,
+    kernel: Option<Arc<Kernel>>,
+    forward: Arc<dyn RealToComplex<f64>>,
+    inverse: Arc<dyn ComplexToReal<f64>>,
+    scratch: Vec<Complex<f64>>,
+    convolvers: [Convolver; 2],
+    // Position inside the current block
+    fill: usize,
+    pre_delay: Vec<(f64, f64)>,
+    position: usize,
+}
+
+impl ConvolutionReverb {
+    /// Without a kernel the wet signal is silent
+    pub fn new(params: Vec<Param>, kernel: Option<Arc<Kernel>>) -> Self {
+        let mut planner = RealFftPlanner::<f64>::new();
+        let forward = planner.plan_fft_forward(FFT_SIZE);
+        let inverse = planner.plan_fft_inverse(FFT_SIZE);
+        let scratch =
+            vec![
+                Complex::default();
+                forward.get_scratch_len().max(inverse.get_scratch_len())
+            ];
+
+        let partitions = kernel
+            .as_ref()
+            .map_or(1, |kernel| kernel.partitions())
+            .max(1);
+        let sample_rate = kernel.as_ref().map_or(44_100.0, |k| k.sample_rate);
+        let max_pre_delay = PARAMS[PRE_DELAY].max / 1_000.0 * sample_rate;
+
+        Self {
+            params,
+            kernel,
+            forward,
+            inverse,
+            scratch,
+            convolvers: [
+                Convolver::new(partitions),
+                Convolver::new(partitions),
+            ],
+            fill: 0,
+            pre_delay: vec![(0.0, 0.0); max_pre_delay as usize + 1],
+            position: 0,
+        }
+    }
+}
+
+impl Effect for ConvolutionReverb {
+    fn params(&self) -> &[Param] {
+        &self.params
+    }
+
+    // The kernel was already built for the engine's sample rate
+    fn reset(&mut self, _sample_rate: f64) {
+        for convolver in &mut self.convolvers {
+            convolver.clear();
+        }
+        self.pre_delay.fill((0.0, 0.0));
+        self.fill = 0;
+    }
+
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let mix = self.params[MIX].get();
+        let kernel = match &self.kernel {
+            Some(kernel) => kernel,
+            None => return (input.0 * (1.0 - mix), input.1 * (1.0 - mix)),
+        };
+
+        let length = self.pre_delay.len();
+        let delay = (self.params[PRE_DELAY].get() / 1_000.0
+            * kernel.sample_rate) as usize;
+        let delay = delay.saturating_sub(PARTITION).min(length - 1);
+
+        self.pre_delay[self.position] = input;
+        let delayed = self.pre_delay[(self.position + length - delay) % length];
+        self.position = (self.position + 1) % length;
+
+        let [left, right] = &mut self.convolvers;
+        left.input[PARTITION + self.fill] = delayed.0;
+        right.input[PARTITION + self.fill] = delayed.1;
+        let wet = (left.output[self.fill], right.output[self.fill]);
+
+        self.fill += 1;
+        if self.fill == PARTITION {
+            self.fill = 0;
+
+            for (convolver, side) in
+                self.convolvers.iter_mut().zip(&kernel.sides)
+            {
+                convolver.process(
+                    side,
+                    self.forward.as_ref(),
+                    self.inverse.as_ref(),
+                    &mut self.scratch,
+                );
+            }
+        }
+
+        (
+            input.0 + (wet.0 - input.0) * mix,
+            input.1 + (wet.1 - input.1) * mix,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SPECS: [ParamSpec; 4] = PARAMS;
+
+    // Spans two partitions and ends inside the fade out
+    const FRAMES: usize = PARTITION + 188;
+    const SAMPLE_RATE: f64 = 1_000.0;
+
+    fn impulse_response() -> Vec<f32> {
+        (0..FRAMES)
+            .map(|frame| {
+                let sign = if frame % 3 == 0 { -0.5 } else { 1.0 };
+                sign * 0.99f32.powi(frame as i32)
+            })
+            .collect()
+    }
+
+    fn reverb(kernel: Kernel) -> ConvolutionReverb {
+        let params = SPECS
+            .iter()
+            .enumerate()
+            .map(|(index, spec)| {
+                let value = if index == MIX { 1.0 } else { 0.0 };
+                Param::new(spec, value)
+            })
+            .collect();
+        ConvolutionReverb::new(params, Some(Arc::new(kernel)))
+    }
+
+    #[test]
+    fn impulse_plays_back_the_trimmed_kernel_a_block_late() {
+        let buffer = SampleBuffer {
+            sample_rate: SAMPLE_RATE,
+            channels: vec![impulse_response()],
+        };
+        let kernel = Kernel::new(&buffer, 0.0, 10.0, SAMPLE_RATE);
+        assert_eq!(kernel.partitions(), 2);
+
+        // Faded over the last 10 ms, then brought to unit energy
+        let fade = FADE_OUT * SAMPLE_RATE;
+        let trimmed: Vec<f64> = impulse_response()
+            .iter()
+            .enumerate()
+            .map(|(frame, sample)| {
+                *sample as f64 * ((FRAMES - frame) as f64 / fade).min(1.0)
+            })
+            .collect();
+        let norm = trimmed.iter().map(|s| s * s).sum::<f64>().sqrt();
+
+        let mut reverb = reverb(kernel);
+        reverb.reset(SAMPLE_RATE);
+        for tick in 0..3 * PARTITION {
+            let input = if tick == 0 { 1.0 } else { 0.0 };
+            let (left, right) = reverb.tick((input, input));
+
+            let expected = tick
+                .checked_sub(PARTITION)
+                .and_then(|frame| trimmed.get(frame))
+                .map_or(0.0, |sample| sample / norm);
+            assert!(
+                (left - expected).abs() < 1e-9,
+                "tick {}: {} is not {}",
+                tick,
+                left,
+                expected
+            );
+            assert!((right - left).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn trims_the_start_and_length() {
+        let buffer = SampleBuffer {
+            sample_rate: SAMPLE_RATE,
+            channels: vec![impulse_response()],
+        };
+
+        // 100 ms from 200 ms in, no matter how much follows
+        let kernel = Kernel::new(&buffer, 0.2, 0.1, SAMPLE_RATE);
+        assert_eq!(kernel.partitions(), 1);
+
+        // Past the end there's nothing left
+        let kernel = Kernel::new(&buffer, 1.0, 0.1, SAMPLE_RATE);
+        assert_eq!(kernel.partitions(), 0);
+    }
+}
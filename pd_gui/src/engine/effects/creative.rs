@@ -0,0 +1,120 @@
+// Creative fundsp chains, wrapped as insert effects
+use fundsp::hacker::*;
+
+use super::{Effect, EffectKind, Param, ParamSpec};
+
+const MIX: ParamSpec = ParamSpec::live("Mix", 0.0, 1.0, 1.0);
+
+pub const CHORUS: [ParamSpec; 4] = [
+    ParamSpec::rebuild("Separation", 0.0, 0.05, 0.015),
+    ParamSpec::rebuild("Variation", 0.0, 0.05, 0.005),
+    ParamSpec::rebuild("Rate", 0.05, 5.0, 0.5).logarithmic(),
+    MIX,
+];
+pub const FLANGER: [ParamSpec; 3] = [
+    ParamSpec::rebuild("Feedback", 0.0, 0.95, 0.6),
+    ParamSpec::live("Rate", 0.01, 5.0, 0.1).logarithmic(),
+    MIX,
+];
+pub const PHASER: [ParamSpec; 3] = [
+    ParamSpec::rebuild("Feedback", 0.0, 0.95, 0.5),
+    ParamSpec::live("Rate", 0.01, 5.0, 0.1).logarithmic(),
+    MIX,
+];
+pub const REVERB: [ParamSpec; 3] = [
+    ParamSpec::rebuild("Room", 1.0, 30.0, 10.0),
+    ParamSpec::rebuild("Time", 0.1, 10.0, 3.0).logarithmic(),
+    ParamSpec::live("Mix", 0.0, 1.0, 0.2),
+];
+pub const MOOG: [ParamSpec; 3] = [
+    ParamSpec::live("Cutoff", 20.0, 20_000.0, 1_000.0).logarithmic(),
+    ParamSpec::live("Resonance", 0.0, 1.0, 0.6),
+    MIX,
+];
+pub const MIX_ONLY: [ParamSpec; 1] = [MIX];
+
+/// A stereo fundsp graph with a dry/wet mix as its last parameter
+pub struct FundspEffect {
+    params: Vec<Param>,
+    unit: Box<dyn AudioUnit64>,
+}
+
+impl FundspEffect {
+    pub fn new(kind: EffectKind, params: Vec<Param>) -> Self {
+        let unit = build_unit(kind, &params);
+
+        Self { params, unit }
+    }
+}
+
+impl Effect for FundspEffect {
+    fn params(&self) -> &[Param] {
+        &self.params
+    }
+
+    fn reset(&mut self, sample_rate: f64) {
+        self.unit.reset(Some(sample_rate));
+    }
+
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let mut output = [0.0; 2];
+        self.unit.tick(&[input.0, input.1], &mut output);
+
+        let mix = self.params.last().map_or(1.0, Param::get);
+        (
+            input.0 + (output[0] - input.0) * mix,
+            input.1 + (output[1] - input.1) * mix,
+        )
+    }
+}
+
+// Live parameters are read from inside the graph through envelopes, which
+// also smooth out sudden jumps
+fn build_unit(kind: EffectKind, params: &[Param]) -> Box<dyn AudioUnit64> {
+    let value = |index: usize| params[index].get();
+
+    match kind {
+        EffectKind::Chorus => {
+            let (separation, variation, rate) = (value(0), value(1), value(2));
+            Box::new(
+                chorus(0, separation, variation, rate)
+                    | chorus(1, separation, variation, rate),
+            )
+        }
+        EffectKind::Flanger => {
+            let feedback = value(0);
+            let (left, right) = (params[1].clone(), params[1].clone());
+            Box::new(
+                flanger(feedback, 0.005, 0.01, move |t| {
+                    lerp11(0.005, 0.01, sin_hz(left.get(), t))
+                }) | flanger(feedback, 0.005, 0.01, move |t| {
+                    lerp11(0.005, 0.01, cos_hz(right.get(), t))
+                }),
+            )
+        }
+        EffectKind::Phaser => {
+            let feedback = value(0);
+            let (left, right) = (params[1].clone(), params[1].clone());
+            Box::new(
+                phaser(feedback, move |t| sin_hz(left.get(), t) * 0.5 + 0.5)
+                    | phaser(feedback, move |t| {
+                        cos_hz(right.get(), t) * 0.5 + 0.5
+                    }),
+            )
+        }
+        EffectKind::Reverb => Box::new(reverb_stereo(value(0), value(1))),
+        EffectKind::Moog => {
+            let filter = || {
+                let (cutoff, resonance) =
+                    (params[0].clone(), params[1].clone());
+                (pass()
+                    | envelope(move |_| cutoff.get())
+                    | envelope(move |_| resonance.get()))
+                    >> moog()
+            };
+            Box::new(filter() | filter())
+        }
+        EffectKind::Declick => Box::new(declick() | declick()),
+        EffectKind::DcBlock => Box::new(dcblock() | dcblock()),
+    }
+}
@@ -0,0 +1,269 @@
+use std::f64::consts::PI;
+
+use super::{Effect, Param, ParamSpec};
+
+pub const BANDS: usize = 4;
+// Sample rate the response curve is drawn at, close enough for any device
+pub const DISPLAY_SAMPLE_RATE: f64 = 48_000.0;
+// Coefficients are recomputed this often while a band glides to new settings
+const UPDATE_SAMPLES: usize = 32;
+// About how long a band takes to reach new settings, so sweeps don't zipper
+const GLIDE_SECONDS: f64 = 0.02;
+
+const fn band(frequency: f64) -> [ParamSpec; 3] {
+    [
+        ParamSpec::live("Frequency", 20.0, 20_000.0, frequency).logarithmic(),
+        ParamSpec::live("Gain", -24.0, 24.0, 0.0),
+        ParamSpec::live("Q", 0.1, 18.0, 0.707).logarithmic(),
+    ]
+}
+
+const LOW: [ParamSpec; 3] = band(100.0);
+const LOW_MID: [ParamSpec; 3] = band(500.0);
+const HIGH_MID: [ParamSpec; 3] = band(2_000.0);
+const HIGH: [ParamSpec; 3] = band(8_000.0);
+
+/// Frequency, gain and Q for each band, low to high
+pub const PARAMS: [ParamSpec; BANDS * 3] = [
+    LOW[0],
+    LOW[1],
+    LOW[2],
+    LOW_MID[0],
+    LOW_MID[1],
+    LOW_MID[2],
+    HIGH_MID[0],
+    HIGH_MID[1],
+    HIGH_MID[2],
+    HIGH[0],
+    HIGH[1],
+    HIGH[2],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    LowShelf,
+    Peak,
+    HighShelf,
+}
+
+/// The outer bands shelve, the inner ones are bells
+pub fn shape(band: usize) -> Shape {
+    match band {
+        0 => Shape::LowShelf,
+        band if band == BANDS - 1 => Shape::HighShelf,
+        _ => Shape::Peak,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Band {
+    pub frequency: f64,
+    pub gain: f64,
+    pub q: f64,
+}
+
+impl Band {
+    pub fn from_params(params: &[Param], band: usize) -> Self {
+        Self {
+            frequency: params[band * 3].get(),
+            gain: params[band * 3 + 1].get(),
+            q: params[band * 3 + 2].get(),
+        }
+    }
+
+    // Moves `amount` of the way towards `target`, frequency and Q on a log
+    // scale. Close enough counts as there
+    fn glide(self, target: Band, amount: f64) -> Self {
+        let log = |from: f64, to: f64| from * (to / from).powf(amount);
+        let band = Self {
+            frequency: log(self.frequency, target.frequency),
+            gain: self.gain + (target.gain - self.gain) * amount,
+            q: log(self.q, target.q),
+        };
+
+        let is_close = (band.frequency / target.frequency - 1.0).abs() < 1e-4
+            && (band.gain - target.gain).abs() < 1e-3
+            && (band.q / target.q - 1.0).abs() < 1e-4;
+        if is_close {
+            target
+        } else {
+            band
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Coefficients {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+}
+
+impl Coefficients {
+    // Biquads from Robert Bristow-Johnson's audio EQ cookbook
+    fn new(shape: Shape, band: Band, sample_rate: f64) -> Self {
+        let a = 10f64.powf(band.gain / 40.0);
+        let w0 =
+            2.0 * PI * band.frequency.min(sample_rate * 0.49) / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * band.q);
+        let shelf = 2.0 * a.sqrt() * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match shape {
+            Shape::Peak => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            Shape::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos + shelf),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - shelf),
+                (a + 1.0) + (a - 1.0) * cos + shelf,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - shelf,
+            ),
+            Shape::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos + shelf),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - shelf),
+                (a + 1.0) - (a - 1.0) * cos + shelf,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - shelf,
+            ),
+        };
+
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    fn magnitude(&self, frequency: f64, sample_rate: f64) -> f64 {
+        let w = 2.0 * PI * frequency / sample_rate;
+        let (sin, cos) = w.sin_cos();
+        let (sin2, cos2) = (2.0 * w).sin_cos();
+
+        let numerator = (self.b0 + self.b1 * cos + self.b2 * cos2).powi(2)
+            + (self.b1 * sin + self.b2 * sin2).powi(2);
+        let denominator = (1.0 + self.a1 * cos + self.a2 * cos2).powi(2)
+            + (self.a1 * sin + self.a2 * sin2).powi(2);
+
+        (numerator / denominator).sqrt()
+    }
+}
+
+/// Gain of all bands together at `frequency`, in dB
+pub fn response_db(bands: &[Band], frequency: f64) -> f64 {
+    let magnitude: f64 = bands
+        .iter()
+        .enumerate()
+        .map(|(index, band)| {
+            Coefficients::new(shape(index), *band, DISPLAY_SAMPLE_RATE)
+                .magnitude(frequency, DISPLAY_SAMPLE_RATE)
+        })
+        .product();
+
+    20.0 * magnitude.max(1e-9).log10()
+}
+
+// Transposed direct form II, one state pair per stereo side
+#[derive(Debug, Clone, Copy, Default)]
+struct Filter {
+    coefficients: Coefficients,
+    state: [(f64, f64); 2],
+}
+
+impl Filter {
+    fn process(&mut self, side: usize, x: f64) -> f64 {
+        let c = self.coefficients;
+        let (z1, z2) = self.state[side];
+
+        let y = c.b0 * x + z1;
+        self.state[side] = (c.b1 * x - c.a1 * y + z2, c.b2 * x - c.a2 * y);
+
+        y
+    }
+}
+
+pub struct ParametricEq {
+    params: Vec<Param>,
+    filters: [Filter; BANDS],
+    // Settings the coefficients were last computed from, gliding towards
+    // the parameters
+    bands: [Option<Band>; BANDS],
+    // Samples until the coefficients are next updated
+    countdown: usize,
+    sample_rate: f64,
+}
+
+impl ParametricEq {
+    pub fn new(params: Vec<Param>) -> Self {
+        Self {
+            params,
+            filters: [Filter::default(); BANDS],
+            bands: [None; BANDS],
+            countdown: 0,
+            sample_rate: DISPLAY_SAMPLE_RATE,
+        }
+    }
+
+    fn update(&mut self) {
+        let amount = 1.0
+            - (-(UPDATE_SAMPLES as f64) / (GLIDE_SECONDS * self.sample_rate))
+                .exp();
+
+        for index in 0..BANDS {
+            let target = Band::from_params(&self.params, index);
+            let band = match self.bands[index] {
+                Some(band) if band == target => continue,
+                Some(band) => band.glide(target, amount),
+                // The first settings apply at once
+                None => target,
+            };
+
+            self.bands[index] = Some(band);
+            self.filters[index].coefficients =
+                Coefficients::new(shape(index), band, self.sample_rate);
+        }
+    }
+}
+
+impl Effect for ParametricEq {
+    fn params(&self) -> &[Param] {
+        &self.params
+    }
+
+    fn reset(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+        self.filters = [Filter::default(); BANDS];
+        self.bands = [None; BANDS];
+        self.countdown = 0;
+    }
+
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        if self.countdown == 0 {
+            self.update();
+            self.countdown = UPDATE_SAMPLES;
+        }
+        self.countdown -= 1;
+
+        // Flat bands pass the signal unchanged, but still run so their state
+        // is current when they're raised
+        let (mut left, mut right) = input;
+        for filter in &mut self.filters {
+            left = filter.process(0, left);
+            right = filter.process(1, right);
+        }
+
+        (left, right)
+    }
+}
@@ -0,0 +1,295 @@
+//! Insert effects for channel strips.
+//!
+//! Parameter values live in shared atomics. The UI, automation and modulation
+//! can change them while the effect runs on the audio thread.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+pub mod chain;
+pub mod compressor;
+pub mod convolution;
+pub mod creative;
+pub mod eq;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scale {
+    Linear,
+    // For frequencies and times, so the knob travel feels even
+    Logarithmic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub min: f64,
+    pub max: f64,
+    pub default: f64,
+    pub scale: Scale,
+    // Live parameters are read while running; changing any other one means
+    // building a new effect instance
+    pub is_live: bool,
+    // Meters are written by the effect (like gain reduction) and only shown
+    pub is_meter: bool,
+}
+
+impl ParamSpec {
+    pub const fn live(
+        name: &'static str,
+        min: f64,
+        max: f64,
+        default: f64,
+    ) -> Self {
+        Self {
+            name,
+            min,
+            max,
+            default,
+            scale: Scale::Linear,
+            is_live: true,
+            is_meter: false,
+        }
+    }
+
+    pub const fn rebuild(
+        name: &'static str,
+        min: f64,
+        max: f64,
+        default: f64,
+    ) -> Self {
+        Self {
+            is_live: false,
+            ..Self::live(name, min, max, default)
+        }
+    }
+
+    pub const fn meter(name: &'static str, min: f64, max: f64) -> Self {
+        Self {
+            is_meter: true,
+            ..Self::live(name, min, max, min)
+        }
+    }
+
+    pub const fn logarithmic(self) -> Self {
+        Self {
+            scale: Scale::Logarithmic,
+            ..self
+        }
+    }
+
+    /// Automation and modulation only move what the processor reads while
+    /// running, and never meters
+    pub fn is_automatable(&self) -> bool {
+        self.is_live && !self.is_meter
+    }
+
+    pub fn to_normal(&self, value: f64) -> f32 {
+        let value = value.clamp(self.min, self.max);
+        let normal = match self.scale {
+            Scale::Linear => (value - self.min) / (self.max - self.min),
+            Scale::Logarithmic => {
+                (value / self.min).ln() / (self.max / self.min).ln()
+            }
+        };
+
+        normal as f32
+    }
+
+    pub fn from_normal(&self, normal: f32) -> f64 {
+        let normal = (normal as f64).clamp(0.0, 1.0);
+
+        match self.scale {
+            Scale::Linear => self.min + (self.max - self.min) * normal,
+            Scale::Logarithmic => self.min * (self.max / self.min).powf(normal),
+        }
+    }
+}
+
+/// A named effect or instrument parameter. Clones share the same value.
+///
+/// Modulation offsets what the processor reads (`get`) from the value that
+/// was set (`base`), so it never overwrites a control or automation move
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub spec: &'static ParamSpec,
+    value: Arc<AtomicU64>,
+    modulated: Arc<AtomicU64>,
+}
+
+impl Param {
+    pub fn new(spec: &'static ParamSpec, value: f64) -> Self {
+        let param = Self {
+            spec,
+            value: Arc::new(AtomicU64::new(0)),
+            modulated: Arc::new(AtomicU64::new(0)),
+        };
+        param.set(value);
+
+        param
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.modulated.load(Ordering::Relaxed))
+    }
+
+    pub fn base(&self) -> f64 {
+        f64::from_bits(self.value.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, value: f64) {
+        let value = value.clamp(self.spec.min, self.spec.max).to_bits();
+        self.value.store(value, Ordering::Relaxed);
+        self.modulated.store(value, Ordering::Relaxed);
+    }
+
+    pub fn normal(&self) -> f32 {
+        self.spec.to_normal(self.base())
+    }
+
+    pub fn set_normal(&self, normal: f32) {
+        self.set(self.spec.from_normal(normal));
+    }
+
+    /// Moves the value read by `get` by `offset`, in normalized units
+    pub fn modulate(&self, offset: f64) {
+        let normal = (self.normal() as f64 + offset).clamp(0.0, 1.0);
+        let value = self.spec.from_normal(normal as f32);
+        self.modulated.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn clear_modulation(&self) {
+        let value = self.value.load(Ordering::Relaxed);
+        self.modulated.store(value, Ordering::Relaxed);
+    }
+}
+
+/// Fresh parameters for `specs`, starting from `values` where given. Meters
+/// always start from rest, whatever was saved for them
+pub fn create_params(
+    specs: &'static [ParamSpec],
+    values: &[f64],
+) -> Vec<Param> {
+    specs
+        .iter()
+        .enumerate()
+        .map(|(index, spec)| {
+            let value = match values.get(index) {
+                Some(value) if !spec.is_meter => *value,
+                _ => spec.default,
+            };
+            Param::new(spec, value)
+        })
+        .collect()
+}
+
+/// A stereo processor sitting in a channel's insert chain
+pub trait Effect: Send {
+    fn params(&self) -> &[Param];
+    fn reset(&mut self, sample_rate: f64);
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EffectKind {
+    Chorus,
+    Flanger,
+    Phaser,
+    Reverb,
+    Moog,
+    Declick,
+    DcBlock,
+    Eq,
+    Compressor,
+    Convolution,
+}
+
+impl EffectKind {
+    pub const ALL: [EffectKind; 10] = [
+        EffectKind::Chorus,
+        EffectKind::Flanger,
+        EffectKind::Phaser,
+        EffectKind::Reverb,
+        EffectKind::Moog,
+        EffectKind::Declick,
+        EffectKind::DcBlock,
+        EffectKind::Eq,
+        EffectKind::Compressor,
+        EffectKind::Convolution,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EffectKind::Chorus => "Chorus",
+            EffectKind::Flanger => "Flanger",
+            EffectKind::Phaser => "Phaser",
+            EffectKind::Reverb => "Reverb",
+            EffectKind::Moog => "Moog filter",
+            EffectKind::Declick => "Declick",
+            EffectKind::DcBlock => "DC block",
+            EffectKind::Eq => "Parametric EQ",
+            EffectKind::Compressor => "Compressor",
+            EffectKind::Convolution => "Convolution reverb",
+        }
+    }
+
+    pub fn params(&self) -> &'static [ParamSpec] {
+        match self {
+            EffectKind::Chorus => &creative::CHORUS,
+            EffectKind::Flanger => &creative::FLANGER,
+            EffectKind::Phaser => &creative::PHASER,
+            EffectKind::Reverb => &creative::REVERB,
+            EffectKind::Moog => &creative::MOOG,
+            EffectKind::Declick | EffectKind::DcBlock => &creative::MIX_ONLY,
+            EffectKind::Eq => &eq::PARAMS,
+            EffectKind::Compressor => &compressor::PARAMS,
+            EffectKind::Convolution => &convolution::PARAMS,
+        }
+    }
+
+    /// Fresh parameters for this kind, starting from `values` where given
+    pub fn create_params(&self, values: &[f64]) -> Vec<Param> {
+        create_params(self.params(), values)
+    }
+
+    /// Builds an effect reading from the given (shared) parameters. A
+    /// convolution reverb gets no kernel here, see `convolution::Kernel`
+    pub fn build(&self, params: &[Param]) -> Box<dyn Effect> {
+        match self {
+            EffectKind::Eq => Box::new(eq::ParametricEq::new(params.to_vec())),
+            EffectKind::Compressor => {
+                Box::new(compressor::Compressor::new(params.to_vec()))
+            }
+            EffectKind::Convolution => Box::new(
+                convolution::ConvolutionReverb::new(params.to_vec(), None),
+            ),
+            _ => Box::new(creative::FundspEffect::new(*self, params.to_vec())),
+        }
+    }
+}
+
+/// An insert as stored on a track
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EffectSlot {
+    pub id: u64,
+    pub kind: EffectKind,
+    pub is_bypassed: bool,
+    // By parameter index; meters stay at their default
+    pub values: Vec<f64>,
+    // Composition sample a convolution reverb convolves with
+    #[serde(default)]
+    pub impulse_response: Option<i64>,
+}
+
+impl EffectSlot {
+    pub fn new(id: u64, kind: EffectKind) -> Self {
+        Self {
+            id,
+            kind,
+            is_bypassed: false,
+            values: kind.params().iter().map(|spec| spec.default).collect(),
+            impulse_response: None,
+        }
+    }
+}
@@ -0,0 +1,317 @@
+//! Granular instrument.
+//!
+//! Every held note, or the drone when it's on, runs a stream spawning short
+//! windowed grains out of a sample around a read head. The head starts at the
+//! position parameter and scans forward unless frozen. Grains play at the
+//! note's pitch relative to C4, with their start jittered around the head and
+//! their place in the stereo field sprayed.
+
+use std::sync::Arc;
+
+use super::effects::{Param, ParamSpec};
+use super::instrument::{pitch_ratio, Adsr, Envelope, Instrument, PITCH};
+use super::sample::SampleBuffer;
+use super::tuning::NoteTable;
+use crate::app::midi::{velocity_to_gain, NoteEvent};
+
+const STREAMS: usize = 8;
+// Grains beyond this many are dropped until others end
+const MAX_GRAINS: usize = 256;
+const ROOT_KEY: u8 = 60;
+
+pub const POSITION: usize = 0;
+pub const SIZE: usize = 1;
+pub const DENSITY: usize = 2;
+pub const JITTER: usize = 3;
+pub const SPRAY: usize = 4;
+pub const SCAN: usize = 5;
+pub const FREEZE: usize = 6;
+pub const WINDOW: usize = 7;
+pub const GAIN: usize = 8;
+pub const PITCH_OFFSET: usize = 9;
+
+/// Size is in milliseconds and density in grains per second. Jitter is the
+/// share of the sample grain starts stray from the head, spray how far grains
+/// pan. Scan is the speed of the head, 1 being the sample's own pace
+pub const PARAMS: [ParamSpec; 10] = [
+    ParamSpec::live("Position", 0.0, 1.0, 0.0),
+    ParamSpec::live("Size", 5.0, 500.0, 80.0).logarithmic(),
+    ParamSpec::live("Density", 1.0, 200.0, 30.0).logarithmic(),
+    ParamSpec::live("Jitter", 0.0, 0.5, 0.02),
+    ParamSpec::live("Spray", 0.0, 1.0, 0.3),
+    ParamSpec::live("Scan", 0.0, 2.0, 1.0),
+    ParamSpec::live("Freeze", 0.0, 1.0, 0.0),
+    ParamSpec::live("Window", 0.0, (Window::ALL.len() - 1) as f64, 0.0),
+    ParamSpec::live("Gain", 0.0, 1.0, 1.0),
+    PITCH,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    Hann,
+    Triangle,
+    // Flat with short fades, keeping more of each grain
+    Tukey,
+    Gauss,
+}
+
+impl Window {
+    pub const ALL: [Window; 4] =
+        [Window::Hann, Window::Triangle, Window::Tukey, Window::Gauss];
+
+    /// The window a parameter value picks
+    pub fn from_value(value: f64) -> Window {
+        Self::ALL[(value.round().max(0.0) as usize).min(Self::ALL.len() - 1)]
+    }
+
+    // Gain at a point of the grain between 0 and 1
+    fn at(&self, x: f64) -> f64 {
+        use std::f64::consts::PI;
+
+        match self {
+            Window::Hann => 0.5 - 0.5 * (2.0 * PI * x).cos(),
+            Window::Triangle => 1.0 - (2.0 * x - 1.0).abs(),
+            Window::Tukey => {
+                const EDGE: f64 = 0.1;
+                let edge = x.min(1.0 - x);
+                if edge >= EDGE {
+                    1.0
+                } else {
+                    0.5 - 0.5 * (PI * edge / EDGE).cos()
+                }
+            }
+            Window::Gauss => (-0.5 * ((x - 0.5) / 0.15).powi(2)).exp(),
+        }
+    }
+}
+
+// Bipolar noise from a xorshift generator
+fn random(seed: &mut u32) -> f64 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 17;
+    *seed ^= *seed << 5;
+    *seed as f64 / u32::MAX as f64 * 2.0 - 1.0
+}
+
+struct Grain {
+    // Frame in the sample and frames moved per output sample
+    position: f64,
+    step: f64,
+    age: usize,
+    length: usize,
+    window: Window,
+    gains: (f64, f64),
+}
+
+struct Stream {
+    note: Option<u8>,
+    pitch: f64,
+    gain: f64,
+    age: u64,
+    envelope: Envelope,
+    // How far the head has scanned from the position parameter, in frames
+    scanned: f64,
+    // Output samples until the next grain
+    countdown: f64,
+}
+
+impl Stream {
+    fn new() -> Self {
+        Self {
+            note: None,
+            pitch: 1.0,
+            gain: 0.0,
+            age: 0,
+            envelope: Envelope::new(Adsr::default()),
+            scanned: 0.0,
+            countdown: 0.0,
+        }
+    }
+}
+
+/// Grain clouds out of one sample. Without a sample it stays silent
+pub struct GranularSynth {
+    params: Vec<Param>,
+    buffer: Option<Arc<SampleBuffer>>,
+    // The drone's stream comes first when it's on
+    drone: bool,
+    streams: Vec<Stream>,
+    grains: Vec<Grain>,
+    notes_played: u64,
+    seed: u32,
+    sample_rate: f64,
+    tuning: NoteTable,
+}
+
+impl GranularSynth {
+    pub fn new(
+        params: Vec<Param>,
+        buffer: Option<Arc<SampleBuffer>>,
+        drone: bool,
+    ) -> Self {
+        Self {
+            params,
+            buffer,
+            drone,
+            streams: (0..STREAMS).map(|_| Stream::new()).collect(),
+            grains: Vec::with_capacity(MAX_GRAINS),
+            notes_played: 0,
+            seed: 0x2545_F491,
+            sample_rate: 44_100.0,
+            tuning: NoteTable::default(),
+        }
+    }
+
+    // Prefer a silent stream, otherwise steal the oldest one. The drone's
+    // stream is never taken
+    fn free_stream(&mut self) -> &mut Stream {
+        let first = usize::from(self.drone);
+        let streams = &self.streams[first..];
+        let index = streams
+            .iter()
+            .position(|stream| stream.envelope.is_idle())
+            .unwrap_or_else(|| {
+                streams
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, stream)| stream.age)
+                    .map(|(index, _)| index)
+                    .unwrap_or(0)
+            });
+
+        &mut self.streams[first + index]
+    }
+}
+
+impl Instrument for GranularSynth {
+    fn reset(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+        self.grains.clear();
+
+        for stream in &mut self.streams {
+            stream.envelope.reset(sample_rate);
+            stream.note = None;
+        }
+
+        if self.drone {
+            let stream = &mut self.streams[0];
+            stream.pitch = 1.0;
+            stream.gain = 1.0;
+            stream.scanned = 0.0;
+            stream.envelope.gate_on();
+        }
+    }
+
+    fn note(&mut self, event: NoteEvent) {
+        match event {
+            NoteEvent::NoteOn { note, velocity } => {
+                let pitch = match self.tuning.ratio(note, ROOT_KEY) {
+                    Some(pitch) => pitch,
+                    None => return,
+                };
+                self.notes_played += 1;
+                let age = self.notes_played;
+
+                let stream = self.free_stream();
+                stream.note = Some(note);
+                stream.pitch = pitch;
+                stream.gain = velocity_to_gain(velocity);
+                stream.age = age;
+                stream.scanned = 0.0;
+                stream.countdown = 0.0;
+                stream.envelope.gate_on();
+            }
+            NoteEvent::NoteOff { note } => {
+                for stream in &mut self.streams {
+                    if stream.note == Some(note) {
+                        stream.note = None;
+                        stream.envelope.gate_off();
+                    }
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self) -> (f64, f64) {
+        let GranularSynth {
+            params,
+            buffer,
+            streams,
+            grains,
+            seed,
+            sample_rate,
+            ..
+        } = self;
+
+        let buffer = match buffer {
+            Some(buffer) if buffer.frames() > 0 => buffer,
+            _ => return (0.0, 0.0),
+        };
+
+        let param = |index: usize| params[index].get();
+        let frames = buffer.frames() as f64;
+        let rate = buffer.sample_rate / *sample_rate;
+        let head = param(POSITION) * frames;
+        let length = (param(SIZE) / 1_000.0 * *sample_rate).max(1.0);
+        let interval = *sample_rate / param(DENSITY).max(0.1);
+        let jitter = param(JITTER) * frames;
+        let spray = param(SPRAY);
+        let scan = if param(FREEZE) >= 0.5 {
+            0.0
+        } else {
+            param(SCAN) * rate
+        };
+        let window = Window::from_value(param(WINDOW));
+        let pitch = pitch_ratio(param(PITCH_OFFSET));
+        // Overlapping grains add up, keep the cloud near unity
+        let gain = param(GAIN) / (length / interval).max(1.0).sqrt();
+
+        for stream in streams.iter_mut() {
+            if stream.envelope.is_idle() {
+                continue;
+            }
+
+            let level = stream.envelope.tick() * stream.gain;
+            stream.scanned = (stream.scanned + scan) % frames;
+            stream.countdown -= 1.0;
+            if stream.countdown > 0.0 {
+                continue;
+            }
+            stream.countdown += interval;
+
+            if grains.len() < MAX_GRAINS {
+                let start = head + stream.scanned + random(seed) * jitter;
+                // Equal power panning, sprayed around the center
+                let angle =
+                    (1.0 + random(seed) * spray) * std::f64::consts::FRAC_PI_4;
+                grains.push(Grain {
+                    position: start.rem_euclid(frames),
+                    step: stream.pitch * pitch * rate,
+                    age: 0,
+                    length: length as usize,
+                    window,
+                    gains: (angle.cos() * level, angle.sin() * level),
+                });
+            }
+        }
+
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for grain in grains.iter_mut() {
+            let shape = grain.window.at(grain.age as f64 / grain.length as f64);
+            let (l, r) = buffer.frame_at(grain.position);
+            left += l * shape * grain.gains.0;
+            right += r * shape * grain.gains.1;
+
+            grain.age += 1;
+            grain.position = (grain.position + grain.step) % frames;
+        }
+        grains.retain(|grain| grain.age < grain.length);
+
+        (left * gain, right * gain)
+    }
+
+    fn set_tuning(&mut self, table: &NoteTable) {
+        self.tuning.clone_from(table);
+    }
+}
@@ -0,0 +1,266 @@
+use fundsp::hacker::{pulse, AudioUnit64};
+use serde::{Deserialize, Serialize};
+
+use super::effects::{Param, ParamSpec};
+use super::tuning::NoteTable;
+use crate::app::midi::{velocity_to_gain, NoteEvent, MAX_NOTE};
+
+/// Pitch offset in semitones, shared by every instrument
+pub const PITCH: ParamSpec = ParamSpec::live("Pitch", -12.0, 12.0, 0.0);
+
+pub fn pitch_ratio(semitones: f64) -> f64 {
+    2f64.powf(semitones / 12.0)
+}
+
+/// A sound source living on a mixer channel that plays note events
+pub trait Instrument: Send {
+    fn reset(&mut self, sample_rate: f64);
+    fn note(&mut self, event: NoteEvent);
+    fn tick(&mut self) -> (f64, f64);
+
+    /// Takes the frequencies notes play at from the next note on
+    fn set_tuning(&mut self, table: &NoteTable);
+
+    fn all_notes_off(&mut self) {
+        for note in 0..=MAX_NOTE {
+            self.note(NoteEvent::NoteOff { note });
+        }
+    }
+}
+
+/// Envelope times are in seconds, sustain is a linear level between 0 and 1
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Adsr {
+    pub attack: f64,
+    pub decay: f64,
+    pub sustain: f64,
+    pub release: f64,
+}
+
+impl Default for Adsr {
+    fn default() -> Self {
+        Self {
+            attack: 0.005,
+            decay: 0.1,
+            sustain: 0.8,
+            release: 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    pub adsr: Adsr,
+    stage: Stage,
+    level: f64,
+    release_step: f64,
+    sample_rate: f64,
+}
+
+impl Envelope {
+    pub fn new(adsr: Adsr) -> Self {
+        Self {
+            adsr,
+            stage: Stage::Idle,
+            level: 0.0,
+            release_step: 0.0,
+            sample_rate: 44_100.0,
+        }
+    }
+
+    pub fn reset(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+        self.stage = Stage::Idle;
+        self.level = 0.0;
+    }
+
+    pub fn gate_on(&mut self) {
+        self.stage = Stage::Attack;
+    }
+
+    pub fn gate_off(&mut self) {
+        if self.stage != Stage::Idle {
+            self.stage = Stage::Release;
+            self.release_step = self.level / self.samples(self.adsr.release);
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.stage == Stage::Idle
+    }
+
+    pub fn tick(&mut self) -> f64 {
+        match self.stage {
+            Stage::Idle => {}
+            Stage::Attack => {
+                self.level += 1.0 / self.samples(self.adsr.attack);
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                let step =
+                    (1.0 - self.adsr.sustain) / self.samples(self.adsr.decay);
+                self.level -= step;
+                if self.level <= self.adsr.sustain {
+                    self.level = self.adsr.sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => self.level = self.adsr.sustain,
+            Stage::Release => {
+                self.level -= self.release_step;
+                if self.level <= 0.0 {
+                    self.level = 0.0;
+                    self.stage = Stage::Idle;
+                }
+            }
+        }
+
+        self.level
+    }
+
+    // Never less than one sample so zero-length stages don't divide by zero
+    fn samples(&self, seconds: f64) -> f64 {
+        (seconds * self.sample_rate).max(1.0)
+    }
+}
+
+const PULSE_SYNTH_VOICES: usize = 8;
+
+pub const PULSE_SYNTH_DUTY: usize = 0;
+pub const PULSE_SYNTH_PITCH: usize = 1;
+pub const PULSE_SYNTH_PARAMS: [ParamSpec; 2] =
+    [ParamSpec::live("Duty", 0.05, 0.95, 0.3), PITCH];
+
+struct PulseVoice {
+    note: Option<u8>,
+    frequency: f64,
+    gain: f64,
+    age: u64,
+    oscillator: Box<dyn AudioUnit64>,
+    envelope: Envelope,
+}
+
+/// A polyphonic pulse wave, its frequencies taken from the tuning
+pub struct PulseSynth {
+    params: Vec<Param>,
+    voices: Vec<PulseVoice>,
+    notes_played: u64,
+    tuning: NoteTable,
+}
+
+impl PulseSynth {
+    pub fn new(params: Vec<Param>) -> Self {
+        let voices = (0..PULSE_SYNTH_VOICES)
+            .map(|_| PulseVoice {
+                note: None,
+                frequency: 110.0,
+                gain: 0.0,
+                age: 0,
+                oscillator: Box::new(pulse()),
+                envelope: Envelope::new(Adsr::default()),
+            })
+            .collect();
+
+        Self {
+            params,
+            voices,
+            notes_played: 0,
+            tuning: NoteTable::default(),
+        }
+    }
+
+    // Prefer a silent voice, otherwise steal the oldest one
+    fn free_voice(&mut self) -> &mut PulseVoice {
+        let index = self
+            .voices
+            .iter()
+            .position(|voice| voice.envelope.is_idle())
+            .unwrap_or_else(|| {
+                self.voices
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, voice)| voice.age)
+                    .map(|(index, _)| index)
+                    .unwrap_or(0)
+            });
+
+        &mut self.voices[index]
+    }
+}
+
+impl Instrument for PulseSynth {
+    fn reset(&mut self, sample_rate: f64) {
+        for voice in &mut self.voices {
+            voice.oscillator.reset(Some(sample_rate));
+            voice.envelope.reset(sample_rate);
+            voice.note = None;
+        }
+    }
+
+    fn note(&mut self, event: NoteEvent) {
+        match event {
+            NoteEvent::NoteOn { note, velocity } => {
+                let frequency = match self.tuning.frequency(note) {
+                    Some(frequency) => frequency,
+                    None => return,
+                };
+                self.notes_played += 1;
+                let age = self.notes_played;
+
+                let voice = self.free_voice();
+                voice.note = Some(note);
+                voice.frequency = frequency;
+                voice.gain = velocity_to_gain(velocity);
+                voice.age = age;
+                voice.envelope.gate_on();
+            }
+            NoteEvent::NoteOff { note } => {
+                for voice in &mut self.voices {
+                    if voice.note == Some(note) {
+                        voice.note = None;
+                        voice.envelope.gate_off();
+                    }
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self) -> (f64, f64) {
+        let mut output = [0.0];
+        let mut mix = 0.0;
+        let duty = self.params[PULSE_SYNTH_DUTY].get();
+        let pitch = pitch_ratio(self.params[PULSE_SYNTH_PITCH].get());
+
+        for voice in &mut self.voices {
+            if voice.envelope.is_idle() {
+                continue;
+            }
+
+            voice
+                .oscillator
+                .tick(&[voice.frequency * pitch, duty], &mut output);
+            mix += output[0] * voice.envelope.tick() * voice.gain;
+        }
+
+        // Leave headroom for all voices playing at once
+        let mix = mix * 0.25;
+
+        (mix, mix)
+    }
+
+    fn set_tuning(&mut self, table: &NoteTable) {
+        self.tuning.clone_from(table);
+    }
+}
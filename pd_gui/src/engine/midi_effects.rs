@@ -0,0 +1,555 @@
+//! MIDI effects: note processors in front of a track's instrument.
+//!
+//! Every note a channel receives, from the keyboard, its clip or its step
+//! sequencer, runs through the track's chain in order before reaching the
+//! instrument. Processors may also emit notes on their own as time passes,
+//! like the arpeggiator does.
+
+use serde::{Deserialize, Serialize};
+
+use super::transport::NOTE_VALUES;
+use crate::app::midi::{NoteEvent, MAX_NOTE};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArpMode {
+    Up,
+    Down,
+    Random,
+    // The order the notes were played in
+    Order,
+}
+
+impl ArpMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArpMode::Up => "Up",
+            ArpMode::Down => "Down",
+            ArpMode::Random => "Random",
+            ArpMode::Order => "Order",
+        }
+    }
+
+    /// The next mode, for editors that cycle through them
+    pub fn next(&self) -> ArpMode {
+        match self {
+            ArpMode::Up => ArpMode::Down,
+            ArpMode::Down => ArpMode::Random,
+            ArpMode::Random => ArpMode::Order,
+            ArpMode::Order => ArpMode::Up,
+        }
+    }
+}
+
+/// Semitones of the scale degrees within one octave
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scale {
+    Major,
+    Minor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    HarmonicMinor,
+    PentatonicMajor,
+    PentatonicMinor,
+}
+
+impl Scale {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scale::Major => "Major",
+            Scale::Minor => "Minor",
+            Scale::Dorian => "Dorian",
+            Scale::Phrygian => "Phrygian",
+            Scale::Lydian => "Lydian",
+            Scale::Mixolydian => "Mixolydian",
+            Scale::HarmonicMinor => "Harmonic minor",
+            Scale::PentatonicMajor => "Pentatonic major",
+            Scale::PentatonicMinor => "Pentatonic minor",
+        }
+    }
+
+    pub fn next(&self) -> Scale {
+        match self {
+            Scale::Major => Scale::Minor,
+            Scale::Minor => Scale::Dorian,
+            Scale::Dorian => Scale::Phrygian,
+            Scale::Phrygian => Scale::Lydian,
+            Scale::Lydian => Scale::Mixolydian,
+            Scale::Mixolydian => Scale::HarmonicMinor,
+            Scale::HarmonicMinor => Scale::PentatonicMajor,
+            Scale::PentatonicMajor => Scale::PentatonicMinor,
+            Scale::PentatonicMinor => Scale::Major,
+        }
+    }
+
+    fn degrees(&self) -> &'static [u8] {
+        match self {
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+            Scale::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+            Scale::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            Scale::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            Scale::PentatonicMajor => &[0, 2, 4, 7, 9],
+            Scale::PentatonicMinor => &[0, 3, 5, 7, 10],
+        }
+    }
+}
+
+/// Chords as steps along the scale from the played degree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChordShape {
+    Triad,
+    Seventh,
+    Ninth,
+    Sus2,
+    Sus4,
+    // Root, fifth and octave
+    Power,
+}
+
+const MAX_CHORD: usize = 4;
+
+impl ChordShape {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChordShape::Triad => "Triad",
+            ChordShape::Seventh => "7th",
+            ChordShape::Ninth => "9th",
+            ChordShape::Sus2 => "Sus2",
+            ChordShape::Sus4 => "Sus4",
+            ChordShape::Power => "Power",
+        }
+    }
+
+    pub fn next(&self) -> ChordShape {
+        match self {
+            ChordShape::Triad => ChordShape::Seventh,
+            ChordShape::Seventh => ChordShape::Ninth,
+            ChordShape::Ninth => ChordShape::Sus2,
+            ChordShape::Sus2 => ChordShape::Sus4,
+            ChordShape::Sus4 => ChordShape::Power,
+            ChordShape::Power => ChordShape::Triad,
+        }
+    }
+
+    // A ninth leaves out the fifth, to stay within `MAX_CHORD` notes. An
+    // octave is as many steps as the scale has degrees
+    fn steps(&self, octave: usize) -> [usize; MAX_CHORD] {
+        match self {
+            ChordShape::Triad => [0, 2, 4, 0],
+            ChordShape::Seventh => [0, 2, 4, 6],
+            ChordShape::Ninth => [0, 2, 6, 8],
+            ChordShape::Sus2 => [0, 1, 4, 0],
+            ChordShape::Sus4 => [0, 3, 4, 0],
+            ChordShape::Power => [0, 4, octave, 0],
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            ChordShape::Seventh | ChordShape::Ninth => 4,
+            _ => 3,
+        }
+    }
+}
+
+pub const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// The serializable description of a MIDI effect, stored on a track
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MidiEffect {
+    // `rate` is an index into `NOTE_VALUES`, `gate` the share of a step a
+    // note is held
+    Arpeggiator {
+        mode: ArpMode,
+        rate: usize,
+        gate: f64,
+        octaves: u8,
+    },
+    // `root` is a pitch class, 0 being C
+    Chord {
+        root: u8,
+        scale: Scale,
+        shape: ChordShape,
+    },
+}
+
+impl MidiEffect {
+    pub const ARPEGGIATOR: MidiEffect = MidiEffect::Arpeggiator {
+        mode: ArpMode::Up,
+        rate: 1,
+        gate: 0.5,
+        octaves: 1,
+    };
+    pub const CHORD: MidiEffect = MidiEffect::Chord {
+        root: 0,
+        scale: Scale::Major,
+        shape: ChordShape::Triad,
+    };
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MidiEffect::Arpeggiator { .. } => "Arpeggiator",
+            MidiEffect::Chord { .. } => "Chord",
+        }
+    }
+
+    pub fn create(&self) -> Box<dyn MidiProcessor> {
+        match *self {
+            MidiEffect::Arpeggiator {
+                mode,
+                rate,
+                gate,
+                octaves,
+            } => Box::new(Arpeggiator::new(
+                mode,
+                NOTE_VALUES[rate.min(NOTE_VALUES.len() - 1)].1,
+                gate,
+                octaves,
+            )),
+            MidiEffect::Chord { root, scale, shape } => {
+                Box::new(ChordGenerator::new(root, scale, shape))
+            }
+        }
+    }
+}
+
+/// A note processor running on the audio thread
+pub trait MidiProcessor: Send {
+    fn reset(&mut self, sample_rate: f64);
+    fn note(&mut self, event: NoteEvent, emit: &mut dyn FnMut(NoteEvent));
+
+    /// Called every frame with the transport position while it runs
+    fn tick(
+        &mut self,
+        _beat: Option<f64>,
+        _tempo: f64,
+        _emit: &mut dyn FnMut(NoteEvent),
+    ) {
+    }
+
+    /// Releases whatever the processor is holding
+    fn all_notes_off(&mut self, emit: &mut dyn FnMut(NoteEvent));
+}
+
+// Runs an event through the processors in order, the last one emitting to
+// `output`
+fn feed(
+    processors: &mut [Box<dyn MidiProcessor>],
+    event: NoteEvent,
+    output: &mut dyn FnMut(NoteEvent),
+) {
+    match processors.split_first_mut() {
+        Some((first, rest)) => {
+            first.note(event, &mut |event| feed(rest, event, output))
+        }
+        None => output(event),
+    }
+}
+
+/// The MIDI effects of a channel, in processing order
+#[derive(Default)]
+pub struct MidiChain {
+    processors: Vec<Box<dyn MidiProcessor>>,
+}
+
+impl MidiChain {
+    pub fn new(effects: &[MidiEffect]) -> Self {
+        Self {
+            processors: effects.iter().map(MidiEffect::create).collect(),
+        }
+    }
+
+    pub fn reset(&mut self, sample_rate: f64) {
+        for processor in &mut self.processors {
+            processor.reset(sample_rate);
+        }
+    }
+
+    pub fn note(
+        &mut self,
+        event: NoteEvent,
+        output: &mut dyn FnMut(NoteEvent),
+    ) {
+        feed(&mut self.processors, event, output);
+    }
+
+    /// Lets every processor emit its timed notes into the ones after it
+    pub fn tick(
+        &mut self,
+        beat: Option<f64>,
+        tempo: f64,
+        output: &mut dyn FnMut(NoteEvent),
+    ) {
+        for index in 0..self.processors.len() {
+            let (processor, rest) =
+                self.processors[index..].split_first_mut().unwrap();
+            processor.tick(beat, tempo, &mut |event| feed(rest, event, output));
+        }
+    }
+
+    pub fn all_notes_off(&mut self, output: &mut dyn FnMut(NoteEvent)) {
+        for index in 0..self.processors.len() {
+            let (processor, rest) =
+                self.processors[index..].split_first_mut().unwrap();
+            processor.all_notes_off(&mut |event| feed(rest, event, output));
+        }
+    }
+}
+
+/// Plays the held notes one after another, on the transport's grid while it
+/// runs and on a clock of its own at the same tempo otherwise
+pub struct Arpeggiator {
+    mode: ArpMode,
+    // Step length in beats
+    rate: f64,
+    gate: f64,
+    octaves: u8,
+    // Held notes in the order they were played
+    held: Vec<(u8, u8)>,
+    // Every note a cycle visits, rebuilt when `held` changes
+    sequence: Vec<(u8, u8)>,
+    step: usize,
+    sounding: Option<u8>,
+    release_at: f64,
+    next_step: f64,
+    clock: f64,
+    sample_rate: f64,
+    seed: u32,
+}
+
+impl Arpeggiator {
+    pub fn new(mode: ArpMode, rate: f64, gate: f64, octaves: u8) -> Self {
+        Self {
+            mode,
+            rate: rate.max(1e-3),
+            gate: gate.clamp(0.05, 1.0),
+            octaves: octaves.max(1),
+            held: Vec::with_capacity(MAX_NOTE as usize + 1),
+            sequence: Vec::with_capacity(4 * (MAX_NOTE as usize + 1)),
+            step: 0,
+            sounding: None,
+            release_at: 0.0,
+            next_step: 0.0,
+            clock: 0.0,
+            sample_rate: 44_100.0,
+            seed: 0x6C07_8965,
+        }
+    }
+
+    fn rebuild(&mut self) {
+        self.sequence.clear();
+        for octave in 0..self.octaves {
+            for (note, velocity) in &self.held {
+                let note = *note as u16 + 12 * octave as u16;
+                if note <= MAX_NOTE as u16 {
+                    self.sequence.push((note as u8, *velocity));
+                }
+            }
+        }
+
+        match self.mode {
+            ArpMode::Up | ArpMode::Random => {
+                self.sequence.sort_by_key(|(note, _)| *note)
+            }
+            ArpMode::Down => self
+                .sequence
+                .sort_by_key(|(note, _)| std::cmp::Reverse(*note)),
+            ArpMode::Order => {}
+        }
+    }
+
+    fn release(&mut self, emit: &mut dyn FnMut(NoteEvent)) {
+        if let Some(note) = self.sounding.take() {
+            emit(NoteEvent::NoteOff { note });
+        }
+    }
+}
+
+impl MidiProcessor for Arpeggiator {
+    fn reset(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+    }
+
+    fn note(&mut self, event: NoteEvent, emit: &mut dyn FnMut(NoteEvent)) {
+        match event {
+            NoteEvent::NoteOn { note, velocity } => {
+                // The first note starts the pattern right away
+                if self.held.is_empty() {
+                    self.step = 0;
+                    self.next_step = self.clock;
+                }
+                self.held.retain(|(held, _)| *held != note);
+                self.held.push((note, velocity));
+            }
+            NoteEvent::NoteOff { note } => {
+                self.held.retain(|(held, _)| *held != note);
+                if self.held.is_empty() {
+                    self.release(emit);
+                }
+            }
+        }
+        self.rebuild();
+    }
+
+    fn tick(
+        &mut self,
+        beat: Option<f64>,
+        tempo: f64,
+        emit: &mut dyn FnMut(NoteEvent),
+    ) {
+        let previous = self.clock;
+        self.clock = match beat {
+            Some(beat) => beat,
+            None => self.clock + tempo / 60.0 / self.sample_rate,
+        };
+        // The transport started or was rewound
+        if self.clock < previous {
+            self.next_step = self.clock;
+            self.release_at = self.clock;
+        }
+
+        if self.sounding.is_some() && self.clock >= self.release_at {
+            self.release(emit);
+        }
+
+        if self.sequence.is_empty() || self.clock < self.next_step {
+            return;
+        }
+
+        let index = match self.mode {
+            ArpMode::Random => {
+                self.seed ^= self.seed << 13;
+                self.seed ^= self.seed >> 17;
+                self.seed ^= self.seed << 5;
+                self.seed as usize % self.sequence.len()
+            }
+            _ => self.step % self.sequence.len(),
+        };
+        let (note, velocity) = self.sequence[index];
+        self.step += 1;
+
+        self.release(emit);
+        emit(NoteEvent::NoteOn { note, velocity });
+        self.sounding = Some(note);
+        self.release_at = self.clock + self.rate * self.gate;
+        // Later steps land on the grid
+        self.next_step = ((self.clock / self.rate).floor() + 1.0) * self.rate;
+    }
+
+    fn all_notes_off(&mut self, emit: &mut dyn FnMut(NoteEvent)) {
+        self.held.clear();
+        self.sequence.clear();
+        self.release(emit);
+    }
+}
+
+/// Turns single notes into chords of the selected scale. Notes outside the
+/// scale are moved down onto it first
+pub struct ChordGenerator {
+    root: u8,
+    scale: Scale,
+    shape: ChordShape,
+    // Played notes with the chord each one started, one entry per note
+    held: Vec<(u8, [u8; MAX_CHORD])>,
+    // How many held chords share each note, which stops once none does
+    sounding: [u8; MAX_NOTE as usize + 1],
+}
+
+impl ChordGenerator {
+    pub fn new(root: u8, scale: Scale, shape: ChordShape) -> Self {
+        Self {
+            root: root % 12,
+            scale,
+            shape,
+            held: Vec::with_capacity(MAX_NOTE as usize + 1),
+            sounding: [0; MAX_NOTE as usize + 1],
+        }
+    }
+
+    fn release(
+        &mut self,
+        chord: [u8; MAX_CHORD],
+        emit: &mut dyn FnMut(NoteEvent),
+    ) {
+        for note in chord.iter().filter(|note| **note <= MAX_NOTE) {
+            let count = &mut self.sounding[*note as usize];
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                emit(NoteEvent::NoteOff { note: *note });
+            }
+        }
+    }
+
+    fn chord(&self, note: u8) -> [u8; MAX_CHORD] {
+        let degrees = self.scale.degrees();
+        let relative = note as i32 - self.root as i32;
+        let octave = relative.div_euclid(12);
+        let pitch_class = relative.rem_euclid(12) as u8;
+        let degree = degrees
+            .iter()
+            .rposition(|degree| *degree <= pitch_class)
+            .unwrap_or(0);
+
+        let mut chord = [u8::MAX; MAX_CHORD];
+        let steps = self.shape.steps(degrees.len());
+        for (slot, step) in chord.iter_mut().zip(&steps).take(self.shape.len())
+        {
+            let index = degree + step;
+            let octaves = octave + (index / degrees.len()) as i32;
+            let note = self.root as i32
+                + 12 * octaves
+                + degrees[index % degrees.len()] as i32;
+            if (0..=MAX_NOTE as i32).contains(&note) {
+                *slot = note as u8;
+            }
+        }
+
+        chord
+    }
+}
+
+impl MidiProcessor for ChordGenerator {
+    fn reset(&mut self, _sample_rate: f64) {}
+
+    fn note(&mut self, event: NoteEvent, emit: &mut dyn FnMut(NoteEvent)) {
+        match event {
+            NoteEvent::NoteOn { note, velocity } => {
+                // A note played again without a release replaces its chord
+                self.note(NoteEvent::NoteOff { note }, emit);
+
+                let chord = self.chord(note);
+                for tone in chord.iter().filter(|tone| **tone <= MAX_NOTE) {
+                    let count = &mut self.sounding[*tone as usize];
+                    *count += 1;
+                    if *count == 1 {
+                        emit(NoteEvent::NoteOn {
+                            note: *tone,
+                            velocity,
+                        });
+                    }
+                }
+                self.held.push((note, chord));
+            }
+            NoteEvent::NoteOff { note } => {
+                if let Some(index) =
+                    self.held.iter().position(|(held, _)| *held == note)
+                {
+                    let (_, chord) = self.held.remove(index);
+                    self.release(chord, emit);
+                }
+            }
+        }
+    }
+
+    fn all_notes_off(&mut self, emit: &mut dyn FnMut(NoteEvent)) {
+        while let Some((_, chord)) = self.held.pop() {
+            self.release(chord, emit);
+        }
+    }
+}
@@ -0,0 +1,792 @@
+//! Realtime audio engine.
+//!
+//! The engine owns the output stream on its own thread. The UI only talks to
+//! it through [`EngineCommand`]s, so the audio callback never waits on state
+//! that the UI is holding. A change of latency reopens the stream around the
+//! same mixer, so nothing that's playing is lost.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use super::midi::{NoteEvent, MAX_NOTE};
+
+pub mod automation;
+pub mod effects;
+pub mod granular;
+pub mod instrument;
+pub mod midi_effects;
+pub mod modulation;
+pub mod patch;
+pub mod sample;
+pub mod sampler;
+pub mod sequencer;
+pub mod transcription;
+pub mod transport;
+pub mod tuning;
+pub mod wavetable;
+
+use automation::LanePlayer;
+use effects::{chain::EffectChain, Effect, Param, ParamSpec};
+use instrument::Instrument;
+use midi_effects::MidiChain;
+use modulation::ModulationPlayer;
+use sequencer::StepSequencer;
+use transport::{ClipPlayer, Transport};
+use tuning::NoteTable;
+
+pub enum EngineCommand {
+    Note {
+        channel: usize,
+        event: NoteEvent,
+    },
+    SetInstrument {
+        channel: usize,
+        instrument: Box<dyn Instrument>,
+    },
+    // Built from the clip on the UI thread, so the callback doesn't
+    // allocate
+    SetClip {
+        channel: usize,
+        clip: Option<ClipPlayer>,
+    },
+    SetSequencer {
+        channel: usize,
+        sequencer: Option<StepSequencer>,
+    },
+    // Inserts are addressed by their slot id, `index` is a chain position
+    InsertEffect {
+        channel: usize,
+        index: usize,
+        id: u64,
+        effect: Box<dyn Effect>,
+        is_bypassed: bool,
+    },
+    ReplaceEffect {
+        channel: usize,
+        id: u64,
+        effect: Box<dyn Effect>,
+    },
+    RemoveEffect {
+        channel: usize,
+        id: u64,
+    },
+    MoveEffect {
+        channel: usize,
+        id: u64,
+        index: usize,
+    },
+    BypassEffect {
+        channel: usize,
+        id: u64,
+        is_bypassed: bool,
+    },
+    ClearEffects {
+        channel: usize,
+    },
+    SetAutomation {
+        channel: usize,
+        lanes: Vec<LanePlayer>,
+    },
+    SetModulation {
+        channel: usize,
+        modulation: ModulationPlayer,
+    },
+    SetMidiEffects {
+        channel: usize,
+        effects: MidiChain,
+    },
+    SetVolume {
+        channel: usize,
+        volume: Param,
+    },
+    // Boxed, a table is much bigger than the other commands
+    SetTuning(Box<NoteTable>),
+    // Drops the channels from this one on, like when a project with fewer
+    // tracks is opened
+    TruncateChannels(usize),
+    Play,
+    Stop,
+    SetTempo(f64),
+}
+
+/// A channel's fader in dB, applied after its inserts
+pub const VOLUME: ParamSpec = ParamSpec::live("Volume", -12.0, 12.0, 0.0);
+
+pub fn db_to_gain(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+// Used until the output device reports its own
+const DEFAULT_SAMPLE_RATE: f64 = 44_100.0;
+// Channels beyond this still work, but the first command to reach one
+// allocates on the audio thread
+const RESERVED_CHANNELS: usize = 32;
+
+/// Whatever the audio thread lets go of. It's sent to another thread to be
+/// dropped there, so the callback never spends time deallocating
+pub enum Garbage {
+    Effect(Box<dyn Effect>),
+    Instrument(Box<dyn Instrument>),
+    Clip(ClipPlayer),
+    Sequencer(StepSequencer),
+    Automation(Vec<LanePlayer>),
+    Modulation(ModulationPlayer),
+    MidiEffects(MidiChain),
+    Tuning(Box<NoteTable>),
+    Param(Param),
+}
+
+pub struct Engine {
+    commands: Sender<EngineCommand>,
+    // Latencies in milliseconds, for the stream to reopen with
+    latency: Sender<f32>,
+    sample_rate: f64,
+    // Transport position in beats, updated once per audio buffer
+    playhead: Arc<AtomicU64>,
+}
+
+impl Engine {
+    /// Opens the default output with buffers of about `latency`
+    /// milliseconds
+    pub fn start(latency: f32) -> Self {
+        let (commands, receiver) = mpsc::channel();
+        let (latency_sender, latencies) = mpsc::channel();
+
+        let (garbage, collector) = mpsc::channel::<Garbage>();
+        thread::spawn(move || for _ in collector {});
+
+        let (rate_sender, rate) = mpsc::channel();
+        let playhead = Arc::new(AtomicU64::new(0));
+        let mixer_playhead = Arc::clone(&playhead);
+
+        thread::spawn(move || {
+            if let Err(err) = run(
+                receiver,
+                garbage,
+                mixer_playhead,
+                rate_sender,
+                latency,
+                latencies,
+            ) {
+                eprintln!("audio engine stopped: {}", err);
+            }
+        });
+
+        // Effects that prepare sample data up front (like convolution
+        // kernels) are built for the device rate, so wait for it
+        let sample_rate = rate.recv().unwrap_or(DEFAULT_SAMPLE_RATE);
+
+        Self {
+            commands,
+            latency: latency_sender,
+            sample_rate,
+            playhead,
+        }
+    }
+
+    pub fn set_latency(&self, latency: f32) {
+        let _ = self.latency.send(latency);
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    pub fn beat(&self) -> f64 {
+        f64::from_bits(self.playhead.load(Ordering::Relaxed))
+    }
+
+    pub fn send(&self, command: EngineCommand) {
+        // Sending only fails when the engine thread could not open a device,
+        // which has already been reported
+        let _ = self.commands.send(command);
+    }
+
+    pub fn note(&self, channel: usize, event: NoteEvent) {
+        self.send(EngineCommand::Note { channel, event });
+    }
+}
+
+fn run(
+    receiver: Receiver<EngineCommand>,
+    garbage: Sender<Garbage>,
+    playhead: Arc<AtomicU64>,
+    sample_rate: Sender<f64>,
+    mut latency: f32,
+    latencies: Receiver<f32>,
+) -> Result<(), anyhow::Error> {
+    let host = cpal::default_host();
+
+    let device = host
+        .default_output_device()
+        .ok_or_else(|| anyhow::anyhow!("failed to find an output device"))?;
+    let supported = device.default_output_config()?;
+    let rate = supported.sample_rate().0 as f64;
+    let _ = sample_rate.send(rate);
+
+    // Only ever locked by one stream at a time, as the old one is dropped
+    // before the next opens
+    let mixer =
+        Arc::new(Mutex::new(Mixer::new(rate, receiver, garbage, playhead)));
+    let mut config: cpal::StreamConfig = supported.config();
+
+    loop {
+        config.buffer_size =
+            buffer_size(latency, rate, supported.buffer_size());
+        let stream = match supported.sample_format() {
+            cpal::SampleFormat::F32 => open::<f32>(&device, &config, &mixer),
+            cpal::SampleFormat::I16 => open::<i16>(&device, &config, &mixer),
+            cpal::SampleFormat::U16 => open::<u16>(&device, &config, &mixer),
+        }?;
+        stream.play()?;
+
+        // Keep the stream alive until the latency changes, or for as long
+        // as the app runs
+        latency = match latencies.recv() {
+            Ok(latency) => latency,
+            Err(_) => return Ok(()),
+        };
+        drop(stream);
+    }
+}
+
+// Frames per buffer for a latency in milliseconds, within what the device
+// takes
+fn buffer_size(
+    latency: f32,
+    sample_rate: f64,
+    supported: &cpal::SupportedBufferSize,
+) -> cpal::BufferSize {
+    match supported {
+        cpal::SupportedBufferSize::Range { min, max } => {
+            let frames = (latency as f64 / 1000.0 * sample_rate).round() as u32;
+            cpal::BufferSize::Fixed(frames.clamp(*min, *max))
+        }
+        cpal::SupportedBufferSize::Unknown => cpal::BufferSize::Default,
+    }
+}
+
+fn open<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mixer: &Arc<Mutex<Mixer>>,
+) -> Result<cpal::Stream, anyhow::Error>
+where
+    T: cpal::Sample,
+{
+    let channels = config.channels as usize;
+    let mixer = Arc::clone(mixer);
+
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| match mixer
+            .try_lock()
+        {
+            Ok(mut mixer) => mixer.process(data, channels),
+            Err(_) => {
+                for sample in data {
+                    *sample = cpal::Sample::from::<f32>(&0.0);
+                }
+            }
+        },
+        super::err_fn,
+    )?;
+
+    Ok(stream)
+}
+
+// Where notes end up once the MIDI effects are through with them
+fn play_note(
+    instrument: &mut InstrumentSlot,
+    modulation: &mut Option<ModulationPlayer>,
+    event: NoteEvent,
+) {
+    instrument.note(event);
+    if let Some(modulation) = modulation.as_mut() {
+        modulation.note(event);
+    }
+}
+
+// How long a replaced instrument takes to fade out
+const INSTRUMENT_FADE_SECONDS: f64 = 0.01;
+
+/// The instrument of a channel. A new one takes over the notes held on the
+/// one it replaces, which crossfades into it and is then sent off as garbage
+struct InstrumentSlot {
+    current: Option<Box<dyn Instrument>>,
+    fading: Option<Box<dyn Instrument>>,
+    // Gain of the fading instrument and how much it drops per frame
+    fade: f64,
+    fade_step: f64,
+    // Velocity of every held note, 0 when released
+    held: [u8; MAX_NOTE as usize + 1],
+}
+
+impl InstrumentSlot {
+    fn new() -> Self {
+        Self {
+            current: None,
+            fading: None,
+            fade: 0.0,
+            fade_step: 0.0,
+            held: [0; MAX_NOTE as usize + 1],
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.current.is_none() && self.fading.is_none()
+    }
+
+    fn note(&mut self, event: NoteEvent) {
+        let (note, velocity) = match event {
+            NoteEvent::NoteOn { note, velocity } => (note, velocity.max(1)),
+            NoteEvent::NoteOff { note } => (note, 0),
+        };
+        if let Some(held) = self.held.get_mut(note as usize) {
+            *held = velocity;
+        }
+
+        if let Some(current) = self.current.as_mut() {
+            current.note(event);
+        }
+    }
+
+    fn all_notes_off(&mut self) {
+        self.held = [0; MAX_NOTE as usize + 1];
+        for instrument in self.current.iter_mut().chain(self.fading.iter_mut())
+        {
+            instrument.all_notes_off();
+        }
+    }
+
+    fn set_tuning(&mut self, table: &NoteTable) {
+        for instrument in self.current.iter_mut().chain(self.fading.iter_mut())
+        {
+            instrument.set_tuning(table);
+        }
+    }
+
+    // The instrument is expected to be reset already
+    fn replace(
+        &mut self,
+        mut instrument: Box<dyn Instrument>,
+        sample_rate: f64,
+        garbage: &Sender<Garbage>,
+    ) {
+        for (note, velocity) in self.held.iter().enumerate() {
+            if *velocity > 0 {
+                instrument.note(NoteEvent::NoteOn {
+                    note: note as u8,
+                    velocity: *velocity,
+                });
+            }
+        }
+
+        if let Some(previous) = self.current.replace(instrument) {
+            // Swapping again mid-fade cuts the oldest one off
+            if let Some(older) = self.fading.replace(previous) {
+                let _ = garbage.send(Garbage::Instrument(older));
+            }
+            self.fade = 1.0;
+            self.fade_step = 1.0 / (INSTRUMENT_FADE_SECONDS * sample_rate);
+        }
+    }
+
+    fn clear(&mut self, garbage: &Sender<Garbage>) {
+        for instrument in
+            self.current.take().into_iter().chain(self.fading.take())
+        {
+            let _ = garbage.send(Garbage::Instrument(instrument));
+        }
+        self.held = [0; MAX_NOTE as usize + 1];
+    }
+
+    fn tick(&mut self, garbage: &Sender<Garbage>) -> (f64, f64) {
+        let (mut left, mut right) = self
+            .current
+            .as_mut()
+            .map_or((0.0, 0.0), |current| current.tick());
+
+        if let Some(fading) = self.fading.as_mut() {
+            let (l, r) = fading.tick();
+            let fade = self.fade.max(0.0);
+            left = left * (1.0 - fade) + l * fade;
+            right = right * (1.0 - fade) + r * fade;
+
+            self.fade -= self.fade_step;
+            if self.fade <= 0.0 {
+                if let Some(fading) = self.fading.take() {
+                    let _ = garbage.send(Garbage::Instrument(fading));
+                }
+            }
+        }
+
+        (left, right)
+    }
+}
+
+struct Channel {
+    instrument: InstrumentSlot,
+    // Notes pass through these before reaching the instrument
+    midi_effects: MidiChain,
+    clip: Option<ClipPlayer>,
+    sequencer: Option<StepSequencer>,
+    inserts: EffectChain,
+    automation: Vec<LanePlayer>,
+    modulation: Option<ModulationPlayer>,
+    // Unity gain until the track's fader arrives
+    volume: Option<Param>,
+}
+
+impl Channel {
+    fn new(sample_rate: f64, garbage: Sender<Garbage>) -> Self {
+        Self {
+            instrument: InstrumentSlot::new(),
+            midi_effects: MidiChain::default(),
+            clip: None,
+            sequencer: None,
+            inserts: EffectChain::new(sample_rate, garbage),
+            automation: vec![],
+            modulation: None,
+            volume: None,
+        }
+    }
+
+    // Empties the channel, keeping what it allocated up front
+    fn clear(&mut self, garbage: &Sender<Garbage>) {
+        let discard = |item| {
+            let _ = garbage.send(item);
+        };
+
+        self.instrument.clear(garbage);
+        discard(Garbage::MidiEffects(std::mem::take(&mut self.midi_effects)));
+        if let Some(clip) = self.clip.take() {
+            discard(Garbage::Clip(clip));
+        }
+        if let Some(sequencer) = self.sequencer.take() {
+            discard(Garbage::Sequencer(sequencer));
+        }
+        self.inserts.drain();
+        discard(Garbage::Automation(std::mem::take(&mut self.automation)));
+        if let Some(modulation) = self.modulation.take() {
+            modulation.clear();
+            discard(Garbage::Modulation(modulation));
+        }
+        if let Some(volume) = self.volume.take() {
+            discard(Garbage::Param(volume));
+        }
+    }
+}
+
+/// Engine-side state, only ever touched from the audio callback
+struct Mixer {
+    sample_rate: f64,
+    receiver: Receiver<EngineCommand>,
+    garbage: Sender<Garbage>,
+    channels: Vec<Channel>,
+    transport: Transport,
+    tuning: Box<NoteTable>,
+    playhead: Arc<AtomicU64>,
+}
+
+impl Mixer {
+    fn new(
+        sample_rate: f64,
+        receiver: Receiver<EngineCommand>,
+        garbage: Sender<Garbage>,
+        playhead: Arc<AtomicU64>,
+    ) -> Self {
+        let mut channels = Vec::with_capacity(RESERVED_CHANNELS);
+        channels.resize_with(RESERVED_CHANNELS, || {
+            Channel::new(sample_rate, garbage.clone())
+        });
+
+        Self {
+            sample_rate,
+            receiver,
+            garbage,
+            channels,
+            transport: Transport::new(sample_rate),
+            tuning: Box::default(),
+            playhead,
+        }
+    }
+
+    fn channel_mut(&mut self, channel: usize) -> &mut Channel {
+        if channel >= self.channels.len() {
+            let sample_rate = self.sample_rate;
+            let garbage = &self.garbage;
+
+            self.channels.resize_with(channel + 1, || {
+                Channel::new(sample_rate, garbage.clone())
+            });
+        }
+
+        &mut self.channels[channel]
+    }
+
+    fn discard(&self, garbage: Garbage) {
+        let _ = self.garbage.send(garbage);
+    }
+
+    fn handle_commands(&mut self) {
+        while let Ok(command) = self.receiver.try_recv() {
+            match command {
+                EngineCommand::Note { channel, event } => {
+                    if let Some(channel) = self.channels.get_mut(channel) {
+                        let Channel {
+                            instrument,
+                            midi_effects,
+                            modulation,
+                            ..
+                        } = channel;
+                        midi_effects.note(event, &mut |event| {
+                            play_note(instrument, modulation, event)
+                        });
+                    }
+                }
+                EngineCommand::SetInstrument {
+                    channel,
+                    mut instrument,
+                } => {
+                    instrument.reset(self.sample_rate);
+                    instrument.set_tuning(&self.tuning);
+                    self.channel_mut(channel);
+                    self.channels[channel].instrument.replace(
+                        instrument,
+                        self.sample_rate,
+                        &self.garbage,
+                    );
+                }
+                EngineCommand::SetClip { channel, clip } => {
+                    let channel = self.channel_mut(channel);
+                    channel.instrument.all_notes_off();
+                    let previous = std::mem::replace(&mut channel.clip, clip);
+                    if let Some(previous) = previous {
+                        self.discard(Garbage::Clip(previous));
+                    }
+                }
+                EngineCommand::SetSequencer { channel, sequencer } => {
+                    let channel = self.channel_mut(channel);
+                    let previous =
+                        std::mem::replace(&mut channel.sequencer, sequencer);
+                    if let Some(previous) = previous {
+                        self.discard(Garbage::Sequencer(previous));
+                    }
+                }
+                EngineCommand::InsertEffect {
+                    channel,
+                    index,
+                    id,
+                    mut effect,
+                    is_bypassed,
+                } => {
+                    effect.reset(self.sample_rate);
+                    self.channel_mut(channel).inserts.insert(
+                        index,
+                        id,
+                        effect,
+                        is_bypassed,
+                    );
+                }
+                EngineCommand::ReplaceEffect {
+                    channel,
+                    id,
+                    mut effect,
+                } => {
+                    effect.reset(self.sample_rate);
+                    self.channel_mut(channel).inserts.replace(id, effect);
+                }
+                EngineCommand::RemoveEffect { channel, id } => {
+                    self.channel_mut(channel).inserts.remove(id);
+                }
+                EngineCommand::MoveEffect { channel, id, index } => {
+                    self.channel_mut(channel).inserts.move_to(id, index);
+                }
+                EngineCommand::BypassEffect {
+                    channel,
+                    id,
+                    is_bypassed,
+                } => {
+                    self.channel_mut(channel)
+                        .inserts
+                        .set_bypass(id, is_bypassed);
+                }
+                EngineCommand::ClearEffects { channel } => {
+                    self.channel_mut(channel).inserts.clear();
+                }
+                EngineCommand::SetAutomation { channel, lanes } => {
+                    let channel = self.channel_mut(channel);
+                    let previous =
+                        std::mem::replace(&mut channel.automation, lanes);
+                    self.discard(Garbage::Automation(previous));
+                }
+                EngineCommand::SetModulation {
+                    channel,
+                    mut modulation,
+                } => {
+                    modulation.reset(self.sample_rate);
+                    let channel = self.channel_mut(channel);
+                    // Parameters the new matrix no longer routes go back to
+                    // their set values
+                    let previous = channel.modulation.replace(modulation);
+                    if let Some(previous) = previous {
+                        previous.clear();
+                        self.discard(Garbage::Modulation(previous));
+                    }
+                }
+                EngineCommand::SetMidiEffects {
+                    channel,
+                    mut effects,
+                } => {
+                    effects.reset(self.sample_rate);
+                    let Channel {
+                        instrument,
+                        midi_effects,
+                        modulation,
+                        ..
+                    } = self.channel_mut(channel);
+                    // Notes the old chain holds would otherwise hang
+                    midi_effects.all_notes_off(&mut |event| {
+                        play_note(instrument, modulation, event)
+                    });
+                    let previous = std::mem::replace(midi_effects, effects);
+                    self.discard(Garbage::MidiEffects(previous));
+                }
+                EngineCommand::SetVolume { channel, volume } => {
+                    let channel = self.channel_mut(channel);
+                    if let Some(previous) = channel.volume.replace(volume) {
+                        self.discard(Garbage::Param(previous));
+                    }
+                }
+                EngineCommand::SetTuning(tuning) => {
+                    for channel in &mut self.channels {
+                        channel.instrument.set_tuning(&tuning);
+                    }
+                    let previous = std::mem::replace(&mut self.tuning, tuning);
+                    self.discard(Garbage::Tuning(previous));
+                }
+                // The channels stay allocated for the tracks to come
+                EngineCommand::TruncateChannels(count) => {
+                    let count = count.min(self.channels.len());
+                    for channel in &mut self.channels[count..] {
+                        channel.clear(&self.garbage);
+                    }
+                }
+                EngineCommand::Play => self.transport.play(),
+                EngineCommand::Stop => {
+                    self.transport.stop();
+                    for channel in &mut self.channels {
+                        let Channel {
+                            instrument,
+                            midi_effects,
+                            modulation,
+                            ..
+                        } = channel;
+                        midi_effects.all_notes_off(&mut |event| {
+                            play_note(instrument, modulation, event)
+                        });
+                        channel.instrument.all_notes_off();
+                        if let Some(modulation) = channel.modulation.as_mut() {
+                            modulation.all_notes_off();
+                        }
+                    }
+                }
+                EngineCommand::SetTempo(tempo) => self.transport.tempo = tempo,
+            }
+        }
+    }
+
+    fn tick(&mut self) -> (f64, f64) {
+        let mut left = 0.0;
+        let mut right = 0.0;
+
+        let beats = self.transport.advance();
+        let tempo = self.transport.tempo;
+
+        let garbage = &self.garbage;
+        for channel in &mut self.channels {
+            let Channel {
+                instrument,
+                midi_effects,
+                clip,
+                sequencer,
+                inserts,
+                automation,
+                modulation,
+                volume,
+            } = channel;
+
+            if instrument.is_empty() {
+                continue;
+            }
+
+            if let Some((from, to)) = beats {
+                let mut note = |event: NoteEvent| {
+                    midi_effects.note(event, &mut |event| {
+                        play_note(instrument, modulation, event)
+                    });
+                };
+                if let Some(clip) = clip.as_mut() {
+                    clip.advance(from, to, &mut note);
+                }
+                if let Some(sequencer) = sequencer.as_mut() {
+                    sequencer.advance(from, to, &mut note);
+                }
+                // Every frame, so parameter moves are sample accurate
+                for lane in automation.iter() {
+                    lane.apply(from);
+                }
+            }
+            midi_effects.tick(
+                beats.map(|(from, _)| from),
+                tempo,
+                &mut |event| play_note(instrument, modulation, event),
+            );
+
+            let output = instrument.tick(garbage);
+            // Moves land on the inserts right away and on the instrument
+            // from the next frame
+            if let Some(modulation) = modulation.as_mut() {
+                modulation.tick(output, beats.map(|(from, _)| from), tempo);
+            }
+
+            let (l, r) = inserts.tick(output);
+            let gain = volume
+                .as_ref()
+                .map_or(1.0, |volume| db_to_gain(volume.get()));
+            left += l * gain;
+            right += r * gain;
+        }
+
+        (left, right)
+    }
+
+    fn process<T>(&mut self, output: &mut [T], channels: usize)
+    where
+        T: cpal::Sample,
+    {
+        self.handle_commands();
+
+        for frame in output.chunks_mut(channels) {
+            let (left, right) = self.tick();
+            let left: T = cpal::Sample::from::<f32>(&(left as f32));
+            let right: T = cpal::Sample::from::<f32>(&(right as f32));
+
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                if channel & 1 == 0 {
+                    *sample = left;
+                } else {
+                    *sample = right;
+                }
+            }
+        }
+
+        self.playhead
+            .store(self.transport.beat().to_bits(), Ordering::Relaxed);
+    }
+}
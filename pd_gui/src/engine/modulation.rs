@@ -0,0 +1,380 @@
+//! Modulation matrix.
+//!
+//! Sources (LFOs, envelopes and envelope followers) are routed to instrument
+//! and insert parameters with a bipolar depth. Offsets are normalized like
+//! automation, and are added on top of whatever value the parameter was set
+//! to, so controls and lanes keep working on modulated parameters.
+
+use serde::{Deserialize, Serialize};
+
+use super::automation::Target;
+use super::effects::Param;
+use super::instrument::{Adsr, Envelope};
+use crate::app::midi::NoteEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    Saw,
+    Square,
+    SampleAndHold,
+}
+
+impl LfoShape {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LfoShape::Sine => "Sine",
+            LfoShape::Triangle => "Triangle",
+            LfoShape::Saw => "Saw",
+            LfoShape::Square => "Square",
+            LfoShape::SampleAndHold => "S&H",
+        }
+    }
+
+    /// The next shape, for editors that cycle through them
+    pub fn next(&self) -> LfoShape {
+        match self {
+            LfoShape::Sine => LfoShape::Triangle,
+            LfoShape::Triangle => LfoShape::Saw,
+            LfoShape::Saw => LfoShape::Square,
+            LfoShape::Square => LfoShape::SampleAndHold,
+            LfoShape::SampleAndHold => LfoShape::Sine,
+        }
+    }
+
+    // Bipolar output at a phase between 0 and 1. Sample and hold is handled
+    // by the player, since it needs memory
+    fn at(&self, phase: f64) -> f64 {
+        match self {
+            LfoShape::Sine => (phase * std::f64::consts::TAU).sin(),
+            LfoShape::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            LfoShape::Saw => 2.0 * phase - 1.0,
+            LfoShape::Square | LfoShape::SampleAndHold => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Source {
+    // Rate in Hz, unless synced to a period in beats
+    Lfo {
+        shape: LfoShape,
+        rate: f64,
+        sync: Option<f64>,
+    },
+    // Retriggered by the track's notes, unipolar
+    Envelope(Adsr),
+    // Level of the track's instrument, times in milliseconds
+    Follower {
+        attack: f64,
+        release: f64,
+    },
+}
+
+impl Source {
+    pub const LFO: Source = Source::Lfo {
+        shape: LfoShape::Sine,
+        rate: 1.0,
+        sync: None,
+    };
+    pub const ENVELOPE: Source = Source::Envelope(Adsr {
+        attack: 0.01,
+        decay: 0.3,
+        sustain: 0.5,
+        release: 0.5,
+    });
+    pub const FOLLOWER: Source = Source::Follower {
+        attack: 10.0,
+        release: 150.0,
+    };
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Source::Lfo { .. } => "LFO",
+            Source::Envelope(_) => "Envelope",
+            Source::Follower { .. } => "Follower",
+        }
+    }
+}
+
+/// One source moving one parameter
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Route {
+    pub source: usize,
+    pub target: Target,
+    // Between -1 and 1, in normalized units of the target
+    pub depth: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Matrix {
+    pub sources: Vec<Source>,
+    pub routes: Vec<Route>,
+}
+
+impl Matrix {
+    pub fn depth(&self, source: usize, target: Target) -> f64 {
+        self.routes
+            .iter()
+            .find(|route| route.source == source && route.target == target)
+            .map_or(0.0, |route| route.depth)
+    }
+
+    /// Sets the depth of a route, removing it when the depth is close to 0
+    pub fn set_depth(&mut self, source: usize, target: Target, depth: f64) {
+        self.routes
+            .retain(|route| route.source != source || route.target != target);
+
+        if depth.abs() > 1e-3 {
+            self.routes.push(Route {
+                source,
+                target,
+                depth: depth.clamp(-1.0, 1.0),
+            });
+        }
+    }
+
+    /// Removes a source and its routes, keeping the other routes on their
+    /// sources
+    pub fn remove_source(&mut self, source: usize) {
+        if source >= self.sources.len() {
+            return;
+        }
+
+        self.sources.remove(source);
+        self.routes.retain(|route| route.source != source);
+        for route in &mut self.routes {
+            if route.source > source {
+                route.source -= 1;
+            }
+        }
+    }
+
+    /// Drops the routes to targets that are gone
+    pub fn retain_targets(&mut self, mut keep: impl FnMut(Target) -> bool) {
+        self.routes.retain(|route| keep(route.target));
+    }
+}
+
+enum SourceState {
+    Lfo {
+        shape: LfoShape,
+        rate: f64,
+        sync: Option<f64>,
+        phase: f64,
+        held: f64,
+        seed: u32,
+    },
+    Envelope {
+        envelope: Envelope,
+        notes_held: usize,
+    },
+    Follower {
+        attack: f64,
+        release: f64,
+        level: f64,
+    },
+}
+
+impl SourceState {
+    fn new(source: &Source) -> Self {
+        match *source {
+            Source::Lfo { shape, rate, sync } => SourceState::Lfo {
+                shape,
+                rate,
+                sync,
+                phase: 0.0,
+                held: 0.0,
+                seed: 0x9E37_79B9,
+            },
+            Source::Envelope(adsr) => SourceState::Envelope {
+                envelope: Envelope::new(adsr),
+                notes_held: 0,
+            },
+            Source::Follower { attack, release } => SourceState::Follower {
+                attack,
+                release,
+                level: 0.0,
+            },
+        }
+    }
+}
+
+// Per-sample smoothing factor reaching about 63% of a step after `ms`
+fn coefficient(ms: f64, sample_rate: f64) -> f64 {
+    1.0 - (-1.0 / (ms / 1_000.0 * sample_rate).max(1.0)).exp()
+}
+
+/// A track's matrix as the engine plays it, moving parameters every frame
+pub struct ModulationPlayer {
+    sources: Vec<SourceState>,
+    values: Vec<f64>,
+    // Every modulated parameter with the sources and depths routed to it
+    targets: Vec<(Param, Vec<(usize, f64)>)>,
+    sample_rate: f64,
+}
+
+impl ModulationPlayer {
+    /// Routes whose source or target can't be found are left out
+    pub fn new(
+        matrix: &Matrix,
+        resolve: impl Fn(Target) -> Option<Param>,
+    ) -> Self {
+        let mut targets: Vec<(Target, Param, Vec<(usize, f64)>)> = vec![];
+        for route in &matrix.routes {
+            if route.source >= matrix.sources.len() {
+                continue;
+            }
+
+            match targets
+                .iter_mut()
+                .find(|(target, ..)| *target == route.target)
+            {
+                Some((.., routes)) => routes.push((route.source, route.depth)),
+                None => {
+                    if let Some(param) = resolve(route.target) {
+                        targets.push((
+                            route.target,
+                            param,
+                            vec![(route.source, route.depth)],
+                        ));
+                    }
+                }
+            }
+        }
+
+        Self {
+            sources: matrix.sources.iter().map(SourceState::new).collect(),
+            values: vec![0.0; matrix.sources.len()],
+            targets: targets
+                .into_iter()
+                .map(|(_, param, routes)| (param, routes))
+                .collect(),
+            sample_rate: 44_100.0,
+        }
+    }
+
+    pub fn reset(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+        for source in &mut self.sources {
+            if let SourceState::Envelope { envelope, .. } = source {
+                envelope.reset(sample_rate);
+            }
+        }
+    }
+
+    // Envelopes open with the first held note and close with the last
+    pub fn note(&mut self, event: NoteEvent) {
+        for source in &mut self.sources {
+            if let SourceState::Envelope {
+                envelope,
+                notes_held,
+            } = source
+            {
+                match event {
+                    NoteEvent::NoteOn { .. } => {
+                        *notes_held += 1;
+                        envelope.gate_on();
+                    }
+                    NoteEvent::NoteOff { .. } if *notes_held > 0 => {
+                        *notes_held -= 1;
+                        if *notes_held == 0 {
+                            envelope.gate_off();
+                        }
+                    }
+                    NoteEvent::NoteOff { .. } => {}
+                }
+            }
+        }
+    }
+
+    pub fn all_notes_off(&mut self) {
+        for source in &mut self.sources {
+            if let SourceState::Envelope {
+                envelope,
+                notes_held,
+            } = source
+            {
+                *notes_held = 0;
+                envelope.gate_off();
+            }
+        }
+    }
+
+    /// Advances every source by one frame and applies the sums. `beat` is
+    /// the transport position while it runs, which synced LFOs lock to
+    pub fn tick(&mut self, output: (f64, f64), beat: Option<f64>, tempo: f64) {
+        let sample_rate = self.sample_rate;
+
+        for (source, value) in self.sources.iter_mut().zip(&mut self.values) {
+            *value = match source {
+                SourceState::Lfo {
+                    shape,
+                    rate,
+                    sync,
+                    phase,
+                    held,
+                    seed,
+                } => {
+                    let previous = *phase;
+                    *phase = match (*sync, beat) {
+                        (Some(period), Some(beat)) => {
+                            (beat / period.max(1e-3)).fract()
+                        }
+                        (Some(period), None) => {
+                            let rate = tempo / 60.0 / period.max(1e-3);
+                            (*phase + rate / sample_rate).fract()
+                        }
+                        (None, _) => (*phase + *rate / sample_rate).fract(),
+                    };
+
+                    if *shape == LfoShape::SampleAndHold {
+                        // A new step every cycle, from a xorshift generator
+                        if *phase < previous {
+                            *seed ^= *seed << 13;
+                            *seed ^= *seed >> 17;
+                            *seed ^= *seed << 5;
+                            *held = *seed as f64 / u32::MAX as f64 * 2.0 - 1.0;
+                        }
+                        *held
+                    } else {
+                        shape.at(*phase)
+                    }
+                }
+                SourceState::Envelope { envelope, .. } => envelope.tick(),
+                SourceState::Follower {
+                    attack,
+                    release,
+                    level,
+                } => {
+                    let input = output.0.abs().max(output.1.abs());
+                    let time = if input > *level { *attack } else { *release };
+                    *level += (input - *level) * coefficient(time, sample_rate);
+                    (*level).min(1.0)
+                }
+            };
+        }
+
+        for (param, routes) in &self.targets {
+            let offset: f64 = routes
+                .iter()
+                .map(|(source, depth)| self.values[*source] * depth)
+                .sum();
+            param.modulate(offset);
+        }
+    }
+
+    /// Puts every modulated parameter back on its set value
+    pub fn clear(&self) {
+        for (param, _) in &self.targets {
+            param.clear_modulation();
+        }
+    }
+}
@@ -0,0 +1,333 @@
+//! Patches drawn as graphs: units as nodes, with wires from output ports to
+//! input ports.
+//!
+//! Every graph has an input node, giving the frequency and gate of the held
+//! note, and an output node taking the left and right channels. A right
+//! channel left unwired copies the left one.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use fundsp::hacker::*;
+use serde::{Deserialize, Serialize};
+
+use super::{build, default_args};
+
+pub const INPUT: &str = "input";
+pub const OUTPUT: &str = "output";
+
+pub const INPUT_NAMES: [&str; 2] = ["freq", "gate"];
+pub const OUTPUT_NAMES: [&str; 2] = ["L", "R"];
+
+/// A port on a node, counted from 0 on its side
+pub type Port = (u64, usize);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Node {
+    pub id: u64,
+    pub unit: String,
+    pub args: Vec<f64>,
+    // Top left corner in the editor
+    pub position: (f32, f32),
+    pub inputs: usize,
+    pub outputs: usize,
+}
+
+impl Node {
+    pub fn is_fixed(&self) -> bool {
+        self.unit == INPUT || self.unit == OUTPUT
+    }
+
+    pub fn label(&self) -> String {
+        if self.args.is_empty() {
+            self.unit.clone()
+        } else {
+            let args: Vec<String> =
+                self.args.iter().map(|arg| format!("{}", arg)).collect();
+            format!("{}({})", self.unit, args.join(", "))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Wire {
+    pub from: Port,
+    pub to: Port,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphError {
+    pub node: Option<u64>,
+    pub message: String,
+}
+
+impl GraphError {
+    fn new(node: Option<u64>, message: &str) -> Self {
+        Self {
+            node,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub wires: Vec<Wire>,
+    next_id: u64,
+}
+
+impl Default for Graph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        let fixed = |id, unit: &str, x, inputs, outputs| Node {
+            id,
+            unit: unit.to_string(),
+            args: vec![],
+            position: (x, 40.0),
+            inputs,
+            outputs,
+        };
+
+        Self {
+            nodes: vec![
+                fixed(0, INPUT, 20.0, 0, 2),
+                fixed(1, OUTPUT, 420.0, 2, 0),
+            ],
+            wires: vec![],
+            next_id: 2,
+        }
+    }
+
+    pub fn node(&self, id: u64) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.id == id)
+    }
+
+    /// Adds a unit with its default arguments
+    pub fn add_node(
+        &mut self,
+        unit: &str,
+        position: (f32, f32),
+    ) -> Option<u64> {
+        let args = default_args(unit)?.to_vec();
+        let net = build(unit, &args).ok()?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.nodes.push(Node {
+            id,
+            unit: unit.to_string(),
+            args,
+            position,
+            inputs: net.inputs(),
+            outputs: net.outputs(),
+        });
+
+        Some(id)
+    }
+
+    pub fn move_node(&mut self, id: u64, position: (f32, f32)) {
+        if let Some(node) = self.nodes.iter_mut().find(|node| node.id == id) {
+            node.position = position;
+        }
+    }
+
+    /// Removes a unit and its wires. Input and output stay
+    pub fn remove_node(&mut self, id: u64) {
+        self.nodes.retain(|node| node.id != id || node.is_fixed());
+        self.prune();
+    }
+
+    /// Wires an output port to an input port, replacing the wire that fed it
+    pub fn connect(&mut self, from: Port, to: Port) {
+        let valid = |port: Port, outputs: bool| {
+            self.node(port.0).map_or(false, |node| {
+                port.1 < if outputs { node.outputs } else { node.inputs }
+            })
+        };
+        if !valid(from, true) || !valid(to, false) {
+            return;
+        }
+
+        self.wires.retain(|wire| wire.to != to);
+        self.wires.push(Wire { from, to });
+    }
+
+    /// Removes the wire feeding an input port, returning where it came from
+    pub fn disconnect(&mut self, to: Port) -> Option<Port> {
+        let index = self.wires.iter().position(|wire| wire.to == to)?;
+        Some(self.wires.remove(index).from)
+    }
+
+    /// Sets an argument of a unit. Ports that disappear with the change, like
+    /// those of a smaller `split`, lose their wires
+    pub fn set_arg(
+        &mut self,
+        id: u64,
+        index: usize,
+        value: f64,
+    ) -> Result<(), GraphError> {
+        let node = match self.nodes.iter_mut().find(|node| node.id == id) {
+            Some(node) => node,
+            None => return Ok(()),
+        };
+        if index >= node.args.len() {
+            return Ok(());
+        }
+
+        let mut args = node.args.clone();
+        args[index] = value;
+        let net = build(&node.unit, &args)
+            .map_err(|message| GraphError::new(Some(id), &message))?;
+
+        node.args = args;
+        node.inputs = net.inputs();
+        node.outputs = net.outputs();
+        self.prune();
+
+        Ok(())
+    }
+
+    // Drops wires whose ends are gone
+    fn prune(&mut self) {
+        let nodes = &self.nodes;
+        let exists = |(id, port): Port, outputs: bool| {
+            nodes.iter().any(|node| {
+                node.id == id
+                    && port < if outputs { node.outputs } else { node.inputs }
+            })
+        };
+        self.wires
+            .retain(|wire| exists(wire.from, true) && exists(wire.to, false));
+    }
+
+    // Kahn's algorithm, since nets can't have feedback
+    fn check_cycles(&self) -> Result<(), GraphError> {
+        let mut incoming: HashMap<u64, usize> =
+            self.nodes.iter().map(|node| (node.id, 0)).collect();
+        for wire in &self.wires {
+            *incoming.entry(wire.to.0).or_default() += 1;
+        }
+
+        let mut ready: Vec<u64> = incoming
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(id, _)| *id)
+            .collect();
+        let mut visited = 0;
+        while let Some(id) = ready.pop() {
+            visited += 1;
+            for wire in self.wires.iter().filter(|wire| wire.from.0 == id) {
+                let count = incoming.entry(wire.to.0).or_default();
+                *count -= 1;
+                if *count == 0 {
+                    ready.push(wire.to.0);
+                }
+            }
+        }
+
+        if visited < self.nodes.len() {
+            let node = incoming
+                .iter()
+                .find(|(_, count)| **count > 0)
+                .map(|(id, _)| *id);
+            return Err(GraphError::new(
+                node,
+                "wires can't loop back to an earlier unit",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Whether both graphs compile to the same net, wherever their nodes
+    /// are placed
+    pub fn is_same_net(&self, other: &Graph) -> bool {
+        let units = |graph: &Graph| {
+            graph
+                .nodes
+                .iter()
+                .map(|node| (node.id, node.unit.as_str(), node.args.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        // An input port is fed by one wire at most, so equal counts with
+        // every wire found mean the same wiring
+        units(self) == units(other)
+            && self.wires.len() == other.wires.len()
+            && self.wires.iter().all(|wire| other.wires.contains(wire))
+    }
+
+    /// Builds the net, with frequency and gate in and two channels out
+    pub fn compile(&self) -> Result<Net64, GraphError> {
+        self.check_cycles()?;
+
+        let output = self.nodes.iter().find(|node| node.unit == OUTPUT);
+        let left = output.and_then(|output| {
+            self.wires.iter().find(|wire| wire.to == (output.id, 0))
+        });
+        if left.is_none() {
+            return Err(GraphError::new(
+                output.map(|output| output.id),
+                "nothing is wired to the left output",
+            ));
+        }
+
+        let mut net = Net64::new(2, 2);
+        let mut ids = HashMap::new();
+        for node in &self.nodes {
+            let unit: Box<dyn AudioUnit64> = match node.unit.as_str() {
+                INPUT | OUTPUT => Box::new(multipass::<U2>()),
+                _ => Box::new(build(&node.unit, &node.args).map_err(
+                    |message| GraphError::new(Some(node.id), &message),
+                )?),
+            };
+
+            let id = net.push(unit);
+            match node.unit.as_str() {
+                INPUT => {
+                    net.connect_input(0, id, 0);
+                    net.connect_input(1, id, 1);
+                }
+                OUTPUT => {
+                    net.connect_output(id, 0, 0);
+                    net.connect_output(id, 1, 1);
+                }
+                _ => {}
+            }
+            ids.insert(node.id, id);
+        }
+
+        let mut wires = self.wires.clone();
+        if let (Some(output), Some(left)) = (output, left) {
+            if !wires.iter().any(|wire| wire.to == (output.id, 1)) {
+                wires.push(Wire {
+                    from: left.from,
+                    to: (output.id, 1),
+                });
+            }
+        }
+
+        for wire in wires {
+            let (from, to) = match (ids.get(&wire.from.0), ids.get(&wire.to.0))
+            {
+                (Some(from), Some(to)) => (*from, *to),
+                _ => continue,
+            };
+            net.connect(from, wire.from.1, to, wire.to.1);
+        }
+
+        Ok(net)
+    }
+}
@@ -0,0 +1,502 @@
+//! Patches: fundsp graphs written as text and compiled at runtime.
+//!
+//! A patch is one expression over units like `saw()` or `moog_hz(800, 0.6)`
+//! combined with `>>`, `|`, `&` and `*`:
+//!
+//! ```text
+//! // Frequency and gate in, mono out
+//! (saw() >> moog_hz(800, 0.6)) * pass()
+//! ```
+//!
+//! Played as an instrument, a patch gets the frequency of the held note on
+//! its first input and its gate (the velocity while held, 0 after) on its
+//! second. It can have up to two outputs.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use fundsp::hacker::*;
+
+use super::effects::{Param, ParamSpec};
+use super::instrument::{pitch_ratio, Instrument, PITCH};
+use super::tuning::NoteTable;
+use crate::app::midi::{velocity_to_gain, NoteEvent, MAX_NOTE};
+
+pub mod graph;
+mod parser;
+
+use parser::{Expr, Operator, Position};
+
+pub const EXTENSION: &str = "patch";
+
+pub const GAIN: usize = 0;
+pub const PITCH_OFFSET: usize = 1;
+pub const PARAMS: [ParamSpec; 2] =
+    [ParamSpec::live("Gain", 0.0, 1.0, 1.0), PITCH];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl PatchError {
+    fn new(position: Position, message: &str) -> Self {
+        Self {
+            line: position.line,
+            column: position.column,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+// Every unit the language knows, with default arguments for editors that
+// create units. The number of defaults is the number of arguments
+const UNITS: &[(&str, &[f64])] = &[
+    ("sine", &[]),
+    ("sine_hz", &[440.0]),
+    ("saw", &[]),
+    ("saw_hz", &[440.0]),
+    ("square", &[]),
+    ("square_hz", &[440.0]),
+    ("triangle", &[]),
+    ("triangle_hz", &[440.0]),
+    ("pulse", &[]),
+    ("noise", &[]),
+    ("white", &[]),
+    ("pink", &[]),
+    ("brown", &[]),
+    ("zero", &[]),
+    ("dc", &[1.0]),
+    ("pass", &[]),
+    ("sink", &[]),
+    ("split", &[2.0]),
+    ("join", &[2.0]),
+    ("pan", &[0.0]),
+    ("lowpass", &[]),
+    ("lowpass_hz", &[1_000.0, 0.7]),
+    ("highpass", &[]),
+    ("highpass_hz", &[200.0, 0.7]),
+    ("bandpass", &[]),
+    ("bandpass_hz", &[1_000.0, 1.0]),
+    ("lowpole", &[]),
+    ("lowpole_hz", &[1_000.0]),
+    ("resonator", &[]),
+    ("resonator_hz", &[1_000.0, 100.0]),
+    ("moog", &[]),
+    ("moog_hz", &[1_000.0, 0.5]),
+    ("butterpass", &[]),
+    ("butterpass_hz", &[1_000.0]),
+    ("dcblock", &[]),
+    ("declick", &[]),
+    ("delay", &[0.25]),
+    ("chorus", &[0.0, 0.015, 0.005, 0.5]),
+    ("reverb_stereo", &[10.0, 3.0]),
+    ("limiter", &[0.001, 0.1]),
+    ("limiter_stereo", &[0.001, 0.1]),
+    ("clip", &[]),
+];
+
+// Largest fan-out of `split` and `join`
+const MAX_CHANNELS: f64 = 16.0;
+
+fn unit(name: &str, args: &[f64]) -> Box<dyn AudioUnit64> {
+    match (name, args) {
+        ("sine", _) => Box::new(sine()),
+        ("sine_hz", [f]) => Box::new(sine_hz(*f)),
+        ("saw", _) => Box::new(saw()),
+        ("saw_hz", [f]) => Box::new(saw_hz(*f)),
+        ("square", _) => Box::new(square()),
+        ("square_hz", [f]) => Box::new(square_hz(*f)),
+        ("triangle", _) => Box::new(triangle()),
+        ("triangle_hz", [f]) => Box::new(triangle_hz(*f)),
+        ("pulse", _) => Box::new(pulse()),
+        ("noise", _) => Box::new(noise()),
+        ("white", _) => Box::new(white()),
+        ("pink", _) => Box::new(pink()),
+        ("brown", _) => Box::new(brown()),
+        ("dc", [x]) => Box::new(dc(*x)),
+        ("sink", _) => Box::new(sink()),
+        ("pan", [x]) => Box::new(pan(*x)),
+        ("lowpass", _) => Box::new(lowpass()),
+        ("lowpass_hz", [f, q]) => Box::new(lowpass_hz(*f, *q)),
+        ("highpass", _) => Box::new(highpass()),
+        ("highpass_hz", [f, q]) => Box::new(highpass_hz(*f, *q)),
+        ("bandpass", _) => Box::new(bandpass()),
+        ("bandpass_hz", [f, q]) => Box::new(bandpass_hz(*f, *q)),
+        ("lowpole", _) => Box::new(lowpole()),
+        ("lowpole_hz", [f]) => Box::new(lowpole_hz(*f)),
+        ("resonator", _) => Box::new(resonator()),
+        ("resonator_hz", [f, bandwidth]) => {
+            Box::new(resonator_hz(*f, *bandwidth))
+        }
+        ("moog", _) => Box::new(moog()),
+        ("moog_hz", [f, q]) => Box::new(moog_hz(*f, *q)),
+        ("butterpass", _) => Box::new(butterpass()),
+        ("butterpass_hz", [f]) => Box::new(butterpass_hz(*f)),
+        ("dcblock", _) => Box::new(dcblock()),
+        ("declick", _) => Box::new(declick()),
+        ("delay", [t]) => Box::new(delay(*t)),
+        ("chorus", [seed, separation, variation, frequency]) => {
+            Box::new(chorus(*seed as i64, *separation, *variation, *frequency))
+        }
+        ("reverb_stereo", [room, time]) => {
+            Box::new(reverb_stereo(*room, *time))
+        }
+        ("limiter", [attack, release]) => {
+            Box::new(limiter((*attack, *release)))
+        }
+        ("limiter_stereo", [attack, release]) => {
+            Box::new(limiter_stereo((*attack, *release)))
+        }
+        ("clip", _) => Box::new(clip()),
+        // `zero`, `pass` and anything unknown, which `build` has already
+        // turned away
+        ("zero", _) => Box::new(zero()),
+        _ => Box::new(pass()),
+    }
+}
+
+// Graph and whether it's a plain number, which `*` treats as a gain
+struct Compiled {
+    net: Net64,
+    constant: Option<f64>,
+}
+
+fn wrap(unit: Box<dyn AudioUnit64>) -> Net64 {
+    Net64::wrap(unit)
+}
+
+// `count` copies of a unit side by side
+fn stack(count: usize, unit: impl Fn() -> Box<dyn AudioUnit64>) -> Net64 {
+    (1..count).fold(wrap(unit()), |net, _| net | wrap(unit()))
+}
+
+fn channels(count: f64) -> Result<usize, String> {
+    if count.fract() != 0.0 || !(1.0..=MAX_CHANNELS).contains(&count) {
+        return Err(format!(
+            "channel count must be between 1 and {}",
+            MAX_CHANNELS
+        ));
+    }
+    Ok(count as usize)
+}
+
+/// Names of every unit, in the order editors list them
+pub fn unit_names() -> Vec<&'static str> {
+    UNITS.iter().map(|(name, _)| *name).collect()
+}
+
+fn default_args(name: &str) -> Option<&'static [f64]> {
+    UNITS
+        .iter()
+        .find(|(unit, _)| *unit == name)
+        .map(|(_, defaults)| *defaults)
+}
+
+// One unit, checked against the table
+fn build(name: &str, args: &[f64]) -> Result<Net64, String> {
+    let arity = match default_args(name) {
+        Some(defaults) => defaults.len(),
+        None => return Err(format!("unknown unit `{}`", name)),
+    };
+    if args.len() != arity {
+        return Err(format!(
+            "`{}` takes {} argument{}, found {}",
+            name,
+            arity,
+            if arity == 1 { "" } else { "s" },
+            args.len()
+        ));
+    }
+
+    // Fan-out and fan-in are built from single channel units
+    let net = match name {
+        "split" => {
+            let count = channels(args[0])?;
+            (1..count).fold(wrap(Box::new(pass())), |net, _| {
+                net ^ wrap(Box::new(pass()))
+            })
+        }
+        "join" => {
+            let count = channels(args[0])?;
+            (1..count).fold(wrap(Box::new(pass())), |net, _| {
+                net + wrap(Box::new(pass()))
+            })
+        }
+        _ => wrap(unit(name, args)),
+    };
+
+    Ok(net)
+}
+
+fn compile_expr(expr: &Expr) -> Result<Compiled, PatchError> {
+    match expr {
+        Expr::Number(value) => Ok(Compiled {
+            net: wrap(Box::new(dc(*value))),
+            constant: Some(*value),
+        }),
+        Expr::Unit {
+            name,
+            args,
+            position,
+        } => Ok(Compiled {
+            net: build(name, args)
+                .map_err(|message| PatchError::new(*position, &message))?,
+            constant: None,
+        }),
+        Expr::Binary {
+            operator,
+            lhs,
+            rhs,
+            position,
+        } => {
+            let lhs = compile_expr(lhs)?;
+            let rhs = compile_expr(rhs)?;
+            let (a, b) = (&lhs.net, &rhs.net);
+
+            let mismatch = |what: &str, left: usize, right: usize| {
+                Err(PatchError::new(
+                    *position,
+                    &format!(
+                        "`{}` needs matching {}, found {} and {}",
+                        operator.symbol(),
+                        what,
+                        left,
+                        right
+                    ),
+                ))
+            };
+
+            let net = match operator {
+                Operator::Stack => lhs.net | rhs.net,
+                Operator::Pipe => {
+                    if a.outputs() != b.inputs() {
+                        return mismatch(
+                            "outputs and inputs",
+                            a.outputs(),
+                            b.inputs(),
+                        );
+                    }
+                    lhs.net >> rhs.net
+                }
+                Operator::Bus => {
+                    if a.inputs() != b.inputs() {
+                        return mismatch("inputs", a.inputs(), b.inputs());
+                    }
+                    if a.outputs() != b.outputs() {
+                        return mismatch("outputs", a.outputs(), b.outputs());
+                    }
+                    lhs.net & rhs.net
+                }
+                // A number scales every channel of the other side
+                Operator::Product => match (lhs.constant, rhs.constant) {
+                    (Some(x), Some(y)) => {
+                        return Ok(Compiled {
+                            net: wrap(Box::new(dc(x * y))),
+                            constant: Some(x * y),
+                        })
+                    }
+                    (Some(gain), None) => {
+                        let count = b.outputs();
+                        rhs.net >> stack(count, || Box::new(mul(gain)))
+                    }
+                    (None, Some(gain)) => {
+                        let count = a.outputs();
+                        lhs.net >> stack(count, || Box::new(mul(gain)))
+                    }
+                    (None, None) => {
+                        if a.outputs() != b.outputs() {
+                            return mismatch(
+                                "outputs",
+                                a.outputs(),
+                                b.outputs(),
+                            );
+                        }
+                        lhs.net * rhs.net
+                    }
+                },
+            };
+
+            Ok(Compiled {
+                net,
+                constant: None,
+            })
+        }
+    }
+}
+
+/// Parses and compiles a patch. Instruments take at most two inputs and
+/// give one or two outputs
+pub fn compile(source: &str) -> Result<Net64, PatchError> {
+    let expr = parser::parse(source)?;
+    let compiled = compile_expr(&expr)?;
+    let net = compiled.net;
+
+    let start = Position { line: 1, column: 1 };
+    if net.inputs() > 2 {
+        return Err(PatchError::new(
+            start,
+            &format!(
+                "a patch takes frequency and gate at most, not {} inputs",
+                net.inputs()
+            ),
+        ));
+    }
+    if !(1..=2).contains(&net.outputs()) {
+        return Err(PatchError::new(
+            start,
+            &format!("a patch gives 1 or 2 outputs, not {}", net.outputs()),
+        ));
+    }
+
+    Ok(net)
+}
+
+pub fn load(path: &Path) -> Result<Net64, PatchError> {
+    let source = fs::read_to_string(path).map_err(|err| PatchError {
+        line: 0,
+        column: 0,
+        message: format!("failed to read {:?}: {}", path, err),
+    })?;
+
+    compile(&source)
+}
+
+/// A patch file, watched for changes
+#[derive(Debug)]
+pub struct PatchFile {
+    pub path: PathBuf,
+    pub error: Option<PatchError>,
+    modified: Option<SystemTime>,
+}
+
+impl PatchFile {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            error: None,
+            modified: None,
+        }
+    }
+
+    /// Compiles the file when it changed since the last call. Failures are
+    /// kept in `error` until the next successful compile
+    pub fn reload(&mut self) -> Option<Net64> {
+        let modified = fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if modified.is_some() && modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+
+        match load(&self.path) {
+            Ok(net) => {
+                self.error = None;
+                Some(net)
+            }
+            Err(err) => {
+                self.error = Some(err);
+                None
+            }
+        }
+    }
+}
+
+/// A monophonic instrument playing a compiled patch. Without a patch it
+/// stays silent
+pub struct PatchSynth {
+    params: Vec<Param>,
+    net: Option<Net64>,
+    // Held notes with their gain, the last one sounding
+    held: Vec<(u8, f64)>,
+    frequency: f64,
+    input: [f64; 2],
+    output: [f64; 2],
+    tuning: NoteTable,
+}
+
+impl PatchSynth {
+    pub fn new(params: Vec<Param>, net: Option<Net64>) -> Self {
+        Self {
+            params,
+            net,
+            // Every note at once, so holding more never allocates
+            held: Vec::with_capacity(MAX_NOTE as usize + 1),
+            frequency: 440.0,
+            input: [0.0; 2],
+            output: [0.0; 2],
+            tuning: NoteTable::default(),
+        }
+    }
+}
+
+impl Instrument for PatchSynth {
+    fn reset(&mut self, sample_rate: f64) {
+        if let Some(net) = self.net.as_mut() {
+            net.reset(Some(sample_rate));
+        }
+        self.held.clear();
+    }
+
+    fn note(&mut self, event: NoteEvent) {
+        match event {
+            NoteEvent::NoteOn { note, velocity } => {
+                let frequency = match self.tuning.frequency(note) {
+                    Some(frequency) => frequency,
+                    None => return,
+                };
+                self.held.retain(|(held, _)| *held != note);
+                self.held.push((note, velocity_to_gain(velocity)));
+                self.frequency = frequency;
+            }
+            NoteEvent::NoteOff { note } => {
+                self.held.retain(|(held, _)| *held != note);
+                // Fall back to the note still held, like a mono synth. Only
+                // notes the tuning plays are held
+                if let Some((note, _)) = self.held.last() {
+                    self.frequency =
+                        self.tuning.frequency(*note).unwrap_or(self.frequency);
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self) -> (f64, f64) {
+        let net = match self.net.as_mut() {
+            Some(net) => net,
+            None => return (0.0, 0.0),
+        };
+
+        let pitch = pitch_ratio(self.params[PITCH_OFFSET].get());
+        let gate = self.held.last().map_or(0.0, |(_, gain)| *gain);
+        self.input = [self.frequency * pitch, gate];
+
+        let (inputs, outputs) = (net.inputs(), net.outputs());
+        net.tick(&self.input[..inputs], &mut self.output[..outputs]);
+
+        let gain = self.params[GAIN].get();
+        let left = self.output[0] * gain;
+        let right = if outputs > 1 {
+            self.output[1] * gain
+        } else {
+            left
+        };
+
+        (left, right)
+    }
+
+    fn set_tuning(&mut self, table: &NoteTable) {
+        self.tuning.clone_from(table);
+    }
+}
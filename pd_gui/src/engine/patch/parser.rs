@@ -0,0 +1,414 @@
+//! Lexer and parser of the patch language.
+//!
+//! Operators bind like they do on fundsp graphs in Rust, from loosest to
+//! tightest: `|` stacks, `&` buses, `>>` pipes and `*` multiplies.
+
+use super::PatchError;
+
+// Deepest nesting of parentheses, so a runaway patch can't overflow the
+// stack while it's parsed and compiled
+const MAX_DEPTH: usize = 64;
+
+/// A position in the patch source, both counted from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Stack,
+    Bus,
+    Pipe,
+    Product,
+}
+
+impl Operator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Operator::Stack => "|",
+            Operator::Bus => "&",
+            Operator::Pipe => ">>",
+            Operator::Product => "*",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Unit {
+        name: String,
+        args: Vec<f64>,
+        position: Position,
+    },
+    Binary {
+        operator: Operator,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+        position: Position,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Operator(Operator),
+    Minus,
+    Open,
+    Close,
+    Comma,
+    End,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Number(number) => format!("number {}", number),
+            Token::Ident(name) => format!("`{}`", name),
+            Token::Operator(operator) => format!("`{}`", operator.symbol()),
+            Token::Minus => "`-`".to_string(),
+            Token::Open => "`(`".to_string(),
+            Token::Close => "`)`".to_string(),
+            Token::Comma => "`,`".to_string(),
+            Token::End => "end of patch".to_string(),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, Position)>, PatchError> {
+    let mut tokens = vec![];
+    let mut chars = source.chars().peekable();
+    let mut position = Position { line: 1, column: 1 };
+
+    while let Some(&c) = chars.peek() {
+        let start = position;
+        let mut advance = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+            let c = chars.next();
+            if c == Some('\n') {
+                position.line += 1;
+                position.column = 1;
+            } else {
+                position.column += 1;
+            }
+            c
+        };
+
+        let token = match c {
+            _ if c.is_whitespace() => {
+                advance(&mut chars);
+                continue;
+            }
+            '/' => {
+                advance(&mut chars);
+                if chars.peek() != Some(&'/') {
+                    return Err(PatchError::new(start, "expected `//`"));
+                }
+                // Comments run to the end of the line
+                while !matches!(chars.peek(), None | Some('\n')) {
+                    advance(&mut chars);
+                }
+                continue;
+            }
+            '>' => {
+                advance(&mut chars);
+                if advance(&mut chars) != Some('>') {
+                    return Err(PatchError::new(start, "expected `>>`"));
+                }
+                Token::Operator(Operator::Pipe)
+            }
+            '|' | '&' | '*' | '-' | '(' | ')' | ',' => {
+                advance(&mut chars);
+                match c {
+                    '|' => Token::Operator(Operator::Stack),
+                    '&' => Token::Operator(Operator::Bus),
+                    '*' => Token::Operator(Operator::Product),
+                    '-' => Token::Minus,
+                    '(' => Token::Open,
+                    ')' => Token::Close,
+                    _ => Token::Comma,
+                }
+            }
+            _ if c.is_ascii_digit() || c == '.' => {
+                let mut text = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_digit() || c == '.' || c == '_') {
+                        break;
+                    }
+                    advance(&mut chars);
+                    if c != '_' {
+                        text.push(c);
+                    }
+                }
+                match text.parse() {
+                    Ok(number) => Token::Number(number),
+                    Err(_) => {
+                        return Err(PatchError::new(
+                            start,
+                            &format!("`{}` is not a number", text),
+                        ))
+                    }
+                }
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    advance(&mut chars);
+                    name.push(c);
+                }
+                Token::Ident(name)
+            }
+            _ => {
+                return Err(PatchError::new(
+                    start,
+                    &format!("unexpected `{}`", c),
+                ))
+            }
+        };
+
+        tokens.push((token, start));
+    }
+
+    tokens.push((Token::End, position));
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, Position)>,
+    cursor: usize,
+    // Parentheses open around the current token
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &(Token, Position) {
+        &self.tokens[self.cursor]
+    }
+
+    fn next(&mut self) -> (Token, Position) {
+        let token = self.tokens[self.cursor].clone();
+        if self.cursor < self.tokens.len() - 1 {
+            self.cursor += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), PatchError> {
+        let (token, position) = self.next();
+        if token == expected {
+            Ok(())
+        } else {
+            Err(PatchError::new(
+                position,
+                &format!(
+                    "expected {}, found {}",
+                    expected.describe(),
+                    token.describe()
+                ),
+            ))
+        }
+    }
+
+    // Operators from loosest to tightest, each level parsing the next one
+    // for its operands
+    fn binary(&mut self, level: usize) -> Result<Expr, PatchError> {
+        const LEVELS: [Operator; 4] = [
+            Operator::Stack,
+            Operator::Bus,
+            Operator::Pipe,
+            Operator::Product,
+        ];
+
+        let operator = match LEVELS.get(level) {
+            Some(operator) => *operator,
+            None => return self.atom(),
+        };
+
+        let mut lhs = self.binary(level + 1)?;
+        while self.peek().0 == Token::Operator(operator) {
+            let (_, position) = self.next();
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary {
+                operator,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+                position,
+            };
+        }
+
+        Ok(lhs)
+    }
+
+    fn atom(&mut self) -> Result<Expr, PatchError> {
+        let (token, position) = self.next();
+
+        match token {
+            Token::Number(number) => Ok(Expr::Number(number)),
+            Token::Minus => match self.next() {
+                (Token::Number(number), _) => Ok(Expr::Number(-number)),
+                (token, position) => Err(PatchError::new(
+                    position,
+                    &format!("expected a number, found {}", token.describe()),
+                )),
+            },
+            Token::Open => {
+                if self.depth == MAX_DEPTH {
+                    return Err(PatchError::new(
+                        position,
+                        &format!(
+                            "parentheses nest deeper than {} levels",
+                            MAX_DEPTH
+                        ),
+                    ));
+                }
+                self.depth += 1;
+                let expr = self.binary(0)?;
+                self.expect(Token::Close)?;
+                self.depth -= 1;
+                Ok(expr)
+            }
+            // Units without arguments can leave out the parentheses
+            Token::Ident(name) => {
+                let mut args = vec![];
+                if self.peek().0 == Token::Open {
+                    self.next();
+                    while self.peek().0 != Token::Close {
+                        if !args.is_empty() {
+                            self.expect(Token::Comma)?;
+                        }
+                        args.push(self.number()?);
+                    }
+                    self.next();
+                }
+
+                Ok(Expr::Unit {
+                    name,
+                    args,
+                    position,
+                })
+            }
+            token => Err(PatchError::new(
+                position,
+                &format!(
+                    "expected a unit or number, found {}",
+                    token.describe()
+                ),
+            )),
+        }
+    }
+
+    // Unit arguments are plain numbers
+    fn number(&mut self) -> Result<f64, PatchError> {
+        let sign = if self.peek().0 == Token::Minus {
+            self.next();
+            -1.0
+        } else {
+            1.0
+        };
+
+        match self.next() {
+            (Token::Number(number), _) => Ok(sign * number),
+            (token, position) => Err(PatchError::new(
+                position,
+                &format!("expected a number, found {}", token.describe()),
+            )),
+        }
+    }
+}
+
+/// Parses a whole patch into one expression
+pub fn parse(source: &str) -> Result<Expr, PatchError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        cursor: 0,
+        depth: 0,
+    };
+
+    let expr = parser.binary(0)?;
+    match parser.next() {
+        (Token::End, _) => Ok(expr),
+        (token, position) => Err(PatchError::new(
+            position,
+            &format!("expected an operator, found {}", token.describe()),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The tree with every binary expression in parentheses
+    fn show(expr: &Expr) -> String {
+        match expr {
+            Expr::Number(number) => number.to_string(),
+            Expr::Unit { name, args, .. } if args.is_empty() => name.clone(),
+            Expr::Unit { name, args, .. } => {
+                let args: Vec<String> =
+                    args.iter().map(|arg| arg.to_string()).collect();
+                format!("{}({})", name, args.join(", "))
+            }
+            Expr::Binary {
+                operator, lhs, rhs, ..
+            } => format!("({} {} {})", show(lhs), operator.symbol(), show(rhs)),
+        }
+    }
+
+    fn parsed(source: &str) -> String {
+        show(&parse(source).unwrap())
+    }
+
+    #[test]
+    fn operators_bind_from_stack_to_product() {
+        assert_eq!(parsed("a | b & c >> d * e"), "(a | (b & (c >> (d * e))))");
+        assert_eq!(parsed("a * b >> c & d | e"), "((((a * b) >> c) & d) | e)");
+    }
+
+    #[test]
+    fn operators_associate_left() {
+        assert_eq!(parsed("a >> b >> c"), "((a >> b) >> c)");
+        assert_eq!(parsed("a | b | c"), "((a | b) | c)");
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        assert_eq!(parsed("(a | b) >> c"), "((a | b) >> c)");
+        assert_eq!(parsed("a >> (b >> c)"), "(a >> (b >> c))");
+    }
+
+    #[test]
+    fn units_take_numbers() {
+        assert_eq!(
+            parsed("moog_hz(800, -0.5) * 0.5 // gain"),
+            "(moog_hz(800, -0.5) * 0.5)"
+        );
+        assert_eq!(parsed("saw() >> pass"), "(saw >> pass)");
+    }
+
+    #[test]
+    fn errors_point_at_the_token() {
+        let err = parse("sine >>\n  |").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 3));
+
+        let err = parse("sine(1, )").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 9));
+    }
+
+    #[test]
+    fn nesting_is_capped() {
+        let nested = |depth: usize| {
+            format!("{}sine{}", "(".repeat(depth), ")".repeat(depth))
+        };
+
+        assert_eq!(parsed(&nested(MAX_DEPTH)), "sine");
+        let err = parse(&nested(MAX_DEPTH + 1)).unwrap_err();
+        assert_eq!(err.column, MAX_DEPTH + 1);
+        assert!(parse(&nested(100_000)).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::app::composition::Sample;
+
+/// Decoded audio of a composition sample, one `Vec` of frames per channel
+#[derive(Debug)]
+pub struct SampleBuffer {
+    pub sample_rate: f64,
+    pub channels: Vec<Vec<f32>>,
+}
+
+impl SampleBuffer {
+    pub fn load(path: &Path) -> Result<Self, hound::Error> {
+        let mut reader = hound::WavReader::open(path)?;
+        let spec = reader.spec();
+        let channel_count = spec.channels.max(1) as usize;
+
+        let interleaved: Vec<f32> = match spec.sample_format {
+            hound::SampleFormat::Float => {
+                reader.samples::<f32>().collect::<Result<_, _>>()?
+            }
+            hound::SampleFormat::Int => {
+                let scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.map(|s| s as f32 / scale))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+
+        let mut channels =
+            vec![
+                Vec::with_capacity(interleaved.len() / channel_count);
+                channel_count
+            ];
+        // A truncated last frame is dropped so every channel has the same
+        // length
+        for frame in interleaved.chunks_exact(channel_count) {
+            for (channel, sample) in frame.iter().enumerate() {
+                channels[channel].push(*sample);
+            }
+        }
+
+        Ok(Self {
+            sample_rate: spec.sample_rate as f64,
+            channels,
+        })
+    }
+
+    pub fn frames(&self) -> usize {
+        self.channels.first().map_or(0, Vec::len)
+    }
+
+    /// Linearly interpolated stereo frame at a fractional position; mono
+    /// buffers play on both sides
+    pub fn frame_at(&self, position: f64) -> (f64, f64) {
+        let index = position as usize;
+        let fraction = position - index as f64;
+
+        let read = |channel: &Vec<f32>| {
+            let a = channel.get(index).copied().unwrap_or(0.0) as f64;
+            let b = channel.get(index + 1).copied().unwrap_or(0.0) as f64;
+            a + (b - a) * fraction
+        };
+
+        match self.channels.as_slice() {
+            [] => (0.0, 0.0),
+            [mono] => {
+                let value = read(mono);
+                (value, value)
+            }
+            [left, right, ..] => (read(left), read(right)),
+        }
+    }
+
+    /// All channels averaged down to one
+    pub fn mono(&self) -> Vec<f32> {
+        let count = self.channels.len().max(1) as f32;
+
+        (0..self.frames())
+            .map(|frame| {
+                self.channels
+                    .iter()
+                    .map(|channel| channel[frame])
+                    .sum::<f32>()
+                    / count
+            })
+            .collect()
+    }
+}
+
+/// Keeps decoded samples around so several instruments can share one buffer
+#[derive(Debug, Default)]
+pub struct SampleLibrary {
+    buffers: HashMap<i64, Arc<SampleBuffer>>,
+}
+
+impl SampleLibrary {
+    /// A sample decoded before, without loading it
+    pub fn get(&self, sample_id: i64) -> Option<&SampleBuffer> {
+        self.buffers.get(&sample_id).map(Arc::as_ref)
+    }
+
+    pub fn get_or_load(
+        &mut self,
+        sample: &Sample,
+    ) -> Result<Arc<SampleBuffer>, hound::Error> {
+        if let Some(buffer) = self.buffers.get(&sample.id) {
+            return Ok(Arc::clone(buffer));
+        }
+
+        let buffer = Arc::new(SampleBuffer::load(&sample.path)?);
+        self.buffers.insert(sample.id, Arc::clone(&buffer));
+
+        Ok(buffer)
+    }
+}
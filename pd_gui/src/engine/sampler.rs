@@ -0,0 +1,247 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::effects::{Param, ParamSpec};
+use super::instrument::{pitch_ratio, Adsr, Envelope, Instrument, PITCH};
+use super::sample::SampleBuffer;
+use super::tuning::NoteTable;
+use crate::app::midi::{velocity_to_gain, NoteEvent, MAX_NOTE};
+
+const SAMPLER_VOICES: usize = 16;
+
+pub const GAIN: usize = 0;
+pub const PITCH_OFFSET: usize = 1;
+pub const PARAMS: [ParamSpec; 2] =
+    [ParamSpec::live("Gain", 0.0, 1.0, 1.0), PITCH];
+
+/// Where a sample sits on the keyboard. Ranges are inclusive; zones that
+/// share a key and velocity take turns (round-robin)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Zone {
+    pub sample_id: i64,
+    pub root_key: u8,
+    pub keys: (u8, u8),
+    pub velocities: (u8, u8),
+    // Start and end frame of a sustain loop
+    pub loop_points: Option<(usize, usize)>,
+}
+
+impl Zone {
+    /// A zone stretching one sample across the whole keyboard around C4
+    pub fn full_range(sample_id: i64) -> Self {
+        Self {
+            sample_id,
+            root_key: 60,
+            keys: (0, MAX_NOTE),
+            velocities: (1, 127),
+            loop_points: None,
+        }
+    }
+
+    fn contains(&self, note: u8, velocity: u8) -> bool {
+        (self.keys.0..=self.keys.1).contains(&note)
+            && (self.velocities.0..=self.velocities.1).contains(&velocity)
+    }
+}
+
+/// The serializable description of a sampler, stored on a track
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SamplerPatch {
+    pub zones: Vec<Zone>,
+    pub adsr: Adsr,
+}
+
+impl SamplerPatch {
+    pub fn single(sample_id: i64) -> Self {
+        Self {
+            zones: vec![Zone::full_range(sample_id)],
+            adsr: Adsr::default(),
+        }
+    }
+}
+
+struct SamplerVoice {
+    note: Option<u8>,
+    zone: usize,
+    position: f64,
+    // Playback rate before the sample/engine sample-rate correction
+    pitch: f64,
+    gain: f64,
+    age: u64,
+    envelope: Envelope,
+}
+
+pub struct Sampler {
+    params: Vec<Param>,
+    zones: Vec<(Zone, Arc<SampleBuffer>)>,
+    voices: Vec<SamplerVoice>,
+    // How many times each key was struck, used to rotate round-robin zones
+    round_robin: [usize; MAX_NOTE as usize + 1],
+    notes_played: u64,
+    sample_rate: f64,
+    tuning: NoteTable,
+}
+
+impl Sampler {
+    /// Zones whose sample `buffer` can't provide are skipped
+    pub fn new(
+        patch: &SamplerPatch,
+        params: Vec<Param>,
+        buffer: impl Fn(i64) -> Option<Arc<SampleBuffer>>,
+    ) -> Self {
+        let zones = patch
+            .zones
+            .iter()
+            .filter_map(|zone| Some((zone.clone(), buffer(zone.sample_id)?)))
+            .collect();
+
+        let voices = (0..SAMPLER_VOICES)
+            .map(|_| SamplerVoice {
+                note: None,
+                zone: 0,
+                position: 0.0,
+                pitch: 1.0,
+                gain: 0.0,
+                age: 0,
+                envelope: Envelope::new(patch.adsr),
+            })
+            .collect();
+
+        Self {
+            params,
+            zones,
+            voices,
+            round_robin: [0; MAX_NOTE as usize + 1],
+            notes_played: 0,
+            sample_rate: 44_100.0,
+            tuning: NoteTable::default(),
+        }
+    }
+
+    fn pick_zone(&mut self, note: u8, velocity: u8) -> Option<usize> {
+        let candidates = self
+            .zones
+            .iter()
+            .filter(|(zone, _)| zone.contains(note, velocity))
+            .count();
+        if candidates == 0 {
+            return None;
+        }
+
+        let struck = self.round_robin.get_mut(note as usize)?;
+        let turn = *struck % candidates;
+        *struck += 1;
+
+        self.zones
+            .iter()
+            .enumerate()
+            .filter(|(_, (zone, _))| zone.contains(note, velocity))
+            .nth(turn)
+            .map(|(index, _)| index)
+    }
+
+    fn free_voice(&mut self) -> &mut SamplerVoice {
+        let index = self
+            .voices
+            .iter()
+            .position(|voice| voice.envelope.is_idle())
+            .unwrap_or_else(|| {
+                self.voices
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, voice)| voice.age)
+                    .map(|(index, _)| index)
+                    .unwrap_or(0)
+            });
+
+        &mut self.voices[index]
+    }
+}
+
+impl Instrument for Sampler {
+    fn reset(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+
+        for voice in &mut self.voices {
+            voice.envelope.reset(sample_rate);
+            voice.note = None;
+        }
+    }
+
+    fn note(&mut self, event: NoteEvent) {
+        match event {
+            NoteEvent::NoteOn { note, velocity } => {
+                let zone = match self.pick_zone(note, velocity) {
+                    Some(zone) => zone,
+                    None => return,
+                };
+                // A zone's root key plays its sample as recorded, like the
+                // pads of a drum kit
+                let root_key = self.zones[zone].0.root_key;
+                let pitch = match self.tuning.ratio(note, root_key) {
+                    Some(pitch) => pitch,
+                    None => return,
+                };
+
+                self.notes_played += 1;
+                let age = self.notes_played;
+
+                let voice = self.free_voice();
+                voice.note = Some(note);
+                voice.zone = zone;
+                voice.position = 0.0;
+                voice.pitch = pitch;
+                voice.gain = velocity_to_gain(velocity);
+                voice.age = age;
+                voice.envelope.gate_on();
+            }
+            NoteEvent::NoteOff { note } => {
+                for voice in &mut self.voices {
+                    if voice.note == Some(note) {
+                        voice.note = None;
+                        voice.envelope.gate_off();
+                    }
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self) -> (f64, f64) {
+        let mut left = 0.0;
+        let mut right = 0.0;
+        let gain = self.params[GAIN].get();
+        let pitch = pitch_ratio(self.params[PITCH_OFFSET].get());
+
+        for voice in &mut self.voices {
+            if voice.envelope.is_idle() {
+                continue;
+            }
+
+            let (zone, buffer) = &self.zones[voice.zone];
+            let (l, r) = buffer.frame_at(voice.position);
+            let level = voice.envelope.tick() * voice.gain * gain;
+            left += l * level;
+            right += r * level;
+
+            voice.position +=
+                voice.pitch * pitch * buffer.sample_rate / self.sample_rate;
+
+            match zone.loop_points {
+                Some((start, end)) if end > start => {
+                    if voice.position >= end as f64 {
+                        voice.position -= (end - start) as f64;
+                    }
+                }
+                _ => {
+                    if voice.position >= buffer.frames() as f64 {
+                        voice.note = None;
+                        voice.envelope.reset(self.sample_rate);
+                    }
+                }
+            }
+        }
+
+        (left, right)
+    }
+}
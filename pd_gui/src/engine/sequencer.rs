@@ -0,0 +1,329 @@
+use serde::{Deserialize, Serialize};
+
+use super::instrument::Adsr;
+use super::sampler::{SamplerPatch, Zone};
+use super::transport::LoopPlayer;
+use crate::app::midi::{NoteEvent, MAX_NOTE};
+
+pub const MIN_STEPS: usize = 16;
+pub const MAX_STEPS: usize = 64;
+// Steps are sixteenth notes
+pub const STEP_LENGTH: f64 = 0.25;
+// Lane `n` of a drum track plays note `DRUM_ROOT_NOTE + n`, like a GM kit
+pub const DRUM_ROOT_NOTE: u8 = 36;
+// Pads above this would play notes past the keyboard
+pub const MAX_PADS: usize = (MAX_NOTE - DRUM_ROOT_NOTE) as usize + 1;
+
+/// The note that triggers a pad, `None` past the keyboard
+pub fn pad_note(pad: usize) -> Option<u8> {
+    if pad < MAX_PADS {
+        Some(DRUM_ROOT_NOTE + pad as u8)
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Step {
+    pub is_active: bool,
+    pub velocity: u8,
+    // Chance between 0 and 1 that an active step actually plays
+    pub probability: f32,
+    // Timing offset as a fraction of a step, between -0.5 and 0.5
+    pub nudge: f32,
+}
+
+impl Default for Step {
+    fn default() -> Self {
+        Self {
+            is_active: false,
+            velocity: 100,
+            probability: 1.0,
+            nudge: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lane {
+    pub sample_id: i64,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pattern {
+    pub name: String,
+    pub length: usize,
+    // Delays every other step, 0 is straight and 1 is a full triplet shuffle
+    pub swing: f32,
+    pub lanes: Vec<Lane>,
+}
+
+impl Pattern {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            length: MIN_STEPS,
+            swing: 0.0,
+            lanes: vec![],
+        }
+    }
+
+    pub fn set_length(&mut self, length: usize) {
+        self.length = length.clamp(MIN_STEPS, MAX_STEPS);
+
+        for lane in &mut self.lanes {
+            lane.steps.resize(self.length, Step::default());
+        }
+    }
+
+    pub fn add_lane(&mut self, sample_id: i64) {
+        self.lanes.push(Lane {
+            sample_id,
+            steps: vec![Step::default(); self.length],
+        });
+    }
+
+    pub fn beats(&self) -> f64 {
+        self.length as f64 * STEP_LENGTH
+    }
+
+    /// When a step plays, in beats from the pattern start
+    pub fn step_time(&self, step: usize, nudge: f32) -> f64 {
+        let offbeat = if step % 2 == 1 {
+            self.swing.clamp(0.0, 1.0) as f64 / 3.0
+        } else {
+            0.0
+        };
+
+        (step as f64 + offbeat + nudge.clamp(-0.5, 0.5) as f64) * STEP_LENGTH
+    }
+}
+
+/// The distinct samples a pattern chain uses, in lane order. A sample's index
+/// is its pad, which decides the note that triggers it
+pub fn kit<'a>(chain: impl IntoIterator<Item = &'a Pattern>) -> Vec<i64> {
+    let mut kit = vec![];
+
+    for pattern in chain {
+        for lane in &pattern.lanes {
+            if !kit.contains(&lane.sample_id) {
+                kit.push(lane.sample_id);
+            }
+        }
+    }
+
+    kit
+}
+
+/// One-shot sampler zones for a kit, one key per pad. Pads past the keyboard
+/// get none
+pub fn kit_patch(kit: &[i64]) -> SamplerPatch {
+    let zones = kit
+        .iter()
+        .enumerate()
+        .filter_map(|(pad, sample_id)| {
+            let note = pad_note(pad)?;
+            Some(Zone {
+                sample_id: *sample_id,
+                root_key: note,
+                keys: (note, note),
+                velocities: (1, 127),
+                loop_points: None,
+            })
+        })
+        .collect();
+
+    SamplerPatch {
+        zones,
+        // Drum hits play out unless a voice gets stolen
+        adsr: Adsr {
+            attack: 0.001,
+            decay: 0.0,
+            sustain: 1.0,
+            release: 0.05,
+        },
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Trigger {
+    pub note: u8,
+    pub velocity: u8,
+    pub probability: f32,
+}
+
+/// Engine-side playback of a pattern chain, synced to the transport
+pub struct StepSequencer {
+    player: LoopPlayer<Trigger>,
+    // xorshift state for step probability, no allocation or locking
+    random: u32,
+}
+
+impl StepSequencer {
+    pub fn new(chain: &[Pattern]) -> Self {
+        let kit = kit(chain);
+        let mut events = vec![];
+        let mut offset = 0.0;
+
+        for pattern in chain {
+            for lane in &pattern.lanes {
+                let note = match kit
+                    .iter()
+                    .position(|id| *id == lane.sample_id)
+                    .and_then(pad_note)
+                {
+                    Some(note) => note,
+                    None => continue,
+                };
+
+                for (index, step) in lane.steps.iter().enumerate() {
+                    if !step.is_active || index >= pattern.length {
+                        continue;
+                    }
+
+                    events.push((
+                        offset + pattern.step_time(index, step.nudge),
+                        Trigger {
+                            note,
+                            velocity: step.velocity,
+                            probability: step.probability,
+                        },
+                    ));
+                }
+            }
+
+            offset += pattern.beats();
+        }
+
+        // Steps nudged before the chain start play at its end instead
+        for (beat, _) in &mut events {
+            *beat = beat.rem_euclid(offset.max(STEP_LENGTH));
+        }
+        events.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        Self {
+            player: LoopPlayer::new(offset, events),
+            random: 0x9E37_79B9,
+        }
+    }
+
+    pub fn advance(
+        &mut self,
+        from: f64,
+        to: f64,
+        mut emit: impl FnMut(NoteEvent),
+    ) {
+        let random = &mut self.random;
+
+        self.player.advance(from, to, |trigger| {
+            *random ^= *random << 13;
+            *random ^= *random >> 17;
+            *random ^= *random << 5;
+            let roll = *random as f32 / u32::MAX as f32;
+
+            if roll < trigger.probability {
+                emit(NoteEvent::NoteOn {
+                    note: trigger.note,
+                    velocity: trigger.velocity,
+                });
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    fn pattern(steps: &[usize], probability: f32) -> Pattern {
+        let mut pattern = Pattern::new("Beat");
+        pattern.add_lane(7);
+        for step in steps {
+            pattern.lanes[0].steps[*step] = Step {
+                is_active: true,
+                probability,
+                ..Step::default()
+            };
+        }
+        pattern
+    }
+
+    #[test]
+    fn swing_delays_offbeat_steps() {
+        let mut pattern = Pattern::new("Swung");
+        pattern.swing = 1.0;
+
+        assert_close(pattern.step_time(0, 0.0), 0.0);
+        assert_close(pattern.step_time(1, 0.0), (1.0 + 1.0 / 3.0) * 0.25);
+        assert_close(pattern.step_time(2, 0.0), 0.5);
+        assert_close(pattern.step_time(3, 0.0), (3.0 + 1.0 / 3.0) * 0.25);
+
+        // Half swing, and a nudge on top of it
+        pattern.swing = 0.5;
+        assert_close(pattern.step_time(5, 0.0), (5.0 + 1.0 / 6.0) * 0.25);
+        assert_close(pattern.step_time(4, -0.5), 3.5 * 0.25);
+    }
+
+    #[test]
+    fn swing_and_nudge_are_clamped() {
+        let mut pattern = Pattern::new("Wild");
+        pattern.swing = 4.0;
+        assert_close(pattern.step_time(1, 2.0), (1.0 + 1.0 / 3.0 + 0.5) * 0.25);
+    }
+
+    #[test]
+    fn plays_steps_at_their_time() {
+        let mut sequencer = StepSequencer::new(&[pattern(&[0, 4, 9], 1.0)]);
+
+        let mut notes = vec![];
+        sequencer.advance(0.0, 4.0, |event| notes.push(event));
+        assert_eq!(
+            notes,
+            vec![
+                NoteEvent::NoteOn {
+                    note: DRUM_ROOT_NOTE,
+                    velocity: 100
+                };
+                3
+            ]
+        );
+
+        // Only the step between beats 1 and 2
+        let mut sequencer = StepSequencer::new(&[pattern(&[0, 4, 9], 1.0)]);
+        let mut count = 0;
+        sequencer.advance(0.5, 2.0, |_| count += 1);
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn probability_decides_whether_steps_play() {
+        let steps: Vec<usize> = (0..MIN_STEPS).collect();
+
+        // Ten times through the pattern, a beat at a time
+        let played = |probability| {
+            let mut sequencer =
+                StepSequencer::new(&[pattern(&steps, probability)]);
+            let mut count = 0;
+            for beat in 0..40 {
+                let from = beat as f64;
+                sequencer.advance(from, from + 1.0, |_| count += 1);
+            }
+            count
+        };
+
+        assert_eq!(played(0.0), 0);
+        assert_eq!(played(1.0), 160);
+        let count = played(0.5);
+        assert!((40..120).contains(&count), "{} of 160 played", count);
+    }
+}
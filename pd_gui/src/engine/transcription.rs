@@ -0,0 +1,532 @@
+//! Audio to MIDI: turns a monophonic recording, like a hummed idea, into a
+//! clip.
+//!
+//! The pitch of every analysis frame is tracked with YIN (de Cheveigné and
+//! Kawahara, 2002), whose aperiodicity gives a confidence. Notes start at
+//! onsets, where the level jumps, and where the pitch settles on another
+//! note. They end where the level or the confidence drops.
+
+use std::sync::Arc;
+
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use serde::{Deserialize, Serialize};
+
+use super::sample::SampleBuffer;
+use super::transport::NOTE_VALUES;
+use crate::app::midi::{ClipNote, MidiClip, MAX_NOTE, MAX_VELOCITY};
+
+// Frames are integrated over `WINDOW` samples and searched for periods up
+// to `MAX_PERIOD` samples, around 43 Hz at 44.1 kHz
+const WINDOW: usize = 1024;
+const MAX_PERIOD: usize = 1024;
+const HOP: usize = 256;
+const MAX_FREQUENCY: f64 = 2_000.0;
+// YIN's absolute threshold on the normalized difference
+const DIP: f64 = 0.15;
+// Frames quieter than this are silence, in dB
+const SILENCE: f64 = -50.0;
+// Frames a new pitch has to hold before it splits a note
+const SETTLE: usize = 3;
+// How far back onsets compare the level, in frames
+const ONSET_SPAN: usize = 3;
+
+/// How recordings are cleaned up into notes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Transcription {
+    // Frames whose pitch confidence is below this, between 0 and 1, end
+    // notes
+    pub confidence: f64,
+    // A level rise of this many dB starts a new note
+    pub sensitivity: f64,
+    // Notes shorter than this, in milliseconds, are dropped
+    pub min_length: f64,
+    // Index into `NOTE_VALUES` of the grid notes snap to
+    pub quantize: Option<usize>,
+}
+
+impl Default for Transcription {
+    fn default() -> Self {
+        Self {
+            confidence: 0.8,
+            sensitivity: 6.0,
+            min_length: 60.0,
+            quantize: Some(1),
+        }
+    }
+}
+
+impl Transcription {
+    pub fn quantize_name(&self) -> &'static str {
+        match self.quantize {
+            Some(index) => NOTE_VALUES[index.min(NOTE_VALUES.len() - 1)].0,
+            None => "Off",
+        }
+    }
+
+    /// The next grid, for editors that cycle through them. The grids stop
+    /// at a bar
+    pub fn next_quantize(&self) -> Option<usize> {
+        match self.quantize {
+            None => Some(0),
+            Some(index) if NOTE_VALUES[index].1 >= 4.0 => None,
+            Some(index) => Some(index + 1),
+        }
+    }
+}
+
+// What the tracker found in one frame
+struct Frame {
+    // Fractional MIDI note, if the frame has a pitch at all
+    pitch: Option<f64>,
+    confidence: f64,
+    level: f64,
+}
+
+/// Finds the periods of frames with YIN, computing the difference function
+/// with FFTs
+struct Tracker {
+    sample_rate: f64,
+    forward: Arc<dyn RealToComplex<f64>>,
+    inverse: Arc<dyn ComplexToReal<f64>>,
+    size: usize,
+}
+
+impl Tracker {
+    fn new(sample_rate: f64) -> Self {
+        let size = (WINDOW + MAX_PERIOD + WINDOW).next_power_of_two();
+        let mut planner = RealFftPlanner::<f64>::new();
+
+        Self {
+            sample_rate,
+            forward: planner.plan_fft_forward(size),
+            inverse: planner.plan_fft_inverse(size),
+            size,
+        }
+    }
+
+    // `samples` holds `WINDOW + MAX_PERIOD` samples
+    fn frame(&self, samples: &[f32]) -> Frame {
+        let energy: f64 = samples[..WINDOW]
+            .iter()
+            .map(|sample| (*sample as f64).powi(2))
+            .sum();
+        let level = 10.0 * (energy / WINDOW as f64).max(1e-12).log10();
+        let unpitched = Frame {
+            pitch: None,
+            confidence: 0.0,
+            level,
+        };
+        if level < SILENCE {
+            return unpitched;
+        }
+
+        // r(tau) = sum over the window of x[j] * x[j + tau], as the cross
+        // correlation of the window with the whole frame
+        let spectrum = |input: &[f32]| {
+            let mut padded = vec![0.0; self.size];
+            for (slot, sample) in padded.iter_mut().zip(input) {
+                *slot = *sample as f64;
+            }
+            let mut spectrum = self.forward.make_output_vec();
+            // The buffers are sized by the plan itself
+            let _ = self.forward.process(&mut padded, &mut spectrum);
+            spectrum
+        };
+        let window = spectrum(&samples[..WINDOW]);
+        let frame = spectrum(samples);
+        let mut product: Vec<Complex<f64>> = window
+            .iter()
+            .zip(&frame)
+            .map(|(a, b)| a.conj() * b)
+            .collect();
+        // The inverse ignores the imaginary parts of DC and Nyquist
+        product[0].im = 0.0;
+        let last = product.len() - 1;
+        product[last].im = 0.0;
+        let mut correlation = self.inverse.make_output_vec();
+        let _ = self.inverse.process(&mut product, &mut correlation);
+        let scale = 1.0 / self.size as f64;
+
+        // Energies of the shifted windows from running sums
+        let mut squares = Vec::with_capacity(samples.len() + 1);
+        squares.push(0.0);
+        for sample in samples {
+            let last = squares[squares.len() - 1];
+            squares.push(last + (*sample as f64).powi(2));
+        }
+
+        // The cumulative mean normalized difference
+        let min_period = (self.sample_rate / MAX_FREQUENCY) as usize;
+        let mut normalized = vec![1.0; MAX_PERIOD];
+        let mut running = 0.0;
+        for tau in 1..MAX_PERIOD {
+            let shifted = squares[tau + WINDOW] - squares[tau];
+            let difference =
+                (energy + shifted - 2.0 * correlation[tau] * scale).max(0.0);
+            running += difference;
+            normalized[tau] = if running > 0.0 {
+                difference * tau as f64 / running
+            } else {
+                1.0
+            };
+        }
+
+        // The first dip under the threshold, followed down to its minimum,
+        // otherwise the deepest one
+        let search = min_period.max(2)..MAX_PERIOD - 1;
+        let tau = search
+            .clone()
+            .find(|tau| normalized[*tau] < DIP)
+            .map(|mut tau| {
+                while tau + 1 < MAX_PERIOD - 1
+                    && normalized[tau + 1] < normalized[tau]
+                {
+                    tau += 1;
+                }
+                tau
+            })
+            .or_else(|| {
+                search.min_by(|a, b| normalized[*a].total_cmp(&normalized[*b]))
+            });
+        let tau = match tau {
+            Some(tau) => tau,
+            None => return unpitched,
+        };
+
+        // Parabolic interpolation between the neighbours
+        let (a, b, c) =
+            (normalized[tau - 1], normalized[tau], normalized[tau + 1]);
+        let curve = a - 2.0 * b + c;
+        let offset = if curve.abs() > 1e-12 {
+            (0.5 * (a - c) / curve).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        let frequency = self.sample_rate / (tau as f64 + offset);
+
+        Frame {
+            pitch: Some(69.0 + 12.0 * (frequency / 440.0).log2()),
+            confidence: (1.0 - b).clamp(0.0, 1.0),
+            level,
+        }
+    }
+}
+
+// A note found in the recording, in seconds
+struct Found {
+    start: f64,
+    end: f64,
+    // The pitch the note started on comes first
+    pitches: Vec<u8>,
+    level: f64,
+}
+
+impl Found {
+    // The note most frames agreed on
+    fn note(&mut self) -> u8 {
+        self.pitches.sort_unstable();
+        self.pitches[self.pitches.len() / 2]
+    }
+
+    fn velocity(&self) -> u8 {
+        let normal = ((self.level - SILENCE) / -SILENCE).clamp(0.0, 1.0);
+        (1.0 + normal * (MAX_VELOCITY - 1) as f64).round() as u8
+    }
+}
+
+impl Transcription {
+    /// Transcribes a buffer, mixed down to mono, into a clip at `tempo`.
+    /// The clip is as many bars long as the notes need
+    pub fn transcribe(&self, buffer: &SampleBuffer, tempo: f64) -> MidiClip {
+        let samples = buffer.mono();
+        let seconds = HOP as f64 / buffer.sample_rate;
+        let tracker = Tracker::new(buffer.sample_rate);
+
+        let frames: Vec<Frame> = (0..)
+            .map(|index| index * HOP)
+            .take_while(|start| start + WINDOW + MAX_PERIOD <= samples.len())
+            .map(|start| {
+                tracker.frame(&samples[start..start + WINDOW + MAX_PERIOD])
+            })
+            .collect();
+
+        let mut found: Vec<Found> = vec![];
+        let mut current: Option<Found> = None;
+        // A pitch that differs from the current note, and for how long
+        let mut pending: Option<(u8, usize)> = None;
+
+        for (index, frame) in frames.iter().enumerate() {
+            let time = index as f64 * seconds;
+            let pitch = frame
+                .pitch
+                .filter(|_| frame.confidence >= self.confidence)
+                .map(|pitch| pitch.round().clamp(0.0, MAX_NOTE as f64) as u8);
+            let onset = index >= ONSET_SPAN
+                && frame.level - frames[index - ONSET_SPAN].level
+                    >= self.sensitivity;
+
+            let pitch = match pitch {
+                Some(pitch) => pitch,
+                None => {
+                    found.extend(current.take());
+                    pending = None;
+                    continue;
+                }
+            };
+
+            let settled = match (&current, pending) {
+                (Some(note), _) if note.pitches.first() == Some(&pitch) => {
+                    pending = None;
+                    false
+                }
+                (Some(_), Some((candidate, count))) if candidate == pitch => {
+                    pending = Some((pitch, count + 1));
+                    count + 1 >= SETTLE
+                }
+                (Some(_), _) => {
+                    pending = Some((pitch, 1));
+                    false
+                }
+                (None, _) => false,
+            };
+
+            let split = onset || settled;
+            if let Some(note) = current.as_mut().filter(|_| !split) {
+                note.end = time + seconds;
+                note.level = note.level.max(frame.level);
+                note.pitches.push(pitch);
+                continue;
+            }
+
+            // A settled pitch started back when it first showed up
+            let start = if settled {
+                time - (SETTLE - 1) as f64 * seconds
+            } else {
+                time
+            };
+            if let Some(mut note) = current.take() {
+                note.end = note.end.min(start);
+                found.push(note);
+            }
+            pending = None;
+            current = Some(Found {
+                start,
+                end: time + seconds,
+                pitches: vec![pitch],
+                level: frame.level,
+            });
+        }
+        found.extend(current);
+
+        self.to_clip(found, tempo)
+    }
+
+    fn to_clip(&self, found: Vec<Found>, tempo: f64) -> MidiClip {
+        let beats = |seconds: f64| seconds * tempo / 60.0;
+        let grid = self
+            .quantize
+            .map(|index| NOTE_VALUES[index.min(NOTE_VALUES.len() - 1)].1);
+        let snap = |beat: f64| match grid {
+            Some(grid) => (beat / grid).round() * grid,
+            None => beat,
+        };
+
+        let mut notes: Vec<ClipNote> = vec![];
+        for mut note in found {
+            if (note.end - note.start) * 1_000.0 < self.min_length {
+                continue;
+            }
+
+            let start = snap(beats(note.start));
+            let mut end = snap(beats(note.end));
+            if let Some(grid) = grid {
+                end = end.max(start + grid);
+            }
+
+            // One note at a time, a later one cuts the one before
+            if let Some(previous) = notes.last_mut() {
+                if previous.start >= start {
+                    continue;
+                }
+                previous.length = previous.length.min(start - previous.start);
+            }
+
+            notes.push(ClipNote {
+                start,
+                length: end - start,
+                note: note.note(),
+                velocity: note.velocity(),
+            });
+        }
+
+        let end = notes
+            .iter()
+            .map(|note| note.start + note.length)
+            .fold(0.0, f64::max);
+
+        MidiClip {
+            length: ((end / 4.0).ceil() * 4.0).max(4.0),
+            notes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 44_100.0;
+
+    fn tone(frequency: f64, seconds: f64) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE) as usize)
+            .map(|index| {
+                let time = index as f64 / SAMPLE_RATE;
+                (0.5 * (std::f64::consts::TAU * frequency * time).sin()) as f32
+            })
+            .collect()
+    }
+
+    fn silence(seconds: f64) -> Vec<f32> {
+        vec![0.0; (seconds * SAMPLE_RATE) as usize]
+    }
+
+    fn found(start: f64, end: f64, pitches: &[u8]) -> Found {
+        Found {
+            start,
+            end,
+            pitches: pitches.to_vec(),
+            level: -10.0,
+        }
+    }
+
+    #[test]
+    fn tracks_the_pitch_of_tones() {
+        let tracker = Tracker::new(SAMPLE_RATE);
+
+        for (frequency, note) in [(110.0, 45.0), (440.0, 69.0), (880.0, 81.0)] {
+            let frame =
+                tracker.frame(&tone(frequency, 0.1)[..WINDOW + MAX_PERIOD]);
+            let pitch = frame.pitch.unwrap();
+            assert!(
+                (pitch - note).abs() < 0.1,
+                "{} Hz as {}",
+                frequency,
+                pitch
+            );
+            assert!(frame.confidence > 0.9);
+        }
+    }
+
+    #[test]
+    fn silence_and_noise_have_no_confident_pitch() {
+        let tracker = Tracker::new(SAMPLE_RATE);
+
+        let frame = tracker.frame(&silence(0.1)[..WINDOW + MAX_PERIOD]);
+        assert_eq!(frame.pitch, None);
+
+        // A fixed linear congruential generator, so the test is repeatable
+        let mut seed: u32 = 1;
+        let noise: Vec<f32> = (0..WINDOW + MAX_PERIOD)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (seed >> 8) as f32 / (1 << 24) as f32 - 0.5
+            })
+            .collect();
+        let frame = tracker.frame(&noise);
+        assert!(frame.confidence < Transcription::default().confidence);
+    }
+
+    #[test]
+    fn transcribes_a_melody() {
+        let samples = [
+            tone(440.0, 0.5),
+            silence(0.5),
+            tone(523.25, 0.5),
+            silence(0.1),
+        ]
+        .concat();
+        let buffer = SampleBuffer {
+            sample_rate: SAMPLE_RATE,
+            channels: vec![samples],
+        };
+
+        // A beat per second, on a 1/16 grid
+        let clip = Transcription::default().transcribe(&buffer, 60.0);
+
+        let notes: Vec<(f64, f64, u8)> = clip
+            .notes
+            .iter()
+            .map(|note| (note.start, note.length, note.note))
+            .collect();
+        assert_eq!(notes, vec![(0.0, 0.5, 69), (1.0, 0.5, 72)]);
+        assert!(clip.notes.iter().all(|note| note.velocity > 64));
+        assert_eq!(clip.length, 4.0);
+    }
+
+    #[test]
+    fn quantizes_to_the_grid() {
+        let sixteenths = Transcription::default();
+
+        // At 120 BPM a beat is half a second
+        let clip = sixteenths.to_clip(
+            vec![
+                found(0.01, 0.26, &[60, 61, 60]),
+                found(0.49, 0.52, &[62]),
+                found(0.6, 0.67, &[64]),
+                found(1.0, 4.1, &[65]),
+            ],
+            120.0,
+        );
+        let notes: Vec<(f64, f64, u8)> = clip
+            .notes
+            .iter()
+            .map(|note| (note.start, note.length, note.note))
+            .collect();
+        // The 30 ms note is dropped, the short one still lasts a step
+        assert_eq!(
+            notes,
+            vec![(0.0, 0.5, 60), (1.25, 0.25, 64), (2.0, 6.25, 65)]
+        );
+        assert_eq!(clip.length, 12.0);
+
+        let free = Transcription {
+            quantize: None,
+            ..sixteenths
+        };
+        let clip = free.to_clip(vec![found(0.01, 0.26, &[60])], 120.0);
+        assert!((clip.notes[0].start - 0.02).abs() < 1e-9);
+        assert!((clip.notes[0].length - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn later_notes_cut_earlier_ones() {
+        let clip = Transcription::default().to_clip(
+            vec![found(0.0, 1.0, &[60]), found(0.25, 1.0, &[67])],
+            120.0,
+        );
+
+        assert_eq!(clip.notes.len(), 2);
+        assert_eq!(clip.notes[0].length, 0.5);
+        assert_eq!(clip.notes[1].start, 0.5);
+    }
+
+    #[test]
+    fn grids_cycle_up_to_a_bar() {
+        let mut transcription = Transcription {
+            quantize: None,
+            ..Transcription::default()
+        };
+        let mut names = vec![transcription.quantize_name()];
+        loop {
+            transcription.quantize = transcription.next_quantize();
+            if transcription.quantize.is_none() {
+                break;
+            }
+            names.push(transcription.quantize_name());
+        }
+
+        assert_eq!(names.first(), Some(&"Off"));
+        assert_eq!(names.last(), Some(&"1 bar"));
+    }
+}
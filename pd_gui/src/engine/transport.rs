@@ -0,0 +1,135 @@
+use crate::app::midi::{MidiClip, NoteEvent};
+
+pub const DEFAULT_TEMPO: f64 = 120.0;
+
+/// Musical note values with their length in beats, shortest first
+pub const NOTE_VALUES: [(&str, f64); 10] = [
+    ("1/32", 0.125),
+    ("1/16", 0.25),
+    ("1/8T", 1.0 / 3.0),
+    ("1/8", 0.5),
+    ("1/4T", 2.0 / 3.0),
+    ("1/4", 1.0),
+    ("1/2", 2.0),
+    ("1 bar", 4.0),
+    ("2 bars", 8.0),
+    ("4 bars", 16.0),
+];
+
+/// The engine's musical clock, advanced once per rendered frame
+#[derive(Debug, Clone)]
+pub struct Transport {
+    pub is_playing: bool,
+    pub tempo: f64,
+    sample_rate: f64,
+    beat: f64,
+}
+
+impl Transport {
+    pub fn new(sample_rate: f64) -> Self {
+        Self {
+            is_playing: false,
+            tempo: DEFAULT_TEMPO,
+            sample_rate,
+            beat: 0.0,
+        }
+    }
+
+    /// Position in beats since the transport was last started
+    pub fn beat(&self) -> f64 {
+        self.beat
+    }
+
+    pub fn beats_per_frame(&self) -> f64 {
+        self.tempo / 60.0 / self.sample_rate
+    }
+
+    pub fn play(&mut self) {
+        self.is_playing = true;
+    }
+
+    /// Stops and rewinds to the start
+    pub fn stop(&mut self) {
+        self.is_playing = false;
+        self.beat = 0.0;
+    }
+
+    /// Moves one frame ahead, returning the beat range `[from, to)` it covered
+    pub fn advance(&mut self) -> Option<(f64, f64)> {
+        if !self.is_playing {
+            return None;
+        }
+
+        let from = self.beat;
+        self.beat += self.beats_per_frame();
+
+        Some((from, self.beat))
+    }
+}
+
+/// Walks a sorted list of looping events as the transport moves over them
+#[derive(Debug)]
+pub struct LoopPlayer<E> {
+    length: f64,
+    events: Vec<(f64, E)>,
+    cursor: usize,
+}
+
+impl<E: Copy> LoopPlayer<E> {
+    pub fn new(length: f64, events: Vec<(f64, E)>) -> Self {
+        Self {
+            length,
+            events,
+            cursor: 0,
+        }
+    }
+
+    /// Emits every event in the transport range `[from, to)`, wrapping around
+    /// the loop end
+    pub fn advance(&mut self, from: f64, to: f64, mut emit: impl FnMut(E)) {
+        if self.length <= 0.0 {
+            return;
+        }
+
+        let start = from % self.length;
+        let end = start + (to - from);
+
+        // The transport was moved, catch the cursor up to the new position
+        if self.cursor > 0 && self.events[self.cursor - 1].0 > start {
+            self.cursor = 0;
+        }
+        while self.cursor < self.events.len()
+            && self.events[self.cursor].0 < start
+        {
+            self.cursor += 1;
+        }
+
+        self.emit_until(end.min(self.length), &mut emit);
+
+        if end >= self.length {
+            // Events sitting exactly on the loop end (like note-offs of cut
+            // notes) still go out before wrapping
+            self.emit_until(f64::INFINITY, &mut emit);
+            self.cursor = 0;
+            self.emit_until(end - self.length, &mut emit);
+        }
+    }
+
+    fn emit_until(&mut self, beat: f64, emit: &mut impl FnMut(E)) {
+        while self.cursor < self.events.len()
+            && self.events[self.cursor].0 < beat
+        {
+            emit(self.events[self.cursor].1);
+            self.cursor += 1;
+        }
+    }
+}
+
+/// Turns a looping clip into note events
+pub type ClipPlayer = LoopPlayer<NoteEvent>;
+
+impl ClipPlayer {
+    pub fn from_clip(clip: &MidiClip) -> Self {
+        Self::new(clip.length, clip.events())
+    }
+}
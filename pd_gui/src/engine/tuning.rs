@@ -0,0 +1,532 @@
+//! Tunings: how note numbers turn into frequencies.
+//!
+//! A tuning is a Scala scale (`.scl`), the pitches of one period above the
+//! root, and a Scala keyboard mapping (`.kbm`) placing scale degrees on MIDI
+//! notes and fixing the frequency of a reference note. Without either, notes
+//! are 12-TET with A4 = 440 Hz.
+//!
+//! See <https://www.huygens-fokker.org/scala/scl_format.html>.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::app::midi::{note_to_hz, MAX_NOTE};
+
+pub const SCALE_EXTENSION: &str = "scl";
+pub const KEYBOARD_EXTENSION: &str = "kbm";
+
+const NOTES: usize = MAX_NOTE as usize + 1;
+// Keyboard mappings repeat far beyond the keyboard past this size
+const MAX_MAP_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TuningError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl TuningError {
+    fn new(line: usize, message: &str) -> Self {
+        Self {
+            line,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for TuningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.line, self.message)
+    }
+}
+
+// Lines that aren't comments, numbered from 1
+fn lines(source: &str) -> impl Iterator<Item = (usize, &str)> {
+    source
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.starts_with('!'))
+}
+
+// The first word of a line, the rest is a comment
+fn first_word(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+fn read(path: &Path) -> Result<String, TuningError> {
+    fs::read_to_string(path).map_err(|err| TuningError {
+        line: 0,
+        message: format!("failed to read {:?}: {}", path, err),
+    })
+}
+
+/// Pitches of one period, in cents above the root. The root itself isn't
+/// listed and the last pitch is the period, usually an octave
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scale {
+    pub description: String,
+    pub cents: Vec<f64>,
+}
+
+impl Scale {
+    pub fn equal(steps: usize) -> Self {
+        Self {
+            description: format!("{}-TET", steps),
+            cents: (1..=steps)
+                .map(|step| 1200.0 * step as f64 / steps as f64)
+                .collect(),
+        }
+    }
+
+    /// Reads a `.scl` file. Pitches with a period are in cents, the others
+    /// ratios like `3/2` or `2`
+    pub fn parse(source: &str) -> Result<Self, TuningError> {
+        let mut lines = lines(source);
+        // The description may be empty, so it's the first line of any kind
+        let (_, description) = lines
+            .next()
+            .ok_or_else(|| TuningError::new(1, "missing description"))?;
+
+        let (line, count) = lines
+            .next()
+            .ok_or_else(|| TuningError::new(2, "missing number of notes"))?;
+        let count: usize = first_word(count)
+            .parse()
+            .map_err(|_| TuningError::new(line, "expected number of notes"))?;
+
+        let cents = lines
+            .take(count)
+            .map(|(line, text)| parse_pitch(first_word(text), line))
+            .collect::<Result<Vec<f64>, TuningError>>()?;
+
+        if cents.len() < count {
+            return Err(TuningError::new(
+                line,
+                &format!("expected {} notes, found {}", count, cents.len()),
+            ));
+        }
+        if count == 0 {
+            return Err(TuningError::new(line, "a scale needs notes"));
+        }
+        if cents[count - 1] <= 0.0 {
+            return Err(TuningError::new(line, "the period must rise"));
+        }
+
+        Ok(Self {
+            description: description.to_string(),
+            cents,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, TuningError> {
+        Self::parse(&read(path)?)
+    }
+
+    fn period(&self) -> f64 {
+        self.cents[self.cents.len() - 1]
+    }
+
+    // Degrees past the period wrap into the next ones
+    fn degree(&self, degree: i64) -> f64 {
+        let steps = self.cents.len() as i64;
+        let periods = degree.div_euclid(steps);
+        let step = degree.rem_euclid(steps) as usize;
+        let within = if step == 0 { 0.0 } else { self.cents[step - 1] };
+
+        periods as f64 * self.period() + within
+    }
+}
+
+fn parse_pitch(text: &str, line: usize) -> Result<f64, TuningError> {
+    let invalid = || TuningError::new(line, &format!("invalid pitch {}", text));
+
+    if text.contains('.') {
+        return text.parse().map_err(|_| invalid());
+    }
+
+    let (numerator, denominator) = match text.split_once('/') {
+        Some((numerator, denominator)) => (numerator, denominator),
+        None => (text, "1"),
+    };
+    let numerator: f64 = numerator.parse().map_err(|_| invalid())?;
+    let denominator: f64 = denominator.parse().map_err(|_| invalid())?;
+    if numerator <= 0.0 || denominator <= 0.0 {
+        return Err(invalid());
+    }
+
+    Ok(1200.0 * (numerator / denominator).log2())
+}
+
+/// Where the degrees of a scale land on the keyboard
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyboardMap {
+    pub name: String,
+    // Keys the mapping covers, the others are silent
+    pub first: u8,
+    pub last: u8,
+    // The key playing the root of the scale
+    pub middle: u8,
+    pub reference: u8,
+    pub reference_frequency: f64,
+    // The degree the mapping repeats at, 0 meaning the scale's period
+    pub octave_degree: usize,
+    // Degrees of the keys from `middle` on, repeating. `None` keys are
+    // silent. An empty mapping runs through the scale degree by degree
+    pub mapping: Vec<Option<usize>>,
+}
+
+impl Default for KeyboardMap {
+    fn default() -> Self {
+        Self {
+            name: "Linear".to_string(),
+            first: 0,
+            last: MAX_NOTE,
+            middle: 60,
+            reference: 69,
+            reference_frequency: 440.0,
+            octave_degree: 0,
+            mapping: vec![],
+        }
+    }
+}
+
+impl KeyboardMap {
+    /// Reads a `.kbm` file, named after it
+    pub fn parse(source: &str, name: &str) -> Result<Self, TuningError> {
+        let mut lines = lines(source).filter(|(_, line)| !line.is_empty());
+        let mut next = |what: &str| {
+            lines
+                .next()
+                .map(|(line, text)| (line, first_word(text)))
+                .ok_or_else(|| {
+                    TuningError::new(0, &format!("missing {}", what))
+                })
+        };
+        let number = |(line, text): (usize, &str), what: &str| {
+            text.parse::<f64>().map_err(|_| {
+                TuningError::new(line, &format!("expected {}", what))
+            })
+        };
+        let key = |(line, text): (usize, &str), what: &str| {
+            text.parse::<u8>()
+                .ok()
+                .filter(|key| *key <= MAX_NOTE)
+                .ok_or_else(|| {
+                    TuningError::new(line, &format!("expected {} key", what))
+                })
+        };
+
+        let size = number(next("map size")?, "map size")? as usize;
+        if size > MAX_MAP_SIZE {
+            return Err(TuningError::new(0, "map size is too large"));
+        }
+        let first = key(next("first key")?, "first")?;
+        let last = key(next("last key")?, "last")?;
+        let middle = key(next("middle key")?, "middle")?;
+        let reference = key(next("reference key")?, "reference")?;
+        let reference_frequency =
+            number(next("reference frequency")?, "reference frequency")?;
+        let octave_degree =
+            number(next("octave degree")?, "octave degree")? as usize;
+
+        // Short mappings leave the keys at their end silent
+        let mapping = (0..size)
+            .map(|_| match next("mapping") {
+                Ok((_, "x")) | Err(_) => Ok(None),
+                Ok(entry) => number(entry, "degree or x")
+                    .map(|degree| Some(degree as usize)),
+            })
+            .collect::<Result<Vec<_>, TuningError>>()?;
+
+        if reference_frequency <= 0.0 {
+            return Err(TuningError::new(
+                0,
+                "reference frequency must be positive",
+            ));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            first,
+            last,
+            middle,
+            reference,
+            reference_frequency,
+            octave_degree,
+            mapping,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, TuningError> {
+        let name = path
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self::parse(&read(path)?, &name)
+    }
+
+    // Cents of a key above the middle key, if it plays
+    fn cents(&self, scale: &Scale, key: u8) -> Option<f64> {
+        if key < self.first || key > self.last {
+            return None;
+        }
+
+        let offset = key as i64 - self.middle as i64;
+        if self.mapping.is_empty() {
+            return Some(scale.degree(offset));
+        }
+
+        let size = self.mapping.len() as i64;
+        let repeats = offset.div_euclid(size);
+        let degree = self.mapping[offset.rem_euclid(size) as usize]?;
+        let octave = match self.octave_degree {
+            0 => scale.period(),
+            degree => scale.degree(degree as i64),
+        };
+
+        Some(repeats as f64 * octave + scale.degree(degree as i64))
+    }
+}
+
+/// The tuning of a composition. Unset parts fall back to 12-TET and the
+/// linear mapping around A4 = 440 Hz
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Tuning {
+    pub scale: Option<Scale>,
+    pub keyboard: Option<KeyboardMap>,
+    // Overrides the frequency of the mapping's reference key
+    pub root_frequency: Option<f64>,
+}
+
+impl Tuning {
+    pub fn name(&self) -> String {
+        let scale = self
+            .scale
+            .as_ref()
+            .map_or("12-TET", |scale| scale.description.as_str());
+        match &self.keyboard {
+            Some(keyboard) => format!("{} ({})", scale, keyboard.name),
+            None => scale.to_string(),
+        }
+    }
+
+    /// The frequency the reference key plays at
+    pub fn reference_frequency(&self) -> f64 {
+        self.root_frequency.unwrap_or_else(|| {
+            self.keyboard
+                .as_ref()
+                .map_or(440.0, |keyboard| keyboard.reference_frequency)
+        })
+    }
+
+    pub fn table(&self) -> NoteTable {
+        let equal;
+        let scale = match &self.scale {
+            Some(scale) => scale,
+            None => {
+                equal = Scale::equal(12);
+                &equal
+            }
+        };
+        let linear;
+        let keyboard = match &self.keyboard {
+            Some(keyboard) => keyboard,
+            None => {
+                linear = KeyboardMap::default();
+                &linear
+            }
+        };
+
+        // An unmapped reference key is taken as the root
+        let reference =
+            keyboard.cents(scale, keyboard.reference).unwrap_or(0.0);
+        let frequency = self.reference_frequency();
+
+        let mut table = NoteTable {
+            frequencies: [None; NOTES],
+        };
+        for (key, slot) in table.frequencies.iter_mut().enumerate() {
+            *slot = keyboard.cents(scale, key as u8).map(|cents| {
+                frequency * 2f64.powf((cents - reference) / 1200.0)
+            });
+        }
+
+        table
+    }
+}
+
+/// The frequency of every note under a tuning, looked up by instruments on
+/// the audio thread
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteTable {
+    frequencies: [Option<f64>; NOTES],
+}
+
+impl Default for NoteTable {
+    fn default() -> Self {
+        Tuning::default().table()
+    }
+}
+
+impl NoteTable {
+    /// `None` for keys the tuning leaves silent
+    pub fn frequency(&self, note: u8) -> Option<f64> {
+        self.frequencies.get(note as usize).copied().flatten()
+    }
+
+    /// The playback rate of a sample recorded at `root_key`, so that the
+    /// root key plays it as it is
+    pub fn ratio(&self, note: u8, root_key: u8) -> Option<f64> {
+        let root = self
+            .frequency(root_key)
+            .unwrap_or_else(|| note_to_hz(root_key));
+        Some(self.frequency(note)? / root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    const PYTHAGOREAN: &str = "! pythagorean.scl
+!
+Pythagorean fifths
+ 3
+!
+9/8
+701.955 cents, the rest is a comment
+2
+";
+
+    const WHITE_KEYS: &str = "! white.kbm
+12
+0
+127
+60
+69
+440.0
+12
+! C to B, black keys silent
+0
+x
+2
+x
+4
+5
+x
+7
+x
+9
+x
+11
+";
+
+    #[test]
+    fn scales_read_cents_and_ratios() {
+        let scale = Scale::parse(PYTHAGOREAN).unwrap();
+
+        assert_eq!(scale.description, "Pythagorean fifths");
+        assert_eq!(scale.cents.len(), 3);
+        assert_close(scale.cents[0], 1200.0 * (9.0f64 / 8.0).log2());
+        assert_close(scale.cents[1], 701.955);
+        assert_close(scale.cents[2], 1200.0);
+    }
+
+    #[test]
+    fn scale_errors_name_the_line() {
+        let err = Scale::parse("Short\n3\n100.0\n200.0\n").unwrap_err();
+        assert_eq!(err.line, 2);
+
+        let err = Scale::parse("Bad\n2\n100.0\nfive\n").unwrap_err();
+        assert_eq!(err.line, 4);
+
+        let err = Scale::parse("Falling\n1\n-100.0\n").unwrap_err();
+        assert_eq!(err.message, "the period must rise");
+
+        assert!(Scale::parse("Empty\n0\n").is_err());
+        assert!(Scale::parse("Zero ratio\n1\n0/1\n").is_err());
+    }
+
+    #[test]
+    fn degrees_wrap_into_periods() {
+        let scale = Scale::parse(PYTHAGOREAN).unwrap();
+
+        assert_close(scale.degree(0), 0.0);
+        assert_close(scale.degree(3), 1200.0);
+        assert_close(scale.degree(5), 1200.0 + 701.955);
+        assert_close(scale.degree(-1), -1200.0 + 701.955);
+    }
+
+    #[test]
+    fn keyboard_maps_read_every_field() {
+        let keyboard = KeyboardMap::parse(WHITE_KEYS, "white").unwrap();
+
+        assert_eq!(keyboard.name, "white");
+        assert_eq!((keyboard.first, keyboard.last), (0, MAX_NOTE));
+        assert_eq!((keyboard.middle, keyboard.reference), (60, 69));
+        assert_close(keyboard.reference_frequency, 440.0);
+        assert_eq!(keyboard.octave_degree, 12);
+        assert_eq!(keyboard.mapping.len(), 12);
+        assert_eq!(keyboard.mapping[0], Some(0));
+        assert_eq!(keyboard.mapping[1], None);
+        assert_eq!(keyboard.mapping[11], Some(11));
+    }
+
+    #[test]
+    fn keyboard_map_errors() {
+        assert!(
+            KeyboardMap::parse("2000\n0\n127\n60\n69\n440\n0\n", "").is_err()
+        );
+        assert!(KeyboardMap::parse("0\n0\n128\n60\n69\n440\n0\n", "").is_err());
+        assert!(KeyboardMap::parse("0\n0\n127\n60\n69\n0\n0\n", "").is_err());
+        assert!(KeyboardMap::parse("0\n0\n127\n60\n", "").is_err());
+    }
+
+    #[test]
+    fn the_default_tuning_is_12_tet() {
+        let table = NoteTable::default();
+
+        assert_close(table.frequency(69).unwrap(), 440.0);
+        assert_close(table.frequency(81).unwrap(), 880.0);
+        assert_close(table.frequency(60).unwrap(), note_to_hz(60));
+        assert_close(table.ratio(72, 60).unwrap(), 2.0);
+    }
+
+    #[test]
+    fn tables_follow_scale_and_mapping() {
+        let quarter_tones = Tuning {
+            scale: Some(Scale::equal(24)),
+            ..Tuning::default()
+        }
+        .table();
+        assert_close(quarter_tones.frequency(69).unwrap(), 440.0);
+        assert_close(
+            quarter_tones.frequency(70).unwrap(),
+            440.0 * 2f64.powf(50.0 / 1200.0),
+        );
+
+        let white_keys = Tuning {
+            keyboard: Some(KeyboardMap::parse(WHITE_KEYS, "white").unwrap()),
+            root_frequency: Some(432.0),
+            ..Tuning::default()
+        }
+        .table();
+        assert_close(white_keys.frequency(69).unwrap(), 432.0);
+        assert_close(white_keys.frequency(57).unwrap(), 216.0);
+        assert_eq!(white_keys.frequency(61), None);
+        assert_eq!(white_keys.ratio(61, 60), None);
+    }
+}
@@ -0,0 +1,376 @@
+//! Wavetable oscillator.
+//!
+//! A table is a row of single-cycle frames, morphed through with the position
+//! parameter. Every frame is resynthesized from its harmonics into mipmap
+//! levels holding fewer and fewer of them, and voices read from the level
+//! whose highest harmonic stays below Nyquist, so high notes don't alias.
+
+use std::sync::Arc;
+
+use realfft::num_complex::Complex;
+use realfft::RealFftPlanner;
+use serde::{Deserialize, Serialize};
+
+use super::effects::{Param, ParamSpec};
+use super::instrument::{pitch_ratio, Adsr, Envelope, Instrument, PITCH};
+use super::sample::SampleBuffer;
+use super::tuning::NoteTable;
+use crate::app::midi::{velocity_to_gain, NoteEvent};
+
+const VOICES: usize = 8;
+
+pub const POSITION: usize = 0;
+pub const GAIN: usize = 1;
+pub const PITCH_OFFSET: usize = 2;
+pub const PARAMS: [ParamSpec; 3] = [
+    ParamSpec::live("Position", 0.0, 1.0, 0.0),
+    ParamSpec::live("Gain", 0.0, 1.0, 1.0),
+    PITCH,
+];
+
+// Length of every mipmap level. The first level keeps all the harmonics that
+// fit, each next one half of them
+const TABLE_SIZE: usize = 2048;
+const HARMONICS: usize = TABLE_SIZE / 2;
+const LEVELS: usize = 11;
+// Tables longer than this are cut down, they would only cost memory
+const MAX_FRAMES: usize = 256;
+// Files up to this long are taken as one cycle when guessing
+const MAX_CYCLE: usize = 4096;
+// Seams of cycles cut out of recordings are crossfaded over this many frames
+const SEAM: usize = 64;
+
+/// How a WAV file is cut into frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Slicing {
+    // The whole file is one cycle
+    SingleCycle,
+    // Cycles of this many frames back to back, like most wavetable files
+    Frames(usize),
+    // A recording, like the captures of the sample creator, cut into
+    // `count` evenly spaced cycles of `length` frames
+    Spread { count: usize, length: usize },
+}
+
+impl Slicing {
+    const ALL: [Slicing; 6] = [
+        Slicing::SingleCycle,
+        Slicing::Frames(256),
+        Slicing::Frames(512),
+        Slicing::Frames(1024),
+        Slicing::Frames(2048),
+        Slicing::Spread {
+            count: 16,
+            length: 2048,
+        },
+    ];
+
+    /// A good guess for a file of this many frames
+    pub fn guess(frames: usize) -> Slicing {
+        if frames <= MAX_CYCLE {
+            Slicing::SingleCycle
+        } else if frames % 2048 == 0 && frames / 2048 <= MAX_FRAMES {
+            Slicing::Frames(2048)
+        } else {
+            Slicing::Spread {
+                count: 16,
+                length: 2048,
+            }
+        }
+    }
+
+    /// The next way of slicing, for editors that cycle through them
+    pub fn next(&self) -> Slicing {
+        let index = Self::ALL.iter().position(|slicing| slicing == self);
+        Self::ALL[index.map_or(0, |index| (index + 1) % Self::ALL.len())]
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Slicing::SingleCycle => "Single cycle".to_string(),
+            Slicing::Frames(size) => format!("{} frame cycles", size),
+            Slicing::Spread { count, .. } => format!("{} slices", count),
+        }
+    }
+
+    fn cut(&self, samples: &[f32]) -> Vec<Vec<f32>> {
+        match *self {
+            Slicing::SingleCycle => vec![samples.to_vec()],
+            Slicing::Frames(size) => samples
+                .chunks_exact(size.max(1))
+                .take(MAX_FRAMES)
+                .map(<[f32]>::to_vec)
+                .collect(),
+            Slicing::Spread { count, length } => {
+                let count = count.clamp(1, MAX_FRAMES);
+                let span = length + SEAM;
+                if samples.len() < span {
+                    return vec![samples.to_vec()];
+                }
+
+                let step =
+                    (samples.len() - span) / count.max(2).saturating_sub(1);
+                (0..count)
+                    .map(|slice| {
+                        let slice = &samples[slice * step..slice * step + span];
+                        // The start blends in what follows the end, so the
+                        // cycle wraps around without a click
+                        (0..length)
+                            .map(|frame| {
+                                if frame < SEAM {
+                                    let fade = frame as f32 / SEAM as f32;
+                                    slice[frame] * fade
+                                        + slice[length + frame] * (1.0 - fade)
+                                } else {
+                                    slice[frame]
+                                }
+                            })
+                            .collect()
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+pub struct Wavetable {
+    // Mipmap levels of every frame
+    frames: Vec<Vec<Vec<f32>>>,
+}
+
+impl Wavetable {
+    /// Resynthesizes cycles of any length into band-limited frames,
+    /// normalized to the loudest one
+    pub fn new(cycles: &[Vec<f32>]) -> Self {
+        let mut planner = RealFftPlanner::<f64>::new();
+        let inverse = planner.plan_fft_inverse(TABLE_SIZE);
+
+        let spectra: Vec<Vec<Complex<f64>>> = cycles
+            .iter()
+            .filter(|cycle| cycle.len() >= 2)
+            .map(|cycle| {
+                let forward = planner.plan_fft_forward(cycle.len());
+                let mut input: Vec<f64> =
+                    cycle.iter().map(|sample| *sample as f64).collect();
+                let mut spectrum = forward.make_output_vec();
+                // The buffers are sized by the plan itself
+                let _ = forward.process(&mut input, &mut spectrum);
+
+                // Harmonic k of the cycle lands on bin k of the table,
+                // whatever the cycle's length. DC is dropped
+                let scale = 1.0 / cycle.len() as f64;
+                let mut harmonics = vec![Complex::new(0.0, 0.0); HARMONICS + 1];
+                for (harmonic, bin) in
+                    harmonics.iter_mut().zip(&spectrum).take(HARMONICS).skip(1)
+                {
+                    *harmonic = *bin * scale;
+                }
+                harmonics
+            })
+            .collect();
+
+        let mut frames: Vec<Vec<Vec<f32>>> = spectra
+            .iter()
+            .map(|harmonics| {
+                (0..LEVELS)
+                    .map(|level| {
+                        let kept = HARMONICS >> level;
+                        let mut spectrum = harmonics.clone();
+                        for bin in spectrum.iter_mut().skip(kept + 1) {
+                            *bin = Complex::new(0.0, 0.0);
+                        }
+
+                        let mut output = inverse.make_output_vec();
+                        let _ = inverse.process(&mut spectrum, &mut output);
+                        output.iter().map(|sample| *sample as f32).collect()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let peak = frames
+            .iter()
+            .flat_map(|levels| &levels[0])
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        if peak > 0.0 {
+            for sample in frames.iter_mut().flatten().flatten() {
+                *sample /= peak;
+            }
+        }
+
+        Self { frames }
+    }
+
+    /// Cuts a buffer, mixed down to mono, into cycles
+    pub fn from_buffer(buffer: &SampleBuffer, slicing: Slicing) -> Self {
+        Self::new(&slicing.cut(&buffer.mono()))
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    // The fullest level without harmonics above Nyquist
+    fn level(frequency: f64, sample_rate: f64) -> usize {
+        let fit = sample_rate / 2.0 / frequency.abs().max(1.0);
+        if fit >= HARMONICS as f64 {
+            return 0;
+        }
+        ((HARMONICS as f64 / fit).log2().ceil() as usize).min(LEVELS - 1)
+    }
+
+    /// Reads a level at a phase between 0 and 1, morphing between the two
+    /// frames around `position`, itself between 0 and 1
+    fn sample(&self, position: f64, phase: f64, level: usize) -> f64 {
+        if self.frames.is_empty() {
+            return 0.0;
+        }
+
+        let read = |frame: usize| {
+            let table = &self.frames[frame][level];
+            let index = phase * TABLE_SIZE as f64;
+            let first = index as usize % TABLE_SIZE;
+            let fraction = index.fract();
+            let a = table[first] as f64;
+            let b = table[(first + 1) % TABLE_SIZE] as f64;
+            a + (b - a) * fraction
+        };
+
+        let frame = position.clamp(0.0, 1.0) * (self.frames.len() - 1) as f64;
+        let first = frame as usize;
+        let fraction = frame.fract();
+        if fraction == 0.0 || first + 1 >= self.frames.len() {
+            read(first)
+        } else {
+            read(first) * (1.0 - fraction) + read(first + 1) * fraction
+        }
+    }
+}
+
+struct WavetableVoice {
+    note: Option<u8>,
+    frequency: f64,
+    phase: f64,
+    gain: f64,
+    age: u64,
+    envelope: Envelope,
+}
+
+/// A polyphonic synth reading one wavetable
+pub struct WavetableSynth {
+    params: Vec<Param>,
+    table: Arc<Wavetable>,
+    voices: Vec<WavetableVoice>,
+    notes_played: u64,
+    sample_rate: f64,
+    tuning: NoteTable,
+}
+
+impl WavetableSynth {
+    pub fn new(params: Vec<Param>, table: Arc<Wavetable>) -> Self {
+        let voices = (0..VOICES)
+            .map(|_| WavetableVoice {
+                note: None,
+                frequency: 110.0,
+                phase: 0.0,
+                gain: 0.0,
+                age: 0,
+                envelope: Envelope::new(Adsr::default()),
+            })
+            .collect();
+
+        Self {
+            params,
+            table,
+            voices,
+            notes_played: 0,
+            sample_rate: 44_100.0,
+            tuning: NoteTable::default(),
+        }
+    }
+
+    // Prefer a silent voice, otherwise steal the oldest one
+    fn free_voice(&mut self) -> &mut WavetableVoice {
+        let index = self
+            .voices
+            .iter()
+            .position(|voice| voice.envelope.is_idle())
+            .unwrap_or_else(|| {
+                self.voices
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, voice)| voice.age)
+                    .map(|(index, _)| index)
+                    .unwrap_or(0)
+            });
+
+        &mut self.voices[index]
+    }
+}
+
+impl Instrument for WavetableSynth {
+    fn reset(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+        for voice in &mut self.voices {
+            voice.envelope.reset(sample_rate);
+            voice.note = None;
+        }
+    }
+
+    fn note(&mut self, event: NoteEvent) {
+        match event {
+            NoteEvent::NoteOn { note, velocity } => {
+                let frequency = match self.tuning.frequency(note) {
+                    Some(frequency) => frequency,
+                    None => return,
+                };
+                self.notes_played += 1;
+                let age = self.notes_played;
+
+                let voice = self.free_voice();
+                voice.note = Some(note);
+                voice.frequency = frequency;
+                voice.phase = 0.0;
+                voice.gain = velocity_to_gain(velocity);
+                voice.age = age;
+                voice.envelope.gate_on();
+            }
+            NoteEvent::NoteOff { note } => {
+                for voice in &mut self.voices {
+                    if voice.note == Some(note) {
+                        voice.note = None;
+                        voice.envelope.gate_off();
+                    }
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self) -> (f64, f64) {
+        let position = self.params[POSITION].get();
+        let pitch = pitch_ratio(self.params[PITCH_OFFSET].get());
+        let mut mix = 0.0;
+
+        for voice in &mut self.voices {
+            if voice.envelope.is_idle() {
+                continue;
+            }
+
+            let frequency = voice.frequency * pitch;
+            let level = Wavetable::level(frequency, self.sample_rate);
+            let sample = self.table.sample(position, voice.phase, level);
+            voice.phase = (voice.phase + frequency / self.sample_rate).fract();
+
+            mix += sample * voice.envelope.tick() * voice.gain;
+        }
+
+        // Leave headroom for all voices playing at once
+        let mix = mix * 0.25 * self.params[GAIN].get();
+
+        (mix, mix)
+    }
+
+    fn set_tuning(&mut self, table: &NoteTable) {
+        self.tuning.clone_from(table);
+    }
+}
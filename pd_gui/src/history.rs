@@ -0,0 +1,256 @@
+//! Undo and redo.
+//!
+//! Every step is a snapshot of the project, in JSON, taken before the edit
+//! that started it. Snapshots are compact next to the samples they refer to,
+//! and taking them covers the panes, the composition, the mixer and the
+//! samples alike, without every edit knowing how to revert itself.
+//!
+//! An edit only becomes a step once the next message shows it changed
+//! something, so edits that turn out to do nothing don't clear the redo
+//! steps.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+// The oldest steps are dropped past this many bytes of snapshots
+const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+// Edits with the same key closer together than this are one step
+const MERGE_WINDOW: Duration = Duration::from_secs(1);
+
+/// How an edit joins the history
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// A step of its own
+    Edit,
+    /// Grabs a control, the edits until it's released are one step
+    Begin,
+    End,
+    /// Edits in a row with the same key are one step, like the moves of a
+    /// slider or a divider
+    Merge(u64),
+}
+
+impl Step {
+    pub fn merge(key: impl Hash) -> Self {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        Step::Merge(hasher.finish())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+    undo: VecDeque<String>,
+    redo: Vec<String>,
+    // Bytes held by both stacks
+    size: usize,
+    // The state before the last edit, until it's known whether it changed
+    pending: Option<String>,
+    // A control is grabbed
+    is_grabbed: bool,
+    // Key and time of the last edit that may be merged with
+    merging: Option<(u64, Instant)>,
+}
+
+impl History {
+    /// Whether there's no edit to settle yet. The edits to a grabbed control
+    /// only settle once it's released
+    pub fn is_settled(&self) -> bool {
+        self.pending.is_none() || self.is_grabbed
+    }
+
+    /// Turns the pending edit into a step if `current` differs from the
+    /// state it started from
+    pub fn settle(&mut self, current: &str) {
+        if self.is_grabbed {
+            return;
+        }
+        if let Some(before) = self.pending.take() {
+            if before != current {
+                self.clear_redo();
+                self.size += before.len();
+                self.undo.push_back(before);
+                self.trim();
+            }
+        }
+    }
+
+    /// Called before an edit is applied, after settling the last one.
+    /// Returns whether it starts a step, which then needs the state it
+    /// starts from
+    pub fn edit(&mut self, step: Step) -> bool {
+        let now = Instant::now();
+        match step {
+            Step::Edit => {
+                self.is_grabbed = false;
+                self.merging = None;
+                true
+            }
+            Step::Begin => {
+                let starts_step = !self.is_grabbed;
+                self.is_grabbed = true;
+                self.merging = None;
+                starts_step
+            }
+            Step::End => {
+                self.is_grabbed = false;
+                false
+            }
+            Step::Merge(_) if self.is_grabbed => false,
+            Step::Merge(key) => {
+                let is_merged = matches!(
+                    self.merging,
+                    Some((last, time))
+                        if last == key && now - time < MERGE_WINDOW
+                );
+                self.merging = Some((key, now));
+                !is_merged
+            }
+        }
+    }
+
+    pub fn start(&mut self, before: String) {
+        self.pending = Some(before);
+    }
+
+    /// The state to go back to, `current` becomes the step to redo
+    pub fn undo(&mut self, current: String) -> Option<String> {
+        let state = self.undo.pop_back()?;
+        self.size -= state.len();
+        self.size += current.len();
+        self.redo.push(current);
+        self.release();
+        Some(state)
+    }
+
+    pub fn redo(&mut self, current: String) -> Option<String> {
+        let state = self.redo.pop()?;
+        self.size -= state.len();
+        self.size += current.len();
+        self.undo.push_back(current);
+        self.release();
+        self.trim();
+        Some(state)
+    }
+
+    // Undoing ends whatever was grabbed or merging
+    fn release(&mut self) {
+        self.pending = None;
+        self.is_grabbed = false;
+        self.merging = None;
+    }
+
+    fn clear_redo(&mut self) {
+        self.size -= self.redo.iter().map(String::len).sum::<usize>();
+        self.redo.clear();
+    }
+
+    fn trim(&mut self) {
+        while self.size > MEMORY_LIMIT {
+            match self.undo.pop_front() {
+                Some(state) => self.size -= state.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Goes through an edit the way the app does: settle the last one, then
+    // start a step if this one needs it
+    fn apply(
+        history: &mut History,
+        state: &mut String,
+        step: Step,
+        next: &str,
+    ) {
+        history.settle(state);
+        if history.edit(step) {
+            history.start(state.clone());
+        }
+        *state = next.to_string();
+    }
+
+    fn undo_all(history: &mut History, mut state: String) -> Vec<String> {
+        let mut states = vec![];
+        while let Some(previous) = history.undo(state) {
+            states.push(previous.clone());
+            state = previous;
+        }
+        states
+    }
+
+    #[test]
+    fn merges_edits_with_the_same_key() {
+        let (mut history, mut state) = (History::default(), "0".to_string());
+        apply(&mut history, &mut state, Step::merge("volume"), "1");
+        apply(&mut history, &mut state, Step::merge("volume"), "2");
+        apply(&mut history, &mut state, Step::merge("volume"), "3");
+        apply(&mut history, &mut state, Step::merge("pan"), "4");
+        history.settle(&state);
+
+        assert_eq!(undo_all(&mut history, state), vec!["3", "0"]);
+    }
+
+    #[test]
+    fn stops_merging_after_the_window() {
+        let (mut history, mut state) = (History::default(), "0".to_string());
+        apply(&mut history, &mut state, Step::merge("volume"), "1");
+
+        let (key, _) = history.merging.unwrap();
+        let earlier = Instant::now().checked_sub(MERGE_WINDOW).unwrap();
+        history.merging = Some((key, earlier));
+        apply(&mut history, &mut state, Step::merge("volume"), "2");
+        history.settle(&state);
+
+        assert_eq!(undo_all(&mut history, state), vec!["1", "0"]);
+    }
+
+    #[test]
+    fn grabbed_controls_make_one_step() {
+        let (mut history, mut state) = (History::default(), "0".to_string());
+        // Grabbing changes nothing yet, the moves after it do
+        apply(&mut history, &mut state, Step::Begin, "0");
+        apply(&mut history, &mut state, Step::merge("volume"), "1");
+        apply(&mut history, &mut state, Step::merge("volume"), "2");
+        apply(&mut history, &mut state, Step::End, "2");
+        apply(&mut history, &mut state, Step::Edit, "3");
+        apply(&mut history, &mut state, Step::Begin, "3");
+        apply(&mut history, &mut state, Step::merge("volume"), "4");
+        apply(&mut history, &mut state, Step::End, "4");
+        history.settle(&state);
+
+        assert_eq!(undo_all(&mut history, state), vec!["3", "2", "0"]);
+    }
+
+    #[test]
+    fn edits_that_change_nothing_keep_redo() {
+        let (mut history, mut state) = (History::default(), "0".to_string());
+        apply(&mut history, &mut state, Step::Edit, "1");
+        history.settle(&state);
+        state = history.undo(state).unwrap();
+
+        apply(&mut history, &mut state, Step::Edit, "0");
+        history.settle(&state);
+        assert_eq!(history.redo(state), Some("1".to_string()));
+    }
+
+    #[test]
+    fn drops_the_oldest_steps_past_the_limit() {
+        let snapshot = |digit: &str| digit.repeat(MEMORY_LIMIT / 2 + 1);
+        let (mut history, mut state) = (History::default(), snapshot("0"));
+        apply(&mut history, &mut state, Step::Edit, &snapshot("1"));
+        apply(&mut history, &mut state, Step::Edit, &snapshot("2"));
+        history.settle(&state);
+        assert!(history.size <= MEMORY_LIMIT);
+
+        let states = undo_all(&mut history, state);
+        assert_eq!(states.len(), 1);
+        assert!(states[0].starts_with('1'));
+    }
+}
@@ -0,0 +1,820 @@
+//! Named actions and the keys bound to them.
+//!
+//! Bindings are kept in `keymap.toml` in the config directory, by action:
+//!
+//! ```toml
+//! save = "Cmd+S"
+//! split-vertical = "Cmd+G V"
+//! undo = ""
+//! ```
+//!
+//! A binding is a key or a chord of two keys pressed in turn, each with any
+//! of `Cmd`, `Ctrl`, `Alt`, `Shift` and `Super`. `Cmd` is the command key on
+//! macOS and Ctrl elsewhere. Only bindings that differ from the defaults are
+//! written, an empty one leaves the action unbound.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context};
+use iced::keyboard::{KeyCode, Modifiers};
+use iced::pane_grid::{Axis, Direction};
+
+use super::paths;
+use super::Message;
+
+const FILE: &str = "keymap.toml";
+// Keys in the longest chord
+pub const CHORD_LENGTH: usize = 2;
+// The second key of a chord has to follow the first within this
+const CHORD_TIMEOUT: Duration = Duration::from_secs(2);
+
+const COMMAND: Modifiers = if cfg!(target_os = "macos") {
+    Modifiers::LOGO
+} else {
+    Modifiers::CTRL
+};
+
+// In the order they're written, `Cmd` first as it's one of the others
+const MODIFIERS: [(&str, Modifiers); 5] = [
+    ("Cmd", COMMAND),
+    ("Ctrl", Modifiers::CTRL),
+    ("Alt", Modifiers::ALT),
+    ("Shift", Modifiers::SHIFT),
+    ("Super", Modifiers::LOGO),
+];
+
+const KEYS: [(KeyCode, &str); 72] = [
+    (KeyCode::A, "A"),
+    (KeyCode::B, "B"),
+    (KeyCode::C, "C"),
+    (KeyCode::D, "D"),
+    (KeyCode::E, "E"),
+    (KeyCode::F, "F"),
+    (KeyCode::G, "G"),
+    (KeyCode::H, "H"),
+    (KeyCode::I, "I"),
+    (KeyCode::J, "J"),
+    (KeyCode::K, "K"),
+    (KeyCode::L, "L"),
+    (KeyCode::M, "M"),
+    (KeyCode::N, "N"),
+    (KeyCode::O, "O"),
+    (KeyCode::P, "P"),
+    (KeyCode::Q, "Q"),
+    (KeyCode::R, "R"),
+    (KeyCode::S, "S"),
+    (KeyCode::T, "T"),
+    (KeyCode::U, "U"),
+    (KeyCode::V, "V"),
+    (KeyCode::W, "W"),
+    (KeyCode::X, "X"),
+    (KeyCode::Y, "Y"),
+    (KeyCode::Z, "Z"),
+    (KeyCode::Key0, "0"),
+    (KeyCode::Key1, "1"),
+    (KeyCode::Key2, "2"),
+    (KeyCode::Key3, "3"),
+    (KeyCode::Key4, "4"),
+    (KeyCode::Key5, "5"),
+    (KeyCode::Key6, "6"),
+    (KeyCode::Key7, "7"),
+    (KeyCode::Key8, "8"),
+    (KeyCode::Key9, "9"),
+    (KeyCode::F1, "F1"),
+    (KeyCode::F2, "F2"),
+    (KeyCode::F3, "F3"),
+    (KeyCode::F4, "F4"),
+    (KeyCode::F5, "F5"),
+    (KeyCode::F6, "F6"),
+    (KeyCode::F7, "F7"),
+    (KeyCode::F8, "F8"),
+    (KeyCode::F9, "F9"),
+    (KeyCode::F10, "F10"),
+    (KeyCode::F11, "F11"),
+    (KeyCode::F12, "F12"),
+    (KeyCode::Up, "Up"),
+    (KeyCode::Down, "Down"),
+    (KeyCode::Left, "Left"),
+    (KeyCode::Right, "Right"),
+    (KeyCode::Space, "Space"),
+    (KeyCode::Enter, "Enter"),
+    (KeyCode::Tab, "Tab"),
+    (KeyCode::Escape, "Esc"),
+    (KeyCode::Backspace, "Backspace"),
+    (KeyCode::Delete, "Delete"),
+    (KeyCode::Home, "Home"),
+    (KeyCode::End, "End"),
+    (KeyCode::PageUp, "PageUp"),
+    (KeyCode::PageDown, "PageDown"),
+    (KeyCode::LBracket, "["),
+    (KeyCode::RBracket, "]"),
+    (KeyCode::Comma, ","),
+    (KeyCode::Period, "."),
+    (KeyCode::Slash, "/"),
+    (KeyCode::Backslash, "\\"),
+    (KeyCode::Semicolon, ";"),
+    (KeyCode::Apostrophe, "'"),
+    (KeyCode::Minus, "-"),
+    (KeyCode::Equals, "="),
+];
+
+/// Something the user can do from the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Action {
+    CommandPalette,
+    SplitVertical,
+    SplitHorizontal,
+    ClosePane,
+    FocusUp,
+    FocusDown,
+    FocusLeft,
+    FocusRight,
+    NewTab,
+    CloseTab,
+    NextTab,
+    PreviousTab,
+    MoveTabRight,
+    MoveTabLeft,
+    Save,
+    SaveAs,
+    Open,
+    Undo,
+    Redo,
+    TogglePlayback,
+    ToggleMusicalTyping,
+    Workspace1,
+    Workspace2,
+    Workspace3,
+    Workspace4,
+    Workspace5,
+    Workspace6,
+    Workspace7,
+    Workspace8,
+    Workspace9,
+}
+
+impl Action {
+    /// Every action, in the order the cheat sheet lists them
+    pub const ALL: [Action; 30] = [
+        Action::CommandPalette,
+        Action::SplitVertical,
+        Action::SplitHorizontal,
+        Action::ClosePane,
+        Action::FocusUp,
+        Action::FocusDown,
+        Action::FocusLeft,
+        Action::FocusRight,
+        Action::NewTab,
+        Action::CloseTab,
+        Action::NextTab,
+        Action::PreviousTab,
+        Action::MoveTabRight,
+        Action::MoveTabLeft,
+        Action::Save,
+        Action::SaveAs,
+        Action::Open,
+        Action::Undo,
+        Action::Redo,
+        Action::TogglePlayback,
+        Action::ToggleMusicalTyping,
+        Action::Workspace1,
+        Action::Workspace2,
+        Action::Workspace3,
+        Action::Workspace4,
+        Action::Workspace5,
+        Action::Workspace6,
+        Action::Workspace7,
+        Action::Workspace8,
+        Action::Workspace9,
+    ];
+
+    /// How it's named in the config file
+    pub fn id(&self) -> &'static str {
+        match self {
+            Action::CommandPalette => "command-palette",
+            Action::SplitVertical => "split-vertical",
+            Action::SplitHorizontal => "split-horizontal",
+            Action::ClosePane => "close-pane",
+            Action::FocusUp => "focus-up",
+            Action::FocusDown => "focus-down",
+            Action::FocusLeft => "focus-left",
+            Action::FocusRight => "focus-right",
+            Action::NewTab => "new-tab",
+            Action::CloseTab => "close-tab",
+            Action::NextTab => "next-tab",
+            Action::PreviousTab => "previous-tab",
+            Action::MoveTabRight => "move-tab-right",
+            Action::MoveTabLeft => "move-tab-left",
+            Action::Save => "save",
+            Action::SaveAs => "save-as",
+            Action::Open => "open",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::TogglePlayback => "toggle-playback",
+            Action::ToggleMusicalTyping => "toggle-musical-typing",
+            Action::Workspace1 => "workspace-1",
+            Action::Workspace2 => "workspace-2",
+            Action::Workspace3 => "workspace-3",
+            Action::Workspace4 => "workspace-4",
+            Action::Workspace5 => "workspace-5",
+            Action::Workspace6 => "workspace-6",
+            Action::Workspace7 => "workspace-7",
+            Action::Workspace8 => "workspace-8",
+            Action::Workspace9 => "workspace-9",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::CommandPalette => "Command palette",
+            Action::SplitVertical => "Split pane vertically",
+            Action::SplitHorizontal => "Split pane horizontally",
+            Action::ClosePane => "Close pane",
+            Action::FocusUp => "Focus pane above",
+            Action::FocusDown => "Focus pane below",
+            Action::FocusLeft => "Focus pane to the left",
+            Action::FocusRight => "Focus pane to the right",
+            Action::NewTab => "New tab",
+            Action::CloseTab => "Close tab",
+            Action::NextTab => "Next tab",
+            Action::PreviousTab => "Previous tab",
+            Action::MoveTabRight => "Move tab right",
+            Action::MoveTabLeft => "Move tab left",
+            Action::Save => "Save",
+            Action::SaveAs => "Save as",
+            Action::Open => "Open",
+            Action::Undo => "Undo",
+            Action::Redo => "Redo",
+            Action::TogglePlayback => "Play or stop",
+            Action::ToggleMusicalTyping => "Musical typing",
+            Action::Workspace1 => "Workspace 1",
+            Action::Workspace2 => "Workspace 2",
+            Action::Workspace3 => "Workspace 3",
+            Action::Workspace4 => "Workspace 4",
+            Action::Workspace5 => "Workspace 5",
+            Action::Workspace6 => "Workspace 6",
+            Action::Workspace7 => "Workspace 7",
+            Action::Workspace8 => "Workspace 8",
+            Action::Workspace9 => "Workspace 9",
+        }
+    }
+
+    /// The heading it's listed under
+    pub fn group(&self) -> &'static str {
+        match self {
+            Action::CommandPalette => "General",
+            Action::SplitVertical
+            | Action::SplitHorizontal
+            | Action::ClosePane
+            | Action::FocusUp
+            | Action::FocusDown
+            | Action::FocusLeft
+            | Action::FocusRight => "Panes",
+            Action::NewTab
+            | Action::CloseTab
+            | Action::NextTab
+            | Action::PreviousTab
+            | Action::MoveTabRight
+            | Action::MoveTabLeft => "Tabs",
+            Action::Save | Action::SaveAs | Action::Open => "Project",
+            Action::Undo | Action::Redo => "History",
+            Action::TogglePlayback | Action::ToggleMusicalTyping => "Transport",
+            _ => "Workspaces",
+        }
+    }
+
+    pub fn message(&self) -> Message {
+        match self {
+            Action::CommandPalette => Message::ToggleCommandPalette,
+            Action::SplitVertical => Message::SplitFocused(Axis::Vertical),
+            Action::SplitHorizontal => Message::SplitFocused(Axis::Horizontal),
+            Action::ClosePane => Message::CloseFocused,
+            Action::FocusUp => Message::FocusAdjacent(Direction::Up),
+            Action::FocusDown => Message::FocusAdjacent(Direction::Down),
+            Action::FocusLeft => Message::FocusAdjacent(Direction::Left),
+            Action::FocusRight => Message::FocusAdjacent(Direction::Right),
+            Action::NewTab => Message::NewTab,
+            Action::CloseTab => Message::CloseActiveTab,
+            Action::NextTab => Message::CycleTab(1),
+            Action::PreviousTab => Message::CycleTab(-1),
+            Action::MoveTabRight => Message::MoveTab(1),
+            Action::MoveTabLeft => Message::MoveTab(-1),
+            Action::Save => Message::SaveProject,
+            Action::SaveAs => Message::SaveProjectAs,
+            Action::Open => Message::OpenProject,
+            Action::Undo => Message::Undo,
+            Action::Redo => Message::Redo,
+            Action::TogglePlayback => Message::TogglePlayback,
+            Action::ToggleMusicalTyping => Message::ToggleMusicalTyping,
+            Action::Workspace1 => Message::SwitchWorkspace(0),
+            Action::Workspace2 => Message::SwitchWorkspace(1),
+            Action::Workspace3 => Message::SwitchWorkspace(2),
+            Action::Workspace4 => Message::SwitchWorkspace(3),
+            Action::Workspace5 => Message::SwitchWorkspace(4),
+            Action::Workspace6 => Message::SwitchWorkspace(5),
+            Action::Workspace7 => Message::SwitchWorkspace(6),
+            Action::Workspace8 => Message::SwitchWorkspace(7),
+            Action::Workspace9 => Message::SwitchWorkspace(8),
+        }
+    }
+
+    fn default_keys(&self) -> Option<&'static str> {
+        let binding = match self {
+            Action::CommandPalette => "Cmd+K",
+            Action::SplitVertical => "Cmd+V",
+            Action::SplitHorizontal => "Cmd+H",
+            Action::ClosePane => "Cmd+W",
+            Action::FocusUp => "Cmd+Up",
+            Action::FocusDown => "Cmd+Down",
+            Action::FocusLeft => "Cmd+Left",
+            Action::FocusRight => "Cmd+Right",
+            Action::NewTab => "Cmd+T",
+            Action::CloseTab => "Cmd+Shift+W",
+            Action::NextTab => "Cmd+]",
+            Action::PreviousTab => "Cmd+[",
+            Action::MoveTabRight => "Cmd+Shift+]",
+            Action::MoveTabLeft => "Cmd+Shift+[",
+            Action::Save => "Cmd+S",
+            Action::SaveAs => "Cmd+Shift+S",
+            Action::Open => "Cmd+O",
+            Action::Undo => "Cmd+Z",
+            Action::Redo => "Cmd+Shift+Z",
+            Action::TogglePlayback => return None,
+            Action::ToggleMusicalTyping => "Cmd+M",
+            Action::Workspace1 => "Cmd+1",
+            Action::Workspace2 => "Cmd+2",
+            Action::Workspace3 => "Cmd+3",
+            Action::Workspace4 => "Cmd+4",
+            Action::Workspace5 => "Cmd+5",
+            Action::Workspace6 => "Cmd+6",
+            Action::Workspace7 => "Cmd+7",
+            Action::Workspace8 => "Cmd+8",
+            Action::Workspace9 => "Cmd+9",
+        };
+        Some(binding)
+    }
+
+    pub fn default_binding(&self) -> Option<Binding> {
+        self.default_keys()
+            .map(|binding| binding.parse().expect("default binding"))
+    }
+}
+
+/// A key with the modifiers held down with it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub key_code: KeyCode,
+    pub modifiers: Modifiers,
+}
+
+impl Key {
+    /// Whether it's a modifier, which only ever goes with other keys
+    pub fn is_modifier(&self) -> bool {
+        matches!(
+            self.key_code,
+            KeyCode::LShift
+                | KeyCode::RShift
+                | KeyCode::LControl
+                | KeyCode::RControl
+                | KeyCode::LAlt
+                | KeyCode::RAlt
+                | KeyCode::LWin
+                | KeyCode::RWin
+        )
+    }
+
+    /// Whether it can be written to the keymap and read back
+    pub fn is_bindable(&self) -> bool {
+        KEYS.iter().any(|(key_code, _)| *key_code == self.key_code)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut modifiers = self.modifiers;
+        for (name, modifier) in MODIFIERS {
+            if modifiers.contains(modifier) {
+                write!(f, "{}+", name)?;
+                modifiers.remove(modifier);
+            }
+        }
+
+        match KEYS.iter().find(|(key_code, _)| *key_code == self.key_code) {
+            Some((_, name)) => write!(f, "{}", name),
+            None => write!(f, "{:?}", self.key_code),
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (modifier_names, key) = match text.rsplit_once('+') {
+            Some((modifiers, key)) => (modifiers, key),
+            None => ("", text),
+        };
+
+        let mut modifiers = Modifiers::empty();
+        for name in modifier_names.split('+').filter(|name| !name.is_empty()) {
+            let (_, modifier) = MODIFIERS
+                .iter()
+                .find(|(known, _)| known.eq_ignore_ascii_case(name))
+                .ok_or_else(|| anyhow!("unknown modifier {:?}", name))?;
+            modifiers |= *modifier;
+        }
+
+        let (key_code, _) = KEYS
+            .iter()
+            .find(|(_, known)| known.eq_ignore_ascii_case(key))
+            .ok_or_else(|| anyhow!("unknown key {:?}", key))?;
+
+        Ok(Key {
+            key_code: *key_code,
+            modifiers,
+        })
+    }
+}
+
+/// A key, or the keys of a chord in the order they're pressed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding(Vec<Key>);
+
+impl Binding {
+    pub fn new(keys: Vec<Key>) -> Self {
+        Binding(keys)
+    }
+
+    pub fn keys(&self) -> &[Key] {
+        &self.0
+    }
+
+    // Pressing one means pressing the start of the other
+    fn overlaps(&self, other: &Binding) -> bool {
+        let length = self.0.len().min(other.0.len());
+        self.0[..length] == other.0[..length]
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys: Vec<String> = self.0.iter().map(Key::to_string).collect();
+        write!(f, "{}", keys.join(" "))
+    }
+}
+
+impl FromStr for Binding {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let keys = text
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<Key>, _>>()?;
+
+        if keys.is_empty() || keys.len() > CHORD_LENGTH {
+            return Err(anyhow!(
+                "{:?} isn't a key or a chord of {} keys",
+                text,
+                CHORD_LENGTH
+            ));
+        }
+        Ok(Binding(keys))
+    }
+}
+
+/// Two actions that can't both be reached, as their bindings overlap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conflict(pub Action, pub Action);
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: BTreeMap<Action, Binding>,
+    // The first key of a chord and when it was pressed
+    pending: Option<(Key, Instant)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            bindings: Action::ALL
+                .iter()
+                .filter_map(|action| Some((*action, action.default_binding()?)))
+                .collect(),
+            pending: None,
+        }
+    }
+}
+
+fn path() -> PathBuf {
+    paths::config_dir().join(FILE)
+}
+
+impl Keymap {
+    /// The defaults with the user's bindings over them
+    pub fn load() -> Result<Self, anyhow::Error> {
+        let mut keymap = Self::default();
+        let path = path();
+        if !path.exists() {
+            return Ok(keymap);
+        }
+
+        let source = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {:?}", path))?;
+        let bindings: BTreeMap<String, String> = toml::from_str(&source)
+            .with_context(|| format!("failed to read {:?}", path))?;
+
+        // A bad entry keeps its default rather than losing the whole file
+        for (id, binding) in bindings {
+            if let Err(err) = keymap.read(&id, &binding) {
+                eprintln!("skipping a key binding in {:?}: {:#}", path, err);
+            }
+        }
+
+        Ok(keymap)
+    }
+
+    // Applies a binding as it's written in the file
+    fn read(&mut self, id: &str, binding: &str) -> Result<(), anyhow::Error> {
+        let action = Action::ALL
+            .into_iter()
+            .find(|action| action.id() == id)
+            .ok_or_else(|| anyhow!("unknown action {:?}", id))?;
+        let binding = if binding.trim().is_empty() {
+            None
+        } else {
+            Some(binding.parse().with_context(|| {
+                format!("failed to read the binding of {}", id)
+            })?)
+        };
+        self.bind(action, binding);
+
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let changed: BTreeMap<&str, String> = Action::ALL
+            .iter()
+            .filter(|action| {
+                self.binding(**action) != action.default_binding().as_ref()
+            })
+            .map(|action| {
+                let binding = self.binding(*action);
+                (
+                    action.id(),
+                    binding.map_or(String::new(), Binding::to_string),
+                )
+            })
+            .collect();
+
+        let path = path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {:?}", dir))?;
+        }
+        fs::write(&path, toml::to_string(&changed)?)
+            .with_context(|| format!("failed to write {:?}", path))?;
+
+        Ok(())
+    }
+
+    pub fn binding(&self, action: Action) -> Option<&Binding> {
+        self.bindings.get(&action)
+    }
+
+    /// Binds `action` to `binding`, or unbinds it
+    pub fn bind(&mut self, action: Action, binding: Option<Binding>) {
+        match binding {
+            Some(binding) => self.bindings.insert(action, binding),
+            None => self.bindings.remove(&action),
+        };
+        self.pending = None;
+    }
+
+    pub fn reset(&mut self, action: Action) {
+        self.bind(action, action.default_binding());
+    }
+
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let bindings: Vec<_> = self.bindings.iter().collect();
+        let mut conflicts = vec![];
+        for (index, (action, binding)) in bindings.iter().enumerate() {
+            for (other, other_binding) in &bindings[index + 1..] {
+                if binding.overlaps(other_binding) {
+                    conflicts.push(Conflict(**action, **other));
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Whether the first key of a chord was pressed
+    pub fn is_pending(&self) -> bool {
+        self.pending
+            .map_or(false, |(_, time)| time.elapsed() < CHORD_TIMEOUT)
+    }
+
+    /// The action `key` completes, if any. A key starting a chord is held
+    /// until the next one
+    pub fn press(&mut self, key: Key) -> Option<Action> {
+        if key.is_modifier() {
+            return None;
+        }
+
+        if let Some((first, time)) = self.pending.take() {
+            if time.elapsed() < CHORD_TIMEOUT {
+                if let Some(action) = self.find(&[first, key]) {
+                    return Some(action);
+                }
+            }
+        }
+
+        let starts_chord = self
+            .bindings
+            .values()
+            .any(|binding| binding.0.len() > 1 && binding.0[0] == key);
+        if starts_chord {
+            self.pending = Some((key, Instant::now()));
+            return None;
+        }
+
+        self.find(&[key])
+    }
+
+    fn find(&self, keys: &[Key]) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, binding)| binding.0 == keys)
+            .map(|(action, _)| *action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key_code: KeyCode, modifiers: Modifiers) -> Key {
+        Key {
+            key_code,
+            modifiers,
+        }
+    }
+
+    #[test]
+    fn parses_keys_with_modifiers() {
+        assert_eq!(
+            "Cmd+Shift+S".parse::<Key>().unwrap(),
+            key(KeyCode::S, COMMAND | Modifiers::SHIFT)
+        );
+        assert_eq!(
+            "alt+pageup".parse::<Key>().unwrap(),
+            key(KeyCode::PageUp, Modifiers::ALT)
+        );
+        assert_eq!(
+            "Space".parse::<Key>().unwrap(),
+            key(KeyCode::Space, Modifiers::empty())
+        );
+        // Only the last plus comes before the key
+        assert_eq!(
+            "Ctrl+=".parse::<Key>().unwrap(),
+            key(KeyCode::Equals, Modifiers::CTRL)
+        );
+    }
+
+    #[test]
+    fn refuses_unknown_keys_and_modifiers() {
+        assert!("Hyper+S".parse::<Key>().is_err());
+        assert!("Cmd+Enterr".parse::<Key>().is_err());
+        assert!("Cmd+".parse::<Key>().is_err());
+    }
+
+    #[test]
+    fn parses_chords() {
+        let binding: Binding = "Cmd+G  V".parse().unwrap();
+        assert_eq!(
+            binding.keys(),
+            [
+                key(KeyCode::G, COMMAND),
+                key(KeyCode::V, Modifiers::empty())
+            ]
+        );
+
+        assert!("".parse::<Binding>().is_err());
+        assert!("A B C".parse::<Binding>().is_err());
+        assert!("A Nope".parse::<Binding>().is_err());
+    }
+
+    #[test]
+    fn writes_bindings_as_they_are_read() {
+        for text in ["Cmd+Shift+]", "Alt+F4", "Cmd+G V", "Esc", "Cmd+\\"] {
+            let binding: Binding = text.parse().unwrap();
+            assert_eq!(binding.to_string(), text);
+        }
+
+        // Ctrl is written as Cmd where Cmd is Ctrl
+        let key = key(KeyCode::S, Modifiers::CTRL);
+        let expected = if COMMAND == Modifiers::CTRL {
+            "Cmd+S"
+        } else {
+            "Ctrl+S"
+        };
+        assert_eq!(key.to_string(), expected);
+    }
+
+    #[test]
+    fn every_bindable_key_reads_back() {
+        let all = Modifiers::CTRL
+            | Modifiers::ALT
+            | Modifiers::SHIFT
+            | Modifiers::LOGO;
+        for (key_code, _) in KEYS {
+            for modifiers in [Modifiers::empty(), Modifiers::SHIFT, all] {
+                let key = key(key_code, modifiers);
+                assert!(key.is_bindable());
+                assert_eq!(key.to_string().parse::<Key>().unwrap(), key);
+            }
+        }
+
+        assert!(!key(KeyCode::LShift, Modifiers::SHIFT).is_bindable());
+        assert!(!key(KeyCode::Numpad1, Modifiers::empty()).is_bindable());
+    }
+
+    #[test]
+    fn skips_bad_entries() {
+        let mut keymap = Keymap::default();
+        assert!(keymap.read("nope", "Cmd+S").is_err());
+        assert!(keymap.read("save", "Cmd+Nope").is_err());
+        assert_eq!(
+            keymap.binding(Action::Save),
+            Action::Save.default_binding().as_ref()
+        );
+
+        keymap.read("save", "Alt+S").unwrap();
+        assert_eq!(
+            keymap.binding(Action::Save),
+            Some(&"Alt+S".parse().unwrap())
+        );
+        keymap.read("save", " ").unwrap();
+        assert_eq!(keymap.binding(Action::Save), None);
+    }
+
+    #[test]
+    fn defaults_parse_without_conflicts() {
+        for action in Action::ALL {
+            action.default_binding();
+        }
+        assert_eq!(Keymap::default().conflicts(), vec![]);
+    }
+
+    #[test]
+    fn finds_overlapping_bindings() {
+        let mut keymap = Keymap::default();
+        keymap.bind(Action::Save, Some("Cmd+Z".parse().unwrap()));
+        assert_eq!(
+            keymap.conflicts(),
+            vec![Conflict(Action::Save, Action::Undo)]
+        );
+
+        // A chord starting with another binding's key hides it
+        keymap.reset(Action::Save);
+        keymap.bind(Action::Open, Some("Cmd+S O".parse().unwrap()));
+        assert_eq!(
+            keymap.conflicts(),
+            vec![Conflict(Action::Save, Action::Open)]
+        );
+
+        // Chords sharing only their first key are told apart
+        keymap.bind(Action::Open, Some("Cmd+G O".parse().unwrap()));
+        keymap.bind(Action::NewTab, Some("Cmd+G T".parse().unwrap()));
+        assert_eq!(keymap.conflicts(), vec![]);
+    }
+
+    #[test]
+    fn presses_keys_and_chords() {
+        let mut keymap = Keymap::default();
+        keymap.bind(Action::SplitVertical, Some("Cmd+G V".parse().unwrap()));
+
+        assert_eq!(keymap.press(key(KeyCode::S, COMMAND)), Some(Action::Save));
+        assert_eq!(keymap.press(key(KeyCode::LShift, Modifiers::SHIFT)), None);
+
+        assert_eq!(keymap.press(key(KeyCode::G, COMMAND)), None);
+        assert!(keymap.is_pending());
+        assert_eq!(
+            keymap.press(key(KeyCode::V, Modifiers::empty())),
+            Some(Action::SplitVertical)
+        );
+        assert!(!keymap.is_pending());
+
+        // A key that doesn't finish the chord counts on its own
+        assert_eq!(keymap.press(key(KeyCode::G, COMMAND)), None);
+        assert_eq!(
+            keymap.press(key(KeyCode::T, COMMAND)),
+            Some(Action::NewTab)
+        );
+    }
+}
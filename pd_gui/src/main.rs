@@ -1,46 +1,35 @@
-use std::{sync::mpsc, thread};
-
 use iced::Application;
 #[path = "./app.rs"]
 mod app;
-use crate::app::{audio, PsycheDaily};
+use crate::app::settings::{self, Flags};
+use crate::app::PsycheDaily;
 use iced::{window, Settings};
 
 fn main() -> iced::Result {
-    let (tx, rx) = mpsc::channel();
-
-    //    let safe_state = Arc::new(Mutex::new(self.switch_on));
-    //     thread::spawn(move || {
-    //         let val = *safe_state.lock().unwrap();
-
-    //         tx.send(val).unwrap();
-    //     });
-
-    thread::spawn(move || {
-        let received_state = rx.recv().unwrap();
-
-        println!("RECEIVED = {:?} ", received_state);
-        audio::open_audio_io(received_state);
+    let args = settings::args();
+    let saved = settings::Settings::load().unwrap_or_else(|err| {
+        eprintln!("{:#}", err);
+        settings::Settings::default()
     });
-
-    // PsycheDaily::get_state(self);
+    let flags = Flags {
+        settings: saved.with_args(&args),
+        saved,
+    };
+    let appearance = &flags.settings.appearance;
+    let min_size = (appearance.min_width, appearance.min_height);
 
     let settings = Settings {
         window: window::Settings {
             // icon: Some(icon.unwrap()),
-            min_size: Some((300, 200)),
+            min_size: Some(min_size),
             transparent: true,
             ..window::Settings::default()
         },
-        ..Settings::default()
+        // The app saves unsaved work for recovery before it closes
+        exit_on_close_request: false,
+        ..Settings::with_flags(flags)
     };
 
     // run the app
     PsycheDaily::run(settings)
 }
-
-// impl PsycheDaily {
-//     pub fn get_state(&self) {
-//         println!("{:?}", self.switch_on);
-//     }
-// }
@@ -0,0 +1,253 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+// Note events shared by every note source (musical typing, clips, sequencers)
+// and every instrument in the engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteEvent {
+    NoteOn { note: u8, velocity: u8 },
+    NoteOff { note: u8 },
+}
+
+pub const MAX_NOTE: u8 = 127;
+pub const MAX_VELOCITY: u8 = 127;
+
+/// Standard MIDI files, dropped on a track to become its clip
+pub const FILE_EXTENSION: &str = "mid";
+
+/// Converts a MIDI note number to a frequency in 12-TET with A4 = 440 Hz
+pub fn note_to_hz(note: u8) -> f64 {
+    440.0 * 2f64.powf((note as f64 - 69.0) / 12.0)
+}
+
+/// Maps a MIDI velocity onto a linear gain between 0 and 1
+pub fn velocity_to_gain(velocity: u8) -> f64 {
+    velocity.min(MAX_VELOCITY) as f64 / MAX_VELOCITY as f64
+}
+
+/// A note in a clip, positioned and sized in beats
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ClipNote {
+    pub start: f64,
+    pub length: f64,
+    pub note: u8,
+    pub velocity: u8,
+}
+
+/// A looping sequence of notes, `length` is in beats
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MidiClip {
+    pub length: f64,
+    pub notes: Vec<ClipNote>,
+}
+
+impl Default for MidiClip {
+    fn default() -> Self {
+        Self {
+            length: 4.0,
+            notes: vec![],
+        }
+    }
+}
+
+impl MidiClip {
+    /// Flattens the clip into note-on/off events sorted by beat. Notes are cut
+    /// at the clip end and note-offs sort before note-ons on the same beat
+    pub fn events(&self) -> Vec<(f64, NoteEvent)> {
+        let mut events: Vec<(f64, NoteEvent)> = self
+            .notes
+            .iter()
+            .filter(|note| note.start < self.length)
+            .flat_map(|note| {
+                let end = (note.start + note.length).min(self.length);
+                [
+                    (
+                        note.start,
+                        NoteEvent::NoteOn {
+                            note: note.note,
+                            velocity: note.velocity,
+                        },
+                    ),
+                    (end, NoteEvent::NoteOff { note: note.note }),
+                ]
+            })
+            .collect();
+
+        events.sort_by(|(a, a_event), (b, b_event)| {
+            a.total_cmp(b).then_with(|| {
+                let is_on = |event: &NoteEvent| {
+                    matches!(event, NoteEvent::NoteOn { .. })
+                };
+                is_on(a_event).cmp(&is_on(b_event))
+            })
+        });
+
+        events
+    }
+
+    /// Reads the notes of every track of a Standard MIDI File. The clip is
+    /// rounded up to whole bars of 4/4
+    pub fn parse_file(bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        let mut reader = Reader { bytes, position: 0 };
+
+        let (kind, header) = reader.chunk()?;
+        if kind != *b"MThd" || header.len() < 6 {
+            return Err(anyhow!("not a MIDI file"));
+        }
+        let tracks = u16::from_be_bytes([header[2], header[3]]);
+        let division = u16::from_be_bytes([header[4], header[5]]);
+        if division & 0x8000 != 0 || division == 0 {
+            return Err(anyhow!("SMPTE timing isn't supported"));
+        }
+        let ticks_per_beat = division as f64;
+
+        let mut notes = vec![];
+        for _ in 0..tracks {
+            let (kind, track) = reader.chunk()?;
+            // Unknown chunks are skipped, as the format asks
+            if kind == *b"MTrk" {
+                read_track(track, ticks_per_beat, &mut notes)?;
+            }
+        }
+
+        let end = notes
+            .iter()
+            .map(|note: &ClipNote| note.start + note.length)
+            .fold(0.0, f64::max);
+        notes.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+        Ok(Self {
+            length: ((end / 4.0).ceil() * 4.0).max(4.0),
+            notes,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let bytes = fs::read(path)
+            .with_context(|| format!("failed to read {:?}", path))?;
+
+        Self::parse_file(&bytes)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_done(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], anyhow::Error> {
+        let end = self.position + count;
+        let bytes = self
+            .bytes
+            .get(self.position..end)
+            .ok_or_else(|| anyhow!("the file ends early"))?;
+        self.position = end;
+
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, anyhow::Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    // A chunk's type and contents
+    fn chunk(&mut self) -> Result<([u8; 4], &'a [u8]), anyhow::Error> {
+        let kind = self.take(4)?;
+        let length = self.take(4)?;
+        let length =
+            u32::from_be_bytes([length[0], length[1], length[2], length[3]]);
+
+        Ok((
+            [kind[0], kind[1], kind[2], kind[3]],
+            self.take(length as usize)?,
+        ))
+    }
+
+    // Variable-length quantities keep 7 bits per byte, at most 4 bytes
+    fn variable(&mut self) -> Result<u32, anyhow::Error> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = (value << 7) | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(anyhow!("invalid variable-length quantity"))
+    }
+}
+
+// Adds the notes of one track chunk. Note-ons with velocity 0 are note-offs
+// and notes still held at the end of the track are dropped
+fn read_track(
+    track: &[u8],
+    ticks_per_beat: f64,
+    notes: &mut Vec<ClipNote>,
+) -> Result<(), anyhow::Error> {
+    let mut reader = Reader {
+        bytes: track,
+        position: 0,
+    };
+    let mut ticks = 0_u64;
+    let mut status = 0_u8;
+    // Start tick and velocity of each sounding note, per channel and key
+    let mut held = [[None; MAX_NOTE as usize + 1]; 16];
+
+    while !reader.is_done() {
+        ticks += reader.variable()? as u64;
+
+        let mut first = reader.byte()?;
+        if first & 0x80 != 0 {
+            status = first;
+            if status < 0xf0 {
+                first = reader.byte()?;
+            }
+        } else if status == 0 || status >= 0xf0 {
+            return Err(anyhow!("data byte without a status"));
+        }
+
+        match status & 0xf0 {
+            0x80 | 0x90 => {
+                let note = first & 0x7f;
+                let velocity = reader.byte()? & 0x7f;
+                let slot = &mut held[(status & 0x0f) as usize][note as usize];
+                if let Some((start, on_velocity)) = slot.take() {
+                    notes.push(ClipNote {
+                        start: start as f64 / ticks_per_beat,
+                        length: (ticks - start) as f64 / ticks_per_beat,
+                        note,
+                        velocity: on_velocity,
+                    });
+                }
+                if status & 0xf0 == 0x90 && velocity > 0 {
+                    *slot = Some((ticks, velocity));
+                }
+            }
+            0xa0 | 0xb0 | 0xe0 => {
+                reader.byte()?;
+            }
+            0xc0 | 0xd0 => {}
+            _ => {
+                // Meta events carry a type before their length, system
+                // exclusive messages don't, and neither sets running status
+                if status == 0xff {
+                    reader.byte()?;
+                }
+                let length = reader.variable()?;
+                reader.take(length as usize)?;
+                status = 0;
+            }
+        }
+    }
+
+    Ok(())
+}
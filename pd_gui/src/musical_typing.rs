@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use iced::keyboard::KeyCode;
+
+use super::midi::{NoteEvent, MAX_NOTE, MAX_VELOCITY};
+
+// Layout follows the usual DAW convention: the home row plays the white keys,
+// the row above plays the black keys, Z/X shift octaves and C/V change velocity
+const LOWEST_OCTAVE: i8 = -1;
+const HIGHEST_OCTAVE: i8 = 8;
+const VELOCITY_STEP: u8 = 20;
+
+#[derive(Debug)]
+pub struct MusicalTyping {
+    pub is_enabled: bool,
+    pub octave: i8,
+    pub velocity: u8,
+    // Remember which note a key started so an octave shift while holding a key
+    // still releases the right note
+    held: HashMap<KeyCode, u8>,
+}
+
+impl Default for MusicalTyping {
+    fn default() -> Self {
+        Self {
+            is_enabled: false,
+            octave: 4,
+            velocity: 100,
+            held: HashMap::new(),
+        }
+    }
+}
+
+impl MusicalTyping {
+    /// Flips musical typing on or off, returning note-offs for held keys
+    pub fn toggle(&mut self) -> Vec<NoteEvent> {
+        self.is_enabled = !self.is_enabled;
+        self.release_all()
+    }
+
+    /// Note-offs for held keys, like when the notes go to another track
+    pub fn release_all(&mut self) -> Vec<NoteEvent> {
+        self.held
+            .drain()
+            .map(|(_, note)| NoteEvent::NoteOff { note })
+            .collect()
+    }
+
+    pub fn key_pressed(&mut self, key_code: KeyCode) -> Option<NoteEvent> {
+        // Key repeat sends presses for held keys; those must not retrigger
+        if !self.is_enabled || self.held.contains_key(&key_code) {
+            return None;
+        }
+
+        match key_code {
+            KeyCode::Z => {
+                self.octave = (self.octave - 1).max(LOWEST_OCTAVE);
+                None
+            }
+            KeyCode::X => {
+                self.octave = (self.octave + 1).min(HIGHEST_OCTAVE);
+                None
+            }
+            KeyCode::C => {
+                self.velocity =
+                    self.velocity.saturating_sub(VELOCITY_STEP).max(1);
+                None
+            }
+            KeyCode::V => {
+                self.velocity =
+                    (self.velocity + VELOCITY_STEP).min(MAX_VELOCITY);
+                None
+            }
+            _ => {
+                let note = self.note_for_key(key_code)?;
+                self.held.insert(key_code, note);
+
+                Some(NoteEvent::NoteOn {
+                    note,
+                    velocity: self.velocity,
+                })
+            }
+        }
+    }
+
+    pub fn key_released(&mut self, key_code: KeyCode) -> Option<NoteEvent> {
+        self.held
+            .remove(&key_code)
+            .map(|note| NoteEvent::NoteOff { note })
+    }
+
+    fn note_for_key(&self, key_code: KeyCode) -> Option<u8> {
+        let offset = semitone_offset(key_code)?;
+        let note = (self.octave as i16 + 1) * 12 + offset;
+
+        if (0..=MAX_NOTE as i16).contains(&note) {
+            Some(note as u8)
+        } else {
+            None
+        }
+    }
+}
+
+fn semitone_offset(key_code: KeyCode) -> Option<i16> {
+    let offset = match key_code {
+        KeyCode::A => 0,
+        KeyCode::W => 1,
+        KeyCode::S => 2,
+        KeyCode::E => 3,
+        KeyCode::D => 4,
+        KeyCode::F => 5,
+        KeyCode::T => 6,
+        KeyCode::G => 7,
+        KeyCode::Y => 8,
+        KeyCode::H => 9,
+        KeyCode::U => 10,
+        KeyCode::J => 11,
+        KeyCode::K => 12,
+        KeyCode::O => 13,
+        KeyCode::L => 14,
+        KeyCode::P => 15,
+        KeyCode::Semicolon => 16,
+        _ => return None,
+    };
+
+    Some(offset)
+}
@@ -0,0 +1,25 @@
+//! Where the app keeps its files, in the usual places for the platform.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use directories::ProjectDirs;
+
+const APP: &str = "psyche-daily";
+
+// The temporary directory stands in when there's no home directory
+fn base(dir: fn(&ProjectDirs) -> &Path) -> PathBuf {
+    ProjectDirs::from("", "", APP)
+        .map(|dirs| dir(&dirs).to_path_buf())
+        .unwrap_or_else(|| env::temp_dir().join(APP))
+}
+
+/// Settings, workspaces and themes
+pub fn config_dir() -> PathBuf {
+    base(ProjectDirs::config_dir)
+}
+
+/// Work kept for recovery
+pub fn data_dir() -> PathBuf {
+    base(ProjectDirs::data_dir)
+}
@@ -0,0 +1,357 @@
+//! Projects on disk.
+//!
+//! A project is a bundle directory holding `project.json`: the composition,
+//! the engine and mixer settings and the pane layout. Samples stay where they
+//! are and are referenced by path, relative to the bundle when they live in
+//! it, so a bundle can be moved along with its samples.
+//!
+//! Every file records the version of the schema it was written with. Older
+//! files are brought up to date by the migrations, one version at a time,
+//! before they are read.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::composition::Composition;
+use super::engine::transcription::Transcription;
+use super::engine::transport::DEFAULT_TEMPO;
+use super::ui::layout::Layout;
+
+pub const EXTENSION: &str = "pdproject";
+const FILE: &str = "project.json";
+
+pub const VERSION: u64 = 2;
+
+// Each one upgrades a file from the version at its index to the next
+const MIGRATIONS: [fn(Value) -> Value; VERSION as usize] = [
+    // Version 0 files hold just a composition, like the backend serves them
+    |composition| json!({ "composition": composition }),
+    |mut project| {
+        if let Some(layout) = project.get_mut("layout") {
+            pane_kinds(layout);
+        }
+        project
+    },
+];
+
+// Version 1 layouts name panes by the id of their content. Panes already
+// stored by kind are left as they are
+pub(crate) fn pane_kinds(layout: &mut Value) {
+    if let Some(split) = layout.get_mut("Split") {
+        pane_kinds(&mut split["a"]);
+        pane_kinds(&mut split["b"]);
+    } else if let Some(pane) = layout.get_mut("Pane") {
+        let id = match pane.get("id") {
+            Some(id) => id.as_u64(),
+            None => return,
+        };
+        let kind = match id {
+            Some(0) => json!("Composition"),
+            Some(1) => json!("SampleCreator"),
+            Some(2) => json!("PatchGraph"),
+            _ => Value::Null,
+        };
+        pane["kind"] = kind;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EngineSettings {
+    pub tempo: f64,
+}
+
+impl Default for EngineSettings {
+    fn default() -> Self {
+        Self {
+            tempo: DEFAULT_TEMPO,
+        }
+    }
+}
+
+/// Mixer state beyond the tracks, which hold their own inserts, automation
+/// and modulation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct MixerSettings {
+    // The channel musical typing plays and the editors show
+    pub focused_track: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct EditorSettings {
+    pub selected_pattern: usize,
+    pub transcription: Transcription,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Project {
+    pub version: u64,
+    pub composition: Composition,
+    #[serde(default)]
+    pub engine: EngineSettings,
+    #[serde(default)]
+    pub mixer: MixerSettings,
+    #[serde(default)]
+    pub editor: EditorSettings,
+    #[serde(default)]
+    pub layout: Layout,
+}
+
+/// A project name from its bundle, for window titles and tabs
+pub fn name(bundle: &Path) -> String {
+    bundle
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Untitled".to_string())
+}
+
+/// Where Save As puts a bundle picked as `path`
+pub fn bundle_path(mut path: PathBuf) -> PathBuf {
+    if path.extension().map_or(true, |ext| ext != EXTENSION) {
+        path.set_extension(EXTENSION);
+    }
+    path
+}
+
+impl Project {
+    /// Writes the bundle, creating it if needed. The file is replaced in one
+    /// step, so a failed save leaves the last one intact
+    pub fn save(&self, bundle: &Path) -> Result<(), anyhow::Error> {
+        fs::create_dir_all(bundle)
+            .with_context(|| format!("failed to create {:?}", bundle))?;
+
+        let mut value = serde_json::to_value(self)?;
+        if let Some(samples) = value["composition"]["samples"].as_array_mut() {
+            for sample in samples {
+                let path = sample["path"].as_str().map(PathBuf::from);
+                if let Some(relative) = path
+                    .as_deref()
+                    .and_then(|path| path.strip_prefix(bundle).ok())
+                {
+                    sample["path"] = json!(relative);
+                }
+            }
+        }
+
+        let file = bundle.join(FILE);
+        let partial = file.with_extension("json.partial");
+        fs::write(&partial, serde_json::to_string_pretty(&value)?)
+            .with_context(|| format!("failed to write {:?}", partial))?;
+        fs::rename(&partial, &file)
+            .with_context(|| format!("failed to write {:?}", file))?;
+
+        Ok(())
+    }
+
+    pub fn load(bundle: &Path) -> Result<Self, anyhow::Error> {
+        let file = bundle.join(FILE);
+        let source = fs::read_to_string(&file)
+            .with_context(|| format!("failed to read {:?}", file))?;
+        let mut value: Value = serde_json::from_str(&source)
+            .with_context(|| format!("{:?} isn't a project", file))?;
+        if !value.is_object() {
+            return Err(anyhow!("{:?} isn't a project", file));
+        }
+
+        let mut version = value["version"].as_u64().unwrap_or(0);
+        if version > VERSION {
+            return Err(anyhow!(
+                "{:?} was saved by a newer version of the app",
+                bundle
+            ));
+        }
+        while version < VERSION {
+            value = MIGRATIONS[version as usize](value);
+            version += 1;
+            value["version"] = json!(version);
+        }
+
+        let mut project: Project = serde_json::from_value(value)
+            .with_context(|| format!("failed to read {:?}", file))?;
+        for sample in &mut project.composition.samples {
+            if sample.path.is_relative() {
+                sample.path = bundle.join(&sample.path);
+            }
+        }
+
+        Ok(project)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::composition::Sample;
+    use crate::app::ui::components::panes::PaneKind;
+    use crate::app::ui::layout::Axis;
+
+    // A bundle of its own for every test, holding `project` as its file
+    fn bundle(name: &str, project: &Value) -> PathBuf {
+        let bundle = std::env::temp_dir().join(format!(
+            "pd_gui_{}_{}.{}",
+            name,
+            std::process::id(),
+            EXTENSION
+        ));
+        let _ = fs::remove_dir_all(&bundle);
+        fs::create_dir_all(&bundle).unwrap();
+        fs::write(bundle.join(FILE), project.to_string()).unwrap();
+        bundle
+    }
+
+    fn composition() -> Value {
+        serde_json::to_value(Composition::default()).unwrap()
+    }
+
+    #[test]
+    fn version_0_files_are_a_composition() {
+        let bundle = bundle("version_0", &composition());
+        let project = Project::load(&bundle).unwrap();
+
+        assert_eq!(project.version, VERSION);
+        assert_eq!(
+            serde_json::to_value(&project.composition).unwrap(),
+            composition()
+        );
+        assert_eq!(project.engine, EngineSettings::default());
+        assert_eq!(project.mixer, MixerSettings::default());
+        assert_eq!(project.layout, Layout::default());
+        fs::remove_dir_all(bundle).unwrap();
+    }
+
+    #[test]
+    fn version_1_panes_get_their_kind() {
+        let pane =
+            |id: u64| json!({ "Pane": { "id": id, "is_pinned": id == 2 } });
+        let bundle = bundle(
+            "version_1",
+            &json!({
+                "version": 1,
+                "composition": composition(),
+                "engine": { "tempo": 90.0 },
+                "layout": { "Split": {
+                    "axis": "Vertical",
+                    "ratio": 0.5,
+                    "a": pane(0),
+                    "b": { "Split": {
+                        "axis": "Horizontal",
+                        "ratio": 0.25,
+                        "a": pane(1),
+                        "b": pane(2),
+                    }},
+                }},
+            }),
+        );
+        let project = Project::load(&bundle).unwrap();
+
+        let pane = |kind: PaneKind, is_pinned: bool| Layout::Pane {
+            kind: Some(kind),
+            is_pinned,
+        };
+        assert_eq!(project.version, VERSION);
+        assert_eq!(project.engine.tempo, 90.0);
+        assert_eq!(
+            project.layout,
+            Layout::Split {
+                axis: Axis::Vertical,
+                ratio: 0.5,
+                a: Box::new(pane(PaneKind::Composition, false)),
+                b: Box::new(Layout::Split {
+                    axis: Axis::Horizontal,
+                    ratio: 0.25,
+                    a: Box::new(pane(PaneKind::SampleCreator, false)),
+                    b: Box::new(pane(PaneKind::PatchGraph, true)),
+                }),
+            }
+        );
+        fs::remove_dir_all(bundle).unwrap();
+    }
+
+    #[test]
+    fn unknown_version_1_panes_offer_the_chooser() {
+        let bundle = bundle(
+            "version_1_chooser",
+            &json!({
+                "version": 1,
+                "composition": composition(),
+                "layout": { "Pane": { "id": 7, "is_pinned": false } },
+            }),
+        );
+        let project = Project::load(&bundle).unwrap();
+
+        assert_eq!(
+            project.layout,
+            Layout::Pane {
+                kind: None,
+                is_pinned: false
+            }
+        );
+        fs::remove_dir_all(bundle).unwrap();
+    }
+
+    #[test]
+    fn newer_and_broken_files_are_refused() {
+        let newer = bundle(
+            "newer",
+            &json!({ "version": VERSION + 1, "composition": composition() }),
+        );
+        assert!(Project::load(&newer).is_err());
+
+        let broken = bundle("broken", &json!([1, 2, 3]));
+        assert!(Project::load(&broken).is_err());
+
+        fs::remove_dir_all(newer).unwrap();
+        fs::remove_dir_all(broken).unwrap();
+    }
+
+    #[test]
+    fn samples_in_the_bundle_move_with_it() {
+        let bundle = bundle("samples", &json!({}));
+        let outside = std::env::temp_dir().join("kick.wav");
+
+        let mut composition = Composition::default();
+        composition.samples = vec![
+            Sample {
+                id: 1,
+                name: "Snare".to_string(),
+                path: bundle.join("snare.wav"),
+            },
+            Sample {
+                id: 2,
+                name: "Kick".to_string(),
+                path: outside.clone(),
+            },
+        ];
+        let project = Project {
+            version: VERSION,
+            composition,
+            engine: EngineSettings::default(),
+            mixer: MixerSettings::default(),
+            editor: EditorSettings::default(),
+            layout: Layout::default(),
+        };
+        project.save(&bundle).unwrap();
+
+        let saved: Value = serde_json::from_str(
+            &fs::read_to_string(bundle.join(FILE)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(saved["composition"]["samples"][0]["path"], "snare.wav");
+
+        let moved = bundle.with_file_name(format!(
+            "pd_gui_samples_moved_{}.{}",
+            std::process::id(),
+            EXTENSION
+        ));
+        let _ = fs::remove_dir_all(&moved);
+        fs::rename(&bundle, &moved).unwrap();
+
+        let samples = Project::load(&moved).unwrap().composition.samples;
+        assert_eq!(samples[0].path, moved.join("snare.wav"));
+        assert_eq!(samples[1].path, outside);
+        fs::remove_dir_all(moved).unwrap();
+    }
+}
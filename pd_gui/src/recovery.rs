@@ -0,0 +1,153 @@
+//! Crash recovery.
+//!
+//! Unsaved work is autosaved in the data directory, one project bundle per
+//! tab with changes. A bundle only exists while its tab has work the user
+//! hasn't saved: saving or closing the tab removes it, so finding any on
+//! launch means the last session ended with changes left.
+//!
+//! The latest state is also stashed in memory, where a panic hook can still
+//! reach it and flush it to the bundles on the way down.
+
+use std::fs;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::paths;
+use super::project::{Project, EXTENSION};
+
+const DIR: &str = "recovery";
+
+// Holds the path of the bundle the work belongs to
+const ORIGIN: &str = "origin";
+
+static STASH: Mutex<Vec<Work>> = Mutex::new(Vec::new());
+
+/// Unsaved work and the bundle it belongs to, if it was ever saved
+pub struct Work {
+    pub project: Project,
+    pub origin: Option<PathBuf>,
+}
+
+pub fn dir() -> PathBuf {
+    paths::data_dir().join(DIR)
+}
+
+// Bundles are numbered in the order of their tabs
+fn bundle(index: usize) -> PathBuf {
+    dir().join(format!("{}.{}", index, EXTENSION))
+}
+
+fn bundles() -> Vec<(usize, PathBuf)> {
+    let entries = match fs::read_dir(dir()) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    let mut bundles: Vec<_> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension().map_or(true, |ext| ext != EXTENSION) {
+                return None;
+            }
+            let index = path.file_stem()?.to_str()?.parse().ok()?;
+            Some((index, path))
+        })
+        .collect();
+    bundles.sort();
+    bundles
+}
+
+/// Work the last session left unsaved, in the order of its tabs
+pub fn pending() -> Vec<Work> {
+    bundles()
+        .into_iter()
+        .filter_map(|(_, bundle)| match Project::load(&bundle) {
+            Ok(project) => Some(Work {
+                project,
+                origin: fs::read_to_string(bundle.join(ORIGIN))
+                    .ok()
+                    .map(PathBuf::from),
+            }),
+            Err(err) => {
+                eprintln!("failed to read recovered work: {:#}", err);
+                None
+            }
+        })
+        .collect()
+}
+
+impl Work {
+    fn save(&self, bundle: &Path) -> Result<(), anyhow::Error> {
+        self.project.save(bundle)?;
+
+        let origin = bundle.join(ORIGIN);
+        match &self.origin {
+            Some(path) => fs::write(origin, path.to_string_lossy().as_bytes())?,
+            None => {
+                let _ = fs::remove_file(origin);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Replaces the recovered work with the unsaved work of every tab
+pub fn save(work: &[Work]) -> Result<(), anyhow::Error> {
+    if work.is_empty() {
+        discard();
+        return Ok(());
+    }
+
+    for (index, work) in work.iter().enumerate() {
+        work.save(&bundle(index))?;
+    }
+
+    // Left by tabs saved or closed since
+    for (index, bundle) in bundles() {
+        if index >= work.len() {
+            let _ = fs::remove_dir_all(bundle);
+        }
+    }
+
+    Ok(())
+}
+
+/// Drops the recovered work, once it's saved or the user turned it down
+pub fn discard() {
+    let dir = dir();
+    if dir.exists() {
+        if let Err(err) = fs::remove_dir_all(&dir) {
+            eprintln!("failed to discard recovered work: {}", err);
+        }
+    }
+}
+
+/// Keeps the latest state for the panic hook, empty when it's all saved
+pub fn stash(work: Vec<Work>) {
+    if let Ok(mut stash) = STASH.lock() {
+        *stash = work;
+    }
+}
+
+/// Flushes the stash before the default hook reports the panic
+pub fn install_panic_hook() {
+    let report = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // The panicking thread may be the one holding the lock
+        if let Ok(stash) = STASH.try_lock() {
+            if !stash.is_empty() {
+                match save(&stash) {
+                    Ok(()) => {
+                        eprintln!("unsaved work was written to {:?}", dir())
+                    }
+                    Err(err) => {
+                        eprintln!("failed to write unsaved work: {:#}", err)
+                    }
+                }
+            }
+        }
+        report(info);
+    }));
+}
@@ -0,0 +1,258 @@
+//! The user's settings, kept in `settings.toml` in the config directory.
+//!
+//! Every field has a default, so the file only needs what differs and
+//! settings added later fill themselves in. Flags on the command line
+//! override the file for one run without being written to it.
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::{value_parser, Arg, ArgMatches};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::engine::transport::DEFAULT_TEMPO;
+use super::paths;
+use super::ui::theme::ThemeType;
+
+const FILE: &str = "settings.toml";
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub audio: AudioSettings,
+    pub backend: BackendSettings,
+    pub appearance: AppearanceSettings,
+    pub paths: PathSettings,
+    pub defaults: DefaultSettings,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    // Milliseconds of audio in each output buffer. Shorter ones respond
+    // sooner, longer ones are safer from dropouts
+    pub latency: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self { latency: 20.0 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackendSettings {
+    // Where artists and compositions are fetched from
+    pub url: String,
+}
+
+impl Default for BackendSettings {
+    fn default() -> Self {
+        Self {
+            url: String::from("http://127.0.0.1:8000"),
+        }
+    }
+}
+
+impl BackendSettings {
+    /// The address of an endpoint, like `artists`
+    pub fn endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.url.trim_end_matches('/'), path)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppearanceSettings {
+    pub theme: ThemeType,
+    // The smallest the window can be made, in pixels
+    pub min_width: u32,
+    pub min_height: u32,
+}
+
+impl Default for AppearanceSettings {
+    fn default() -> Self {
+        Self {
+            theme: ThemeType::default(),
+            min_width: 300,
+            min_height: 200,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathSettings {
+    // Where the dialogs to open and save projects start
+    pub projects: Option<PathBuf>,
+}
+
+/// What new compositions and sessions start with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DefaultSettings {
+    pub tempo: f64,
+    pub musical_typing: bool,
+}
+
+impl Default for DefaultSettings {
+    fn default() -> Self {
+        Self {
+            tempo: DEFAULT_TEMPO,
+            musical_typing: false,
+        }
+    }
+}
+
+/// What the app starts with: the settings as saved, and with the command
+/// line over them
+#[derive(Debug, Clone, Default)]
+pub struct Flags {
+    pub saved: Settings,
+    pub settings: Settings,
+}
+
+fn path() -> PathBuf {
+    paths::config_dir().join(FILE)
+}
+
+impl Settings {
+    /// The saved settings, or the defaults before any were saved
+    pub fn load() -> Result<Self, anyhow::Error> {
+        let path = path();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let source = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {:?}", path))?;
+        toml::from_str(&source)
+            .with_context(|| format!("failed to read {:?}", path))
+    }
+
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let path = path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {:?}", dir))?;
+        }
+        fs::write(&path, toml::to_string(self)?)
+            .with_context(|| format!("failed to write {:?}", path))?;
+
+        Ok(())
+    }
+
+    /// These settings with the flags given on the command line over them
+    pub fn with_args(&self, args: &ArgMatches) -> Self {
+        let mut settings = self.clone();
+
+        if let Some(latency) = args.get_one::<f32>("latency") {
+            settings.audio.latency = *latency;
+        }
+        if let Some(url) = args.get_one::<String>("backend") {
+            settings.backend.url = url.clone();
+        }
+        if let Some(theme) = args.get_one::<String>("theme") {
+            settings.appearance.theme = ThemeType::from(theme.clone());
+        }
+        if let Some(projects) = args.get_one::<PathBuf>("projects") {
+            settings.paths.projects = Some(projects.clone());
+        }
+        if let Some(tempo) = args.get_one::<f64>("tempo") {
+            settings.defaults.tempo = *tempo;
+        }
+
+        settings
+    }
+
+    /// Takes over the settings that changed from `before` to `after`. The
+    /// rest are left alone, so overrides from the command line that weren't
+    /// touched don't end up saved
+    pub fn take_changes(&mut self, before: &Settings, after: &Settings) {
+        let (before, after) =
+            match (serde_json::to_value(before), serde_json::to_value(after)) {
+                (Ok(before), Ok(after)) => (before, after),
+                _ => return,
+            };
+        let mut settings = match serde_json::to_value(&*self) {
+            Ok(settings) => settings,
+            Err(_) => return,
+        };
+
+        changes(&mut settings, &before, &after);
+        if let Ok(settings) = serde_json::from_value(settings) {
+            *self = settings;
+        }
+    }
+}
+
+// Copies the fields that differ between `before` and `after` into `target`
+fn changes(target: &mut Value, before: &Value, after: &Value) {
+    match (target, before, after) {
+        (
+            Value::Object(target),
+            Value::Object(before),
+            Value::Object(after),
+        ) => {
+            for (key, value) in after {
+                match target.get_mut(key) {
+                    Some(field) => changes(
+                        field,
+                        before.get(key).unwrap_or(&Value::Null),
+                        value,
+                    ),
+                    None => {
+                        target.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (target, before, after) => {
+            if before != after {
+                *target = after.clone();
+            }
+        }
+    }
+}
+
+/// The command line, whose flags override the settings for one run
+pub fn args() -> ArgMatches {
+    clap::Command::new("psyche-daily")
+        .arg(
+            Arg::new("latency")
+                .long("latency")
+                .value_name("MS")
+                .value_parser(value_parser!(f32))
+                .help("Length of the audio output buffers"),
+        )
+        .arg(
+            Arg::new("backend")
+                .long("backend")
+                .value_name("URL")
+                .help("Server to fetch artists and compositions from"),
+        )
+        .arg(
+            Arg::new("theme")
+                .long("theme")
+                .value_name("NAME")
+                .help("light, dark, high-contrast or a user theme"),
+        )
+        .arg(
+            Arg::new("projects")
+                .long("projects")
+                .value_name("DIR")
+                .value_parser(value_parser!(PathBuf))
+                .help("Where to open and save projects"),
+        )
+        .arg(
+            Arg::new("tempo")
+                .long("tempo")
+                .value_name("BPM")
+                .value_parser(value_parser!(f64))
+                .help("Tempo of new compositions"),
+        )
+        .get_matches()
+}
@@ -0,0 +1,31 @@
+//! Compositions open side by side.
+//!
+//! Only the active composition lives in the app and plays through the
+//! engine. The others are parked as project snapshots, with their undo
+//! history and save state, until their tab is switched to.
+
+use std::path::PathBuf;
+
+use super::history::History;
+use super::project::{self, Project};
+
+pub struct Tab {
+    pub project: Project,
+    // The bundle it was last saved to or opened from
+    pub path: Option<PathBuf>,
+    // The project as last saved, in JSON
+    pub saved: Option<String>,
+    // Whether the project differs from `saved`, it doesn't change while
+    // parked
+    pub is_unsaved: bool,
+    pub history: History,
+}
+
+/// The name a tab shows, from its bundle or else its composition
+pub fn name(path: Option<&PathBuf>, title: &str) -> String {
+    match path {
+        Some(path) => project::name(path),
+        None if title.is_empty() => "Untitled".to_string(),
+        None => title.to_string(),
+    }
+}
@@ -6,7 +6,6 @@ pub struct ChannelFader {
     pub channel_fader_db_state: v_slider::State,
     pub db_tick_marks: tick_marks::Group,
     pub db_text_marks: text_marks::Group,
-    pub output_text: String,
 }
 
 impl ChannelFader {
@@ -34,8 +33,6 @@ impl ChannelFader {
             db_text_marks: text_marks::Group::min_max_and_center(
                 "-12", "+12", "0",
             ),
-
-            output_text: String::from("Channel [n]"),
         }
     }
 }
@@ -3,6 +3,8 @@ use iced_native::renderer;
 use iced_native::widget::Widget;
 use iced_native::{Color, Element, Length, Point, Rectangle, Size};
 
+use crate::app::ui::theme::palette;
+
 // struct LefChannel {
 //     width: f32,
 //     height: f32,
@@ -70,7 +72,7 @@ where
             border_color: Color::TRANSPARENT,
         };
 
-        renderer.fill_quad(quad, Color::from_rgba(0., 1., 0., 0.7));
+        renderer.fill_quad(quad, palette().meter);
     }
 }
 
@@ -1,3 +1,4 @@
 pub mod channel_fader;
 pub mod level_meter;
 pub mod test_canvas;
+pub mod track_list;
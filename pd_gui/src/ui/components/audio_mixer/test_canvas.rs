@@ -6,6 +6,8 @@ use iced_native::{
     layout, Element, Layout, Length, Point, Rectangle, Size, Vector,
 };
 
+use crate::app::ui::theme::palette;
+
 #[derive(Default)]
 pub struct Rainbow;
 
@@ -55,12 +57,13 @@ where
 
         use iced_native::Renderer as _;
 
+        let palette = palette();
         let x = Primitive::Quad {
             bounds: layout.bounds(),
-            background: iced::Background::Color(iced::Color::BLACK),
+            background: iced::Background::Color(palette.meter_background),
             border_radius: 0.0,
             border_width: 0.1,
-            border_color: iced::Color::WHITE,
+            border_color: palette.border,
         };
 
         renderer.draw_primitive(x);
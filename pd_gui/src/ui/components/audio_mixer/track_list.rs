@@ -0,0 +1,36 @@
+use iced::{button, Button, Element, Row, Text};
+
+use crate::app::{composition::Track, ui::components::panes::style, Message};
+
+/// A button per track, the focused one highlighted. Clicking one focuses it
+#[derive(Debug, Default)]
+pub struct TrackList {
+    buttons: Vec<button::State>,
+}
+
+impl TrackList {
+    pub fn view<'a>(
+        &'a mut self,
+        tracks: &[Track],
+        focused: usize,
+    ) -> Element<'a, Message> {
+        self.buttons.resize_with(tracks.len(), button::State::new);
+
+        let mut row = Row::new().spacing(5);
+        for ((index, track), state) in
+            tracks.iter().enumerate().zip(&mut self.buttons)
+        {
+            row = row.push(
+                Button::new(state, Text::new(&track.name).size(14))
+                    .on_press(Message::FocusTrack(index))
+                    .style(if index == focused {
+                        style::Button::Primary
+                    } else {
+                        style::Button::Control
+                    }),
+            );
+        }
+
+        row.into()
+    }
+}
@@ -0,0 +1,585 @@
+use std::fmt;
+
+use iced::canvas::{
+    self, event, Canvas, Cursor, Event, Frame, Geometry, Path, Stroke,
+};
+use iced::{
+    button, keyboard, mouse, pick_list, Button, Color, Column, Element, Length,
+    PickList, Point, Rectangle, Row, Size, Text,
+};
+use iced_aw::graphics::icons::icon_to_char;
+
+use crate::app::{
+    composition::Track,
+    engine::automation::{Curve, Lane, Mode, Target},
+    ui::components::panes::style,
+    ui::theme::palette,
+    Message,
+};
+
+const LANE_HEIGHT: u16 = 80;
+// Lanes show at least this many beats, and always whole bars
+const MIN_BEATS: f64 = 16.0;
+const BEATS_PER_BAR: f64 = 4.0;
+// How close the cursor needs to be to grab a breakpoint or handle
+const GRAB_DISTANCE: f32 = 6.0;
+
+/// A change to one breakpoint of a lane
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AutomationEdit {
+    Add { beat: f64, value: f64 },
+    Move { point: usize, beat: f64, value: f64 },
+    Remove(usize),
+    SetCurve { point: usize, curve: Curve },
+}
+
+// A live parameter as offered in the "add lane" pick list
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TargetChoice {
+    target: Target,
+    name: String,
+}
+
+impl fmt::Display for TargetChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Drag {
+    Point(usize),
+    // One of the two bezier handles of the segment starting at `point`
+    Handle { point: usize, is_second: bool },
+}
+
+#[derive(Debug)]
+struct LaneWidgets {
+    remove: button::State,
+    drag: Option<Drag>,
+    modifiers: keyboard::Modifiers,
+}
+
+/// The automation lanes of one track with its recording mode
+#[derive(Debug)]
+pub struct AutomationEditor {
+    mode_buttons: Vec<button::State>,
+    add_lane: pick_list::State<TargetChoice>,
+    lanes: Vec<LaneWidgets>,
+}
+
+impl AutomationEditor {
+    pub fn new() -> Self {
+        Self {
+            mode_buttons: Mode::ALL
+                .iter()
+                .map(|_| button::State::new())
+                .collect(),
+            add_lane: pick_list::State::default(),
+            lanes: vec![],
+        }
+    }
+
+    /// `playhead` is the transport position while it runs
+    pub fn view<'a>(
+        &'a mut self,
+        track: &'a Track,
+        playhead: Option<f64>,
+    ) -> Element<'a, Message> {
+        let AutomationEditor {
+            mode_buttons,
+            add_lane,
+            lanes,
+        } = self;
+
+        let mut modes = Row::new().spacing(5);
+        for (mode, state) in Mode::ALL.iter().zip(mode_buttons) {
+            modes = modes.push(
+                Button::new(state, Text::new(mode.name()).size(14))
+                    .on_press(Message::SetAutomationMode(*mode))
+                    .style(if *mode == track.automation_mode {
+                        style::Button::Primary
+                    } else {
+                        style::Button::Control
+                    }),
+            );
+        }
+
+        let choices: Vec<TargetChoice> = targets(track)
+            .filter(|choice| {
+                !track
+                    .automation
+                    .iter()
+                    .any(|lane| lane.target == choice.target)
+            })
+            .collect();
+
+        modes = modes.push(
+            PickList::new(add_lane, choices, None, |choice: TargetChoice| {
+                Message::AddAutomationLane(choice.target)
+            })
+            .placeholder("Add lane")
+            .text_size(14),
+        );
+
+        let mut editor = Column::new()
+            .spacing(10)
+            .push(Text::new(format!("{} automation", track.name)).size(16))
+            .push(modes);
+
+        let beats = track
+            .automation
+            .iter()
+            .filter_map(|lane| lane.breakpoints.last())
+            .map(|point| point.beat)
+            .chain(playhead)
+            .fold(MIN_BEATS, f64::max);
+        let beats = (beats / BEATS_PER_BAR).ceil() * BEATS_PER_BAR;
+
+        lanes.resize_with(track.automation.len(), || LaneWidgets {
+            remove: button::State::new(),
+            drag: None,
+            modifiers: keyboard::Modifiers::empty(),
+        });
+
+        for (index, (lane, widgets)) in
+            track.automation.iter().zip(lanes.iter_mut()).enumerate()
+        {
+            let LaneWidgets {
+                remove,
+                drag,
+                modifiers,
+            } = widgets;
+
+            let header = Row::new()
+                .spacing(5)
+                .push(
+                    Text::new(target_name(track, lane.target))
+                        .width(Length::Fill)
+                        .size(14),
+                )
+                .push(
+                    Button::new(
+                        remove,
+                        Text::new(icon_to_char(iced_aw::Icon::X))
+                            .font(iced_aw::ICON_FONT),
+                    )
+                    .on_press(Message::RemoveAutomationLane(index))
+                    .style(style::Button::Destructive),
+                );
+
+            let canvas = Canvas::new(LaneCanvas {
+                index,
+                lane,
+                drag,
+                modifiers,
+                beats,
+                playhead,
+            })
+            .width(Length::Fill)
+            .height(Length::Units(LANE_HEIGHT));
+
+            editor = editor.push(header).push(canvas);
+        }
+
+        editor.into()
+    }
+}
+
+/// The track's volume, then every parameter of its instrument and inserts
+/// that can move while it plays
+pub fn target_list(track: &Track) -> Vec<Target> {
+    targets(track).map(|choice| choice.target).collect()
+}
+
+fn targets(track: &Track) -> impl Iterator<Item = TargetChoice> + '_ {
+    let volume = std::iter::once(TargetChoice {
+        target: Target::Volume,
+        name: target_name(track, Target::Volume),
+    });
+
+    let instrument = track
+        .instrument
+        .params()
+        .iter()
+        .enumerate()
+        .filter(|(_, spec)| spec.is_automatable())
+        .map(move |(param, _)| {
+            let target = Target::Instrument { param };
+            TargetChoice {
+                target,
+                name: target_name(track, target),
+            }
+        });
+
+    let inserts = track.inserts.iter().flat_map(move |slot| {
+        slot.kind
+            .params()
+            .iter()
+            .enumerate()
+            .filter(|(_, spec)| spec.is_automatable())
+            .map(move |(param, _)| {
+                let target = Target::Effect { id: slot.id, param };
+                TargetChoice {
+                    target,
+                    name: target_name(track, target),
+                }
+            })
+    });
+
+    volume.chain(instrument).chain(inserts)
+}
+
+pub fn target_name(track: &Track, target: Target) -> String {
+    match target {
+        Target::Volume => "Volume".to_string(),
+        Target::Instrument { param } => track
+            .instrument
+            .params()
+            .get(param)
+            .map(|spec| format!("Instrument {}", spec.name))
+            .unwrap_or_else(|| "Missing parameter".to_string()),
+        Target::Effect { id, param } => track
+            .inserts
+            .iter()
+            .find(|slot| slot.id == id)
+            .and_then(|slot| {
+                let spec = slot.kind.params().get(param)?;
+                Some(format!("{} {}", slot.kind.name(), spec.name))
+            })
+            .unwrap_or_else(|| "Missing parameter".to_string()),
+    }
+}
+
+struct LaneCanvas<'a> {
+    index: usize,
+    lane: &'a Lane,
+    drag: &'a mut Option<Drag>,
+    modifiers: &'a mut keyboard::Modifiers,
+    beats: f64,
+    playhead: Option<f64>,
+}
+
+impl<'a> LaneCanvas<'a> {
+    fn point_for(&self, size: Size, beat: f64, value: f64) -> Point {
+        Point::new(
+            (beat / self.beats) as f32 * size.width,
+            (1.0 - value) as f32 * size.height,
+        )
+    }
+
+    fn beat_and_value(&self, size: Size, point: Point) -> (f64, f64) {
+        (
+            (point.x / size.width).clamp(0.0, 1.0) as f64 * self.beats,
+            1.0 - (point.y / size.height).clamp(0.0, 1.0) as f64,
+        )
+    }
+
+    // Screen positions of the bezier handles of the segment after `point`
+    fn handles(&self, size: Size, point: usize) -> Option<(Point, Point)> {
+        let from = self.lane.breakpoints.get(point)?;
+        let to = self.lane.breakpoints.get(point + 1)?;
+
+        match from.curve {
+            Curve::Bezier { x1, y1, x2, y2 } => {
+                let start = self.point_for(size, from.beat, from.value);
+                let end = self.point_for(size, to.beat, to.value);
+                let handle = |x: f64, y: f64| {
+                    Point::new(
+                        start.x + (end.x - start.x) * x as f32,
+                        start.y + (end.y - start.y) * y as f32,
+                    )
+                };
+
+                Some((handle(x1, y1), handle(x2, y2)))
+            }
+            _ => None,
+        }
+    }
+
+    fn point_at(&self, size: Size, position: Point) -> Option<usize> {
+        self.lane.breakpoints.iter().position(|point| {
+            self.point_for(size, point.beat, point.value)
+                .distance(position)
+                < GRAB_DISTANCE
+        })
+    }
+
+    fn handle_at(&self, size: Size, position: Point) -> Option<Drag> {
+        (0..self.lane.breakpoints.len()).find_map(|point| {
+            let (first, second) = self.handles(size, point)?;
+            if first.distance(position) < GRAB_DISTANCE {
+                Some(Drag::Handle {
+                    point,
+                    is_second: false,
+                })
+            } else if second.distance(position) < GRAB_DISTANCE {
+                Some(Drag::Handle {
+                    point,
+                    is_second: true,
+                })
+            } else {
+                None
+            }
+        })
+    }
+
+    // The breakpoint starting the segment under a beat
+    fn segment_at(&self, beat: f64) -> Option<usize> {
+        let next = self
+            .lane
+            .breakpoints
+            .partition_point(|point| point.beat <= beat);
+
+        if next > 0 && next < self.lane.breakpoints.len() {
+            Some(next - 1)
+        } else {
+            None
+        }
+    }
+
+    fn edit(&self, edit: AutomationEdit) -> Option<Message> {
+        Some(Message::EditAutomation(self.index, edit))
+    }
+
+    fn drag_to(
+        &self,
+        drag: Drag,
+        size: Size,
+        position: Point,
+    ) -> Option<Message> {
+        match drag {
+            Drag::Point(point) => {
+                let (beat, value) = self.beat_and_value(size, position);
+                self.edit(AutomationEdit::Move { point, beat, value })
+            }
+            Drag::Handle { point, is_second } => {
+                let from = self.lane.breakpoints.get(point)?;
+                let to = self.lane.breakpoints.get(point + 1)?;
+                let start = self.point_for(size, from.beat, from.value);
+                let end = self.point_for(size, to.beat, to.value);
+
+                let relative = |position: f32, start: f32, end: f32| {
+                    if (end - start).abs() < f32::EPSILON {
+                        None
+                    } else {
+                        Some(((position - start) / (end - start)) as f64)
+                    }
+                };
+                let x = relative(position.x, start.x, end.x)?.clamp(0.0, 1.0);
+
+                let curve = match from.curve {
+                    Curve::Bezier { x1, y1, x2, y2 } => {
+                        // A flat segment has no height to place handles in
+                        let y = |current| {
+                            relative(position.y, start.y, end.y)
+                                .map_or(current, |y| y.clamp(-1.0, 2.0))
+                        };
+                        if is_second {
+                            Curve::Bezier {
+                                x1,
+                                y1,
+                                x2: x,
+                                y2: y(y2),
+                            }
+                        } else {
+                            Curve::Bezier {
+                                x1: x,
+                                y1: y(y1),
+                                x2,
+                                y2,
+                            }
+                        }
+                    }
+                    curve => curve,
+                };
+
+                self.edit(AutomationEdit::SetCurve { point, curve })
+            }
+        }
+    }
+}
+
+impl<'a> canvas::Program<Message> for LaneCanvas<'a> {
+    fn update(
+        &mut self,
+        event: Event,
+        bounds: Rectangle,
+        cursor: Cursor,
+    ) -> (event::Status, Option<Message>) {
+        let size = bounds.size();
+
+        match event {
+            Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => {
+                *self.modifiers = modifiers;
+                return (event::Status::Ignored, None);
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                if self.drag.take().is_some() {
+                    return (event::Status::Captured, None);
+                }
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) => {
+                // Drags keep going outside the lane, clamped to its edges
+                if let (Some(drag), Some(position)) =
+                    (*self.drag, cursor.position())
+                {
+                    let position = Point::new(
+                        position.x - bounds.x,
+                        position.y - bounds.y,
+                    );
+                    return (
+                        event::Status::Captured,
+                        self.drag_to(drag, size, position),
+                    );
+                }
+            }
+            _ => {}
+        }
+
+        let position = match cursor.position_in(&bounds) {
+            Some(position) => position,
+            None => return (event::Status::Ignored, None),
+        };
+        let (beat, value) = self.beat_and_value(size, position);
+
+        let message = match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                if let Some(point) = self.point_at(size, position) {
+                    *self.drag = Some(Drag::Point(point));
+                    None
+                } else if let Some(handle) = self.handle_at(size, position) {
+                    *self.drag = Some(handle);
+                    None
+                } else {
+                    // The new breakpoint lands at this index, so it can be
+                    // dragged right away
+                    let point = self
+                        .lane
+                        .breakpoints
+                        .partition_point(|point| point.beat <= beat);
+                    *self.drag = Some(Drag::Point(point));
+                    self.edit(AutomationEdit::Add { beat, value })
+                }
+            }
+            // Right click removes a breakpoint, or cycles the curve type of
+            // the segment under the cursor
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right)) => {
+                if let Some(point) = self.point_at(size, position) {
+                    self.edit(AutomationEdit::Remove(point))
+                } else if let Some(point) = self.segment_at(beat) {
+                    let curve = self.lane.breakpoints[point].curve.next();
+                    self.edit(AutomationEdit::SetCurve { point, curve })
+                } else {
+                    return (event::Status::Ignored, None);
+                }
+            }
+            // Scrolling bends exponential segments
+            Event::Mouse(mouse::Event::WheelScrolled { delta }) => {
+                let point = match self.segment_at(beat) {
+                    Some(point) => point,
+                    None => return (event::Status::Ignored, None),
+                };
+                let amount = match delta {
+                    mouse::ScrollDelta::Lines { y, .. } => y,
+                    mouse::ScrollDelta::Pixels { y, .. } => y / 20.0,
+                };
+
+                match self.lane.breakpoints[point].curve {
+                    Curve::Exponential { curvature } => {
+                        let step =
+                            if self.modifiers.shift() { 0.1 } else { 0.5 };
+                        self.edit(AutomationEdit::SetCurve {
+                            point,
+                            curve: Curve::Exponential {
+                                curvature: (curvature + amount as f64 * step)
+                                    .clamp(-12.0, 12.0),
+                            },
+                        })
+                    }
+                    _ => return (event::Status::Ignored, None),
+                }
+            }
+            _ => return (event::Status::Ignored, None),
+        };
+
+        (event::Status::Captured, message)
+    }
+
+    fn draw(&self, bounds: Rectangle, _cursor: Cursor) -> Vec<Geometry> {
+        let palette = palette();
+        let mut frame = Frame::new(bounds.size());
+        let size = bounds.size();
+
+        frame.fill_rectangle(Point::ORIGIN, size, palette.background);
+
+        // Beats faint, bars stronger
+        for beat in 0..=self.beats as usize {
+            let x = self.point_for(size, beat as f64, 0.0).x;
+            let alpha = if beat as f64 % BEATS_PER_BAR == 0.0 {
+                0.25
+            } else {
+                0.08
+            };
+            frame.stroke(
+                &Path::line(Point::new(x, 0.0), Point::new(x, size.height)),
+                Stroke::default().with_width(1.0).with_color(Color {
+                    a: alpha,
+                    ..palette.text
+                }),
+            );
+        }
+
+        if !self.lane.breakpoints.is_empty() {
+            let steps = size.width.max(1.0) as usize / 2;
+            let curve = Path::new(|builder| {
+                for step in 0..=steps {
+                    let beat = step as f64 / steps as f64 * self.beats;
+                    let value = self.lane.value_at(beat).unwrap_or(0.0);
+                    let point = self.point_for(size, beat, value);
+
+                    if step == 0 {
+                        builder.move_to(point);
+                    } else {
+                        builder.line_to(point);
+                    }
+                }
+            });
+            frame.stroke(
+                &curve,
+                Stroke::default()
+                    .with_width(2.0)
+                    .with_color(palette.primary),
+            );
+        }
+
+        for (index, point) in self.lane.breakpoints.iter().enumerate() {
+            let center = self.point_for(size, point.beat, point.value);
+
+            if let Some((first, second)) = self.handles(size, index) {
+                let next = &self.lane.breakpoints[index + 1];
+                let end = self.point_for(size, next.beat, next.value);
+                let line = Stroke::default()
+                    .with_width(1.0)
+                    .with_color(palette.hovered);
+
+                frame.stroke(&Path::line(center, first), line.clone());
+                frame.stroke(&Path::line(end, second), line);
+                frame.fill(&Path::circle(first, 3.0), palette.hovered);
+                frame.fill(&Path::circle(second, 3.0), palette.hovered);
+            }
+
+            frame.fill(&Path::circle(center, 4.0), palette.accent);
+        }
+
+        if let Some(playhead) = self.playhead {
+            let x = self.point_for(size, playhead, 0.0).x;
+            frame.stroke(
+                &Path::line(Point::new(x, 0.0), Point::new(x, size.height)),
+                Stroke::default().with_width(1.0).with_color(palette.text),
+            );
+        }
+
+        vec![frame.into_geometry()]
+    }
+}
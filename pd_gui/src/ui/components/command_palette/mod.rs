@@ -0,0 +1,196 @@
+use iced::{
+    button, text_input, Button, Color, Column, Container, Element, Length, Row,
+    Text, TextInput,
+};
+
+use crate::app::{ui::components::panes::style, ui::theme::palette, Message};
+
+// Matches past these are left out, the query narrows them down instead
+const MAX_RESULTS: usize = 12;
+
+/// Something the palette can run
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    // The keys that run it too
+    pub binding: Option<String>,
+    pub message: Message,
+}
+
+// How well `query` matches `name`, `None` unless its characters all appear
+// in order. Runs of characters and the starts of words count the most
+fn score(query: &str, name: &str) -> Option<i32> {
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut last: Option<usize> = None;
+
+    for wanted in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found =
+            position + name[position..].iter().position(|c| *c == wanted)?;
+
+        score += 1;
+        if last.map_or(false, |last| last + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || name[found - 1] == ' ' {
+            score += 10;
+        }
+        // Matches far into the name are worth a little less
+        score -= (found - position).min(3) as i32;
+
+        last = Some(found);
+        position = found + 1;
+    }
+
+    Some(score)
+}
+
+/// The entries matching `query`, best first
+pub fn search(entries: Vec<Entry>, query: &str) -> Vec<Entry> {
+    let mut matches: Vec<(i32, Entry)> = entries
+        .into_iter()
+        .filter_map(|entry| Some((score(query, &entry.name)?, entry)))
+        .collect();
+    // Stable, so equal matches keep the order they were registered in
+    matches.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    matches
+        .into_iter()
+        .take(MAX_RESULTS)
+        .map(|(_, entry)| entry)
+        .collect()
+}
+
+/// Runs any action by name, opened over the app
+#[derive(Debug, Default)]
+pub struct CommandPalette {
+    input: text_input::State,
+    entries: Vec<button::State>,
+}
+
+impl CommandPalette {
+    /// Types into the search field from the start
+    pub fn focus(&mut self) {
+        self.input.focus();
+    }
+
+    pub fn view<'a>(
+        &'a mut self,
+        query: &str,
+        entries: &[Entry],
+        selected: usize,
+    ) -> Element<'a, Message> {
+        let palette = palette();
+        let mut column = Column::new().spacing(5).push(
+            TextInput::new(
+                &mut self.input,
+                "Search actions",
+                query,
+                Message::SetPaletteQuery,
+            )
+            .on_submit(Message::RunCommand(selected))
+            .padding(6)
+            .size(16),
+        );
+
+        self.entries.resize_with(entries.len(), button::State::new);
+        for (index, (entry, state)) in
+            entries.iter().zip(self.entries.iter_mut()).enumerate()
+        {
+            let mut row = Row::new().push(
+                Text::new(entry.name.as_str()).width(Length::Fill).size(14),
+            );
+            if let Some(binding) = &entry.binding {
+                row = row.push(Text::new(binding.as_str()).size(14));
+            }
+
+            column = column.push(
+                Button::new(state, row)
+                    .width(Length::Fill)
+                    .padding(4)
+                    .on_press(Message::RunCommand(index))
+                    .style(if index == selected {
+                        style::Button::Primary
+                    } else {
+                        style::Button::Flat
+                    }),
+            );
+        }
+        if entries.is_empty() {
+            column = column.push(
+                Text::new("No matching actions").size(14).color(Color {
+                    a: 0.6,
+                    ..palette.text
+                }),
+            );
+        }
+
+        Container::new(column)
+            .width(Length::Units(480))
+            .padding(10)
+            .style(style::Pane { is_focused: true })
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(names: &[&str]) -> Vec<Entry> {
+        names
+            .iter()
+            .map(|name| Entry {
+                name: name.to_string(),
+                binding: None,
+                message: Message::Undo,
+            })
+            .collect()
+    }
+
+    fn names(entries: &[Entry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[test]
+    fn needs_every_character_in_order() {
+        assert!(score("sv", "Save").is_some());
+        assert_eq!(score("vs", "Save"), None);
+        assert_eq!(score("x", "Save"), None);
+    }
+
+    #[test]
+    fn ignores_case_and_whitespace() {
+        assert_eq!(score("n p", "New pattern"), score("np", "New pattern"));
+        assert_eq!(score(" NP ", "New pattern"), score("np", "New pattern"));
+    }
+
+    #[test]
+    fn ranks_word_starts_first() {
+        let found = search(entries(&["Open", "New pattern"]), "p");
+        assert_eq!(names(&found), ["New pattern", "Open"]);
+
+        // A run of characters beats the same ones spread out
+        let found = search(entries(&["Split track", "Stop"]), "st");
+        assert_eq!(names(&found), ["Stop", "Split track"]);
+    }
+
+    #[test]
+    fn keeps_the_order_of_equal_matches() {
+        let found = search(entries(&["Save", "Solo", "Stop", "Open"]), "s");
+        assert_eq!(names(&found), ["Save", "Solo", "Stop"]);
+    }
+
+    #[test]
+    fn cuts_off_at_the_most_results() {
+        let tracks: Vec<String> = (0..MAX_RESULTS + 5)
+            .map(|n| format!("Track {}", n))
+            .collect();
+        let tracks: Vec<&str> = tracks.iter().map(String::as_str).collect();
+
+        let found = search(entries(&tracks), "");
+        assert_eq!(found.len(), MAX_RESULTS);
+        assert_eq!(found[0].name, "Track 0");
+    }
+}
@@ -0,0 +1,189 @@
+use iced::canvas::{self, Canvas, Cursor, Frame, Geometry, Path, Stroke};
+use iced::{Color, Column, Element, Length, Point, Rectangle, Row, Size, Text};
+use iced_audio::{knob, v_slider, Knob, Normal, NormalParam, VSlider};
+
+use crate::app::{
+    engine::effects::{
+        compressor::{
+            self, ATTACK, GAIN_REDUCTION, KNEE, MAKEUP, RATIO, RELEASE,
+            THRESHOLD,
+        },
+        Param,
+    },
+    ui::components::panes::style,
+    ui::theme::palette,
+    Message,
+};
+
+const CURVE_RANGE: f64 = 60.0;
+
+/// Knobs for the dynamics, a fader for makeup gain, the transfer curve and
+/// a gain reduction meter
+pub fn view<'a>(
+    id: u64,
+    params: &'a [Param],
+    knobs: &'a mut Vec<knob::State>,
+    fader: &'a mut v_slider::State,
+) -> Element<'a, Message> {
+    knobs
+        .resize_with(params.len(), || knob::State::new(NormalParam::default()));
+
+    let mut controls = Row::new().spacing(15);
+    for (index, state) in knobs.iter_mut().enumerate() {
+        if ![THRESHOLD, RATIO, ATTACK, RELEASE, KNEE].contains(&index) {
+            continue;
+        }
+
+        let param = &params[index];
+        state.set_normal(Normal::from_clipped(param.normal()));
+
+        controls = controls.push(
+            Column::new()
+                .spacing(5)
+                .push(
+                    Knob::new(state, move |normal| {
+                        Message::SetEffectParam(id, index, normal)
+                    })
+                    .on_grab(move || Some(Message::GrabEffectParam(id, index)))
+                    .on_release(move || {
+                        Some(Message::ReleaseEffectParam(id, index))
+                    }),
+                )
+                .push(Text::new(param.spec.name).size(12))
+                .push(Text::new(format!("{:.1}", param.get())).size(12)),
+        );
+    }
+
+    fader.set_normal(Normal::from_clipped(params[MAKEUP].normal()));
+
+    let makeup = Column::new()
+        .spacing(5)
+        .max_height(140)
+        .push(
+            VSlider::new(fader, move |normal| {
+                Message::SetEffectParam(id, MAKEUP, normal)
+            })
+            .on_grab(move || Some(Message::GrabEffectParam(id, MAKEUP)))
+            .on_release(move || Some(Message::ReleaseEffectParam(id, MAKEUP)))
+            .style(style::Slider),
+        )
+        .push(
+            Text::new(format!("Makeup {:.1}", params[MAKEUP].get())).size(12),
+        );
+
+    let graph = Row::new()
+        .spacing(10)
+        .height(Length::Units(140))
+        .push(
+            Canvas::new(TransferCurve { params })
+                .width(Length::Units(140))
+                .height(Length::Units(140)),
+        )
+        .push(
+            Canvas::new(GainReductionMeter {
+                param: &params[GAIN_REDUCTION],
+            })
+            .width(Length::Units(14))
+            .height(Length::Units(140)),
+        )
+        .push(makeup);
+
+    Column::new()
+        .spacing(10)
+        .push(controls)
+        .push(graph)
+        .push(
+            Text::new(format!(
+                "Gain reduction {:.1} dB",
+                params[GAIN_REDUCTION].get()
+            ))
+            .size(12),
+        )
+        .into()
+}
+
+struct TransferCurve<'a> {
+    params: &'a [Param],
+}
+
+impl<'a> canvas::Program<Message> for TransferCurve<'a> {
+    fn draw(&self, bounds: Rectangle, _cursor: Cursor) -> Vec<Geometry> {
+        let palette = palette();
+        let mut frame = Frame::new(bounds.size());
+        let size = bounds.width.min(bounds.height);
+        let value = |index: usize| self.params[index].get();
+
+        // Input runs left to right and output bottom to top, -60 to 0 dB
+        let to_point = |input: f64, output: f64| {
+            Point::new(
+                ((input + CURVE_RANGE) / CURVE_RANGE) as f32 * size,
+                (1.0 - (output + CURVE_RANGE) / CURVE_RANGE) as f32 * size,
+            )
+        };
+
+        frame.fill_rectangle(
+            Point::ORIGIN,
+            Size::new(size, size),
+            palette.background,
+        );
+        frame.stroke(
+            &Path::line(
+                to_point(-CURVE_RANGE, -CURVE_RANGE),
+                to_point(0.0, 0.0),
+            ),
+            Stroke::default().with_width(1.0).with_color(Color {
+                a: 0.15,
+                ..palette.text
+            }),
+        );
+
+        let curve = Path::new(|builder| {
+            builder.move_to(to_point(-CURVE_RANGE, -CURVE_RANGE));
+            for step in 1..=60 {
+                let input = -CURVE_RANGE + step as f64;
+                let output = compressor::transfer(
+                    input,
+                    value(THRESHOLD),
+                    value(RATIO),
+                    value(KNEE),
+                );
+                builder.line_to(to_point(input, output));
+            }
+        });
+        frame.stroke(
+            &curve,
+            Stroke::default()
+                .with_width(2.0)
+                .with_color(palette.primary),
+        );
+
+        vec![frame.into_geometry()]
+    }
+}
+
+struct GainReductionMeter<'a> {
+    param: &'a Param,
+}
+
+impl<'a> canvas::Program<Message> for GainReductionMeter<'a> {
+    fn draw(&self, bounds: Rectangle, _cursor: Cursor) -> Vec<Geometry> {
+        let palette = palette();
+        let mut frame = Frame::new(bounds.size());
+
+        frame.fill_rectangle(
+            Point::ORIGIN,
+            bounds.size(),
+            palette.meter_background,
+        );
+
+        // Gain reduction hangs down from the top
+        let height = self.param.normal() * bounds.height;
+        frame.fill_rectangle(
+            Point::ORIGIN,
+            Size::new(bounds.width, height),
+            palette.accent,
+        );
+
+        vec![frame.into_geometry()]
+    }
+}
@@ -0,0 +1,152 @@
+use iced::canvas::{self, Canvas, Cursor, Frame, Geometry, Path, Stroke};
+use iced::{Color, Column, Element, Length, Point, Rectangle, Row, Text};
+use iced_audio::{knob, Knob, Normal, NormalParam};
+
+use crate::app::{
+    engine::effects::{
+        eq::{self, Band, BANDS},
+        Param,
+    },
+    ui::theme::palette,
+    Message,
+};
+
+const MIN_FREQUENCY: f64 = 20.0;
+const MAX_FREQUENCY: f64 = 20_000.0;
+const DB_RANGE: f64 = 24.0;
+const CURVE_POINTS: usize = 200;
+
+/// Response curve above one column of frequency, gain and Q knobs per band
+pub fn view<'a>(
+    id: u64,
+    params: &'a [Param],
+    knobs: &'a mut Vec<knob::State>,
+) -> Element<'a, Message> {
+    knobs
+        .resize_with(params.len(), || knob::State::new(NormalParam::default()));
+
+    let curve = Canvas::new(ResponseCurve { params })
+        .width(Length::Fill)
+        .height(Length::Units(140));
+
+    let mut bands = Row::new().spacing(20);
+    let mut knobs = knobs.iter_mut().enumerate();
+
+    for band in 0..BANDS {
+        let mut column = Column::new()
+            .spacing(5)
+            .push(Text::new(format!("Band {}", band + 1)).size(14));
+
+        for (index, state) in knobs.by_ref().take(3) {
+            let param = &params[index];
+            state.set_normal(Normal::from_clipped(param.normal()));
+
+            column = column
+                .push(
+                    Knob::new(state, move |normal| {
+                        Message::SetEffectParam(id, index, normal)
+                    })
+                    .on_grab(move || Some(Message::GrabEffectParam(id, index)))
+                    .on_release(move || {
+                        Some(Message::ReleaseEffectParam(id, index))
+                    }),
+                )
+                .push(
+                    Text::new(format!(
+                        "{} {:.1}",
+                        param.spec.name,
+                        param.get()
+                    ))
+                    .size(12),
+                );
+        }
+
+        bands = bands.push(column);
+    }
+
+    Column::new().spacing(10).push(curve).push(bands).into()
+}
+
+struct ResponseCurve<'a> {
+    params: &'a [Param],
+}
+
+impl<'a> ResponseCurve<'a> {
+    fn x(frequency: f64, width: f32) -> f32 {
+        ((frequency / MIN_FREQUENCY).ln()
+            / (MAX_FREQUENCY / MIN_FREQUENCY).ln()) as f32
+            * width
+    }
+
+    fn y(db: f64, height: f32) -> f32 {
+        (0.5 - db.clamp(-DB_RANGE, DB_RANGE) / (2.0 * DB_RANGE)) as f32 * height
+    }
+}
+
+impl<'a> canvas::Program<Message> for ResponseCurve<'a> {
+    fn draw(&self, bounds: Rectangle, _cursor: Cursor) -> Vec<Geometry> {
+        let palette = palette();
+        let mut frame = Frame::new(bounds.size());
+        let (width, height) = (bounds.width, bounds.height);
+
+        frame.fill_rectangle(Point::ORIGIN, bounds.size(), palette.background);
+
+        let grid = Stroke::default().with_width(1.0).with_color(Color {
+            a: 0.15,
+            ..palette.text
+        });
+        for frequency in [100.0, 1_000.0, 10_000.0] {
+            let x = Self::x(frequency, width);
+            frame.stroke(
+                &Path::line(Point::new(x, 0.0), Point::new(x, height)),
+                grid.clone(),
+            );
+        }
+        for db in [-12.0, 0.0, 12.0] {
+            let y = Self::y(db, height);
+            frame.stroke(
+                &Path::line(Point::new(0.0, y), Point::new(width, y)),
+                grid.clone(),
+            );
+        }
+
+        let bands: Vec<Band> = (0..BANDS)
+            .map(|band| Band::from_params(self.params, band))
+            .collect();
+
+        let curve = Path::new(|builder| {
+            for point in 0..=CURVE_POINTS {
+                let position = point as f64 / CURVE_POINTS as f64;
+                let frequency = MIN_FREQUENCY
+                    * (MAX_FREQUENCY / MIN_FREQUENCY).powf(position);
+                let point = Point::new(
+                    position as f32 * width,
+                    Self::y(eq::response_db(&bands, frequency), height),
+                );
+
+                if point.x == 0.0 {
+                    builder.move_to(point);
+                } else {
+                    builder.line_to(point);
+                }
+            }
+        });
+        frame.stroke(
+            &curve,
+            Stroke::default()
+                .with_width(2.0)
+                .with_color(palette.primary),
+        );
+
+        // A handle on every band's center
+        for band in &bands {
+            let center = Point::new(
+                Self::x(band.frequency, width),
+                Self::y(band.gain, height),
+            );
+            frame.fill(&Path::circle(center, 4.0), palette.accent);
+        }
+
+        vec![frame.into_geometry()]
+    }
+}
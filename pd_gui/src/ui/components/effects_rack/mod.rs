@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use iced::{
+    button, pick_list, Button, Column, Element, Length, PickList, Row, Text,
+};
+use iced_audio::{h_slider, knob, v_slider, HSlider, Normal, NormalParam};
+use iced_aw::graphics::icons::icon_to_char;
+
+mod compressor_editor;
+mod eq_editor;
+
+use crate::app::{
+    composition::{Sample, Track},
+    engine::effects::{EffectKind, EffectSlot, Param},
+    ui::components::panes::style,
+    Message,
+};
+
+#[derive(Debug)]
+struct SlotWidgets {
+    bypass: button::State,
+    up: button::State,
+    down: button::State,
+    remove: button::State,
+    sliders: Vec<h_slider::State>,
+    // Used by the dedicated editors of the mixing effects
+    knobs: Vec<knob::State>,
+    fader: v_slider::State,
+    impulse_response: pick_list::State<SampleChoice>,
+}
+
+/// A composition sample as offered in a pick list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleChoice {
+    pub id: i64,
+    pub name: String,
+}
+
+impl fmt::Display for SampleChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl SlotWidgets {
+    fn new() -> Self {
+        Self {
+            bypass: button::State::new(),
+            up: button::State::new(),
+            down: button::State::new(),
+            remove: button::State::new(),
+            sliders: vec![],
+            knobs: vec![],
+            fader: v_slider::State::new(NormalParam::default()),
+            impulse_response: pick_list::State::default(),
+        }
+    }
+}
+
+/// The insert chain of one track, with a row of buttons to add effects
+#[derive(Debug)]
+pub struct EffectsRack {
+    add_buttons: Vec<button::State>,
+    slots: Vec<SlotWidgets>,
+}
+
+impl EffectsRack {
+    pub fn new() -> Self {
+        Self {
+            add_buttons: EffectKind::ALL
+                .iter()
+                .map(|_| button::State::new())
+                .collect(),
+            slots: vec![],
+        }
+    }
+
+    pub fn view<'a>(
+        &'a mut self,
+        track: &'a Track,
+        params: &'a HashMap<u64, Vec<Param>>,
+        samples: &'a [Sample],
+    ) -> Element<'a, Message> {
+        let mut add = Row::new().spacing(5);
+        for (kind, state) in EffectKind::ALL.iter().zip(&mut self.add_buttons) {
+            add = add.push(
+                Button::new(state, Text::new(kind.name()).size(14))
+                    .on_press(Message::AddEffect(*kind))
+                    .style(style::Button::Control),
+            );
+        }
+
+        let mut rack = Column::new()
+            .spacing(10)
+            .push(Text::new(format!("{} inserts", track.name)).size(16))
+            .push(add);
+
+        self.slots
+            .resize_with(track.inserts.len(), SlotWidgets::new);
+
+        for (slot, widgets) in track.inserts.iter().zip(&mut self.slots) {
+            rack = rack.push(slot_view(
+                slot,
+                widgets,
+                params.get(&slot.id),
+                samples,
+            ));
+        }
+
+        rack.into()
+    }
+}
+
+fn slot_view<'a>(
+    slot: &'a EffectSlot,
+    widgets: &'a mut SlotWidgets,
+    params: Option<&'a Vec<Param>>,
+    samples: &'a [Sample],
+) -> Element<'a, Message> {
+    let SlotWidgets {
+        bypass,
+        up,
+        down,
+        remove,
+        sliders,
+        knobs,
+        fader,
+        impulse_response,
+    } = widgets;
+
+    let icon = |icon| Text::new(icon_to_char(icon)).font(iced_aw::ICON_FONT);
+
+    let header = Row::new()
+        .spacing(5)
+        .push(Text::new(slot.kind.name()).width(Length::Fill).size(16))
+        .push(
+            Button::new(bypass, icon(iced_aw::Icon::Power))
+                .on_press(Message::ToggleBypass(slot.id))
+                .style(if slot.is_bypassed {
+                    style::Button::Control
+                } else {
+                    style::Button::Primary
+                }),
+        )
+        .push(
+            Button::new(up, icon(iced_aw::Icon::ArrowUp))
+                .on_press(Message::MoveEffect(slot.id, -1))
+                .style(style::Button::Control),
+        )
+        .push(
+            Button::new(down, icon(iced_aw::Icon::ArrowDown))
+                .on_press(Message::MoveEffect(slot.id, 1))
+                .style(style::Button::Control),
+        )
+        .push(
+            Button::new(remove, icon(iced_aw::Icon::X))
+                .on_press(Message::RemoveEffect(slot.id))
+                .style(style::Button::Destructive),
+        );
+
+    let mut column = Column::new().spacing(5).push(header);
+
+    let params = match params {
+        Some(params) => params,
+        None => return column.into(),
+    };
+
+    match slot.kind {
+        EffectKind::Eq => {
+            return column.push(eq_editor::view(slot.id, params, knobs)).into()
+        }
+        EffectKind::Compressor => {
+            return column
+                .push(compressor_editor::view(slot.id, params, knobs, fader))
+                .into()
+        }
+        EffectKind::Convolution => {
+            let choices: Vec<SampleChoice> = samples
+                .iter()
+                .map(|sample| SampleChoice {
+                    id: sample.id,
+                    name: sample.name.clone(),
+                })
+                .collect();
+            let selected = choices
+                .iter()
+                .find(|choice| Some(choice.id) == slot.impulse_response)
+                .cloned();
+
+            let id = slot.id;
+            column = column.push(
+                Row::new()
+                    .spacing(10)
+                    .push(
+                        Text::new("Impulse").width(Length::Units(90)).size(14),
+                    )
+                    .push(
+                        PickList::new(
+                            impulse_response,
+                            choices,
+                            selected,
+                            move |choice| {
+                                Message::SetImpulseResponse(id, choice.id)
+                            },
+                        )
+                        .placeholder("Drop a WAV to add one")
+                        .text_size(14),
+                    ),
+            );
+        }
+        _ => {}
+    }
+
+    sliders.resize_with(params.len(), || {
+        h_slider::State::new(NormalParam::default())
+    });
+
+    for (index, (param, slider)) in params.iter().zip(sliders).enumerate() {
+        if param.spec.is_meter {
+            continue;
+        }
+
+        // Automation and modulation move parameters too, so always show the
+        // current value
+        slider.set_normal(Normal::from_clipped(param.normal()));
+
+        let id = slot.id;
+        column = column.push(
+            Row::new()
+                .spacing(10)
+                .push(
+                    Text::new(param.spec.name)
+                        .width(Length::Units(90))
+                        .size(14),
+                )
+                .push(
+                    HSlider::new(slider, move |normal| {
+                        Message::SetEffectParam(id, index, normal)
+                    })
+                    .on_grab(move || Some(Message::GrabEffectParam(id, index)))
+                    .on_release(move || {
+                        Some(Message::ReleaseEffectParam(id, index))
+                    })
+                    .style(style::Slider),
+                )
+                .push(
+                    Text::new(format!("{:.2}", param.get()))
+                        .width(Length::Units(60))
+                        .size(14),
+                ),
+        );
+    }
+
+    column.into()
+}
@@ -0,0 +1,477 @@
+use iced::canvas::{
+    self, event, Canvas, Cursor, Event, Frame, Geometry, Path, Stroke,
+};
+use iced::{
+    alignment, button, mouse, pick_list, text_input, Button, Column, Element,
+    Length, PickList, Point, Rectangle, Row, Size, Text, TextInput, Vector,
+};
+
+use crate::app::{
+    composition::{Track, TrackInstrument},
+    engine::patch::graph::{
+        Graph, GraphError, Node, Port, INPUT, INPUT_NAMES, OUTPUT, OUTPUT_NAMES,
+    },
+    engine::patch::unit_names,
+    ui::components::panes::style,
+    ui::theme::palette,
+    Message,
+};
+
+const CANVAS_HEIGHT: u16 = 360;
+const NODE_WIDTH: f32 = 120.0;
+const HEADER_HEIGHT: f32 = 22.0;
+const PORT_SPACING: f32 = 18.0;
+const PORT_RADIUS: f32 = 5.0;
+// How close the cursor needs to be to grab a port
+const GRAB_DISTANCE: f32 = 8.0;
+
+/// A change to the graph of the focused track
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphEdit {
+    AddNode(&'static str),
+    MoveNode { id: u64, x: f32, y: f32 },
+    RemoveNode(u64),
+    Connect { from: Port, to: Port },
+    Disconnect(Port),
+    // Typed text of an argument, applied once it parses
+    SetArg { id: u64, index: usize, text: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Drag {
+    // Offset of the cursor from the node's corner
+    Node { id: u64, offset: Vector },
+    // A wire being pulled out of an output port
+    Wire { from: Port },
+}
+
+/// The patch graph of one track: units as boxes, wired from output ports on
+/// their right to input ports on their left
+#[derive(Debug)]
+pub struct GraphEditor {
+    use_graph: button::State,
+    add_node: pick_list::State<&'static str>,
+    args: Vec<text_input::State>,
+    drag: Option<Drag>,
+    selected: Option<u64>,
+}
+
+impl GraphEditor {
+    pub fn new() -> Self {
+        Self {
+            use_graph: button::State::new(),
+            add_node: pick_list::State::default(),
+            args: vec![],
+            drag: None,
+            selected: None,
+        }
+    }
+
+    /// `draft` is the argument being typed, as typed. `error` is why the
+    /// graph doesn't compile, the last good version playing meanwhile
+    pub fn view<'a>(
+        &'a mut self,
+        track: &'a Track,
+        draft: Option<&'a (u64, usize, String)>,
+        error: Option<&'a GraphError>,
+    ) -> Element<'a, Message> {
+        let GraphEditor {
+            use_graph,
+            add_node,
+            args,
+            drag,
+            selected,
+        } = self;
+
+        let mut editor = Column::new()
+            .spacing(10)
+            .push(Text::new(format!("{} patch graph", track.name)).size(16));
+
+        let graph = match &track.instrument {
+            TrackInstrument::Graph(graph) => graph,
+            _ => {
+                return editor
+                    .push(
+                        Button::new(
+                            use_graph,
+                            Text::new("Play a patch graph").size(14),
+                        )
+                        .on_press(Message::UseGraphInstrument)
+                        .style(style::Button::Primary),
+                    )
+                    .into()
+            }
+        };
+
+        if selected.map_or(false, |id| graph.node(id).is_none()) {
+            *selected = None;
+        }
+
+        let mut toolbar = Row::new().spacing(10).push(
+            PickList::new(add_node, unit_names(), None, |unit| {
+                Message::EditGraph(GraphEdit::AddNode(unit))
+            })
+            .placeholder("Add unit")
+            .text_size(14),
+        );
+
+        // Arguments of the selected unit
+        let node = selected.and_then(|id| graph.node(id));
+        let arg_count = node.map_or(0, |node| node.args.len());
+        args.resize_with(arg_count, text_input::State::new);
+
+        if let Some(node) = node {
+            toolbar = toolbar.push(Text::new(&node.unit).size(14));
+
+            for (index, (arg, state)) in node.args.iter().zip(args).enumerate()
+            {
+                let id = node.id;
+                let value = match draft {
+                    Some((node, arg, text)) if *node == id && *arg == index => {
+                        text.clone()
+                    }
+                    _ => format!("{}", arg),
+                };
+
+                toolbar = toolbar.push(
+                    TextInput::new(state, "", &value, move |text| {
+                        Message::EditGraph(GraphEdit::SetArg {
+                            id,
+                            index,
+                            text,
+                        })
+                    })
+                    .width(Length::Units(70))
+                    .padding(4)
+                    .size(14),
+                );
+            }
+        }
+
+        editor = editor.push(toolbar).push(
+            Canvas::new(GraphCanvas {
+                graph,
+                drag,
+                selected,
+                error_node: error.and_then(|error| error.node),
+            })
+            .width(Length::Fill)
+            .height(Length::Units(CANVAS_HEIGHT)),
+        );
+
+        if let Some(error) = error {
+            editor = editor.push(
+                Text::new(error.to_string())
+                    .size(14)
+                    .color(palette().destructive),
+            );
+        }
+
+        editor.into()
+    }
+}
+
+fn node_size(node: &Node) -> Size {
+    let ports = node.inputs.max(node.outputs).max(1) as f32;
+    Size::new(NODE_WIDTH, HEADER_HEIGHT + ports * PORT_SPACING + 4.0)
+}
+
+fn node_bounds(node: &Node) -> Rectangle {
+    Rectangle::new(
+        Point::new(node.position.0, node.position.1),
+        node_size(node),
+    )
+}
+
+fn port_y(node: &Node, port: usize) -> f32 {
+    node.position.1 + HEADER_HEIGHT + (port as f32 + 0.5) * PORT_SPACING
+}
+
+fn input_point(node: &Node, port: usize) -> Point {
+    Point::new(node.position.0, port_y(node, port))
+}
+
+fn output_point(node: &Node, port: usize) -> Point {
+    Point::new(node.position.0 + NODE_WIDTH, port_y(node, port))
+}
+
+fn port_name(node: &Node, port: usize, is_input: bool) -> String {
+    let names = match node.unit.as_str() {
+        INPUT => &INPUT_NAMES[..],
+        OUTPUT => &OUTPUT_NAMES[..],
+        _ => &[][..],
+    };
+    names.get(port).map_or_else(
+        || {
+            if is_input {
+                format!("in {}", port + 1)
+            } else {
+                format!("out {}", port + 1)
+            }
+        },
+        |name| name.to_string(),
+    )
+}
+
+struct GraphCanvas<'a> {
+    graph: &'a Graph,
+    drag: &'a mut Option<Drag>,
+    selected: &'a mut Option<u64>,
+    error_node: Option<u64>,
+}
+
+impl<'a> GraphCanvas<'a> {
+    fn output_at(&self, position: Point) -> Option<Port> {
+        self.graph.nodes.iter().find_map(|node| {
+            (0..node.outputs)
+                .find(|port| {
+                    output_point(node, *port).distance(position) < GRAB_DISTANCE
+                })
+                .map(|port| (node.id, port))
+        })
+    }
+
+    fn input_at(&self, position: Point) -> Option<Port> {
+        self.graph.nodes.iter().find_map(|node| {
+            (0..node.inputs)
+                .find(|port| {
+                    input_point(node, *port).distance(position) < GRAB_DISTANCE
+                })
+                .map(|port| (node.id, port))
+        })
+    }
+
+    // Topmost node under a point, the last drawn
+    fn node_at(&self, position: Point) -> Option<&'a Node> {
+        self.graph
+            .nodes
+            .iter()
+            .rev()
+            .find(|node| node_bounds(node).contains(position))
+    }
+
+    fn edit(&self, edit: GraphEdit) -> Option<Message> {
+        Some(Message::EditGraph(edit))
+    }
+}
+
+impl<'a> canvas::Program<Message> for GraphCanvas<'a> {
+    fn update(
+        &mut self,
+        event: Event,
+        bounds: Rectangle,
+        cursor: Cursor,
+    ) -> (event::Status, Option<Message>) {
+        let position = cursor.position().map(|position| {
+            Point::new(position.x - bounds.x, position.y - bounds.y)
+        });
+
+        match event {
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                return match self.drag.take() {
+                    Some(Drag::Wire { from }) => {
+                        let to = position
+                            .and_then(|position| self.input_at(position));
+                        (
+                            event::Status::Captured,
+                            to.and_then(|to| {
+                                self.edit(GraphEdit::Connect { from, to })
+                            }),
+                        )
+                    }
+                    Some(Drag::Node { .. }) => (event::Status::Captured, None),
+                    None => (event::Status::Ignored, None),
+                };
+            }
+            // Nodes stay inside the canvas, wires just follow the cursor
+            Event::Mouse(mouse::Event::CursorMoved { .. }) => {
+                match (*self.drag, position) {
+                    (Some(Drag::Node { id, offset }), Some(position)) => {
+                        let x = (position.x - offset.x)
+                            .clamp(0.0, (bounds.width - NODE_WIDTH).max(0.0));
+                        let y = (position.y - offset.y).clamp(
+                            0.0,
+                            (bounds.height - HEADER_HEIGHT).max(0.0),
+                        );
+                        return (
+                            event::Status::Captured,
+                            self.edit(GraphEdit::MoveNode { id, x, y }),
+                        );
+                    }
+                    (Some(Drag::Wire { .. }), _) => {
+                        return (event::Status::Captured, None)
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+
+        let position = match cursor.position_in(&bounds) {
+            Some(position) => position,
+            None => return (event::Status::Ignored, None),
+        };
+
+        let message = match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                if let Some(from) = self.output_at(position) {
+                    *self.drag = Some(Drag::Wire { from });
+                    None
+                } else if let Some(to) = self.input_at(position) {
+                    // Grabbing a wired input unplugs the wire, to plug it
+                    // somewhere else
+                    let from = self
+                        .graph
+                        .wires
+                        .iter()
+                        .find(|wire| wire.to == to)
+                        .map(|wire| wire.from);
+                    match from {
+                        Some(from) => {
+                            *self.drag = Some(Drag::Wire { from });
+                            self.edit(GraphEdit::Disconnect(to))
+                        }
+                        None => None,
+                    }
+                } else if let Some(node) = self.node_at(position) {
+                    *self.selected = Some(node.id);
+                    *self.drag = Some(Drag::Node {
+                        id: node.id,
+                        offset: position
+                            - Point::new(node.position.0, node.position.1),
+                    });
+                    None
+                } else {
+                    *self.selected = None;
+                    None
+                }
+            }
+            // Right click removes a unit
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right)) => {
+                match self.node_at(position) {
+                    Some(node) if !node.is_fixed() => {
+                        self.edit(GraphEdit::RemoveNode(node.id))
+                    }
+                    _ => return (event::Status::Ignored, None),
+                }
+            }
+            _ => return (event::Status::Ignored, None),
+        };
+
+        (event::Status::Captured, message)
+    }
+
+    fn draw(&self, bounds: Rectangle, cursor: Cursor) -> Vec<Geometry> {
+        let palette = palette();
+        let mut frame = Frame::new(bounds.size());
+
+        frame.fill_rectangle(Point::ORIGIN, bounds.size(), palette.background);
+
+        let cable = |frame: &mut Frame, from: Point, to: Point, color| {
+            let bend = ((to.x - from.x).abs() / 2.0).max(30.0);
+            let path = Path::new(|builder| {
+                builder.move_to(from);
+                builder.bezier_curve_to(
+                    Point::new(from.x + bend, from.y),
+                    Point::new(to.x - bend, to.y),
+                    to,
+                );
+            });
+            frame.stroke(
+                &path,
+                Stroke::default().with_width(2.0).with_color(color),
+            );
+        };
+
+        for node in &self.graph.nodes {
+            let size = node_size(node);
+            let corner = Point::new(node.position.0, node.position.1);
+            let is_selected = *self.selected == Some(node.id);
+
+            frame.fill_rectangle(corner, size, palette.surface);
+            frame.fill_rectangle(
+                corner,
+                Size::new(size.width, HEADER_HEIGHT),
+                if is_selected {
+                    palette.primary
+                } else {
+                    palette.accent
+                },
+            );
+            frame.stroke(
+                &Path::rectangle(corner, size),
+                Stroke::default().with_width(1.0).with_color(
+                    if self.error_node == Some(node.id) {
+                        palette.destructive
+                    } else {
+                        palette.hovered
+                    },
+                ),
+            );
+            frame.fill_text(canvas::Text {
+                content: node.label(),
+                position: Point::new(corner.x + 6.0, corner.y + 4.0),
+                color: palette.on_primary,
+                size: 14.0,
+                ..canvas::Text::default()
+            });
+
+            for port in 0..node.inputs {
+                let point = input_point(node, port);
+                frame.fill(&Path::circle(point, PORT_RADIUS), palette.hovered);
+                frame.fill_text(canvas::Text {
+                    content: port_name(node, port, true),
+                    position: Point::new(point.x + 8.0, point.y),
+                    color: palette.text,
+                    size: 12.0,
+                    vertical_alignment: alignment::Vertical::Center,
+                    ..canvas::Text::default()
+                });
+            }
+
+            for port in 0..node.outputs {
+                let point = output_point(node, port);
+                frame.fill(&Path::circle(point, PORT_RADIUS), palette.hovered);
+                frame.fill_text(canvas::Text {
+                    content: port_name(node, port, false),
+                    position: Point::new(point.x - 8.0, point.y),
+                    color: palette.text,
+                    size: 12.0,
+                    horizontal_alignment: alignment::Horizontal::Right,
+                    vertical_alignment: alignment::Vertical::Center,
+                    ..canvas::Text::default()
+                });
+            }
+        }
+
+        for wire in &self.graph.wires {
+            if let (Some(from), Some(to)) =
+                (self.graph.node(wire.from.0), self.graph.node(wire.to.0))
+            {
+                cable(
+                    &mut frame,
+                    output_point(from, wire.from.1),
+                    input_point(to, wire.to.1),
+                    palette.primary,
+                );
+            }
+        }
+
+        if let (Some(Drag::Wire { from }), Some(position)) =
+            (*self.drag, cursor.position())
+        {
+            if let Some(node) = self.graph.node(from.0) {
+                let to =
+                    Point::new(position.x - bounds.x, position.y - bounds.y);
+                cable(
+                    &mut frame,
+                    output_point(node, from.1),
+                    to,
+                    palette.hovered,
+                );
+            }
+        }
+
+        vec![frame.into_geometry()]
+    }
+}
@@ -0,0 +1,136 @@
+use iced::{button, Button, Column, Element, Length, Row, Text};
+
+use crate::app::{
+    keymap::{Action, Conflict, Key, Keymap},
+    ui::components::panes::style,
+    ui::theme::palette,
+    Message,
+};
+
+// The buttons next to a binding
+#[derive(Debug, Default)]
+struct BindingButtons {
+    rebind: button::State,
+    reset: button::State,
+}
+
+/// The cheat sheet of every action and its keys, which also rebinds them
+#[derive(Debug, Default)]
+pub struct KeymapEditor {
+    bindings: Vec<BindingButtons>,
+    done: button::State,
+    cancel: button::State,
+}
+
+fn keys_text(keys: &[Key]) -> String {
+    let keys: Vec<String> = keys.iter().map(Key::to_string).collect();
+    keys.join(" ")
+}
+
+impl KeymapEditor {
+    /// `rebinding` is the action being rebound and the keys pressed so far
+    pub fn view<'a>(
+        &'a mut self,
+        keymap: &Keymap,
+        rebinding: Option<&(Action, Vec<Key>)>,
+    ) -> Element<'a, Message> {
+        let palette = palette();
+        let binding_text = |action: Action| {
+            keymap
+                .binding(action)
+                .map_or(String::from("None"), |binding| binding.to_string())
+        };
+
+        let mut column = Column::new().spacing(5);
+
+        let conflicts = keymap.conflicts();
+        for Conflict(action, other) in &conflicts {
+            column = column.push(
+                Text::new(format!(
+                    "{} ({}) clashes with {} ({})",
+                    action.name(),
+                    binding_text(*action),
+                    other.name(),
+                    binding_text(*other),
+                ))
+                .size(14)
+                .color(palette.destructive),
+            );
+        }
+
+        if let Some((action, keys)) = rebinding {
+            let prompt = if keys.is_empty() {
+                format!("Press the keys for {}, Esc cancels", action.name())
+            } else {
+                format!("{} {} ...", action.name(), keys_text(keys))
+            };
+
+            let mut done =
+                Button::new(&mut self.done, Text::new("Done").size(14))
+                    .style(style::Button::Primary);
+            if !keys.is_empty() {
+                done = done.on_press(Message::FinishBinding);
+            }
+
+            column = column.push(
+                Row::new()
+                    .spacing(10)
+                    .push(Text::new(prompt).width(Length::Fill).size(14))
+                    .push(done)
+                    .push(
+                        Button::new(
+                            &mut self.cancel,
+                            Text::new("Cancel").size(14),
+                        )
+                        .on_press(Message::CancelBinding)
+                        .style(style::Button::Control),
+                    ),
+            );
+        }
+
+        self.bindings
+            .resize_with(Action::ALL.len(), BindingButtons::default);
+
+        let mut group = "";
+        for (action, BindingButtons { rebind, reset }) in
+            Action::ALL.into_iter().zip(self.bindings.iter_mut())
+        {
+            if action.group() != group {
+                group = action.group();
+                column = column.push(Text::new(group).size(16));
+            }
+
+            let is_conflicting =
+                conflicts.iter().any(|Conflict(first, second)| {
+                    *first == action || *second == action
+                });
+            let mut binding = Text::new(binding_text(action))
+                .width(Length::Units(140))
+                .size(14);
+            if is_conflicting {
+                binding = binding.color(palette.destructive);
+            }
+
+            let mut reset = Button::new(reset, Text::new("Reset").size(14))
+                .style(style::Button::Control);
+            if keymap.binding(action) != action.default_binding().as_ref() {
+                reset = reset.on_press(Message::ResetBinding(action));
+            }
+
+            column = column.push(
+                Row::new()
+                    .spacing(10)
+                    .push(Text::new(action.name()).width(Length::Fill).size(14))
+                    .push(binding)
+                    .push(
+                        Button::new(rebind, Text::new("Rebind").size(14))
+                            .on_press(Message::RecordBinding(action))
+                            .style(style::Button::Control),
+                    )
+                    .push(reset),
+            );
+        }
+
+        column.into()
+    }
+}
@@ -0,0 +1,289 @@
+use iced::{button, Button, Column, Element, Length, Row, Text};
+use iced_audio::{h_slider, HSlider, Normal, NormalParam};
+use iced_aw::graphics::icons::icon_to_char;
+
+use crate::app::{
+    composition::Track,
+    engine::effects::ParamSpec,
+    engine::midi_effects::{MidiEffect, NOTE_NAMES},
+    engine::transport::NOTE_VALUES,
+    ui::components::panes::style,
+    Message,
+};
+
+const RATE: ParamSpec =
+    ParamSpec::live("Rate", 0.0, (NOTE_VALUES.len() - 1) as f64, 1.0);
+const GATE: ParamSpec = ParamSpec::live("Gate", 0.05, 1.0, 0.5);
+const OCTAVES: ParamSpec = ParamSpec::live("Octaves", 1.0, 4.0, 1.0);
+
+// A slider of an arpeggiator, writing its value back into a copy of it
+struct Control {
+    name: &'static str,
+    spec: &'static ParamSpec,
+    value: f64,
+    label: String,
+    set: fn(MidiEffect, f64) -> MidiEffect,
+}
+
+fn controls(effect: &MidiEffect) -> Vec<Control> {
+    match *effect {
+        MidiEffect::Arpeggiator {
+            rate,
+            gate,
+            octaves,
+            ..
+        } => vec![
+            Control {
+                name: "Rate",
+                spec: &RATE,
+                value: rate as f64,
+                label: NOTE_VALUES[rate.min(NOTE_VALUES.len() - 1)]
+                    .0
+                    .to_string(),
+                set: |effect, value| match effect {
+                    MidiEffect::Arpeggiator {
+                        mode,
+                        gate,
+                        octaves,
+                        ..
+                    } => MidiEffect::Arpeggiator {
+                        mode,
+                        rate: value.round() as usize,
+                        gate,
+                        octaves,
+                    },
+                    other => other,
+                },
+            },
+            Control {
+                name: "Gate",
+                spec: &GATE,
+                value: gate,
+                label: format!("{:.0}%", gate * 100.0),
+                set: |effect, value| match effect {
+                    MidiEffect::Arpeggiator {
+                        mode,
+                        rate,
+                        octaves,
+                        ..
+                    } => MidiEffect::Arpeggiator {
+                        mode,
+                        rate,
+                        gate: value,
+                        octaves,
+                    },
+                    other => other,
+                },
+            },
+            Control {
+                name: "Octaves",
+                spec: &OCTAVES,
+                value: octaves as f64,
+                label: octaves.to_string(),
+                set: |effect, value| match effect {
+                    MidiEffect::Arpeggiator {
+                        mode, rate, gate, ..
+                    } => MidiEffect::Arpeggiator {
+                        mode,
+                        rate,
+                        gate,
+                        octaves: value.round() as u8,
+                    },
+                    other => other,
+                },
+            },
+        ],
+        MidiEffect::Chord { .. } => vec![],
+    }
+}
+
+#[derive(Debug)]
+struct EffectWidgets {
+    // Mode of an arpeggiator, root of a chord generator
+    first: button::State,
+    scale: button::State,
+    shape: button::State,
+    remove: button::State,
+    sliders: Vec<h_slider::State>,
+}
+
+impl EffectWidgets {
+    fn new() -> Self {
+        Self {
+            first: button::State::new(),
+            scale: button::State::new(),
+            shape: button::State::new(),
+            remove: button::State::new(),
+            sliders: vec![],
+        }
+    }
+}
+
+/// The MIDI effects of one track, in the order notes pass through them
+#[derive(Debug)]
+pub struct MidiEffectsRack {
+    add_buttons: Vec<button::State>,
+    effects: Vec<EffectWidgets>,
+}
+
+impl MidiEffectsRack {
+    pub fn new() -> Self {
+        Self {
+            add_buttons: (0..2).map(|_| button::State::new()).collect(),
+            effects: vec![],
+        }
+    }
+
+    pub fn view<'a>(&'a mut self, track: &'a Track) -> Element<'a, Message> {
+        let MidiEffectsRack {
+            add_buttons,
+            effects,
+        } = self;
+
+        let mut add = Row::new().spacing(5);
+        for (effect, state) in [MidiEffect::ARPEGGIATOR, MidiEffect::CHORD]
+            .iter()
+            .zip(add_buttons)
+        {
+            add = add.push(
+                Button::new(state, Text::new(effect.name()).size(14))
+                    .on_press(Message::AddMidiEffect(*effect))
+                    .style(style::Button::Control),
+            );
+        }
+
+        let mut rack = Column::new()
+            .spacing(10)
+            .push(Text::new(format!("{} MIDI effects", track.name)).size(16))
+            .push(add);
+
+        effects.resize_with(track.midi_effects.len(), EffectWidgets::new);
+
+        for (index, (effect, widgets)) in
+            track.midi_effects.iter().zip(effects).enumerate()
+        {
+            let EffectWidgets {
+                first,
+                scale,
+                shape,
+                remove,
+                sliders,
+            } = widgets;
+
+            let cycle = |state, label: &str, effect| {
+                Button::new(state, Text::new(label).size(14))
+                    .on_press(Message::SetMidiEffect(index, effect))
+                    .style(style::Button::Control)
+            };
+
+            let mut header = Row::new().spacing(5).push(
+                Text::new(format!("{} {}", effect.name(), index + 1))
+                    .width(Length::Fill)
+                    .size(14),
+            );
+
+            header = match *effect {
+                MidiEffect::Arpeggiator {
+                    mode,
+                    rate,
+                    gate,
+                    octaves,
+                } => header.push(cycle(
+                    first,
+                    mode.name(),
+                    MidiEffect::Arpeggiator {
+                        mode: mode.next(),
+                        rate,
+                        gate,
+                        octaves,
+                    },
+                )),
+                MidiEffect::Chord {
+                    root,
+                    scale: current,
+                    shape: chord,
+                } => header
+                    .push(cycle(
+                        first,
+                        NOTE_NAMES[root as usize % 12],
+                        MidiEffect::Chord {
+                            root: (root + 1) % 12,
+                            scale: current,
+                            shape: chord,
+                        },
+                    ))
+                    .push(cycle(
+                        scale,
+                        current.name(),
+                        MidiEffect::Chord {
+                            root,
+                            scale: current.next(),
+                            shape: chord,
+                        },
+                    ))
+                    .push(cycle(
+                        shape,
+                        chord.name(),
+                        MidiEffect::Chord {
+                            root,
+                            scale: current,
+                            shape: chord.next(),
+                        },
+                    )),
+            };
+
+            header = header.push(
+                Button::new(
+                    remove,
+                    Text::new(icon_to_char(iced_aw::Icon::X))
+                        .font(iced_aw::ICON_FONT),
+                )
+                .on_press(Message::RemoveMidiEffect(index))
+                .style(style::Button::Destructive),
+            );
+
+            let mut column = Column::new().spacing(5).push(header);
+
+            let controls = controls(effect);
+            sliders.resize_with(controls.len(), || {
+                h_slider::State::new(NormalParam::default())
+            });
+
+            for (control, slider) in controls.into_iter().zip(sliders) {
+                slider.set_normal(Normal::from_clipped(
+                    control.spec.to_normal(control.value),
+                ));
+
+                let (effect, spec, set) = (*effect, control.spec, control.set);
+                column = column.push(
+                    Row::new()
+                        .spacing(10)
+                        .push(
+                            Text::new(control.name)
+                                .width(Length::Units(90))
+                                .size(14),
+                        )
+                        .push(
+                            HSlider::new(slider, move |normal| {
+                                let value = spec.from_normal(normal.as_f32());
+                                Message::SetMidiEffect(
+                                    index,
+                                    set(effect, value),
+                                )
+                            })
+                            .style(style::Slider),
+                        )
+                        .push(
+                            Text::new(control.label)
+                                .width(Length::Units(60))
+                                .size(14),
+                        ),
+                );
+            }
+
+            rack = rack.push(column);
+        }
+
+        rack.into()
+    }
+}
@@ -1,3 +1,17 @@
 pub mod audio_mixer;
+pub mod automation;
+pub mod command_palette;
+pub mod effects_rack;
+pub mod graph_editor;
+pub mod keymap;
+pub mod midi_effects;
+pub mod modulation;
 pub mod panes;
 pub mod sample_creator;
+pub mod sampler;
+pub mod settings;
+pub mod step_sequencer;
+pub mod tabs;
+pub mod transcription;
+pub mod tuning;
+pub mod workspaces;
@@ -0,0 +1,492 @@
+use iced::{button, Button, Column, Element, Length, Row, Text};
+use iced_audio::{
+    h_slider, knob, FloatRange, HSlider, Knob, Normal, NormalParam,
+};
+use iced_aw::graphics::icons::icon_to_char;
+
+use crate::app::{
+    composition::{Sample, Track, TrackInstrument},
+    engine::automation::Target,
+    engine::effects::{Param, ParamSpec},
+    engine::instrument::Adsr,
+    engine::modulation::Source,
+    engine::patch::PatchFile,
+    engine::transport::NOTE_VALUES,
+    ui::components::automation::{target_list, target_name},
+    ui::components::panes::style,
+    ui::theme::palette,
+    Message,
+};
+
+// Ranges of the source controls. Envelope times are in seconds, follower
+// times in milliseconds
+const RATE: ParamSpec = ParamSpec::live("Rate", 0.05, 20.0, 1.0).logarithmic();
+const DIVISION: ParamSpec =
+    ParamSpec::live("Rate", 0.0, (NOTE_VALUES.len() - 1) as f64, 5.0);
+const STAGE: ParamSpec = ParamSpec::live("Time", 0.001, 5.0, 0.1).logarithmic();
+const SUSTAIN: ParamSpec = ParamSpec::live("Sustain", 0.0, 1.0, 0.5);
+const ATTACK: ParamSpec =
+    ParamSpec::live("Attack", 1.0, 200.0, 10.0).logarithmic();
+const RELEASE: ParamSpec =
+    ParamSpec::live("Release", 10.0, 2_000.0, 150.0).logarithmic();
+
+// A slider of a source, writing its value back into a copy of the source
+struct Control {
+    name: &'static str,
+    spec: &'static ParamSpec,
+    value: f64,
+    label: String,
+    set: fn(Source, f64) -> Source,
+}
+
+impl Control {
+    fn new(
+        name: &'static str,
+        spec: &'static ParamSpec,
+        value: f64,
+        set: fn(Source, f64) -> Source,
+    ) -> Self {
+        Self {
+            name,
+            spec,
+            value,
+            label: format!("{:.2}", value),
+            set,
+        }
+    }
+}
+
+fn controls(source: &Source) -> Vec<Control> {
+    match *source {
+        Source::Lfo {
+            sync: Some(period), ..
+        } => {
+            let index = NOTE_VALUES
+                .iter()
+                .position(|(_, beats)| (beats - period).abs() < 1e-6)
+                .unwrap_or(0);
+
+            vec![Control {
+                label: NOTE_VALUES[index].0.to_string(),
+                ..Control::new(
+                    "Rate",
+                    &DIVISION,
+                    index as f64,
+                    |source, index| match source {
+                        Source::Lfo { shape, rate, .. } => Source::Lfo {
+                            shape,
+                            rate,
+                            sync: Some(NOTE_VALUES[index.round() as usize].1),
+                        },
+                        other => other,
+                    },
+                )
+            }]
+        }
+        Source::Lfo { rate, .. } => vec![Control {
+            label: format!("{:.2} Hz", rate),
+            ..Control::new("Rate", &RATE, rate, |source, rate| match source {
+                Source::Lfo { shape, sync, .. } => {
+                    Source::Lfo { shape, rate, sync }
+                }
+                other => other,
+            })
+        }],
+        Source::Envelope(adsr) => {
+            vec![
+                Control::new("Attack", &STAGE, adsr.attack, |source, value| {
+                    with_adsr(source, |adsr| adsr.attack = value)
+                }),
+                Control::new("Decay", &STAGE, adsr.decay, |source, value| {
+                    with_adsr(source, |adsr| adsr.decay = value)
+                }),
+                Control::new(
+                    "Sustain",
+                    &SUSTAIN,
+                    adsr.sustain,
+                    |source, value| {
+                        with_adsr(source, |adsr| adsr.sustain = value)
+                    },
+                ),
+                Control::new(
+                    "Release",
+                    &STAGE,
+                    adsr.release,
+                    |source, value| {
+                        with_adsr(source, |adsr| adsr.release = value)
+                    },
+                ),
+            ]
+        }
+        Source::Follower { attack, release } => vec![
+            Control::new("Attack", &ATTACK, attack, |source, attack| {
+                match source {
+                    Source::Follower { release, .. } => {
+                        Source::Follower { attack, release }
+                    }
+                    other => other,
+                }
+            }),
+            Control::new("Release", &RELEASE, release, |source, release| {
+                match source {
+                    Source::Follower { attack, .. } => {
+                        Source::Follower { attack, release }
+                    }
+                    other => other,
+                }
+            }),
+        ],
+    }
+}
+
+fn sample_name(samples: &[Sample], id: i64) -> &str {
+    samples
+        .iter()
+        .find(|sample| sample.id == id)
+        .map_or("Missing sample", |sample| &sample.name)
+}
+
+fn with_adsr(source: Source, apply: impl FnOnce(&mut Adsr)) -> Source {
+    match source {
+        Source::Envelope(mut adsr) => {
+            apply(&mut adsr);
+            Source::Envelope(adsr)
+        }
+        other => other,
+    }
+}
+
+#[derive(Debug)]
+struct SourceWidgets {
+    shape: button::State,
+    sync: button::State,
+    remove: button::State,
+    sliders: Vec<h_slider::State>,
+    depths: Vec<knob::State>,
+}
+
+impl SourceWidgets {
+    fn new() -> Self {
+        Self {
+            shape: button::State::new(),
+            sync: button::State::new(),
+            remove: button::State::new(),
+            sliders: vec![],
+            depths: vec![],
+        }
+    }
+}
+
+/// The instrument parameters of one track and the sources modulating them
+/// and its inserts. Every source gets a row of depth knobs, one per target
+#[derive(Debug)]
+pub struct ModulationMatrix {
+    slicing: button::State,
+    drone: button::State,
+    add_buttons: Vec<button::State>,
+    instrument_sliders: Vec<h_slider::State>,
+    sources: Vec<SourceWidgets>,
+}
+
+impl ModulationMatrix {
+    pub fn new() -> Self {
+        Self {
+            slicing: button::State::new(),
+            drone: button::State::new(),
+            add_buttons: (0..3).map(|_| button::State::new()).collect(),
+            instrument_sliders: vec![],
+            sources: vec![],
+        }
+    }
+
+    pub fn view<'a>(
+        &'a mut self,
+        track: &'a Track,
+        instrument_params: Option<&'a Vec<Param>>,
+        patch: Option<&'a PatchFile>,
+        samples: &'a [Sample],
+    ) -> Element<'a, Message> {
+        let ModulationMatrix {
+            slicing,
+            drone,
+            add_buttons,
+            instrument_sliders,
+            sources,
+        } = self;
+
+        let mut matrix = Column::new()
+            .spacing(10)
+            .push(Text::new(format!("{} instrument", track.name)).size(16));
+
+        if let Some(patch) = patch {
+            let name = patch
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            matrix = matrix.push(Text::new(format!("Patch {}", name)).size(14));
+
+            // The last good version keeps playing while there are errors
+            if let Some(error) = &patch.error {
+                matrix = matrix.push(
+                    Text::new(error.to_string())
+                        .size(14)
+                        .color(palette().destructive),
+                );
+            }
+        }
+
+        if let TrackInstrument::Wavetable {
+            sample_id,
+            slicing: current,
+        } = &track.instrument
+        {
+            let name = sample_name(samples, *sample_id);
+            matrix = matrix.push(
+                Row::new()
+                    .spacing(10)
+                    .push(
+                        Text::new(format!("Wavetable {}", name))
+                            .width(Length::Fill)
+                            .size(14),
+                    )
+                    .push(
+                        Button::new(
+                            slicing,
+                            Text::new(current.name()).size(14),
+                        )
+                        .on_press(Message::SetWavetableSlicing(current.next()))
+                        .style(style::Button::Control),
+                    ),
+            );
+        }
+
+        if let TrackInstrument::Granular {
+            sample_id,
+            drone: is_drone,
+        } = &track.instrument
+        {
+            matrix = matrix.push(
+                Row::new()
+                    .spacing(10)
+                    .push(
+                        Text::new(format!(
+                            "Granular {}",
+                            sample_name(samples, *sample_id)
+                        ))
+                        .width(Length::Fill)
+                        .size(14),
+                    )
+                    .push(
+                        Button::new(drone, Text::new("Drone").size(14))
+                            .on_press(Message::ToggleGranularDrone)
+                            .style(if *is_drone {
+                                style::Button::Primary
+                            } else {
+                                style::Button::Control
+                            }),
+                    ),
+            );
+        }
+
+        let params = instrument_params.map_or(&[][..], |params| &params[..]);
+        instrument_sliders.resize_with(params.len(), || {
+            h_slider::State::new(NormalParam::default())
+        });
+
+        for (index, (param, slider)) in
+            params.iter().zip(instrument_sliders).enumerate()
+        {
+            slider.set_normal(Normal::from_clipped(param.normal()));
+
+            matrix = matrix.push(
+                Row::new()
+                    .spacing(10)
+                    .push(
+                        Text::new(param.spec.name)
+                            .width(Length::Units(90))
+                            .size(14),
+                    )
+                    .push(
+                        HSlider::new(slider, move |normal| {
+                            Message::SetInstrumentParam(index, normal)
+                        })
+                        .on_grab(move || {
+                            Some(Message::GrabInstrumentParam(index))
+                        })
+                        .on_release(move || {
+                            Some(Message::ReleaseInstrumentParam(index))
+                        })
+                        .style(style::Slider),
+                    )
+                    .push(
+                        Text::new(format!("{:.2}", param.get()))
+                            .width(Length::Units(60))
+                            .size(14),
+                    ),
+            );
+        }
+
+        let mut add = Row::new().spacing(5);
+        for (source, state) in [Source::LFO, Source::ENVELOPE, Source::FOLLOWER]
+            .iter()
+            .zip(add_buttons)
+        {
+            add = add.push(
+                Button::new(state, Text::new(source.name()).size(14))
+                    .on_press(Message::AddModulationSource(*source))
+                    .style(style::Button::Control),
+            );
+        }
+
+        matrix = matrix
+            .push(Text::new(format!("{} modulation", track.name)).size(16))
+            .push(add);
+
+        let targets = target_list(track);
+        let depth_range = FloatRange::new(-1.0, 1.0);
+
+        sources.resize_with(track.modulation.sources.len(), SourceWidgets::new);
+
+        for (index, (source, widgets)) in
+            track.modulation.sources.iter().zip(sources).enumerate()
+        {
+            let SourceWidgets {
+                shape,
+                sync,
+                remove,
+                sliders,
+                depths,
+            } = widgets;
+
+            let mut header = Row::new().spacing(5).push(
+                Text::new(format!("{} {}", source.name(), index + 1))
+                    .width(Length::Fill)
+                    .size(14),
+            );
+
+            if let Source::Lfo {
+                shape: lfo_shape,
+                rate,
+                sync: lfo_sync,
+            } = *source
+            {
+                header = header
+                    .push(
+                        Button::new(
+                            shape,
+                            Text::new(lfo_shape.name()).size(14),
+                        )
+                        .on_press(Message::SetModulationSource(
+                            index,
+                            Source::Lfo {
+                                shape: lfo_shape.next(),
+                                rate,
+                                sync: lfo_sync,
+                            },
+                        ))
+                        .style(style::Button::Control),
+                    )
+                    .push(
+                        Button::new(sync, Text::new("Sync").size(14))
+                            .on_press(Message::SetModulationSource(
+                                index,
+                                Source::Lfo {
+                                    shape: lfo_shape,
+                                    rate,
+                                    sync: match lfo_sync {
+                                        Some(_) => None,
+                                        None => Some(1.0),
+                                    },
+                                },
+                            ))
+                            .style(if lfo_sync.is_some() {
+                                style::Button::Primary
+                            } else {
+                                style::Button::Control
+                            }),
+                    );
+            }
+
+            header = header.push(
+                Button::new(
+                    remove,
+                    Text::new(icon_to_char(iced_aw::Icon::X))
+                        .font(iced_aw::ICON_FONT),
+                )
+                .on_press(Message::RemoveModulationSource(index))
+                .style(style::Button::Destructive),
+            );
+
+            let mut column = Column::new().spacing(5).push(header);
+
+            let controls = controls(source);
+            sliders.resize_with(controls.len(), || {
+                h_slider::State::new(NormalParam::default())
+            });
+
+            for (control, slider) in controls.into_iter().zip(sliders) {
+                slider.set_normal(Normal::from_clipped(
+                    control.spec.to_normal(control.value),
+                ));
+
+                let (source, spec, set) = (*source, control.spec, control.set);
+                column = column.push(
+                    Row::new()
+                        .spacing(10)
+                        .push(
+                            Text::new(control.name)
+                                .width(Length::Units(90))
+                                .size(14),
+                        )
+                        .push(
+                            HSlider::new(slider, move |normal| {
+                                let value = spec.from_normal(normal.as_f32());
+                                Message::SetModulationSource(
+                                    index,
+                                    set(source, value),
+                                )
+                            })
+                            .style(style::Slider),
+                        )
+                        .push(
+                            Text::new(control.label)
+                                .width(Length::Units(60))
+                                .size(14),
+                        ),
+                );
+            }
+
+            depths.resize_with(targets.len(), || {
+                knob::State::new(depth_range.default_normal_param())
+            });
+
+            let mut knobs = Row::new().spacing(10);
+            for (target, knob) in targets.iter().zip(depths) {
+                let depth = track.modulation.depth(index, *target);
+                knob.set_normal(depth_range.map_to_normal(depth as f32));
+
+                let target: Target = *target;
+                knobs = knobs.push(
+                    Column::new()
+                        .spacing(2)
+                        .width(Length::Units(70))
+                        .align_items(iced::Alignment::Center)
+                        .push(Knob::new(knob, move |normal| {
+                            Message::SetModulationDepth {
+                                source: index,
+                                target,
+                                depth: depth_range.unmap_to_value(normal)
+                                    as f64,
+                            }
+                        }))
+                        .push(Text::new(target_name(track, target)).size(12)),
+                );
+            }
+
+            matrix = matrix.push(column.push(knobs));
+        }
+
+        matrix.into()
+    }
+}
@@ -1,7 +1,45 @@
 use iced::{button, pane_grid, Button, Element, Text};
+use serde::{Deserialize, Serialize};
 
 use crate::app::Message;
 
+/// What a pane shows. Every kind keeps its own widget state in `Content`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PaneKind {
+    Composition,
+    SampleCreator,
+    Mixer,
+    Browser,
+    PatchGraph,
+    Keymap,
+    Settings,
+}
+
+impl PaneKind {
+    /// Every kind, in the order the chooser offers them
+    pub const ALL: [PaneKind; 7] = [
+        PaneKind::Composition,
+        PaneKind::SampleCreator,
+        PaneKind::Mixer,
+        PaneKind::Browser,
+        PaneKind::PatchGraph,
+        PaneKind::Keymap,
+        PaneKind::Settings,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            PaneKind::Composition => "Composition",
+            PaneKind::SampleCreator => "Sample creator",
+            PaneKind::Mixer => "Mixer",
+            PaneKind::Browser => "Samples",
+            PaneKind::PatchGraph => "Patch graph",
+            PaneKind::Keymap => "Key bindings",
+            PaneKind::Settings => "Settings",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Pane {
     pub is_pinned: bool,
@@ -11,11 +49,12 @@ pub struct Pane {
 }
 
 impl Pane {
-    pub fn new(id: usize) -> Self {
+    /// A pane of `kind`, or one offering the kinds to choose from
+    pub fn new(kind: Option<PaneKind>) -> Self {
         Self {
             is_pinned: false,
             pin_button: button::State::new(),
-            content: content::Content::new(id),
+            content: content::Content::new(kind),
             controls: Controls::new(),
         }
     }
@@ -23,83 +62,235 @@ impl Pane {
 
 // enum PanesGridMessage {}
 pub mod content {
+    use std::collections::HashMap;
+
     use iced_aw::graphics::icons::icon_to_char;
 
+    use super::PaneKind;
     use crate::app::{
-        ui::components::audio_mixer::{
-            channel_fader::ChannelFader, test_canvas,
+        composition::{Composition, TrackInstrument},
+        engine::effects::Param,
+        engine::patch::{graph::GraphError, PatchFile},
+        engine::sample::SampleLibrary,
+        engine::transcription::Transcription,
+        engine::VOLUME,
+        keymap::{Action, Key, Keymap},
+        settings::Settings,
+        ui::components::{
+            audio_mixer::{
+                channel_fader::ChannelFader, test_canvas, track_list::TrackList,
+            },
+            automation::AutomationEditor,
+            effects_rack::EffectsRack,
+            graph_editor::GraphEditor,
+            keymap::KeymapEditor,
+            midi_effects::MidiEffectsRack,
+            modulation::ModulationMatrix,
+            sampler::ZoneEditor,
+            settings::SettingsEditor,
+            step_sequencer::StepSequencerEditor,
+            transcription::TranscriptionSettings,
+            tuning::TuningEditor,
         },
+        ui::theme::ThemeType,
         Message,
     };
 
+    /// What the panes show, borrowed from the app for one frame
+    pub struct Context<'a> {
+        pub composition: &'a Composition,
+        pub selected_pattern: usize,
+        pub focused_track: usize,
+        pub effect_params: &'a HashMap<u64, Vec<Param>>,
+        pub instrument_params: Option<&'a Vec<Param>>,
+        pub patch: Option<&'a PatchFile>,
+        pub graph_draft: Option<&'a (u64, usize, String)>,
+        pub graph_error: Option<&'a GraphError>,
+        pub transcription: &'a Transcription,
+        pub playhead: Option<f64>,
+        pub has_sample_creator_open: bool,
+        pub keymap: &'a Keymap,
+        // The action being rebound and the keys pressed so far
+        pub rebinding: Option<&'a (Action, Vec<Key>)>,
+        pub settings: &'a Settings,
+        pub settings_draft: Option<&'a Settings>,
+        pub themes: &'a [ThemeType],
+        pub sample_library: &'a SampleLibrary,
+    }
+
     #[derive(Debug)]
     pub struct Content {
-        pub id: usize,
-        pub scroll: iced::scrollable::State,
-        pub split_horizontally: iced::button::State,
-        pub split_vertically: iced::button::State,
-        pub close: iced::button::State,
-        pub pane_name: String,
-        // TODO audio_bus: -> Shows the audio output signal in decibels,
-        pub channel_fader: ChannelFader,
-        open_audio_io: iced::button::State,
+        scroll: iced::scrollable::State,
+        state: State,
+    }
+
+    #[derive(Debug)]
+    enum State {
+        Chooser(Chooser),
+        Composition(CompositionPane),
+        SampleCreator(SampleCreatorPane),
+        Mixer(MixerPane),
+        Browser(BrowserPane),
+        PatchGraph(PatchGraphPane),
+        Keymap(KeymapPane),
+        Settings(SettingsPane),
     }
 
     impl Content {
-        pub fn new(id: usize) -> Self {
+        pub fn new(kind: Option<PaneKind>) -> Self {
+            let state = match kind {
+                None => State::Chooser(Chooser::default()),
+                Some(PaneKind::Composition) => {
+                    State::Composition(CompositionPane::new())
+                }
+                Some(PaneKind::SampleCreator) => {
+                    State::SampleCreator(SampleCreatorPane::new())
+                }
+                Some(PaneKind::Mixer) => State::Mixer(MixerPane::new()),
+                Some(PaneKind::Browser) => {
+                    State::Browser(BrowserPane::default())
+                }
+                Some(PaneKind::PatchGraph) => {
+                    State::PatchGraph(PatchGraphPane::new())
+                }
+                Some(PaneKind::Keymap) => State::Keymap(KeymapPane::default()),
+                Some(PaneKind::Settings) => {
+                    State::Settings(SettingsPane::new())
+                }
+            };
+
             Content {
-                id,
                 scroll: iced::scrollable::State::new(),
-                split_horizontally: iced::button::State::new(),
-                split_vertically: iced::button::State::new(),
-                close: iced::button::State::new(),
-                pane_name: "Composition".to_string(),
-                // AUDIO UI
-                channel_fader: ChannelFader::new(),
-                open_audio_io: iced::button::State::new(),
+                state,
+            }
+        }
+
+        /// `None` while the pane offers the kinds to choose from
+        pub fn kind(&self) -> Option<PaneKind> {
+            match self.state {
+                State::Chooser(_) => None,
+                State::Composition(_) => Some(PaneKind::Composition),
+                State::SampleCreator(_) => Some(PaneKind::SampleCreator),
+                State::Mixer(_) => Some(PaneKind::Mixer),
+                State::Browser(_) => Some(PaneKind::Browser),
+                State::PatchGraph(_) => Some(PaneKind::PatchGraph),
+                State::Keymap(_) => Some(PaneKind::Keymap),
+                State::Settings(_) => Some(PaneKind::Settings),
             }
         }
-        pub fn view(
-            &mut self,
+
+        pub fn title(&self) -> &'static str {
+            self.kind().map_or("New pane", |kind| kind.title())
+        }
+
+        pub fn view<'a>(
+            &'a mut self,
             pane: iced::pane_grid::Pane,
-            total_panes: usize,
-            is_pinned: bool,
-            has_sample_creator_open: bool,
-            pane_name: String,
-        ) -> iced::Element<Message> {
-            let Content {
-                scroll,
-                split_horizontally,
-                // split_vertically,
-                // close,
-                // pane_name,
-                ..
-            } = self;
-
-            let button = |state, label, message, style| {
-                iced::Button::new(
-                    state,
-                    iced::Text::new(label)
-                        .width(iced::Length::Fill)
-                        .horizontal_alignment(
-                            iced::alignment::Horizontal::Center,
-                        )
-                        .size(16),
-                )
+            context: &Context<'a>,
+        ) -> iced::Element<'a, Message> {
+            let content = iced::Scrollable::new(&mut self.scroll)
                 .width(iced::Length::Fill)
-                .padding(8)
-                .on_press(message)
-                .style(style)
+                .height(iced::Length::Fill)
+                .spacing(10);
+
+            let content = match &mut self.state {
+                State::Chooser(chooser) => chooser.view(content, pane),
+                State::Composition(composition) => {
+                    composition.view(content, pane, context)
+                }
+                State::SampleCreator(sample_creator) => {
+                    sample_creator.view(content, context)
+                }
+                State::Mixer(mixer) => mixer.view(content, context),
+                State::Browser(browser) => browser.view(content, context),
+                State::PatchGraph(graph) => graph.view(content, context),
+                State::Keymap(keymap) => keymap.view(content, context),
+                State::Settings(settings) => settings.view(content, context),
             };
 
-            let mut content = iced::Scrollable::new(scroll)
+            iced::Container::new(content)
                 .width(iced::Length::Fill)
                 .height(iced::Length::Fill)
-                .spacing(10);
+                .padding(5)
+                .into()
+        }
+    }
 
-            if self.id == 0 && !has_sample_creator_open {
-                content = content.push(button(
-                    split_horizontally,
+    type Scrollable<'a> = iced::Scrollable<'a, Message>;
+
+    // A big button filling the pane's width
+    fn wide_button<'a>(
+        state: &'a mut iced::button::State,
+        label: &str,
+        message: Message,
+        style: super::style::Button,
+    ) -> iced::Button<'a, Message> {
+        iced::Button::new(
+            state,
+            iced::Text::new(label)
+                .width(iced::Length::Fill)
+                .horizontal_alignment(iced::alignment::Horizontal::Center)
+                .size(16),
+        )
+        .width(iced::Length::Fill)
+        .padding(8)
+        .on_press(message)
+        .style(style)
+    }
+
+    // Offers every kind when a pane was just split off
+    #[derive(Debug, Default)]
+    struct Chooser {
+        kinds: [iced::button::State; PaneKind::ALL.len()],
+    }
+
+    impl Chooser {
+        fn view<'a>(
+            &'a mut self,
+            mut content: Scrollable<'a>,
+            pane: iced::pane_grid::Pane,
+        ) -> Scrollable<'a> {
+            content = content.push(iced::Text::new("Open").size(16));
+            for (state, kind) in self.kinds.iter_mut().zip(PaneKind::ALL) {
+                content = content.push(wide_button(
+                    state,
+                    kind.title(),
+                    Message::SetPaneKind(pane, kind),
+                    super::style::Button::Control,
+                ));
+            }
+            content
+        }
+    }
+
+    // The tuning and the step editor
+    #[derive(Debug)]
+    struct CompositionPane {
+        create_sample: iced::button::State,
+        tuning: TuningEditor,
+        step_sequencer: StepSequencerEditor,
+    }
+
+    impl CompositionPane {
+        fn new() -> Self {
+            Self {
+                create_sample: iced::button::State::new(),
+                tuning: TuningEditor::new(),
+                step_sequencer: StepSequencerEditor::new(),
+            }
+        }
+
+        fn view<'a>(
+            &'a mut self,
+            mut content: Scrollable<'a>,
+            pane: iced::pane_grid::Pane,
+            context: &Context<'a>,
+        ) -> Scrollable<'a> {
+            let composition = context.composition;
+
+            if !context.has_sample_creator_open {
+                content = content.push(wide_button(
+                    &mut self.create_sample,
                     "Create new sample",
                     Message::OpenCreateNewSamplePane(
                         iced::pane_grid::Axis::Horizontal,
@@ -109,71 +300,357 @@ pub mod content {
                 ))
             };
 
-            // pane with ID 1 is sample creator // TODO: find a better denomination to identify panes
-            if self.id == 1 {
-                //
-                let ChannelFader {
-                    db_tick_marks,
-                    db_text_marks,
-                    channel_fader_db_state,
-                    output_text,
-                    ..
-                } = &mut self.channel_fader;
-
-                let v_slider_db = iced_audio::VSlider::new(
-                    channel_fader_db_state,
-                    Message::DB,
+            content = content.push(self.tuning.view(&composition.tuning));
+
+            // The first drum track of the composition gets a step editor
+            let chain =
+                composition.tracks.iter().find_map(|track| {
+                    match &track.instrument {
+                        TrackInstrument::StepSequencer { chain } => Some(chain),
+                        _ => None,
+                    }
+                });
+
+            if let Some(chain) = chain {
+                content = content.push(self.step_sequencer.view(
+                    composition,
+                    chain,
+                    context.selected_pattern,
+                ));
+            }
+
+            content
+        }
+    }
+
+    // The input fader, recording and how recordings become clips
+    #[derive(Debug)]
+    struct SampleCreatorPane {
+        // TODO audio_bus: -> Shows the audio output signal in decibels,
+        channel_fader: ChannelFader,
+        open_audio_io: iced::button::State,
+        transcription: TranscriptionSettings,
+    }
+
+    impl SampleCreatorPane {
+        fn new() -> Self {
+            Self {
+                channel_fader: ChannelFader::new(),
+                open_audio_io: iced::button::State::new(),
+                transcription: TranscriptionSettings::new(),
+            }
+        }
+
+        fn view<'a>(
+            &'a mut self,
+            mut content: Scrollable<'a>,
+            context: &Context<'a>,
+        ) -> Scrollable<'a> {
+            let ChannelFader {
+                db_range,
+                db_tick_marks,
+                db_text_marks,
+                channel_fader_db_state,
+            } = &mut self.channel_fader;
+
+            // The fader moves the focused track's volume
+            let track = context.composition.tracks.get(context.focused_track);
+            let volume = track.map_or(VOLUME.default, |track| track.volume);
+            channel_fader_db_state
+                .set_normal(db_range.map_to_normal(volume as f32));
+            let output_text = match track {
+                Some(track) => format!("{}  {:+.1} dB", track.name, volume),
+                None => "No track".to_string(),
+            };
+
+            let db_range = *db_range;
+            let v_slider_db = iced_audio::VSlider::new(
+                channel_fader_db_state,
+                move |normal| {
+                    Message::SetVolume(db_range.unmap_to_value(normal) as f64)
+                },
+            )
+            .on_grab(|| Some(Message::GrabVolume))
+            .on_release(|| Some(Message::ReleaseVolume))
+            .tick_marks(db_tick_marks)
+            .text_marks(db_text_marks)
+            .style(style::Slider);
+
+            let level_meter_test_l = test_canvas::Rainbow::new();
+            let level_meter_test_r = test_canvas::Rainbow::new();
+
+            // push the widgets into rows
+            let v_slider_row = iced::Row::new()
+                .spacing(20)
+                .max_height(200)
+                .push(
+                    iced::Column::new()
+                        .max_width(120)
+                        .height(iced::Length::Fill)
+                        // .spacing(10)
+                        .push(v_slider_db),
                 )
-                .tick_marks(db_tick_marks)
-                .text_marks(db_text_marks);
-
-                let level_meter_test_l = test_canvas::Rainbow::new();
-                let level_meter_test_r = test_canvas::Rainbow::new();
-
-                // push the widgets into rows
-                let v_slider_row = iced::Row::new()
-                    .spacing(20)
-                    .max_height(200)
-                    .push(
-                        iced::Column::new()
-                            .max_width(120)
-                            .height(iced::Length::Fill)
-                            // .spacing(10)
-                            .push(v_slider_db),
-                    )
-                    .push(
-                        iced::Row::new()
-                            .max_width(120)
-                            .height(iced::Length::Fill)
-                            // .push(level_meter::level_meter(10., 200.))
-                            .push(level_meter_test_l)
-                            .spacing(10) // .push(level_meter::level_meter(10., 200.)),
-                            .push(level_meter_test_r),
-                    );
-
-                let channel_fader = iced::Column::new()
-                    .spacing(20)
-                    .padding(20)
-                    .push(v_slider_row)
-                    .push(iced::Text::new(output_text.to_string()).size(16));
-
-                content = content.push(channel_fader);
-
-                let open_audio_btn = iced::Button::new(
-                    &mut self.open_audio_io,
-                    iced::Text::new(icon_to_char(iced_aw::Icon::RecordCircle))
+                .push(
+                    iced::Row::new()
+                        .max_width(120)
+                        .height(iced::Length::Fill)
+                        // .push(level_meter::level_meter(10., 200.))
+                        .push(level_meter_test_l)
+                        .spacing(10) // .push(level_meter::level_meter(10., 200.)),
+                        .push(level_meter_test_r),
+                );
+
+            let channel_fader = iced::Column::new()
+                .spacing(20)
+                .padding(20)
+                .push(v_slider_row)
+                .push(iced::Text::new(output_text).size(16));
+
+            content = content.push(channel_fader);
+
+            let open_audio_btn = iced::Button::new(
+                &mut self.open_audio_io,
+                iced::Text::new(icon_to_char(iced_aw::Icon::RecordCircle))
+                    .font(iced_aw::ICON_FONT),
+            )
+            .on_press(Message::OpenAudioDefaultChannel);
+
+            content
+                .push(open_audio_btn)
+                .push(self.transcription.view(context.transcription))
+        }
+    }
+
+    // The tracks to focus, then the focused track's sampler zones,
+    // modulation, MIDI effects, inserts and automation
+    #[derive(Debug)]
+    struct MixerPane {
+        track_list: TrackList,
+        zone_editor: ZoneEditor,
+        modulation: ModulationMatrix,
+        midi_effects: MidiEffectsRack,
+        effects_rack: EffectsRack,
+        automation: AutomationEditor,
+    }
+
+    impl MixerPane {
+        fn new() -> Self {
+            Self {
+                track_list: TrackList::default(),
+                zone_editor: ZoneEditor::new(),
+                modulation: ModulationMatrix::new(),
+                midi_effects: MidiEffectsRack::new(),
+                effects_rack: EffectsRack::new(),
+                automation: AutomationEditor::new(),
+            }
+        }
+
+        fn view<'a>(
+            &'a mut self,
+            content: Scrollable<'a>,
+            context: &Context<'a>,
+        ) -> Scrollable<'a> {
+            let composition = context.composition;
+            let content = content.push(
+                self.track_list
+                    .view(&composition.tracks, context.focused_track),
+            );
+            let track = match composition.tracks.get(context.focused_track) {
+                Some(track) => track,
+                None => return content,
+            };
+
+            let content = match &track.instrument {
+                TrackInstrument::Sampler(patch) => {
+                    content.push(self.zone_editor.view(
+                        patch,
+                        &composition.samples,
+                        context.sample_library,
+                    ))
+                }
+                _ => content,
+            };
+
+            content
+                .push(self.modulation.view(
+                    track,
+                    context.instrument_params,
+                    context.patch,
+                    &composition.samples,
+                ))
+                .push(self.midi_effects.view(track))
+                .push(self.effects_rack.view(
+                    track,
+                    context.effect_params,
+                    &composition.samples,
+                ))
+                .push(self.automation.view(track, context.playhead))
+        }
+    }
+
+    // The buttons next to a composition sample
+    #[derive(Debug, Default)]
+    struct SampleButtons {
+        play: iced::button::State,
+        wavetable: iced::button::State,
+        granular: iced::button::State,
+        transcribe: iced::button::State,
+        add_lane: iced::button::State,
+    }
+
+    // Every sample in the composition can be played as an instrument, a
+    // wavetable or grains, turned into a clip or triggered from a step
+    // sequencer lane
+    #[derive(Debug, Default)]
+    struct BrowserPane {
+        sample_buttons: Vec<SampleButtons>,
+    }
+
+    impl BrowserPane {
+        fn view<'a>(
+            &'a mut self,
+            mut content: Scrollable<'a>,
+            context: &Context<'a>,
+        ) -> Scrollable<'a> {
+            let icon = |state, icon, message, style| {
+                iced::Button::new(
+                    state,
+                    iced::Text::new(icon_to_char(icon))
                         .font(iced_aw::ICON_FONT),
                 )
-                .on_press(Message::OpenAudioDefaultChannel);
+                .on_press(message)
+                .style(style)
+            };
 
-                content = content.push(open_audio_btn);
+            let samples = &context.composition.samples;
+            self.sample_buttons
+                .resize_with(samples.len(), SampleButtons::default);
+
+            for (
+                sample,
+                SampleButtons {
+                    play,
+                    wavetable,
+                    granular,
+                    transcribe,
+                    add_lane,
+                },
+            ) in samples.iter().zip(self.sample_buttons.iter_mut())
+            {
+                content = content.push(
+                    iced::Row::new()
+                        .spacing(10)
+                        .push(
+                            iced::Text::new(&sample.name)
+                                .width(iced::Length::Fill)
+                                .size(16),
+                        )
+                        .push(icon(
+                            play,
+                            iced_aw::Icon::Soundwave,
+                            Message::UseSampleAsInstrument(sample.id),
+                            super::style::Button::Primary,
+                        ))
+                        .push(icon(
+                            wavetable,
+                            iced_aw::Icon::Layers,
+                            Message::UseSampleAsWavetable(sample.id),
+                            super::style::Button::Primary,
+                        ))
+                        .push(icon(
+                            granular,
+                            iced_aw::Icon::Stars,
+                            Message::UseSampleAsGranular(sample.id),
+                            super::style::Button::Primary,
+                        ))
+                        .push(icon(
+                            transcribe,
+                            iced_aw::Icon::MusicNoteList,
+                            Message::SampleToClip(sample.id),
+                            super::style::Button::Primary,
+                        ))
+                        .push(icon(
+                            add_lane,
+                            iced_aw::Icon::Grid3x3Gap,
+                            Message::AddDrumLane(sample.id),
+                            super::style::Button::Control,
+                        )),
+                );
             }
 
-            iced::Container::new(content)
-                .width(iced::Length::Fill)
-                .height(iced::Length::Fill)
-                .padding(5)
-                .into()
+            content
+        }
+    }
+
+    // The patch graph of the focused track
+    #[derive(Debug)]
+    struct PatchGraphPane {
+        graph_editor: GraphEditor,
+    }
+
+    impl PatchGraphPane {
+        fn new() -> Self {
+            Self {
+                graph_editor: GraphEditor::new(),
+            }
+        }
+
+        fn view<'a>(
+            &'a mut self,
+            content: Scrollable<'a>,
+            context: &Context<'a>,
+        ) -> Scrollable<'a> {
+            match context.composition.tracks.get(context.focused_track) {
+                Some(track) => content.push(self.graph_editor.view(
+                    track,
+                    context.graph_draft,
+                    context.graph_error,
+                )),
+                None => content,
+            }
+        }
+    }
+
+    // The key bindings and the cheat sheet
+    #[derive(Debug, Default)]
+    struct KeymapPane {
+        keymap_editor: KeymapEditor,
+    }
+
+    impl KeymapPane {
+        fn view<'a>(
+            &'a mut self,
+            content: Scrollable<'a>,
+            context: &Context<'a>,
+        ) -> Scrollable<'a> {
+            content.push(
+                self.keymap_editor.view(context.keymap, context.rebinding),
+            )
+        }
+    }
+
+    // The settings file, edited live
+    #[derive(Debug)]
+    struct SettingsPane {
+        settings_editor: SettingsEditor,
+    }
+
+    impl SettingsPane {
+        fn new() -> Self {
+            Self {
+                settings_editor: SettingsEditor::new(),
+            }
+        }
+
+        fn view<'a>(
+            &'a mut self,
+            content: Scrollable<'a>,
+            context: &Context<'a>,
+        ) -> Scrollable<'a> {
+            content.push(self.settings_editor.view(
+                context.settings_draft.unwrap_or(context.settings),
+                context.themes,
+            ))
         }
     }
 }
@@ -209,16 +686,19 @@ impl Controls {
 
 pub mod style {
     use iced::{button, container, Background, Color, Vector};
+    use iced_audio::style::{h_slider, v_slider};
 
-    use crate::app::ui::colors::{
-        ACTIVE, HOVERED, PANE_ID_COLOR_FOCUSED, SURFACE,
-    };
+    use crate::app::ui::theme::palette;
 
     pub struct PsycheDaily;
 
     impl container::StyleSheet for PsycheDaily {
         fn style(&self) -> container::Style {
+            let palette = palette();
+
             container::Style {
+                text_color: Some(palette.text),
+                background: Some(Background::Color(palette.background)),
                 ..Default::default()
             }
         }
@@ -230,6 +710,7 @@ pub mod style {
 
     impl container::StyleSheet for TitleBar {
         fn style(&self) -> container::Style {
+            let palette = palette();
             let pane = Pane {
                 is_focused: self.is_focused,
             }
@@ -237,9 +718,12 @@ pub mod style {
 
             container::Style {
                 text_color: if self.is_focused {
-                    Some(Color::from_rgba(120., 120., 120., 0.5))
+                    Some(palette.text)
                 } else {
-                    Some(Color::from_rgba(255., 255., 100., 0.9))
+                    Some(Color {
+                        a: 0.6,
+                        ..palette.text
+                    })
                 },
                 background: Some(pane.border_color.into()),
                 ..Default::default()
@@ -253,13 +737,18 @@ pub mod style {
 
     impl container::StyleSheet for Pane {
         fn style(&self) -> container::Style {
+            let palette = palette();
+
             container::Style {
-                background: Some(Background::Color(SURFACE)),
+                background: Some(Background::Color(palette.surface)),
                 border_width: 1.0,
                 border_color: if self.is_focused {
-                    Color::from_rgba(0.5, 0.5, 0.5, 1.)
+                    palette.border
                 } else {
-                    Color::from_rgba(0.5, 0.5, 0.5, 0.)
+                    Color {
+                        a: 0.0,
+                        ..palette.border
+                    }
                 },
                 ..Default::default()
             }
@@ -271,17 +760,19 @@ pub mod style {
         Destructive,
         Control,
         Pin,
+        // Text until it's hovered, for rows of choices
+        Flat,
     }
 
     impl button::StyleSheet for Button {
         fn active(&self) -> button::Style {
+            let palette = palette();
             let (background, text_color) = match self {
-                Button::Primary => (Some(ACTIVE), Color::WHITE),
-                Button::Destructive => {
-                    (None, Color::from_rgb8(0xFF, 0x47, 0x47))
-                }
-                Button::Control => (Some(PANE_ID_COLOR_FOCUSED), Color::WHITE),
-                Button::Pin => (Some(ACTIVE), Color::WHITE),
+                Button::Primary => (Some(palette.primary), palette.on_primary),
+                Button::Destructive => (None, palette.destructive),
+                Button::Control => (Some(palette.accent), palette.on_primary),
+                Button::Pin => (Some(palette.primary), palette.on_primary),
+                Button::Flat => (None, palette.text),
             };
 
             button::Style {
@@ -294,16 +785,21 @@ pub mod style {
         }
 
         fn hovered(&self) -> button::Style {
+            let palette = palette();
             let active = self.active();
 
             let background = match self {
-                Button::Primary => Some(HOVERED),
+                Button::Primary => Some(palette.hovered),
                 Button::Destructive => Some(Color {
                     a: 0.2,
                     ..active.text_color
                 }),
-                Button::Control => Some(PANE_ID_COLOR_FOCUSED),
-                Button::Pin => Some(HOVERED),
+                Button::Control => Some(palette.accent),
+                Button::Pin => Some(palette.hovered),
+                Button::Flat => Some(Color {
+                    a: 0.2,
+                    ..palette.primary
+                }),
             };
 
             button::Style {
@@ -312,4 +808,53 @@ pub mod style {
             }
         }
     }
+
+    /// Sliders and faders
+    pub struct Slider;
+
+    // Both sliders take the same rect, as different types
+    macro_rules! rect {
+        ($module:ident, $handle:ident) => {{
+            let palette = palette();
+
+            $module::Style::Rect($module::RectStyle {
+                back_color: palette.rail,
+                back_border_width: 1.0,
+                back_border_radius: 2.0,
+                back_border_color: palette.border,
+                filled_color: palette.primary,
+                handle_color: palette.$handle,
+                handle_width: 4,
+                handle_filled_gap: 1.0,
+            })
+        }};
+    }
+
+    impl h_slider::StyleSheet for Slider {
+        fn active(&self) -> h_slider::Style {
+            rect!(h_slider, accent)
+        }
+
+        fn hovered(&self) -> h_slider::Style {
+            rect!(h_slider, hovered)
+        }
+
+        fn dragging(&self) -> h_slider::Style {
+            rect!(h_slider, hovered)
+        }
+    }
+
+    impl v_slider::StyleSheet for Slider {
+        fn active(&self) -> v_slider::Style {
+            rect!(v_slider, accent)
+        }
+
+        fn hovered(&self) -> v_slider::Style {
+            rect!(v_slider, hovered)
+        }
+
+        fn dragging(&self) -> v_slider::Style {
+            rect!(v_slider, hovered)
+        }
+    }
 }
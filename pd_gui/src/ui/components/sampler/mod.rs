@@ -0,0 +1,432 @@
+use iced::{
+    button, pick_list, Button, Column, Element, Length, PickList, Row, Text,
+};
+use iced_audio::{h_slider, HSlider, Normal, NormalParam};
+use iced_aw::graphics::icons::icon_to_char;
+
+use crate::app::{
+    composition::Sample,
+    engine::effects::ParamSpec,
+    engine::instrument::Adsr,
+    engine::midi_effects::NOTE_NAMES,
+    engine::sample::SampleLibrary,
+    engine::sampler::{SamplerPatch, Zone},
+    midi::{MAX_NOTE, MAX_VELOCITY},
+    ui::components::effects_rack::SampleChoice,
+    ui::components::panes::style,
+    Message,
+};
+
+const KEY: ParamSpec = ParamSpec::live("Key", 0.0, MAX_NOTE as f64, 60.0);
+const VELOCITY: ParamSpec =
+    ParamSpec::live("Velocity", 1.0, MAX_VELOCITY as f64, 127.0);
+const ATTACK: ParamSpec =
+    ParamSpec::live("Attack", 0.001, 5.0, 0.005).logarithmic();
+const DECAY: ParamSpec =
+    ParamSpec::live("Decay", 0.001, 5.0, 0.1).logarithmic();
+const SUSTAIN: ParamSpec = ParamSpec::live("Sustain", 0.0, 1.0, 0.8);
+const RELEASE: ParamSpec =
+    ParamSpec::live("Release", 0.001, 10.0, 0.2).logarithmic();
+
+// A slider writing its value back into a copy of a zone or an envelope
+struct Control<T> {
+    name: &'static str,
+    spec: &'static ParamSpec,
+    value: f64,
+    label: String,
+    set: fn(T, f64) -> T,
+}
+
+// Middle C is C4
+fn note_name(note: u8) -> String {
+    format!("{}{}", NOTE_NAMES[note as usize % 12], note as i32 / 12 - 1)
+}
+
+fn zone_controls(zone: &Zone) -> Vec<Control<Zone>> {
+    // Moving one end of a range past the other drags the other along
+    vec![
+        Control {
+            name: "Lowest key",
+            spec: &KEY,
+            value: zone.keys.0 as f64,
+            label: note_name(zone.keys.0),
+            set: |mut zone, value| {
+                let key = value.round() as u8;
+                zone.keys = (key, zone.keys.1.max(key));
+                zone
+            },
+        },
+        Control {
+            name: "Highest key",
+            spec: &KEY,
+            value: zone.keys.1 as f64,
+            label: note_name(zone.keys.1),
+            set: |mut zone, value| {
+                let key = value.round() as u8;
+                zone.keys = (zone.keys.0.min(key), key);
+                zone
+            },
+        },
+        Control {
+            name: "Root key",
+            spec: &KEY,
+            value: zone.root_key as f64,
+            label: note_name(zone.root_key),
+            set: |mut zone, value| {
+                zone.root_key = value.round() as u8;
+                zone
+            },
+        },
+        Control {
+            name: "Lowest velocity",
+            spec: &VELOCITY,
+            value: zone.velocities.0 as f64,
+            label: zone.velocities.0.to_string(),
+            set: |mut zone, value| {
+                let velocity = value.round() as u8;
+                zone.velocities = (velocity, zone.velocities.1.max(velocity));
+                zone
+            },
+        },
+        Control {
+            name: "Highest velocity",
+            spec: &VELOCITY,
+            value: zone.velocities.1 as f64,
+            label: zone.velocities.1.to_string(),
+            set: |mut zone, value| {
+                let velocity = value.round() as u8;
+                zone.velocities = (zone.velocities.0.min(velocity), velocity);
+                zone
+            },
+        },
+    ]
+}
+
+fn envelope_controls(adsr: &Adsr) -> Vec<Control<Adsr>> {
+    let seconds = |value: f64| format!("{:.0} ms", value * 1000.0);
+
+    vec![
+        Control {
+            name: "Attack",
+            spec: &ATTACK,
+            value: adsr.attack,
+            label: seconds(adsr.attack),
+            set: |adsr, value| Adsr {
+                attack: value,
+                ..adsr
+            },
+        },
+        Control {
+            name: "Decay",
+            spec: &DECAY,
+            value: adsr.decay,
+            label: seconds(adsr.decay),
+            set: |adsr, value| Adsr {
+                decay: value,
+                ..adsr
+            },
+        },
+        Control {
+            name: "Sustain",
+            spec: &SUSTAIN,
+            value: adsr.sustain,
+            label: format!("{:.0}%", adsr.sustain * 100.0),
+            set: |adsr, value| Adsr {
+                sustain: value,
+                ..adsr
+            },
+        },
+        Control {
+            name: "Release",
+            spec: &RELEASE,
+            value: adsr.release,
+            label: seconds(adsr.release),
+            set: |adsr, value| Adsr {
+                release: value,
+                ..adsr
+            },
+        },
+    ]
+}
+
+// A named slider with its value next to it
+fn slider_row<'a>(
+    name: &str,
+    slider: impl Into<Element<'a, Message>>,
+    label: String,
+) -> Row<'a, Message> {
+    Row::new()
+        .spacing(10)
+        .push(Text::new(name).width(Length::Units(110)).size(14))
+        .push(slider)
+        .push(Text::new(label).width(Length::Units(60)).size(14))
+}
+
+fn push_controls<'a, T: Clone + 'a>(
+    mut column: Column<'a, Message>,
+    current: &T,
+    controls: Vec<Control<T>>,
+    sliders: &'a mut Vec<h_slider::State>,
+    message: impl Fn(T) -> Message + Clone + 'a,
+) -> Column<'a, Message> {
+    sliders.resize_with(controls.len(), || {
+        h_slider::State::new(NormalParam::default())
+    });
+
+    for (control, slider) in controls.into_iter().zip(sliders) {
+        slider.set_normal(Normal::from_clipped(
+            control.spec.to_normal(control.value),
+        ));
+
+        let (current, spec, set, message) =
+            (current.clone(), control.spec, control.set, message.clone());
+        column = column.push(slider_row(
+            control.name,
+            HSlider::new(slider, move |normal| {
+                message(set(current.clone(), spec.from_normal(normal.as_f32())))
+            })
+            .style(style::Slider),
+            control.label,
+        ));
+    }
+
+    column
+}
+
+#[derive(Debug)]
+struct ZoneWidgets {
+    sample: pick_list::State<SampleChoice>,
+    is_looping: button::State,
+    remove: button::State,
+    sliders: Vec<h_slider::State>,
+    loop_sliders: [h_slider::State; 2],
+}
+
+impl ZoneWidgets {
+    fn new() -> Self {
+        Self {
+            sample: pick_list::State::default(),
+            is_looping: button::State::new(),
+            remove: button::State::new(),
+            sliders: vec![],
+            loop_sliders: [
+                h_slider::State::new(NormalParam::default()),
+                h_slider::State::new(NormalParam::default()),
+            ],
+        }
+    }
+}
+
+/// The zones and the envelope of a sampler
+#[derive(Debug)]
+pub struct ZoneEditor {
+    add: button::State,
+    envelope: Vec<h_slider::State>,
+    zones: Vec<ZoneWidgets>,
+}
+
+impl ZoneEditor {
+    pub fn new() -> Self {
+        Self {
+            add: button::State::new(),
+            envelope: vec![],
+            zones: vec![],
+        }
+    }
+
+    pub fn view<'a>(
+        &'a mut self,
+        patch: &'a SamplerPatch,
+        samples: &[Sample],
+        library: &SampleLibrary,
+    ) -> Element<'a, Message> {
+        let ZoneEditor {
+            add,
+            envelope,
+            zones,
+        } = self;
+
+        let mut add = Button::new(add, Text::new("Add zone").size(14))
+            .style(style::Button::Control);
+        if !patch.zones.is_empty() || !samples.is_empty() {
+            add = add.on_press(Message::AddZone);
+        }
+
+        let mut editor = Column::new()
+            .spacing(10)
+            .push(
+                Row::new()
+                    .spacing(5)
+                    .push(Text::new("Sampler").width(Length::Fill).size(16))
+                    .push(add),
+            )
+            .push(
+                Text::new(
+                    "Zones sharing a key and velocity take turns playing it",
+                )
+                .size(14),
+            );
+
+        let choices: Vec<SampleChoice> = samples
+            .iter()
+            .map(|sample| SampleChoice {
+                id: sample.id,
+                name: sample.name.clone(),
+            })
+            .collect();
+
+        zones.resize_with(patch.zones.len(), ZoneWidgets::new);
+
+        for (index, (zone, widgets)) in
+            patch.zones.iter().zip(zones).enumerate()
+        {
+            let ZoneWidgets {
+                sample,
+                is_looping,
+                remove,
+                sliders,
+                loop_sliders,
+            } = widgets;
+
+            let selected = choices
+                .iter()
+                .find(|choice| choice.id == zone.sample_id)
+                .cloned();
+            let picked = zone.clone();
+            let pick_list = PickList::new(
+                sample,
+                choices.clone(),
+                selected,
+                move |choice| {
+                    // Loop points belong to the old sample
+                    Message::SetZone(
+                        index,
+                        Zone {
+                            sample_id: choice.id,
+                            loop_points: None,
+                            ..picked.clone()
+                        },
+                    )
+                },
+            )
+            .text_size(14);
+
+            // Loops can only be placed on samples that were decoded
+            let buffer = library.get(zone.sample_id);
+            let frames = buffer.map_or(0, |buffer| buffer.frames());
+            let mut loop_button =
+                Button::new(is_looping, Text::new("Loop").size(14)).style(
+                    if zone.loop_points.is_some() {
+                        style::Button::Primary
+                    } else {
+                        style::Button::Control
+                    },
+                );
+            if frames > 0 {
+                loop_button = loop_button.on_press(Message::SetZone(
+                    index,
+                    Zone {
+                        loop_points: match zone.loop_points {
+                            Some(_) => None,
+                            None => Some((0, frames)),
+                        },
+                        ..zone.clone()
+                    },
+                ));
+            }
+
+            let header = Row::new()
+                .spacing(5)
+                .push(
+                    Text::new(format!("Zone {}", index + 1))
+                        .width(Length::Fill)
+                        .size(14),
+                )
+                .push(pick_list)
+                .push(loop_button)
+                .push(
+                    Button::new(
+                        remove,
+                        Text::new(icon_to_char(iced_aw::Icon::X))
+                            .font(iced_aw::ICON_FONT),
+                    )
+                    .on_press(Message::RemoveZone(index))
+                    .style(style::Button::Destructive),
+                );
+
+            let mut column = push_controls(
+                Column::new().spacing(5).push(header),
+                zone,
+                zone_controls(zone),
+                sliders,
+                move |zone| Message::SetZone(index, zone),
+            );
+
+            if let (Some((start, end)), Some(buffer)) =
+                (zone.loop_points, buffer.filter(|_| frames > 0))
+            {
+                let [start_slider, end_slider] = loop_sliders;
+                let at = |frame: usize| frame as f64 / frames as f64;
+                let seconds = |frame: usize| {
+                    format!("{:.2} s", frame as f64 / buffer.sample_rate)
+                };
+
+                start_slider.set_normal(Normal::from_clipped(at(start) as f32));
+                end_slider.set_normal(Normal::from_clipped(at(end) as f32));
+
+                // The start always stays before the end
+                let (looped, frame) = (zone.clone(), move |normal: Normal| {
+                    (normal.as_f32() as f64 * frames as f64) as usize
+                });
+                column = column.push(slider_row(
+                    "Loop start",
+                    HSlider::new(start_slider, move |normal| {
+                        Message::SetZone(
+                            index,
+                            Zone {
+                                loop_points: Some((
+                                    frame(normal).min(end.saturating_sub(1)),
+                                    end,
+                                )),
+                                ..looped.clone()
+                            },
+                        )
+                    })
+                    .style(style::Slider),
+                    seconds(start),
+                ));
+
+                let looped = zone.clone();
+                column = column.push(slider_row(
+                    "Loop end",
+                    HSlider::new(end_slider, move |normal| {
+                        Message::SetZone(
+                            index,
+                            Zone {
+                                loop_points: Some((
+                                    start,
+                                    frame(normal).max(start + 1),
+                                )),
+                                ..looped.clone()
+                            },
+                        )
+                    })
+                    .style(style::Slider),
+                    seconds(end),
+                ));
+            }
+
+            editor = editor.push(column);
+        }
+
+        editor = editor.push(Text::new("Envelope").size(14));
+        push_controls(
+            editor,
+            &patch.adsr,
+            envelope_controls(&patch.adsr),
+            envelope,
+            Message::SetSamplerEnvelope,
+        )
+        .into()
+    }
+}
@@ -0,0 +1,246 @@
+use iced::{
+    button, pick_list, text_input, Button, Column, Element, Length, PickList,
+    Row, Text, TextInput,
+};
+use iced_audio::{h_slider, HSlider, Normal, NormalParam};
+
+use crate::app::{
+    engine::effects::ParamSpec,
+    settings::{BackendSettings, Settings},
+    ui::components::panes::style,
+    ui::theme::ThemeType,
+    Message,
+};
+
+const LATENCY: ParamSpec = ParamSpec::live("Latency", 1.0, 200.0, 20.0);
+const TEMPO: ParamSpec = ParamSpec::live("Tempo", 40.0, 240.0, 120.0);
+const MIN_WIDTH: ParamSpec =
+    ParamSpec::live("Min width", 200.0, 1600.0, 300.0).logarithmic();
+const MIN_HEIGHT: ParamSpec =
+    ParamSpec::live("Min height", 150.0, 1200.0, 200.0).logarithmic();
+
+type Setter = fn(Settings, f64) -> Settings;
+
+/// Everything in the settings file. Sliders apply and save once let go of
+/// and the server once submitted, other changes as they're made
+#[derive(Debug)]
+pub struct SettingsEditor {
+    sliders: Vec<h_slider::State>,
+    backend: text_input::State,
+    theme: pick_list::State<ThemeType>,
+    musical_typing: button::State,
+    choose_projects: button::State,
+    clear_projects: button::State,
+}
+
+impl SettingsEditor {
+    pub fn new() -> Self {
+        Self {
+            sliders: (0..4)
+                .map(|_| h_slider::State::new(NormalParam::default()))
+                .collect(),
+            backend: text_input::State::new(),
+            theme: pick_list::State::default(),
+            musical_typing: button::State::new(),
+            choose_projects: button::State::new(),
+            clear_projects: button::State::new(),
+        }
+    }
+
+    pub fn view<'a>(
+        &'a mut self,
+        settings: &Settings,
+        themes: &[ThemeType],
+    ) -> Element<'a, Message> {
+        let SettingsEditor {
+            sliders,
+            backend,
+            theme,
+            musical_typing,
+            choose_projects,
+            clear_projects,
+        } = self;
+        let mut sliders = sliders.iter_mut();
+
+        let mut slider = |spec: &'static ParamSpec,
+                          value: f64,
+                          label: String,
+                          set: Setter| {
+            let state = sliders.next()?;
+            state.set_normal(Normal::from_clipped(spec.to_normal(value)));
+
+            let settings = settings.clone();
+            Some(
+                Row::new()
+                    .spacing(10)
+                    .push(
+                        Text::new(spec.name).width(Length::Units(90)).size(14),
+                    )
+                    .push(
+                        HSlider::new(state, move |normal| {
+                            let value = spec.from_normal(normal.as_f32());
+                            Message::EditSettings(set(settings.clone(), value))
+                        })
+                        .on_release(|| Some(Message::ApplySettings))
+                        .style(style::Slider),
+                    )
+                    .push(Text::new(label).width(Length::Units(60)).size(14)),
+            )
+        };
+
+        let mut column = Column::new().spacing(5);
+
+        column = column
+            .push(Text::new("Audio").size(16))
+            .push(Text::new("Raise the latency if playback crackles").size(12));
+        if let Some(row) = slider(
+            &LATENCY,
+            settings.audio.latency as f64,
+            format!("{:.0} ms", settings.audio.latency),
+            |mut settings, latency| {
+                settings.audio.latency = latency.round() as f32;
+                settings
+            },
+        ) {
+            column = column.push(row);
+        }
+
+        let backend_settings = settings.clone();
+        column = column.push(Text::new("Backend").size(16)).push(
+            Row::new()
+                .spacing(10)
+                .push(Text::new("Server").width(Length::Units(90)).size(14))
+                .push(
+                    TextInput::new(
+                        backend,
+                        "http://127.0.0.1:8000",
+                        &settings.backend.url,
+                        move |url| {
+                            Message::EditSettings(Settings {
+                                backend: BackendSettings { url },
+                                ..backend_settings.clone()
+                            })
+                        },
+                    )
+                    .on_submit(Message::ApplySettings)
+                    .size(14)
+                    .padding(3),
+                ),
+        );
+
+        let theme_settings = settings.clone();
+        column = column
+            .push(Text::new("Appearance").size(16))
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .push(Text::new("Theme").width(Length::Units(90)).size(14))
+                    .push(
+                        PickList::new(
+                            theme,
+                            themes.to_vec(),
+                            Some(settings.appearance.theme.clone()),
+                            move |theme| {
+                                let mut settings = theme_settings.clone();
+                                settings.appearance.theme = theme;
+                                Message::SetSettings(settings)
+                            },
+                        )
+                        .text_size(14),
+                    ),
+            )
+            .push(Text::new("The window size applies on next launch").size(12));
+        if let Some(row) = slider(
+            &MIN_WIDTH,
+            settings.appearance.min_width as f64,
+            format!("{} px", settings.appearance.min_width),
+            |mut settings, width| {
+                settings.appearance.min_width = width.round() as u32;
+                settings
+            },
+        ) {
+            column = column.push(row);
+        }
+        if let Some(row) = slider(
+            &MIN_HEIGHT,
+            settings.appearance.min_height as f64,
+            format!("{} px", settings.appearance.min_height),
+            |mut settings, height| {
+                settings.appearance.min_height = height.round() as u32;
+                settings
+            },
+        ) {
+            column = column.push(row);
+        }
+
+        let projects = match &settings.paths.projects {
+            Some(dir) => dir.display().to_string(),
+            None => String::from("Not set"),
+        };
+        let mut clear =
+            Button::new(clear_projects, Text::new("Clear").size(14))
+                .style(style::Button::Control);
+        if settings.paths.projects.is_some() {
+            let mut cleared = settings.clone();
+            cleared.paths.projects = None;
+            clear = clear.on_press(Message::SetSettings(cleared));
+        }
+        column = column.push(Text::new("Paths").size(16)).push(
+            Row::new()
+                .spacing(10)
+                .push(Text::new("Projects").width(Length::Units(90)).size(14))
+                .push(Text::new(projects).width(Length::Fill).size(14))
+                .push(
+                    Button::new(choose_projects, Text::new("Choose").size(14))
+                        .on_press(Message::PickProjectsDir)
+                        .style(style::Button::Control),
+                )
+                .push(clear),
+        );
+
+        let mut toggled = settings.clone();
+        toggled.defaults.musical_typing = !settings.defaults.musical_typing;
+        column = column.push(Text::new("New sessions").size(16));
+        if let Some(row) = slider(
+            &TEMPO,
+            settings.defaults.tempo,
+            format!("{:.0} BPM", settings.defaults.tempo),
+            |mut settings, tempo| {
+                settings.defaults.tempo = tempo.round();
+                settings
+            },
+        ) {
+            column = column.push(row);
+        }
+        column = column.push(
+            Row::new()
+                .spacing(10)
+                .push(
+                    Text::new("Musical typing")
+                        .width(Length::Units(90))
+                        .size(14),
+                )
+                .push(
+                    Button::new(
+                        musical_typing,
+                        Text::new(if settings.defaults.musical_typing {
+                            "On"
+                        } else {
+                            "Off"
+                        })
+                        .size(14),
+                    )
+                    .on_press(Message::SetSettings(toggled))
+                    .style(
+                        if settings.defaults.musical_typing {
+                            style::Button::Primary
+                        } else {
+                            style::Button::Control
+                        },
+                    ),
+                ),
+        );
+
+        column.into()
+    }
+}
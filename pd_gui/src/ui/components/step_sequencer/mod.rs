@@ -0,0 +1,298 @@
+use iced::canvas::{self, event, Canvas, Cursor, Event, Frame, Geometry};
+use iced::{
+    button, keyboard, mouse, Button, Color, Column, Element, Length, Point,
+    Rectangle, Row, Size, Text,
+};
+use iced_audio::{h_slider, HSlider, Normal, NormalParam};
+
+use crate::app::{
+    composition::Composition,
+    engine::sequencer::{Pattern, MAX_STEPS, MIN_STEPS},
+    ui::components::panes::style,
+    ui::theme::palette,
+    Message,
+};
+
+const LANE_HEIGHT: f32 = 24.0;
+const CELL_GAP: f32 = 2.0;
+
+/// What scrolling over a step changes: plain scroll edits velocity, shift
+/// edits probability and alt edits the nudge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StepField {
+    Velocity,
+    Probability,
+    Nudge,
+}
+
+#[derive(Debug)]
+pub struct StepSequencerEditor {
+    modifiers: keyboard::Modifiers,
+    pattern_buttons: Vec<button::State>,
+    add_pattern: button::State,
+    shorter: button::State,
+    longer: button::State,
+    swing: h_slider::State,
+}
+
+impl StepSequencerEditor {
+    pub fn new() -> Self {
+        Self {
+            modifiers: keyboard::Modifiers::empty(),
+            pattern_buttons: vec![],
+            add_pattern: button::State::new(),
+            shorter: button::State::new(),
+            longer: button::State::new(),
+            swing: h_slider::State::new(NormalParam::default()),
+        }
+    }
+
+    /// `chain` holds the pattern indices of the drum track, `selected` is a
+    /// position in that chain
+    pub fn view<'a>(
+        &'a mut self,
+        composition: &'a Composition,
+        chain: &[usize],
+        selected: usize,
+    ) -> Element<'a, Message> {
+        let StepSequencerEditor {
+            modifiers,
+            pattern_buttons,
+            add_pattern,
+            shorter,
+            longer,
+            swing,
+        } = self;
+
+        pattern_buttons.resize_with(chain.len(), button::State::new);
+
+        let mut patterns = Row::new().spacing(5);
+        for (position, state) in pattern_buttons.iter_mut().enumerate() {
+            patterns = patterns.push(
+                Button::new(state, Text::new(format!("{}", position + 1)))
+                    .on_press(Message::SelectPattern(position))
+                    .style(if position == selected {
+                        style::Button::Primary
+                    } else {
+                        style::Button::Control
+                    }),
+            );
+        }
+        patterns = patterns.push(
+            Button::new(add_pattern, Text::new("+"))
+                .on_press(Message::AddPattern)
+                .style(style::Button::Control),
+        );
+
+        let mut editor = Column::new().spacing(10).push(patterns);
+
+        let index = match chain.get(selected) {
+            Some(index) => *index,
+            None => return editor.into(),
+        };
+        let pattern = match composition.patterns.get(index) {
+            Some(pattern) => pattern,
+            None => return editor.into(),
+        };
+
+        swing.set_normal(Normal::from_clipped(pattern.swing));
+
+        let length = pattern.length;
+        let settings = Row::new()
+            .spacing(10)
+            .push(
+                Button::new(shorter, Text::new("-"))
+                    .on_press(Message::SetPatternLength {
+                        pattern: index,
+                        length: length.saturating_sub(MIN_STEPS),
+                    })
+                    .style(style::Button::Control),
+            )
+            .push(Text::new(format!("{} steps", length)).size(16))
+            .push(
+                Button::new(longer, Text::new("+"))
+                    .on_press(Message::SetPatternLength {
+                        pattern: index,
+                        length: (length + MIN_STEPS).min(MAX_STEPS),
+                    })
+                    .style(style::Button::Control),
+            )
+            .push(Text::new("Swing").size(16))
+            .push(
+                HSlider::new(swing, move |normal| {
+                    Message::SetSwing(index, normal)
+                })
+                .width(Length::Units(120))
+                .style(style::Slider),
+            );
+
+        let grid = Canvas::new(StepGrid {
+            modifiers,
+            index,
+            pattern,
+        })
+        .width(Length::Fill)
+        .height(Length::Units(
+            (pattern.lanes.len().max(1) as f32 * LANE_HEIGHT) as u16,
+        ));
+
+        editor = editor.push(settings).push(grid);
+
+        for lane in &pattern.lanes {
+            let name = composition
+                .sample(lane.sample_id)
+                .map_or("Missing sample", |sample| sample.name.as_str());
+            editor = editor.push(Text::new(name).size(12));
+        }
+
+        editor.into()
+    }
+}
+
+struct StepGrid<'a> {
+    modifiers: &'a mut keyboard::Modifiers,
+    index: usize,
+    pattern: &'a Pattern,
+}
+
+impl<'a> StepGrid<'a> {
+    fn cell_size(&self, bounds: Rectangle) -> Size {
+        Size::new(bounds.width / self.pattern.length as f32, LANE_HEIGHT)
+    }
+
+    fn step_at(
+        &self,
+        bounds: Rectangle,
+        position: Point,
+    ) -> Option<(usize, usize)> {
+        let cell = self.cell_size(bounds);
+        let step = (position.x / cell.width) as usize;
+        let lane = (position.y / cell.height) as usize;
+
+        if step < self.pattern.length && lane < self.pattern.lanes.len() {
+            Some((lane, step))
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> canvas::Program<Message> for StepGrid<'a> {
+    fn update(
+        &mut self,
+        event: Event,
+        bounds: Rectangle,
+        cursor: Cursor,
+    ) -> (event::Status, Option<Message>) {
+        if let Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) =
+            event
+        {
+            *self.modifiers = modifiers;
+            return (event::Status::Ignored, None);
+        }
+
+        let (lane, step) = match cursor
+            .position_in(&bounds)
+            .and_then(|position| self.step_at(bounds, position))
+        {
+            Some(cell) => cell,
+            None => return (event::Status::Ignored, None),
+        };
+
+        let message = match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                Message::ToggleStep {
+                    pattern: self.index,
+                    lane,
+                    step,
+                }
+            }
+            Event::Mouse(mouse::Event::WheelScrolled { delta }) => {
+                let amount = match delta {
+                    mouse::ScrollDelta::Lines { y, .. } => y,
+                    mouse::ScrollDelta::Pixels { y, .. } => y / 20.0,
+                };
+                let field = if self.modifiers.shift() {
+                    StepField::Probability
+                } else if self.modifiers.alt() {
+                    StepField::Nudge
+                } else {
+                    StepField::Velocity
+                };
+
+                Message::AdjustStep {
+                    pattern: self.index,
+                    lane,
+                    step,
+                    field,
+                    amount,
+                }
+            }
+            _ => return (event::Status::Ignored, None),
+        };
+
+        (event::Status::Captured, Some(message))
+    }
+
+    fn draw(&self, bounds: Rectangle, _cursor: Cursor) -> Vec<Geometry> {
+        let palette = palette();
+        let mut frame = Frame::new(bounds.size());
+        let cell = self.cell_size(bounds);
+
+        for (lane_index, lane) in self.pattern.lanes.iter().enumerate() {
+            for (index, step) in
+                lane.steps.iter().take(self.pattern.length).enumerate()
+            {
+                let x = index as f32 * cell.width + step.nudge * cell.width;
+                let y = lane_index as f32 * cell.height;
+                let size = Size::new(
+                    (cell.width - CELL_GAP).max(1.0),
+                    cell.height - CELL_GAP,
+                );
+
+                // Every beat gets a slightly different background
+                let background = Color {
+                    a: if (index / 4) % 2 == 0 { 0.04 } else { 0.08 },
+                    ..palette.text
+                };
+                frame.fill_rectangle(
+                    Point::new(index as f32 * cell.width, y),
+                    size,
+                    background,
+                );
+
+                if step.is_active {
+                    // Velocity sets the height, probability the opacity
+                    let height = size.height * step.velocity as f32 / 127.0;
+                    let color = if step.probability < 1.0 {
+                        Color {
+                            a: 0.3 + 0.7 * step.probability,
+                            ..palette.hovered
+                        }
+                    } else {
+                        palette.primary
+                    };
+
+                    frame.fill_rectangle(
+                        Point::new(x, y + size.height - height),
+                        Size::new(size.width, height),
+                        color,
+                    );
+                }
+            }
+        }
+
+        // Mark the steps swing pushes back
+        if self.pattern.swing > 0.0 {
+            for index in (1..self.pattern.length).step_by(2) {
+                frame.fill_rectangle(
+                    Point::new(index as f32 * cell.width, 0.0),
+                    Size::new(1.0, bounds.height),
+                    palette.accent,
+                );
+            }
+        }
+
+        vec![frame.into_geometry()]
+    }
+}
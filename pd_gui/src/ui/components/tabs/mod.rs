@@ -0,0 +1,88 @@
+use iced::{button, Button, Element, Row, Text};
+use iced_aw::graphics::icons::icon_to_char;
+
+use crate::app::{ui::components::panes::style, Message};
+
+/// What a tab shows
+pub struct TabLabel {
+    pub name: String,
+    pub is_unsaved: bool,
+}
+
+#[derive(Debug, Default)]
+struct TabButtons {
+    select: button::State,
+    close: button::State,
+}
+
+/// The open compositions. The active one can be moved along the bar
+#[derive(Debug, Default)]
+pub struct TabBar {
+    tabs: Vec<TabButtons>,
+    move_left: button::State,
+    move_right: button::State,
+    new_tab: button::State,
+}
+
+impl TabBar {
+    pub fn view(
+        &mut self,
+        labels: Vec<TabLabel>,
+        active: usize,
+    ) -> Element<Message> {
+        let icon =
+            |icon| Text::new(icon_to_char(icon)).font(iced_aw::ICON_FONT);
+        let count = labels.len();
+        self.tabs.resize_with(count, TabButtons::default);
+
+        let mut row = Row::new().spacing(5).padding(5);
+        for (index, (label, TabButtons { select, close })) in
+            labels.into_iter().zip(self.tabs.iter_mut()).enumerate()
+        {
+            let is_active = index == active;
+            let name = if label.is_unsaved {
+                format!("{} *", label.name)
+            } else {
+                label.name
+            };
+
+            row = row
+                .push(
+                    Button::new(select, Text::new(name).size(14))
+                        .on_press(Message::SwitchTab(index))
+                        .style(if is_active {
+                            style::Button::Primary
+                        } else {
+                            style::Button::Control
+                        }),
+                )
+                .push(
+                    Button::new(close, icon(iced_aw::Icon::X))
+                        .on_press(Message::CloseTab(index))
+                        .style(style::Button::Destructive),
+                );
+        }
+
+        let mut move_left =
+            Button::new(&mut self.move_left, icon(iced_aw::Icon::ArrowLeft))
+                .style(style::Button::Control);
+        if active > 0 {
+            move_left = move_left.on_press(Message::MoveTab(-1));
+        }
+        let mut move_right =
+            Button::new(&mut self.move_right, icon(iced_aw::Icon::ArrowRight))
+                .style(style::Button::Control);
+        if active + 1 < count {
+            move_right = move_right.on_press(Message::MoveTab(1));
+        }
+
+        row.push(move_left)
+            .push(move_right)
+            .push(
+                Button::new(&mut self.new_tab, icon(iced_aw::Icon::Plus))
+                    .on_press(Message::NewTab)
+                    .style(style::Button::Control),
+            )
+            .into()
+    }
+}
@@ -0,0 +1,110 @@
+use iced::{button, Button, Column, Element, Length, Row, Text};
+use iced_audio::{h_slider, HSlider, Normal, NormalParam};
+
+use crate::app::{
+    engine::effects::ParamSpec, engine::transcription::Transcription,
+    ui::components::panes::style, Message,
+};
+
+const CONFIDENCE: ParamSpec = ParamSpec::live("Confidence", 0.0, 1.0, 0.8);
+const SENSITIVITY: ParamSpec = ParamSpec::live("Onsets", 1.0, 24.0, 6.0);
+const MIN_LENGTH: ParamSpec =
+    ParamSpec::live("Shortest", 10.0, 500.0, 60.0).logarithmic();
+
+type Setter = fn(Transcription, f64) -> Transcription;
+
+/// How samples turned into clips get cleaned up. The samples themselves are
+/// transcribed from their buttons in the sample list
+#[derive(Debug)]
+pub struct TranscriptionSettings {
+    sliders: Vec<h_slider::State>,
+    quantize: button::State,
+}
+
+impl TranscriptionSettings {
+    pub fn new() -> Self {
+        Self {
+            sliders: (0..3)
+                .map(|_| h_slider::State::new(NormalParam::default()))
+                .collect(),
+            quantize: button::State::new(),
+        }
+    }
+
+    pub fn view<'a>(
+        &'a mut self,
+        settings: &Transcription,
+    ) -> Element<'a, Message> {
+        let settings = *settings;
+        let header = Row::new()
+            .spacing(10)
+            .push(Text::new("Audio to MIDI").width(Length::Fill).size(16))
+            .push(Text::new("Quantize").size(14))
+            .push(
+                Button::new(
+                    &mut self.quantize,
+                    Text::new(settings.quantize_name()).size(14),
+                )
+                .on_press(Message::SetTranscription(Transcription {
+                    quantize: settings.next_quantize(),
+                    ..settings
+                }))
+                .style(style::Button::Control),
+            );
+
+        let controls: [(&'static ParamSpec, f64, String, Setter); 3] = [
+            (
+                &CONFIDENCE,
+                settings.confidence,
+                format!("{:.0}%", settings.confidence * 100.0),
+                |settings, confidence| Transcription {
+                    confidence,
+                    ..settings
+                },
+            ),
+            (
+                &SENSITIVITY,
+                settings.sensitivity,
+                format!("{:.1} dB", settings.sensitivity),
+                |settings, sensitivity| Transcription {
+                    sensitivity,
+                    ..settings
+                },
+            ),
+            (
+                &MIN_LENGTH,
+                settings.min_length,
+                format!("{:.0} ms", settings.min_length),
+                |settings, min_length| Transcription {
+                    min_length,
+                    ..settings
+                },
+            ),
+        ];
+
+        let mut column = Column::new().spacing(5).push(header);
+        for ((spec, value, label, set), slider) in
+            controls.into_iter().zip(&mut self.sliders)
+        {
+            slider.set_normal(Normal::from_clipped(spec.to_normal(value)));
+
+            column = column.push(
+                Row::new()
+                    .spacing(10)
+                    .push(
+                        Text::new(spec.name).width(Length::Units(90)).size(14),
+                    )
+                    .push(
+                        HSlider::new(slider, move |normal| {
+                            let value = spec.from_normal(normal.as_f32());
+                            Message::SetTranscription(set(settings, value))
+                        })
+                        .style(style::Slider),
+                    )
+                    .push(Text::new(label).width(Length::Units(60)).size(14)),
+            );
+        }
+
+        column.into()
+    }
+}
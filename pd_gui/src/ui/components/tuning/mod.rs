@@ -0,0 +1,63 @@
+use iced::{button, Button, Column, Element, Length, Row, Text};
+use iced_audio::{h_slider, HSlider, Normal, NormalParam};
+
+use crate::app::{
+    engine::effects::ParamSpec, engine::tuning::Tuning,
+    ui::components::panes::style, Message,
+};
+
+/// Frequency of the reference key, A4 unless a keyboard mapping moves it
+pub const ROOT_FREQUENCY: ParamSpec =
+    ParamSpec::live("Root", 20.0, 2_000.0, 440.0).logarithmic();
+
+/// The composition's tuning. Scales and keyboard mappings come in by
+/// dropping `.scl` and `.kbm` files on the window
+#[derive(Debug)]
+pub struct TuningEditor {
+    root: h_slider::State,
+    reset: button::State,
+}
+
+impl TuningEditor {
+    pub fn new() -> Self {
+        Self {
+            root: h_slider::State::new(NormalParam::default()),
+            reset: button::State::new(),
+        }
+    }
+
+    pub fn view<'a>(&'a mut self, tuning: &Tuning) -> Element<'a, Message> {
+        let frequency = tuning.reference_frequency();
+        self.root.set_normal(Normal::from_clipped(
+            ROOT_FREQUENCY.to_normal(frequency),
+        ));
+
+        let header = Row::new()
+            .spacing(10)
+            .push(
+                Text::new(format!("Tuning {}", tuning.name()))
+                    .width(Length::Fill)
+                    .size(16),
+            )
+            .push(
+                Button::new(&mut self.reset, Text::new("12-TET").size(14))
+                    .on_press(Message::ResetTuning)
+                    .style(style::Button::Control),
+            );
+
+        let root = Row::new()
+            .spacing(10)
+            .push(Text::new("Root").width(Length::Units(90)).size(14))
+            .push(
+                HSlider::new(&mut self.root, Message::SetRootFrequency)
+                    .style(style::Slider),
+            )
+            .push(
+                Text::new(format!("{:.2} Hz", frequency))
+                    .width(Length::Units(80))
+                    .size(14),
+            );
+
+        Column::new().spacing(5).push(header).push(root).into()
+    }
+}
@@ -0,0 +1,106 @@
+use std::fmt;
+
+use iced::{
+    button, pick_list, text_input, Button, Element, Length, PickList, Row,
+    Text, TextInput,
+};
+
+use crate::app::{
+    ui::components::panes::style, ui::workspaces::Workspaces, Message,
+};
+
+// A workspace as offered in the pick list
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct WorkspaceChoice {
+    index: usize,
+    name: String,
+}
+
+impl fmt::Display for WorkspaceChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// Switches between the named workspaces and saves the panes as one
+#[derive(Debug)]
+pub struct WorkspaceBar {
+    pick: pick_list::State<WorkspaceChoice>,
+    name: text_input::State,
+    save: button::State,
+    delete: button::State,
+}
+
+impl WorkspaceBar {
+    pub fn new() -> Self {
+        Self {
+            pick: pick_list::State::default(),
+            name: text_input::State::new(),
+            save: button::State::new(),
+            delete: button::State::new(),
+        }
+    }
+
+    pub fn view<'a>(
+        &'a mut self,
+        workspaces: &Workspaces,
+        current: Option<usize>,
+        name: &str,
+    ) -> Element<'a, Message> {
+        let choices: Vec<WorkspaceChoice> = workspaces
+            .saved
+            .iter()
+            .enumerate()
+            .map(|(index, workspace)| WorkspaceChoice {
+                index,
+                name: workspace.name.clone(),
+            })
+            .collect();
+        let selected = current.and_then(|index| choices.get(index).cloned());
+
+        let mut save = Button::new(&mut self.save, Text::new("Save").size(14))
+            .style(style::Button::Control);
+        if !name.trim().is_empty() {
+            save = save.on_press(Message::SaveWorkspace);
+        }
+
+        let mut delete =
+            Button::new(&mut self.delete, Text::new("Delete").size(14))
+                .style(style::Button::Destructive);
+        if current.is_some() {
+            delete = delete.on_press(Message::DeleteWorkspace);
+        }
+
+        Row::new()
+            .spacing(10)
+            .padding(5)
+            .push(Text::new("Workspace").size(14))
+            .push(
+                PickList::new(
+                    &mut self.pick,
+                    choices,
+                    selected,
+                    |choice: WorkspaceChoice| {
+                        Message::SwitchWorkspace(choice.index)
+                    },
+                )
+                .placeholder("None")
+                .text_size(14),
+            )
+            .push(
+                TextInput::new(
+                    &mut self.name,
+                    "Name",
+                    name,
+                    Message::SetWorkspaceName,
+                )
+                .on_submit(Message::SaveWorkspace)
+                .width(Length::Units(140))
+                .padding(4)
+                .size(14),
+            )
+            .push(save)
+            .push(delete)
+            .into()
+    }
+}
@@ -0,0 +1,99 @@
+//! The pane tree, in a form that can be saved and rebuilt.
+
+use iced::pane_grid::{self, Configuration, Node};
+use serde::{Deserialize, Serialize};
+
+use super::components::panes::{Pane, PaneKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+impl From<pane_grid::Axis> for Axis {
+    fn from(axis: pane_grid::Axis) -> Self {
+        match axis {
+            pane_grid::Axis::Horizontal => Axis::Horizontal,
+            pane_grid::Axis::Vertical => Axis::Vertical,
+        }
+    }
+}
+
+impl From<Axis> for pane_grid::Axis {
+    fn from(axis: Axis) -> Self {
+        match axis {
+            Axis::Horizontal => pane_grid::Axis::Horizontal,
+            Axis::Vertical => pane_grid::Axis::Vertical,
+        }
+    }
+}
+
+/// Panes are stored by kind, the state of their widgets starts over
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Layout {
+    Split {
+        axis: Axis,
+        ratio: f32,
+        a: Box<Layout>,
+        b: Box<Layout>,
+    },
+    Pane {
+        // `None` for panes still offering the kinds to choose from
+        #[serde(default)]
+        kind: Option<PaneKind>,
+        is_pinned: bool,
+    },
+}
+
+impl Default for Layout {
+    // Just the composition
+    fn default() -> Self {
+        Layout::Pane {
+            kind: Some(PaneKind::Composition),
+            is_pinned: false,
+        }
+    }
+}
+
+impl Layout {
+    pub fn capture(panes: &pane_grid::State<Pane>) -> Self {
+        Self::from_node(panes, panes.layout())
+    }
+
+    fn from_node(panes: &pane_grid::State<Pane>, node: &Node) -> Self {
+        match node {
+            Node::Split {
+                axis, ratio, a, b, ..
+            } => Layout::Split {
+                axis: (*axis).into(),
+                ratio: *ratio,
+                a: Box::new(Self::from_node(panes, a)),
+                b: Box::new(Self::from_node(panes, b)),
+            },
+            Node::Pane(pane) => {
+                let pane = panes.get(pane);
+                Layout::Pane {
+                    kind: pane.and_then(|pane| pane.content.kind()),
+                    is_pinned: pane.map_or(false, |pane| pane.is_pinned),
+                }
+            }
+        }
+    }
+
+    pub fn configuration(&self) -> Configuration<Pane> {
+        match self {
+            Layout::Split { axis, ratio, a, b } => Configuration::Split {
+                axis: (*axis).into(),
+                ratio: *ratio,
+                a: Box::new(a.configuration()),
+                b: Box::new(b.configuration()),
+            },
+            Layout::Pane { kind, is_pinned } => {
+                let mut pane = Pane::new(*kind);
+                pane.is_pinned = *is_pinned;
+                Configuration::Pane(pane)
+            }
+        }
+    }
+}
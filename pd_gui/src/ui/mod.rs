@@ -1,2 +1,4 @@
-pub mod colors;
 pub mod components;
+pub mod layout;
+pub mod theme;
+pub mod workspaces;
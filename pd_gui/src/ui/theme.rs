@@ -0,0 +1,306 @@
+//! The palette every style and canvas draws with.
+//!
+//! Light, dark and high contrast palettes are built in. User palettes are
+//! TOML files in the `themes` config directory, named after the file. They
+//! start from the palette named by `base`, dark unless given, and set any
+//! of its colors as `#RRGGBB` or `#RRGGBBAA`:
+//!
+//! ```toml
+//! base = "dark"
+//! primary = "#E07A5F"
+//! meter = "#81B29A"
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use anyhow::{anyhow, Context};
+use iced::Color;
+use serde::{Deserialize, Serialize};
+
+use crate::app::paths;
+
+const EXTENSION: &str = "toml";
+
+// `None` until a theme is picked, which is the light one
+static PALETTE: RwLock<Option<Palette>> = RwLock::new(None);
+
+// Saved by name, as in `From<String>`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum ThemeType {
+    Light,
+    Dark,
+    HighContrast,
+    // A palette file in the themes directory, by name
+    User(String),
+}
+
+impl Default for ThemeType {
+    fn default() -> Self {
+        ThemeType::Light
+    }
+}
+
+/// `light`, `dark` and `high-contrast` are built in, other names are
+/// palette files
+impl From<String> for ThemeType {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "light" => ThemeType::Light,
+            "dark" => ThemeType::Dark,
+            "high-contrast" => ThemeType::HighContrast,
+            _ => ThemeType::User(name),
+        }
+    }
+}
+
+impl From<ThemeType> for String {
+    fn from(theme: ThemeType) -> Self {
+        match theme {
+            ThemeType::Light => String::from("light"),
+            ThemeType::Dark => String::from("dark"),
+            ThemeType::HighContrast => String::from("high-contrast"),
+            ThemeType::User(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for ThemeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThemeType::Light => write!(f, "Light"),
+            ThemeType::Dark => write!(f, "Dark"),
+            ThemeType::HighContrast => write!(f, "High contrast"),
+            ThemeType::User(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Palette {
+    // The window behind the panes
+    #[serde(with = "hex")]
+    pub background: Color,
+    // Panes and the boxes drawn on them
+    #[serde(with = "hex")]
+    pub surface: Color,
+    #[serde(with = "hex")]
+    pub text: Color,
+    // Pane borders and title bars
+    #[serde(with = "hex")]
+    pub border: Color,
+    // Active buttons, curves and filled sliders
+    #[serde(with = "hex")]
+    pub primary: Color,
+    #[serde(with = "hex")]
+    pub hovered: Color,
+    // Control buttons, handles and the focused pane's title
+    #[serde(with = "hex")]
+    pub accent: Color,
+    // Titles of the other panes
+    #[serde(with = "hex")]
+    pub accent_muted: Color,
+    // Text on primary and accent buttons
+    #[serde(with = "hex")]
+    pub on_primary: Color,
+    #[serde(with = "hex")]
+    pub destructive: Color,
+    #[serde(with = "hex")]
+    pub meter: Color,
+    #[serde(with = "hex")]
+    pub meter_background: Color,
+    // The empty part of sliders and faders
+    #[serde(with = "hex")]
+    pub rail: Color,
+}
+
+fn rgb(hex: u32) -> Color {
+    Color::from_rgb8((hex >> 16) as u8, (hex >> 8) as u8, hex as u8)
+}
+
+impl Palette {
+    pub fn light() -> Self {
+        Self {
+            background: Color::WHITE,
+            surface: rgb(0xF2F3F5),
+            text: Color::BLACK,
+            border: Color::from_rgb(0.5, 0.5, 0.5),
+            primary: rgb(0x7289DA),
+            hovered: rgb(0x677BC4),
+            accent: rgb(0xFF4747),
+            accent_muted: rgb(0xFFC7C7),
+            on_primary: Color::WHITE,
+            destructive: rgb(0xFF4747),
+            meter: Color::from_rgba(0.0, 1.0, 0.0, 0.7),
+            meter_background: Color::BLACK,
+            rail: rgb(0xD4D7DD),
+        }
+    }
+
+    pub fn dark() -> Self {
+        Self {
+            background: rgb(0x1E1F22),
+            surface: rgb(0x2B2D31),
+            text: rgb(0xE6E6E6),
+            border: rgb(0x5A5D63),
+            primary: rgb(0x7289DA),
+            hovered: rgb(0x5B6EAE),
+            accent: rgb(0xFF5C5C),
+            accent_muted: rgb(0x9A5656),
+            on_primary: Color::WHITE,
+            destructive: rgb(0xFF6B6B),
+            meter: rgb(0x3DDC84),
+            meter_background: rgb(0x111214),
+            rail: rgb(0x404249),
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        Self {
+            background: Color::BLACK,
+            surface: Color::BLACK,
+            text: Color::WHITE,
+            border: Color::WHITE,
+            primary: rgb(0xFFD400),
+            hovered: rgb(0xFFE866),
+            accent: rgb(0x00E5FF),
+            accent_muted: rgb(0x80F2FF),
+            on_primary: Color::BLACK,
+            destructive: rgb(0xFF3030),
+            meter: rgb(0x00FF00),
+            meter_background: Color::BLACK,
+            rail: rgb(0x5C5C5C),
+        }
+    }
+
+    /// A user palette, from the text of its file
+    pub fn parse(source: &str) -> Result<Self, anyhow::Error> {
+        let mut colors: toml::value::Table = toml::from_str(source)?;
+        let base = match colors.remove("base") {
+            None => Palette::dark(),
+            Some(base) => match base.as_str() {
+                Some("light") => Palette::light(),
+                Some("dark") => Palette::dark(),
+                Some("high-contrast") => Palette::high_contrast(),
+                _ => return Err(anyhow!("unknown base palette {}", base)),
+            },
+        };
+
+        let mut palette = toml::Value::try_from(base)?;
+        if let Some(table) = palette.as_table_mut() {
+            for (name, color) in colors {
+                table.insert(name, color);
+            }
+        }
+        Ok(palette.try_into()?)
+    }
+}
+
+fn themes_dir() -> PathBuf {
+    paths::config_dir().join("themes")
+}
+
+/// The palette files in the themes directory, by name
+pub fn user_themes() -> Vec<ThemeType> {
+    let entries = match fs::read_dir(themes_dir()) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    let mut themes: Vec<ThemeType> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == EXTENSION))
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_string_lossy().into_owned();
+            Some(ThemeType::User(name))
+        })
+        .collect();
+    themes.sort_by_key(|theme| theme.to_string());
+    themes
+}
+
+/// The built in themes, then the user's
+pub fn themes() -> Vec<ThemeType> {
+    let mut themes =
+        vec![ThemeType::Light, ThemeType::Dark, ThemeType::HighContrast];
+    themes.extend(user_themes());
+    themes
+}
+
+pub fn load(theme: &ThemeType) -> Result<Palette, anyhow::Error> {
+    match theme {
+        ThemeType::Light => Ok(Palette::light()),
+        ThemeType::Dark => Ok(Palette::dark()),
+        ThemeType::HighContrast => Ok(Palette::high_contrast()),
+        ThemeType::User(name) => {
+            let path = themes_dir().join(name).with_extension(EXTENSION);
+            let source = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {:?}", path))?;
+            Palette::parse(&source)
+                .with_context(|| format!("failed to read {:?}", path))
+        }
+    }
+}
+
+/// The palette in use, read by the styles each time they're drawn
+pub fn palette() -> Palette {
+    match PALETTE.read() {
+        Ok(palette) => palette.unwrap_or_else(Palette::light),
+        Err(_) => Palette::light(),
+    }
+}
+
+pub fn set_palette(palette: Palette) {
+    if let Ok(mut current) = PALETTE.write() {
+        *current = Some(palette);
+    }
+}
+
+// Colors as `#RRGGBB`, or `#RRGGBBAA` when they're translucent
+mod hex {
+    use iced::Color;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        color: &Color,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let [r, g, b, a] = color.into_rgba8();
+        let text = if a == u8::MAX {
+            format!("#{:02X}{:02X}{:02X}", r, g, b)
+        } else {
+            format!("#{:02X}{:02X}{:02X}{:02X}", r, g, b, a)
+        };
+        serializer.serialize_str(&text)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Color, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        parse(&text).ok_or_else(|| {
+            D::Error::custom(format!("{:?} isn't a #RRGGBB color", text))
+        })
+    }
+
+    fn parse(text: &str) -> Option<Color> {
+        let digits = text.strip_prefix('#')?;
+        let value = u32::from_str_radix(digits, 16).ok()?;
+        let byte = |shift: u32| (value >> shift) as u8;
+
+        match digits.len() {
+            6 => Some(Color::from_rgb8(byte(16), byte(8), byte(0))),
+            8 => Some(Color::from_rgba8(
+                byte(24),
+                byte(16),
+                byte(8),
+                byte(0) as f32 / 255.0,
+            )),
+            _ => None,
+        }
+    }
+}
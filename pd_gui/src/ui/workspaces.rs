@@ -0,0 +1,234 @@
+//! Named pane layouts, and the layout the app was closed with.
+//!
+//! They are kept in `workspaces.json` in the config directory, apart from
+//! projects, so they follow the user from one project to the next. Like
+//! projects, the file records the version of its schema and older ones are
+//! migrated when loaded.
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::components::panes::PaneKind;
+use super::layout::{Axis, Layout};
+use crate::app::paths;
+use crate::app::project::pane_kinds;
+
+const FILE: &str = "workspaces.json";
+
+pub const VERSION: u64 = 1;
+
+// Each one upgrades a file from the version at its index to the next
+const MIGRATIONS: [fn(Value) -> Value; VERSION as usize] = [
+    // Version 0 files name panes by the id of their content
+    |mut workspaces| {
+        if let Some(last) = workspaces.get_mut("last") {
+            pane_kinds(last);
+        }
+        if let Some(saved) = workspaces["saved"].as_array_mut() {
+            for workspace in saved {
+                if let Some(layout) = workspace.get_mut("layout") {
+                    pane_kinds(layout);
+                }
+            }
+        }
+        workspaces
+    },
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Workspace {
+    pub name: String,
+    pub layout: Layout,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Workspaces {
+    #[serde(default)]
+    pub version: u64,
+    // Restored on launch
+    #[serde(default)]
+    pub last: Layout,
+    #[serde(default)]
+    pub saved: Vec<Workspace>,
+}
+
+impl Default for Workspaces {
+    fn default() -> Self {
+        let pane = |kind| Layout::Pane {
+            kind: Some(kind),
+            is_pinned: false,
+        };
+        let split = |axis, ratio, a, b| Layout::Split {
+            axis,
+            ratio,
+            a: Box::new(a),
+            b: Box::new(b),
+        };
+
+        Self {
+            version: VERSION,
+            last: Layout::default(),
+            saved: vec![
+                Workspace {
+                    name: "Recording".to_string(),
+                    layout: split(
+                        Axis::Vertical,
+                        0.6,
+                        pane(PaneKind::Composition),
+                        split(
+                            Axis::Horizontal,
+                            0.5,
+                            pane(PaneKind::SampleCreator),
+                            pane(PaneKind::Browser),
+                        ),
+                    ),
+                },
+                Workspace {
+                    name: "Mixing".to_string(),
+                    layout: split(
+                        Axis::Horizontal,
+                        0.35,
+                        pane(PaneKind::Composition),
+                        pane(PaneKind::Mixer),
+                    ),
+                },
+                Workspace {
+                    name: "Sound design".to_string(),
+                    layout: split(
+                        Axis::Vertical,
+                        0.4,
+                        pane(PaneKind::Browser),
+                        pane(PaneKind::PatchGraph),
+                    ),
+                },
+            ],
+        }
+    }
+}
+
+fn path() -> PathBuf {
+    paths::config_dir().join(FILE)
+}
+
+impl Workspaces {
+    /// The saved workspaces, or the built in ones on the first launch
+    pub fn load() -> Result<Self, anyhow::Error> {
+        let path = path();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let source = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {:?}", path))?;
+        let value: Value = serde_json::from_str(&source)
+            .with_context(|| format!("failed to read {:?}", path))?;
+        Self::from_value(value)
+            .with_context(|| format!("failed to read {:?}", path))
+    }
+
+    fn from_value(mut value: Value) -> Result<Self, anyhow::Error> {
+        if !value.is_object() {
+            return Err(anyhow!("not a list of workspaces"));
+        }
+
+        let mut version = value["version"].as_u64().unwrap_or(0);
+        if version > VERSION {
+            return Err(anyhow!("saved by a newer version of the app"));
+        }
+        while version < VERSION {
+            value = MIGRATIONS[version as usize](value);
+            version += 1;
+            value["version"] = json!(version);
+        }
+
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let path = path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {:?}", dir))?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write {:?}", path))?;
+
+        Ok(())
+    }
+
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.saved
+            .iter()
+            .position(|workspace| workspace.name == name)
+    }
+
+    /// Saves `layout` under `name`, replacing a workspace of that name.
+    /// Returns its index
+    pub fn insert(&mut self, name: String, layout: Layout) -> usize {
+        match self.position(&name) {
+            Some(index) => {
+                self.saved[index].layout = layout;
+                index
+            }
+            None => {
+                self.saved.push(Workspace { name, layout });
+                self.saved.len() - 1
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_pane_ids_to_kinds() {
+        let pane = |id| json!({ "Pane": { "id": id, "is_pinned": false } });
+        let value = json!({
+            "last": {
+                "Split": {
+                    "axis": "Vertical",
+                    "ratio": 0.5,
+                    "a": pane(0),
+                    "b": pane(1),
+                }
+            },
+            "saved": [{ "name": "Patching", "layout": pane(2) }],
+        });
+
+        let workspaces = Workspaces::from_value(value).unwrap();
+        let kind = |kind| Layout::Pane {
+            kind: Some(kind),
+            is_pinned: false,
+        };
+        assert_eq!(workspaces.version, VERSION);
+        assert_eq!(
+            workspaces.last,
+            Layout::Split {
+                axis: Axis::Vertical,
+                ratio: 0.5,
+                a: Box::new(kind(PaneKind::Composition)),
+                b: Box::new(kind(PaneKind::SampleCreator)),
+            }
+        );
+        assert_eq!(workspaces.saved[0].layout, kind(PaneKind::PatchGraph));
+    }
+
+    #[test]
+    fn keeps_current_workspaces() {
+        let workspaces = Workspaces::default();
+        let value = serde_json::to_value(&workspaces).unwrap();
+        assert_eq!(Workspaces::from_value(value).unwrap(), workspaces);
+    }
+
+    #[test]
+    fn refuses_newer_workspaces() {
+        let value = json!({ "version": VERSION + 1 });
+        assert!(Workspaces::from_value(value).is_err());
+    }
+}